@@ -22,7 +22,7 @@
       total : 0.0,
     };
 
-    fetch("http://localhost:8002/compute", {
+    fetch("http://localhost:8002/v1/compute", {
       method: "POST",
       body: JSON.stringify(data),
       headers: { "Content-type": "application/json" },
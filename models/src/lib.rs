@@ -0,0 +1,23 @@
+//! The types `order_total` and `sales_tax_rate` exchange, kept in one place
+//! so the two services cannot drift apart: the rate lookup's request and
+//! response, and the zip code to rate table both of them embed.
+//!
+//! Orders never cross between the services; `Order` lives in
+//! `order_total_core`. With the `logging` feature, it also holds the logging
+//! setup they share.
+
+#[cfg(feature = "logging")]
+pub mod logging;
+pub mod rate;
+
+pub use rate::{
+    JurisdictionLevel, ParseError, RateComponent, RateRequest, RateResponse, RateRow, DATE_PARAM,
+    FIND_RATE_PATH, RATE_JSON,
+};
+
+/// The `zip,rate[,state,county,city,special[,effective_from]]` CSV the sales
+/// tax rate service answers from, also compiled into `order_total` for
+/// `TAX_RATE_SOURCE=embedded`. The state to special columns break the rate
+/// down by jurisdiction level where it is known; `effective_from` dates a
+/// rate that replaced an earlier row of the same zip code.
+pub const RATES_BY_ZIPCODE_CSV: &[u8] = include_bytes!("rates_by_zipcode.csv");
@@ -0,0 +1,111 @@
+//! The logging setup and the request span both services use. Each brings
+//! its own OpenTelemetry layer and trace propagation, which are built on
+//! the HTTP client and runtime it runs on.
+
+#[cfg(not(feature = "http1"))]
+use http::{header::HeaderValue, HeaderMap, Request, Response};
+#[cfg(feature = "http1")]
+use http1::{header::HeaderValue, HeaderMap, Request, Response};
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Instant;
+use tracing::{field, Instrument, Span};
+use tracing_subscriber::fmt::{self, MakeWriter};
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// The header carrying the id that ties the logs of one request together
+/// across both services.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The filter of `RUST_LOG`, `info` when it is unset or invalid.
+pub fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+/// Installs the global tracing subscriber: `filter`, the `otel` layer
+/// exporting spans if there is one, and log lines written to `writer`, one
+/// JSON object per line with `LOG_FORMAT=json`. An `otel` that failed to
+/// build is logged, and the logs go on without it.
+pub fn init<F, L, E, W>(filter: F, otel: Result<Option<L>, E>, writer: W)
+where
+    F: Layer<Registry> + Send + Sync + 'static,
+    L: Layer<Layered<F, Registry>> + Send + Sync + 'static,
+    E: Display,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let (otel, otel_error) = match otel {
+        Ok(layer) => (layer, None),
+        Err(err) => (None, Some(err)),
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(otel);
+    match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_writer(writer),
+            )
+            .init(),
+        _ => registry.with(fmt::layer().with_writer(writer)).init(),
+    }
+    if let Some(err) = otel_error {
+        tracing::warn!("OpenTelemetry export disabled: {err:#}");
+    }
+}
+
+/// Runs `handler` inside a `request` span carrying `request_id`, the method
+/// and the path, and logs the response status and latency when it
+/// completes. Handlers can fill in the `subject`, `tenant` and `zip` fields
+/// with `Span::current().record(..)`. `set_parent` joins the span to the
+/// caller's trace, e.g. from a W3C `traceparent` header.
+///
+/// The request id, if there is one, is echoed in the `X-Request-Id`
+/// response header.
+pub async fn traced<B, R, E, F, Fut>(
+    req: Request<B>,
+    request_id: Option<HeaderValue>,
+    set_parent: impl FnOnce(&Span, &HeaderMap),
+    handler: F,
+) -> Result<Response<R>, E>
+where
+    F: FnOnce(Request<B>) -> Fut,
+    Fut: Future<Output = Result<Response<R>, E>>,
+    E: Display,
+{
+    let span = tracing::info_span!(
+        "request",
+        otel.kind = "server",
+        request_id = request_id.as_ref().and_then(|id| id.to_str().ok()),
+        method = %req.method(),
+        path = %req.uri().path(),
+        subject = field::Empty,
+        tenant = field::Empty,
+        zip = field::Empty,
+        status = field::Empty,
+        latency_ms = field::Empty,
+    );
+    set_parent(&span, req.headers());
+
+    async move {
+        let start = Instant::now();
+        let mut result = handler(req).await;
+        let span = Span::current();
+        span.record("latency_ms", start.elapsed().as_millis() as u64);
+        match &mut result {
+            Ok(response) => {
+                if let Some(id) = request_id {
+                    response.headers_mut().insert(REQUEST_ID_HEADER, id);
+                }
+                span.record("status", response.status().as_u16());
+                tracing::info!("request completed");
+            }
+            Err(err) => tracing::error!(error = %err, "request failed"),
+        }
+        result
+    }
+    .instrument(span)
+    .await
+}
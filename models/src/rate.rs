@@ -0,0 +1,272 @@
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The path the sales tax rate service answers lookups on.
+pub const FIND_RATE_PATH: &str = "/find_rate";
+
+/// The media type of a lookup answered as JSON, with its components; a
+/// lookup sent with this `Accept` header gets one.
+pub const RATE_JSON: &str = "application/json";
+
+/// The query parameter a lookup names the day it wants the rate of in.
+pub const DATE_PARAM: &str = "date";
+
+/// A rate lookup. On the wire, the body of `POST /find_rate` is the zip code
+/// as plain text, e.g. `78701`. A lookup of the rate in effect on another
+/// day than today adds it as a query parameter, e.g. `?date=2024-01-01`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateRequest {
+    pub zip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+}
+
+/// The answer to a lookup. On the wire, the body is the rate as a plain
+/// decimal, e.g. `0.0825`, or, for a lookup that accepts `RATE_JSON`, the
+/// response as JSON, e.g.
+/// `{"rate":0.0825,"components":[{"level":"state","rate":0.0625},...]}`. A
+/// zip code without a rate is answered with a `404` and no body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateResponse {
+    pub rate: Decimal,
+    /// What the rate is made of, by jurisdiction level, where known; they
+    /// add up to `rate`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<RateComponent>,
+}
+
+/// The part of a composite rate one jurisdiction levies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateComponent {
+    pub level: JurisdictionLevel,
+    pub rate: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JurisdictionLevel {
+    State,
+    County,
+    City,
+    /// A special purpose district, such as a transit authority.
+    Special,
+    /// A national tax, such as Canada's GST or HST.
+    Federal,
+    /// A Canadian provincial sales tax, such as Quebec's QST.
+    Province,
+}
+
+impl JurisdictionLevel {
+    /// The levels of a US rate, in the order of the columns of the rates
+    /// CSV.
+    pub const ALL: [Self; 4] = [Self::State, Self::County, Self::City, Self::Special];
+}
+
+/// A body that is not a valid request or response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The zip code is empty or not UTF-8.
+    InvalidZip,
+    /// The rate is not a decimal number.
+    InvalidRate(String),
+    /// The rate, or one of its components, is not a fraction from `0` up
+    /// to, but not including, `1`.
+    RateOutOfRange(Decimal),
+    /// The date is not a date such as `2024-01-01`.
+    InvalidDate(String),
+}
+
+impl RateRequest {
+    pub fn new(zip: impl Into<String>) -> Self {
+        Self {
+            zip: zip.into(),
+            date: None,
+        }
+    }
+
+    /// The lookup of the rate in effect on `date`, or today for `None`.
+    pub fn on(mut self, date: Option<NaiveDate>) -> Self {
+        self.date = date;
+        self
+    }
+
+    /// Reads a request body, ignoring surrounding whitespace.
+    pub fn from_body(body: &[u8]) -> Result<Self, ParseError> {
+        let zip = std::str::from_utf8(body)
+            .map_err(|_| ParseError::InvalidZip)?
+            .trim();
+        if zip.is_empty() {
+            return Err(ParseError::InvalidZip);
+        }
+        Ok(Self::new(zip))
+    }
+
+    pub fn to_body(&self) -> String {
+        self.zip.trim().to_owned()
+    }
+
+    /// Reads the `date` parameter from the query string of the request, if
+    /// it has one.
+    pub fn with_query(mut self, query: Option<&str>) -> Result<Self, ParseError> {
+        let date = query
+            .unwrap_or_default()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(DATE_PARAM)?.strip_prefix('='));
+        if let Some(date) = date {
+            let parsed = date
+                .parse()
+                .map_err(|_| ParseError::InvalidDate(date.to_owned()))?;
+            self.date = Some(parsed);
+        }
+        Ok(self)
+    }
+
+    /// The query string to send the request with, if it needs one.
+    pub fn query(&self) -> Option<String> {
+        self.date.map(|date| format!("{DATE_PARAM}={date}"))
+    }
+
+    /// The day the rate is wanted for: `date`, or today.
+    pub fn effective_date(&self) -> NaiveDate {
+        self.date.unwrap_or_else(|| Utc::now().date_naive())
+    }
+}
+
+/// One row of the rates CSV, after the zip code: a rate, and the day it
+/// took effect, or `None` for a rate that was always in effect. A zip code
+/// can have several rows, each in effect until the next one takes over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateRow {
+    pub rate: RateResponse,
+    pub effective_from: Option<NaiveDate>,
+}
+
+impl RateRow {
+    /// Reads the rate and its components, as `RateResponse::from_csv_fields`
+    /// does, and then the optional `effective_from` column.
+    pub fn from_csv_fields<'a>(fields: impl Iterator<Item = &'a str>) -> Result<Self, ParseError> {
+        let fields: Vec<&str> = fields.collect();
+        let (rate, rest) = fields.split_at(fields.len().min(1 + JurisdictionLevel::ALL.len()));
+        let effective_from = match rest.first().map(|date| date.trim()) {
+            None | Some("") => None,
+            Some(date) => Some(
+                date.parse()
+                    .map_err(|_| ParseError::InvalidDate(date.to_owned()))?,
+            ),
+        };
+        Ok(Self {
+            rate: RateResponse::from_csv_fields(rate.iter().copied())?,
+            effective_from,
+        })
+    }
+
+    /// Of the rows of one zip code, the one in effect on `date`: the last
+    /// to take effect by then.
+    pub fn in_effect<'a>(
+        rows: impl IntoIterator<Item = &'a RateRow>,
+        date: NaiveDate,
+    ) -> Option<&'a RateRow> {
+        rows.into_iter()
+            .filter(|row| row.effective_from.is_none_or(|from| from <= date))
+            .max_by_key(|row| row.effective_from)
+    }
+}
+
+impl RateResponse {
+    /// Reads a response body of either form, ignoring surrounding
+    /// whitespace.
+    pub fn from_body(body: &str) -> Result<Self, ParseError> {
+        let body = body.trim();
+        let response: Self = if body.starts_with('{') {
+            serde_json::from_str(body).map_err(|_| ParseError::InvalidRate(body.to_owned()))?
+        } else {
+            body.parse()?
+        };
+        response.in_range()
+    }
+
+    /// The response, if its rate and each of its components is in `0..1`;
+    /// a rate of `1` or more would tax an order as much as it costs, or more.
+    fn in_range(self) -> Result<Self, ParseError> {
+        let range = Decimal::ZERO..Decimal::ONE;
+        let mut rates =
+            std::iter::once(self.rate).chain(self.components.iter().map(|part| part.rate));
+        match rates.find(|rate| !range.contains(rate)) {
+            Some(rate) => Err(ParseError::RateOutOfRange(rate)),
+            None => Ok(self),
+        }
+    }
+
+    /// The plain decimal body, without the components.
+    pub fn to_body(&self) -> String {
+        self.rate.to_string()
+    }
+
+    /// The body of a lookup that accepts `RATE_JSON`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a RateResponse always serializes")
+    }
+
+    /// Reads the fields of a rates CSV row after the zip code: the rate,
+    /// then optionally its state, county, city and special district parts.
+    /// Parts left empty are left out.
+    pub fn from_csv_fields<'a>(
+        mut fields: impl Iterator<Item = &'a str>,
+    ) -> Result<Self, ParseError> {
+        let mut response: Self = fields.next().unwrap_or_default().parse()?;
+        for (level, field) in JurisdictionLevel::ALL.into_iter().zip(fields) {
+            let field = field.trim();
+            if field.is_empty() {
+                continue;
+            }
+            let rate = field
+                .parse()
+                .map_err(|_| ParseError::InvalidRate(field.to_owned()))?;
+            response.components.push(RateComponent { level, rate });
+        }
+        response.in_range()
+    }
+}
+
+impl FromStr for RateResponse {
+    type Err = ParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        value
+            .parse::<Decimal>()
+            .map(Self::from)
+            .map_err(|_| ParseError::InvalidRate(value.to_owned()))
+    }
+}
+
+impl From<Decimal> for RateResponse {
+    fn from(rate: Decimal) -> Self {
+        Self {
+            rate,
+            components: Vec::new(),
+        }
+    }
+}
+
+impl From<RateResponse> for Decimal {
+    fn from(response: RateResponse) -> Self {
+        response.rate
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidZip => f.write_str("the zip code is empty or not UTF-8"),
+            Self::InvalidRate(rate) => write!(f, "invalid rate {rate:?}"),
+            Self::RateOutOfRange(rate) => write!(f, "rate {rate} is not in 0..1"),
+            Self::InvalidDate(date) => write!(f, "invalid date {date:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,78 @@
+//! Generates the gRPC server for `proto/order_total.proto`. The proto is
+//! parsed by protox rather than protoc, so the build needs no protobuf
+//! toolchain installed. Also captures what `GET /version` reports of the
+//! build, see `version`.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "wasmedge")]
+use tonic_build_0_11 as tonic_build;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    build_info();
+
+    const PROTO: &str = "proto/order_total.proto";
+    println!("cargo:rerun-if-changed={PROTO}");
+
+    let descriptors = protox::compile([PROTO], ["proto"])?;
+    let path = PathBuf::from(std::env::var("OUT_DIR")?).join("order_total_descriptor.bin");
+    std::fs::write(&path, prost_0_12::Message::encode_to_vec(&descriptors))?;
+
+    // tonic's transport (its own hyper server) does not build for WASI; the
+    // generated service is served by our hyper server instead, see `grpc`.
+    let builder = tonic_build::configure()
+        .build_transport(false)
+        .build_client(false)
+        .file_descriptor_set_path(&path)
+        .skip_protoc_run();
+    #[cfg(feature = "wasmedge")]
+    builder.compile(&[PROTO], &["proto"])?;
+    #[cfg(feature = "native")]
+    builder.compile_protos(&[PROTO], &["proto"])?;
+    Ok(())
+}
+
+/// The commit, time, target and profile of the build, as `ORDER_TOTAL_*`
+/// variables for `env!`. `GIT_SHA` stands in for the commit where there is
+/// no repository, as in a Docker build, and `SOURCE_DATE_EPOCH` for the
+/// time of a reproducible build.
+fn build_info() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    };
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        // A new commit or checkout moves HEAD or the ref it points to.
+        println!("cargo:rerun-if-changed={git_dir}/HEAD");
+        println!("cargo:rerun-if-changed={git_dir}/refs/heads");
+    }
+    let git_sha = std::env::var("GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(|| git(&["rev-parse", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_owned());
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        });
+    println!("cargo:rustc-env=ORDER_TOTAL_GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=ORDER_TOTAL_BUILT_AT={built_at}");
+    for (name, var) in [
+        ("TARGET", "ORDER_TOTAL_TARGET"),
+        ("PROFILE", "ORDER_TOTAL_PROFILE"),
+    ] {
+        let value = std::env::var(name).unwrap_or_default();
+        println!("cargo:rustc-env={var}={value}");
+    }
+}
@@ -0,0 +1,349 @@
+//! Between the records of the WIT world and `order_total_core`'s types.
+//! Decimals cross as strings, so they are read and written exactly; a field
+//! that does not parse is a `validation-failed` error naming it, as a value
+//! of the wrong type is over HTTP. The fields the computation fills in are
+//! ignored on input.
+
+use crate::types as wit;
+use chrono::NaiveDate;
+use models::JurisdictionLevel;
+use order_total_core::{
+    AddressParts, ComputeError, Conversion, Discount, DiscountKind, DiscountStage, ErrorCode,
+    ExemptionCertificate, FieldError, LineItem, Order, PriceMismatch, RateSource, RoundingMode,
+    RoundingOverride, RoundingScope, TaxCategory, TaxComponent, VatSummary,
+};
+use rust_decimal::Decimal;
+
+/// Collects the fields that do not parse, so that all are reported at once.
+#[derive(Default)]
+struct Reader {
+    errors: Vec<FieldError>,
+}
+
+impl Reader {
+    fn decimal(&mut self, field: impl Into<String>, value: &str) -> Decimal {
+        value.trim().parse().unwrap_or_else(|_| {
+            let message = "must be a decimal number, e.g. \"21.65\"";
+            self.errors.push(FieldError::new(field, message));
+            Decimal::ZERO
+        })
+    }
+
+    fn date(&mut self, field: &str, value: &str) -> Option<NaiveDate> {
+        let date = value.trim().parse().ok();
+        if date.is_none() {
+            let message = "must be a date, e.g. \"2025-08-08\"";
+            self.errors.push(FieldError::new(field, message));
+        }
+        date
+    }
+}
+
+pub fn order_in(order: wit::Order) -> Result<Order, ComputeError> {
+    let mut read = Reader::default();
+    let line_items = order
+        .line_items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| LineItem {
+            product_id: item.product_id,
+            quantity: item.quantity,
+            unit_price: read.decimal(format!("line_items[{index}].unit_price"), &item.unit_price),
+            tax_category: tax_category_in(item.tax_category),
+            subtotal: Decimal::ZERO,
+            discount: Decimal::ZERO,
+            tax_rate: None,
+            tax_holiday: None,
+            tax: Decimal::ZERO,
+            total: Decimal::ZERO,
+        })
+        .collect();
+    let discounts = order
+        .discounts
+        .into_iter()
+        .enumerate()
+        .map(|(index, discount)| Discount {
+            code: discount.code,
+            kind: match discount.kind {
+                wit::DiscountKind::Percentage => DiscountKind::Percentage,
+                wit::DiscountKind::Fixed => DiscountKind::Fixed,
+            },
+            value: read.decimal(format!("discounts[{index}].value"), &discount.value),
+            applies: match discount.applies {
+                wit::DiscountStage::PreTax => DiscountStage::PreTax,
+                wit::DiscountStage::PostTax => DiscountStage::PostTax,
+            },
+            amount: Decimal::ZERO,
+        })
+        .collect();
+    let order = Order {
+        order_id: order.order_id,
+        product_id: order.product_id,
+        quantity: order.quantity,
+        subtotal: read.decimal("subtotal", &order.subtotal),
+        shipping_address: order.shipping_address,
+        shipping_address_parts: None,
+        shipping_zip: order.shipping_zip,
+        shipping_country: order.shipping_country,
+        total: Decimal::ZERO,
+        currency: order.currency,
+        convert_to: order.convert_to,
+        converted: None,
+        order_date: order
+            .order_date
+            .and_then(|date| read.date("order_date", &date)),
+        tax_holiday: None,
+        tax_rate: None,
+        rate_source: None,
+        tax_amount: Decimal::ZERO,
+        jurisdiction: None,
+        rounding: order.rounding.map(|rounding| RoundingOverride {
+            mode: rounding.mode.map(|mode| match mode {
+                wit::RoundingMode::HalfUp => RoundingMode::HalfUp,
+                wit::RoundingMode::HalfEven => RoundingMode::HalfEven,
+                wit::RoundingMode::Up => RoundingMode::Up,
+                wit::RoundingMode::Down => RoundingMode::Down,
+            }),
+            scope: rounding.scope.map(|scope| match scope {
+                wit::RoundingScope::PerLine => RoundingScope::PerLine,
+                wit::RoundingScope::PerOrder => RoundingScope::PerOrder,
+            }),
+        }),
+        line_items,
+        price_mismatches: Vec::new(),
+        discounts,
+        discount_amount: Decimal::ZERO,
+        tax_components: Vec::new(),
+        vat_id: order.vat_id,
+        vat: None,
+        exemption_certificate: order.exemption_certificate.map(|certificate| {
+            ExemptionCertificate {
+                id: certificate.id,
+                jurisdiction: certificate.jurisdiction,
+                holder: certificate.holder,
+            }
+        }),
+        dry_run: order.dry_run,
+    };
+    match read.errors.is_empty() {
+        true => Ok(order),
+        false => Err(ComputeError::Validation(read.errors)),
+    }
+}
+
+pub fn order_out(order: Order) -> wit::Order {
+    wit::Order {
+        order_id: order.order_id,
+        product_id: order.product_id,
+        quantity: order.quantity,
+        subtotal: order.subtotal.to_string(),
+        shipping_address: order.shipping_address,
+        shipping_address_parts: order.shipping_address_parts.map(address_parts_out),
+        shipping_zip: order.shipping_zip,
+        shipping_country: order.shipping_country,
+        total: order.total.to_string(),
+        currency: order.currency,
+        convert_to: order.convert_to,
+        converted: order.converted.map(conversion_out),
+        order_date: order.order_date.map(|date| date.to_string()),
+        tax_holiday: order.tax_holiday,
+        tax_rate: order.tax_rate.map(|rate| rate.to_string()),
+        rate_source: order.rate_source.map(|source| match source {
+            RateSource::Lookup => wit::RateSource::Lookup,
+            RateSource::Default => wit::RateSource::Default,
+            RateSource::Exempt => wit::RateSource::Exempt,
+            RateSource::ReverseCharge => wit::RateSource::ReverseCharge,
+        }),
+        tax_amount: order.tax_amount.to_string(),
+        jurisdiction: order.jurisdiction,
+        rounding: order.rounding.map(|rounding| wit::RoundingOverride {
+            mode: rounding.mode.map(|mode| match mode {
+                RoundingMode::HalfUp => wit::RoundingMode::HalfUp,
+                RoundingMode::HalfEven => wit::RoundingMode::HalfEven,
+                RoundingMode::Up => wit::RoundingMode::Up,
+                RoundingMode::Down => wit::RoundingMode::Down,
+            }),
+            scope: rounding.scope.map(|scope| match scope {
+                RoundingScope::PerLine => wit::RoundingScope::PerLine,
+                RoundingScope::PerOrder => wit::RoundingScope::PerOrder,
+            }),
+        }),
+        line_items: order.line_items.into_iter().map(line_item_out).collect(),
+        price_mismatches: order
+            .price_mismatches
+            .into_iter()
+            .map(price_mismatch_out)
+            .collect(),
+        discounts: order.discounts.into_iter().map(discount_out).collect(),
+        discount_amount: order.discount_amount.to_string(),
+        tax_components: order
+            .tax_components
+            .into_iter()
+            .map(tax_component_out)
+            .collect(),
+        vat_id: order.vat_id,
+        vat: order.vat.map(vat_out),
+        exemption_certificate: order.exemption_certificate.map(|certificate| {
+            wit::ExemptionCertificate {
+                id: certificate.id,
+                jurisdiction: certificate.jurisdiction,
+                holder: certificate.holder,
+            }
+        }),
+        dry_run: order.dry_run,
+    }
+}
+
+fn tax_category_in(category: wit::TaxCategory) -> TaxCategory {
+    match category {
+        wit::TaxCategory::Standard => TaxCategory::Standard,
+        wit::TaxCategory::Grocery => TaxCategory::Grocery,
+        wit::TaxCategory::Clothing => TaxCategory::Clothing,
+        wit::TaxCategory::Exempt => TaxCategory::Exempt,
+    }
+}
+
+fn tax_category_out(category: TaxCategory) -> wit::TaxCategory {
+    match category {
+        TaxCategory::Standard => wit::TaxCategory::Standard,
+        TaxCategory::Grocery => wit::TaxCategory::Grocery,
+        TaxCategory::Clothing => wit::TaxCategory::Clothing,
+        TaxCategory::Exempt => wit::TaxCategory::Exempt,
+    }
+}
+
+fn line_item_out(item: LineItem) -> wit::LineItem {
+    wit::LineItem {
+        product_id: item.product_id,
+        quantity: item.quantity,
+        unit_price: item.unit_price.to_string(),
+        tax_category: tax_category_out(item.tax_category),
+        subtotal: item.subtotal.to_string(),
+        discount: item.discount.to_string(),
+        tax_rate: item.tax_rate.map(|rate| rate.to_string()),
+        tax_holiday: item.tax_holiday,
+        tax: item.tax.to_string(),
+        total: item.total.to_string(),
+    }
+}
+
+fn address_parts_out(parts: AddressParts) -> wit::AddressParts {
+    wit::AddressParts {
+        street: parts.street,
+        unit: parts.unit,
+        city: parts.city,
+        region: parts.region,
+        postal_code: parts.postal_code,
+    }
+}
+
+fn conversion_out(conversion: Conversion) -> wit::Conversion {
+    wit::Conversion {
+        currency: conversion.currency,
+        fx_rate: conversion.fx_rate.to_string(),
+        subtotal: conversion.subtotal.to_string(),
+        tax_amount: conversion.tax_amount.to_string(),
+        total: conversion.total.to_string(),
+    }
+}
+
+fn price_mismatch_out(mismatch: PriceMismatch) -> wit::PriceMismatch {
+    wit::PriceMismatch {
+        field: mismatch.field,
+        product_id: mismatch.product_id,
+        submitted: mismatch.submitted.to_string(),
+        catalog: mismatch.catalog.to_string(),
+    }
+}
+
+fn discount_out(discount: Discount) -> wit::Discount {
+    wit::Discount {
+        code: discount.code,
+        kind: match discount.kind {
+            DiscountKind::Percentage => wit::DiscountKind::Percentage,
+            DiscountKind::Fixed => wit::DiscountKind::Fixed,
+        },
+        value: discount.value.to_string(),
+        applies: match discount.applies {
+            DiscountStage::PreTax => wit::DiscountStage::PreTax,
+            DiscountStage::PostTax => wit::DiscountStage::PostTax,
+        },
+        amount: discount.amount.to_string(),
+    }
+}
+
+fn tax_component_out(component: TaxComponent) -> wit::TaxComponent {
+    let level = match component.level {
+        JurisdictionLevel::State => "state",
+        JurisdictionLevel::County => "county",
+        JurisdictionLevel::City => "city",
+        JurisdictionLevel::Special => "special",
+        JurisdictionLevel::Federal => "federal",
+        JurisdictionLevel::Province => "province",
+    };
+    wit::TaxComponent {
+        level: level.to_owned(),
+        name: component.name,
+        rate: component.rate.to_string(),
+        amount: component.amount.to_string(),
+    }
+}
+
+fn vat_out(vat: VatSummary) -> wit::VatSummary {
+    wit::VatSummary {
+        country: vat.country,
+        vat_id: vat.vat_id,
+        reverse_charge: vat.reverse_charge,
+        net_amount: vat.net_amount.to_string(),
+        vat_amount: vat.vat_amount.to_string(),
+        gross_amount: vat.gross_amount.to_string(),
+    }
+}
+
+/// The error body of `err`, as the HTTP API would answer it.
+pub fn error_out(err: ComputeError) -> wit::ComputeError {
+    let (_, response) = err.into_parts();
+    wit::ComputeError {
+        code: error_code_out(response.code()),
+        message: response.message().to_owned(),
+        errors: response
+            .errors()
+            .iter()
+            .map(|error| wit::FieldError {
+                field: error.field.clone(),
+                message: error.message.clone(),
+            })
+            .collect(),
+    }
+}
+
+fn error_code_out(code: ErrorCode) -> wit::ErrorCode {
+    match code {
+        ErrorCode::InvalidRequest => wit::ErrorCode::InvalidRequest,
+        ErrorCode::ValidationFailed => wit::ErrorCode::ValidationFailed,
+        ErrorCode::PayloadTooLarge => wit::ErrorCode::PayloadTooLarge,
+        ErrorCode::UnsupportedEncoding => wit::ErrorCode::UnsupportedEncoding,
+        ErrorCode::TaxRateUnavailable => wit::ErrorCode::TaxRateUnavailable,
+        ErrorCode::FxRateUnavailable => wit::ErrorCode::FxRateUnavailable,
+        ErrorCode::UpstreamUnavailable => wit::ErrorCode::UpstreamUnavailable,
+        ErrorCode::UpstreamTimeout => wit::ErrorCode::UpstreamTimeout,
+        ErrorCode::DeadlineExceeded => wit::ErrorCode::DeadlineExceeded,
+        ErrorCode::CircuitOpen => wit::ErrorCode::CircuitOpen,
+        ErrorCode::OrderNotFound => wit::ErrorCode::OrderNotFound,
+        ErrorCode::PersistenceDisabled => wit::ErrorCode::PersistenceDisabled,
+        ErrorCode::JobNotFound => wit::ErrorCode::JobNotFound,
+        ErrorCode::JobNotFinished => wit::ErrorCode::JobNotFinished,
+        ErrorCode::QuoteInvalid => wit::ErrorCode::QuoteInvalid,
+        ErrorCode::QuoteExpired => wit::ErrorCode::QuoteExpired,
+        ErrorCode::QuoteAlreadyFinalized => wit::ErrorCode::QuoteAlreadyFinalized,
+        ErrorCode::ExemptionRejected => wit::ErrorCode::ExemptionRejected,
+        ErrorCode::IdempotencyKeyInFlight => wit::ErrorCode::IdempotencyKeyInFlight,
+        ErrorCode::IdempotencyKeyReused => wit::ErrorCode::IdempotencyKeyReused,
+        ErrorCode::Unauthorized => wit::ErrorCode::Unauthorized,
+        ErrorCode::InvalidApiKey => wit::ErrorCode::InvalidApiKey,
+        ErrorCode::UnknownTenant => wit::ErrorCode::UnknownTenant,
+        ErrorCode::RateLimited => wit::ErrorCode::RateLimited,
+        ErrorCode::Overloaded => wit::ErrorCode::Overloaded,
+        ErrorCode::InternalError => wit::ErrorCode::InternalError,
+    }
+}
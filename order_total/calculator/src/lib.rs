@@ -0,0 +1,92 @@
+//! The `calculator` world of `order_total/core/wit/order-total.wit` as a
+//! WebAssembly component: `compute-total` runs `order_total_core`'s
+//! `Calculator` on the order, looking its rate up through the host's
+//! `find-rate`.
+//!
+//! ```bash
+//! cargo build -p order-total-calculator --target wasm32-wasip2 --release
+//! ```
+//!
+//! The calculator is configured from the component's environment like the
+//! service is (`ROUNDING_MODE`, `TAX_CATEGORY_TABLE`, `FX_RATE_TABLE` and
+//! so on), except for what needs the network: there is no product catalog,
+//! exemption certificates are accepted when well formed, and a zip code the
+//! host knows no rate for fails with `tax-rate-unavailable`.
+
+mod convert;
+
+use async_trait::async_trait;
+use models::RateRequest;
+use order_total_core::{ComputeError, TaxRate, TaxRateProvider};
+use std::sync::Arc;
+
+wit_bindgen::generate!({
+    world: "calculator",
+    path: "../core/wit",
+});
+
+pub use pazustep::order_total::types;
+
+use exports::pazustep::order_total::compute::Guest;
+
+struct Component;
+
+impl Guest for Component {
+    fn compute_total(order: types::Order) -> Result<types::ComputedOrder, types::ComputeError> {
+        compute_total(order, pazustep::order_total::tax_rates::find_rate)
+    }
+}
+
+// The export names are not valid symbols for a native linker, and only a
+// component has a host to call them.
+#[cfg(target_arch = "wasm32")]
+export!(Component);
+
+/// Computes `order` as the exported `compute-total` does, with `find_rate`
+/// standing in for the host's import.
+pub fn compute_total<F>(
+    order: types::Order,
+    find_rate: F,
+) -> Result<types::ComputedOrder, types::ComputeError>
+where
+    F: Fn(&str) -> Result<Option<String>, String> + Send + Sync + 'static,
+{
+    let order = convert::order_in(order).map_err(convert::error_out)?;
+    let calculator = calculator(find_rate)
+        .map_err(|err| convert::error_out(ComputeError::Unexpected(err.into())))?;
+    futures_executor::block_on(calculator.compute(order))
+        .map(|(order, _rate)| convert::order_out(order))
+        .map_err(convert::error_out)
+}
+
+fn calculator<F>(find_rate: F) -> anyhow::Result<order_total_core::Calculator>
+where
+    F: Fn(&str) -> Result<Option<String>, String> + Send + Sync + 'static,
+{
+    order_total_core::Calculator::for_component(Arc::new(HostRates(find_rate)))
+}
+
+/// Looks rates up through the host. It answers for the zip code alone, so
+/// an `order-date` does not change the rate, only the tax holidays.
+struct HostRates<F>(F);
+
+#[async_trait]
+impl<F> TaxRateProvider for HostRates<F>
+where
+    F: Fn(&str) -> Result<Option<String>, String> + Send + Sync,
+{
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        let rate = match (self.0)(&request.zip) {
+            Ok(Some(rate)) => rate,
+            Ok(None) => return Err(ComputeError::TaxRateNotAvailable),
+            Err(err) => {
+                tracing::warn!(error = %err, "the host could not look the rate up");
+                return Err(ComputeError::UpstreamUnavailable);
+            }
+        };
+        TaxRate::from_body(&rate).map_err(|err| {
+            tracing::warn!(rate, error = %err, "the host answered an invalid rate");
+            ComputeError::UpstreamUnavailable
+        })
+    }
+}
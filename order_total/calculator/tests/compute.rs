@@ -0,0 +1,78 @@
+//! `compute-total` with a closure standing in for the host's `find-rate`.
+
+use order_total_calculator::compute_total;
+use order_total_calculator::types::{ErrorCode, Order, RateSource};
+
+const ZIP: &str = "78701";
+
+fn order(zip: &str, subtotal: &str) -> Order {
+    Order {
+        order_id: 123,
+        product_id: 321,
+        quantity: 2,
+        subtotal: subtotal.to_owned(),
+        shipping_address: "123 Main St, Anytown USA".to_owned(),
+        shipping_address_parts: None,
+        shipping_zip: zip.to_owned(),
+        shipping_country: None,
+        total: "0".to_owned(),
+        currency: None,
+        convert_to: None,
+        converted: None,
+        order_date: None,
+        tax_holiday: None,
+        tax_rate: None,
+        rate_source: None,
+        tax_amount: "0".to_owned(),
+        jurisdiction: None,
+        rounding: None,
+        line_items: Vec::new(),
+        price_mismatches: Vec::new(),
+        discounts: Vec::new(),
+        discount_amount: "0".to_owned(),
+        tax_components: Vec::new(),
+        vat_id: None,
+        vat: None,
+        exemption_certificate: None,
+        dry_run: false,
+    }
+}
+
+fn rates(zip: &str) -> Result<Option<String>, String> {
+    Ok((zip == ZIP).then(|| "0.0825".to_owned()))
+}
+
+#[test]
+fn the_order_is_computed_with_the_hosts_rate() {
+    let computed = compute_total(order(ZIP, "20.00"), rates).unwrap();
+
+    assert_eq!(computed.tax_rate.as_deref(), Some("0.0825"));
+    assert_eq!(computed.tax_amount, "1.65");
+    assert_eq!(computed.total, "21.65");
+    assert!(matches!(computed.rate_source, Some(RateSource::Lookup)));
+}
+
+#[test]
+fn a_zip_the_host_knows_no_rate_for_is_unavailable() {
+    let error = compute_total(order("00000", "20.00"), rates).unwrap_err();
+
+    assert!(matches!(error.code, ErrorCode::TaxRateUnavailable));
+}
+
+#[test]
+fn a_failing_host_is_an_unavailable_upstream() {
+    let failing = |_: &str| Err("no route to the rate service".to_owned());
+
+    let error = compute_total(order(ZIP, "20.00"), failing).unwrap_err();
+
+    assert!(matches!(error.code, ErrorCode::UpstreamUnavailable));
+}
+
+#[test]
+fn decimals_that_do_not_parse_are_validation_errors() {
+    let error = compute_total(order(ZIP, "twenty"), rates).unwrap_err();
+
+    assert!(matches!(error.code, ErrorCode::ValidationFailed));
+    assert_eq!(error.errors.len(), 1);
+    assert_eq!(error.errors[0].field, "subtotal");
+}
@@ -0,0 +1,106 @@
+use order_total_core::{ErrorCode, FieldError};
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+/// Why `Client::compute` failed.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The service refused the order (a `4xx`); retrying will not help.
+    /// Validation failures carry the offending fields in `errors`.
+    Rejected(ApiError),
+    /// The service kept failing (a `5xx`, or `429`/`409` that did not clear)
+    /// until the attempts ran out.
+    Unavailable(ApiError),
+    /// No attempt got an answer within the configured timeout.
+    Timeout,
+    /// The service could not be reached.
+    Transport(reqwest::Error),
+    /// The service answered `200` with a body that is not an order.
+    Decode(serde_json::Error),
+}
+
+/// The error body the service answers with, plus its status code.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: u16,
+    /// What went wrong, to branch on; `None` when the body was not the
+    /// service's (e.g. a proxy's error page).
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    /// Quote this when reporting a problem to the service's operators.
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub errors: Vec<FieldError>,
+    /// The `Retry-After` the service asked for, if any.
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
+}
+
+impl ClientError {
+    /// The `ApiError` of a `Rejected` or `Unavailable` error.
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Self::Rejected(err) | Self::Unavailable(err) => Some(err),
+            Self::Timeout | Self::Transport(_) | Self::Decode(_) => None,
+        }
+    }
+
+    /// Whether another attempt may succeed.
+    pub(crate) fn is_transient(&self) -> bool {
+        !matches!(self, Self::Rejected(_) | Self::Decode(_))
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rejected(err) => write!(f, "order rejected: {err}"),
+            Self::Unavailable(err) => write!(f, "order_total unavailable: {err}"),
+            Self::Timeout => f.write_str("order_total did not respond in time"),
+            Self::Transport(err) => write!(f, "cannot reach order_total: {err}"),
+            Self::Decode(err) => write!(f, "invalid response from order_total: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(err) => Some(err),
+            Self::Decode(err) => Some(err),
+            Self::Rejected(_) | Self::Unavailable(_) | Self::Timeout => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {} {}", self.status, self.message)?;
+        if !self.errors.is_empty() {
+            let fields: Vec<String> = self
+                .errors
+                .iter()
+                .map(|error| format!("{}: {}", error.field, error.message))
+                .collect();
+            write!(f, " ({})", fields.join("; "))?;
+        }
+        if let Some(id) = &self.request_id {
+            write!(f, " [request {id}]")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(value: reqwest::Error) -> Self {
+        if value.is_timeout() {
+            Self::Timeout
+        } else {
+            Self::Transport(value)
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! A client for the `order_total` API.
+//!
+//! ```ignore
+//! let client = order_total_client::Client::builder("http://localhost:8002")
+//!     .api_key("partner-key")
+//!     .build()?;
+//! let computed = client.compute(&order).await?;
+//! println!("{}", computed.total);
+//! ```
+//!
+//! `compute` retries connection failures, timeouts, `5xx`, `429` and `409`
+//! answers with jittered exponential backoff (honoring `Retry-After`), and
+//! sends the same `Idempotency-Key` with every attempt, so a retry never
+//! computes (or stores) an order twice.
+
+mod error;
+
+pub use error::{ApiError, ClientError};
+pub use order_total_core::{ErrorCode, FieldError, LineItem, Order, RateSource, RoundingOverride};
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// An order as the service returns it, with `total`, `tax_amount`,
+/// `tax_rate` and the other computed fields filled in.
+pub type ComputedOrder = Order;
+
+/// A handle on one `order_total` deployment. Cloning it is cheap and shares
+/// the connection pool.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    compute_url: String,
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+/// Configures a `Client`; see `Client::builder`.
+#[derive(Debug)]
+pub struct ClientBuilder {
+    base_url: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    headers: HeaderMap,
+}
+
+impl Client {
+    /// Starts configuring a client for the service at `base_url`, e.g.
+    /// `http://localhost:8002`. Defaults: a 10s timeout per attempt (2s to
+    /// connect), 3 attempts, backoff from 100ms up to 2s.
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(2),
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Computes the total of `order` with `POST /v1/compute`.
+    pub async fn compute(&self, order: &Order) -> Result<ComputedOrder, ClientError> {
+        let body = serde_json::to_vec(order).expect("an Order always serializes");
+        let key = uuid::Uuid::new_v4().to_string();
+        let mut attempt = 1;
+        loop {
+            match self.attempt(&body, &key).await {
+                Err(err) if attempt < self.max_attempts && err.is_transient() => {
+                    let requested = err.api_error().and_then(|err| err.retry_after);
+                    tokio::time::sleep(requested.unwrap_or_else(|| self.delay(attempt))).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn attempt(&self, body: &[u8], key: &str) -> Result<ComputedOrder, ClientError> {
+        let response = self
+            .http
+            .post(&self.compute_url)
+            .header("Content-Type", "application/json")
+            .header("Idempotency-Key", key)
+            .body(body.to_vec())
+            .send()
+            .await?;
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        let bytes = response.bytes().await?;
+        if status.is_success() {
+            return serde_json::from_slice(&bytes).map_err(ClientError::Decode);
+        }
+
+        let mut error = serde_json::from_slice(&bytes).unwrap_or_else(|_| ApiError {
+            status: 0,
+            code: None,
+            message: String::from_utf8_lossy(&bytes).into_owned(),
+            request_id: None,
+            errors: Vec::new(),
+            retry_after: None,
+        });
+        error.status = status.as_u16();
+        error.retry_after = retry_after;
+        let retryable = status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::CONFLICT;
+        Err(if retryable {
+            ClientError::Unavailable(error)
+        } else {
+            ClientError::Rejected(error)
+        })
+    }
+
+    /// "Full jitter" backoff after the given (1-based) failed attempt, as the
+    /// service itself uses towards the sales tax rate service.
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let ceiling = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        if millis == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+impl ClientBuilder {
+    /// Bounds each attempt, connecting included.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Attempts per `compute` call, the first one included (at least 1).
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// The backoff between attempts: grows from `base` and never exceeds `max`.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.base_delay = base;
+        self.max_delay = max;
+        self
+    }
+
+    /// Authenticates with a partner key (`X-Api-Key`).
+    pub fn api_key(self, key: &str) -> Self {
+        self.header("X-Api-Key", key)
+    }
+
+    /// Authenticates with a JWT (`Authorization: Bearer`).
+    pub fn bearer_token(self, token: &str) -> Self {
+        self.header(AUTHORIZATION.as_str(), &format!("Bearer {token}"))
+    }
+
+    fn header(mut self, name: &'static str, value: &str) -> Self {
+        if let Ok(mut value) = HeaderValue::from_str(value) {
+            value.set_sensitive(true);
+            self.headers.insert(name, value);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Client, ClientError> {
+        let http = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .default_headers(self.headers)
+            .build()
+            .map_err(ClientError::Transport)?;
+        Ok(Client {
+            http,
+            compute_url: format!("{}/v1/compute", self.base_url.trim_end_matches('/')),
+            max_attempts: self.max_attempts,
+            base_delay: self.base_delay,
+            max_delay: self.max_delay,
+        })
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Street suffixes and directions as commonly abbreviated (after USPS
+/// Publication 28), and the words they stand for.
+const STREET_WORDS: &[(&str, &str)] = &[
+    ("ALY", "ALLEY"),
+    ("AV", "AVENUE"),
+    ("AVE", "AVENUE"),
+    ("BLVD", "BOULEVARD"),
+    ("CIR", "CIRCLE"),
+    ("CT", "COURT"),
+    ("CTR", "CENTER"),
+    ("DR", "DRIVE"),
+    ("EXPY", "EXPRESSWAY"),
+    ("FWY", "FREEWAY"),
+    ("HWY", "HIGHWAY"),
+    ("LN", "LANE"),
+    ("PKWY", "PARKWAY"),
+    ("PL", "PLACE"),
+    ("PLZ", "PLAZA"),
+    ("RD", "ROAD"),
+    ("SQ", "SQUARE"),
+    ("ST", "STREET"),
+    ("TER", "TERRACE"),
+    ("TRL", "TRAIL"),
+    ("N", "NORTH"),
+    ("S", "SOUTH"),
+    ("E", "EAST"),
+    ("W", "WEST"),
+    ("NE", "NORTHEAST"),
+    ("NW", "NORTHWEST"),
+    ("SE", "SOUTHEAST"),
+    ("SW", "SOUTHWEST"),
+];
+
+/// The words that start a unit within a building, abbreviated or not; what
+/// follows one is the unit's number and is not expanded.
+const UNIT_WORDS: &[(&str, &str)] = &[
+    ("APT", "APARTMENT"),
+    ("BLDG", "BUILDING"),
+    ("DEPT", "DEPARTMENT"),
+    ("FL", "FLOOR"),
+    ("RM", "ROOM"),
+    ("STE", "SUITE"),
+    ("UNIT", "UNIT"),
+];
+
+/// Country names ending an address, which the parts leave out.
+const COUNTRIES: &[&str] = &["US", "USA", "UNITED STATES", "CANADA"];
+
+/// The parts of a shipping address, as far as they can be told apart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct AddressParts {
+    /// The house number and street, e.g. `123 MAIN STREET`.
+    pub street: String,
+    /// e.g. `SUITE 200`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    pub city: String,
+    /// The state or province, e.g. `TX`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<String>,
+}
+
+/// `address` without its formatting noise, so two spellings of one address
+/// compare equal: in upper case, with single spaces, `, ` between its
+/// lines, no periods after abbreviations, and the abbreviated street
+/// suffixes, directions and unit words of the street line spelled out, e.g.
+/// `123 N. Main St ,Ste 200` becomes `123 NORTH MAIN STREET, SUITE 200`.
+pub fn normalize(address: &str) -> String {
+    let lines: Vec<String> = address
+        .split([',', '\n'])
+        .map(|line| {
+            line.split_whitespace()
+                .map(|word| word.trim_end_matches('.').to_uppercase())
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|words| !words.is_empty())
+        .enumerate()
+        .map(|(index, words)| {
+            if index == 0 {
+                expand(&words)
+            } else {
+                expand_unit(&words).unwrap_or(words)
+            }
+            .join(" ")
+        })
+        .collect();
+    lines.join(", ")
+}
+
+/// Spells out the words of a street line; from a unit word on, only the
+/// unit word itself.
+fn expand(words: &[String]) -> Vec<String> {
+    let unit = words.iter().position(|word| unit_word(word).is_some());
+    let (street, unit) = words.split_at(unit.unwrap_or(words.len()));
+    street
+        .iter()
+        .map(|word| {
+            STREET_WORDS
+                .iter()
+                .find(|(short, _)| short == word)
+                .map_or_else(|| word.clone(), |(_, long)| (*long).to_owned())
+        })
+        .chain(expand_unit(unit).unwrap_or_default())
+        .collect()
+}
+
+/// A line starting with a unit word, with that word spelled out.
+fn expand_unit(words: &[String]) -> Option<Vec<String>> {
+    let long = unit_word(words.first()?)?;
+    Some(
+        std::iter::once(long.to_owned())
+            .chain(words[1..].iter().cloned())
+            .collect(),
+    )
+}
+
+fn unit_word(word: &str) -> Option<&'static str> {
+    UNIT_WORDS
+        .iter()
+        .find(|(short, long)| word == *short || word == *long)
+        .map(|(_, long)| *long)
+}
+
+/// Tells the parts of a `normalize`d address apart: the street line first,
+/// a unit on it or on a line of its own, then the city, and at the end an
+/// optional state or province and postal code (and country), e.g.
+/// `123 MAIN STREET, SUITE 200, AUSTIN, TX 78701`. `None` for an address
+/// without both a street and a city.
+pub fn parse(normalized: &str) -> Option<AddressParts> {
+    let mut lines: Vec<&str> = normalized.split(", ").collect();
+    if let Some(last) = lines.last_mut() {
+        *last = strip_country(last);
+    }
+    lines.retain(|line| !line.is_empty());
+    if lines.len() < 2 {
+        return None;
+    }
+
+    let street_line = lines.remove(0);
+    let (street, mut unit) = match street_line
+        .split(' ')
+        .position(|word| unit_word(word).is_some())
+    {
+        Some(at) if at > 0 => {
+            let words: Vec<&str> = street_line.split(' ').collect();
+            (words[..at].join(" "), Some(words[at..].join(" ")))
+        }
+        _ => (street_line.to_owned(), None),
+    };
+    if lines.len() > 1 && unit_word(lines[0].split(' ').next()?).is_some() {
+        unit = Some(lines.remove(0).to_owned());
+    }
+
+    let mut words: Vec<&str> = lines.pop()?.split(' ').collect();
+    let mut postal = Vec::new();
+    while postal.len() < 2
+        && words
+            .last()
+            .is_some_and(|word| word.contains(char::is_numeric))
+    {
+        postal.insert(0, words.pop()?);
+    }
+    let region = match words.last() {
+        Some(word) if word.len() == 2 && word.chars().all(|c| c.is_ascii_alphabetic()) => {
+            words.pop().map(str::to_owned)
+        }
+        _ => None,
+    };
+    let city = if words.is_empty() {
+        lines.pop()?.to_owned()
+    } else {
+        words.join(" ")
+    };
+    Some(AddressParts {
+        street,
+        unit,
+        city,
+        region,
+        postal_code: (!postal.is_empty()).then(|| postal.join(" ")),
+    })
+}
+
+/// `line` without a country name at its end.
+fn strip_country(line: &str) -> &str {
+    COUNTRIES
+        .iter()
+        .find_map(|country| {
+            let rest = line.strip_suffix(country)?;
+            (rest.is_empty() || rest.ends_with(' ')).then(|| rest.trim_end())
+        })
+        .unwrap_or(line)
+}
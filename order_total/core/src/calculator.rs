@@ -0,0 +1,266 @@
+use crate::address;
+use crate::canada::CanadianTaxes;
+use crate::catalog::{self, ProductCatalog};
+use crate::category::CategoryAdjustments;
+use crate::currency::{self, Conversion, FxRateProvider, FxRateTable};
+use crate::error::ComputeError;
+use crate::exemption::{AcceptWellFormed, ExemptionVerifier};
+use crate::holiday::HolidayCalendar;
+use crate::json_error::JsonError;
+use crate::jurisdiction;
+use crate::order::{Order, RateSource, TaxComponent, CANADA};
+use crate::postal;
+use crate::rounding::RoundingStrategy;
+use crate::tax_rate::{TaxRate, TaxRateProvider};
+use crate::vat::{self, VatRates, VatSummary};
+use chrono::Utc;
+use models::RateRequest;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+/// Computes order totals from the rates a `TaxRateProvider` reports.
+pub struct Calculator {
+    pub tax_rates: Arc<dyn TaxRateProvider>,
+    /// Used for orders that do not bring their own `rounding`.
+    pub rounding: RoundingStrategy,
+    /// Applied when the provider knows no rate for a zip code; without one
+    /// such orders fail with `TaxRateNotAvailable`.
+    pub default_tax_rate: Option<Decimal>,
+    /// Adjusts the rate for line items of a reduced or exempt
+    /// `tax_category`.
+    pub categories: CategoryAdjustments,
+    /// Decides whether an order's `exemption_certificate` makes it tax
+    /// free.
+    pub exemptions: Arc<dyn ExemptionVerifier>,
+    /// The taxes of orders shipped to Canada, which are not looked up.
+    pub canada: CanadianTaxes,
+    /// The VAT of orders shipped to the EU, which is not looked up either.
+    pub vat: VatRates,
+    /// Exempts the line items of orders placed during a tax holiday.
+    pub holidays: HolidayCalendar,
+    /// Converts the totals of orders that ask for them in another currency.
+    pub fx_rates: Arc<dyn FxRateProvider>,
+    /// Prices orders by their `product_id`s; without one, the prices they
+    /// are sent with are trusted.
+    pub catalog: Option<Arc<dyn ProductCatalog>>,
+}
+
+impl Calculator {
+    /// The calculator of a WebAssembly component, looking rates up with
+    /// `tax_rates`. It is configured from the environment like the service
+    /// is (`ROUNDING_MODE`, `TAX_CATEGORY_TABLE`, `FX_RATE_TABLE` and so
+    /// on), except for what needs the network: there is no product catalog
+    /// or default rate, and exemption certificates are accepted when well
+    /// formed.
+    pub fn for_component(tax_rates: Arc<dyn TaxRateProvider>) -> anyhow::Result<Self> {
+        Ok(Self {
+            tax_rates,
+            rounding: RoundingStrategy::from_env()?,
+            default_tax_rate: None,
+            categories: CategoryAdjustments::load()?,
+            exemptions: Arc::new(AcceptWellFormed),
+            canada: CanadianTaxes::load()?,
+            vat: VatRates::load()?,
+            holidays: HolidayCalendar::load()?,
+            fx_rates: Arc::new(FxRateTable::load()?),
+            catalog: None,
+        })
+    }
+
+    /// Computes the order of a request `body`, returning the status and
+    /// JSON body of the response: the computed order, or the error body the
+    /// service would answer with.
+    pub async fn compute_json(&self, body: &[u8]) -> (u16, String) {
+        let order: Order = match serde_json::from_slice(body) {
+            Ok(order) => order,
+            Err(err) => return ComputeError::MalformedJson(JsonError::new(&err, body)).into_json(),
+        };
+        match self.compute(order).await {
+            Ok((order, _rate)) => (
+                200,
+                serde_json::to_string(&order).expect("an Order always serializes"),
+            ),
+            Err(err) => err.into_json(),
+        }
+    }
+
+    /// Validates `order` and computes its totals using the rate for its
+    /// shipping zip code, or the default rate when none is known, returning
+    /// the order along with that rate. The order also gets the jurisdiction
+    /// the rate applies in, and each line item is taxed at that rate as
+    /// adjusted for its category there. Where the provider breaks the rate
+    /// down by jurisdiction level, the tax is itemized the same way. Its own
+    /// `rounding`, if any, takes precedence over the configured one.
+    ///
+    /// An order shipped to Canada is taxed instead at the combined rate of
+    /// its province's taxes in `canada`, itemized by tax. One shipped to
+    /// the EU is charged the member state's VAT, at the reduced rate for
+    /// groceries, unless the buyer's `vat_id` makes it a reverse-charged B2B
+    /// sale; either way its `vat` sums the VAT up. One with an
+    /// `exemption_certificate` the verifier accepts is computed at a zero
+    /// rate, without looking one up. Line items that a tax holiday held on
+    /// the `order_date` (or today) exempts are not taxed wherever shipped,
+    /// and the rate looked up is the one in effect on that day.
+    ///
+    /// The `shipping_address` is normalized and broken into its parts where
+    /// it can be. With a `catalog`, the order is priced by it, and the
+    /// prices it was sent with that differ are flagged in `price_mismatches`.
+    /// The `shipping_zip` is written in its country's canonical form, and a
+    /// ZIP+4 code is looked up by its first five digits. Money is rounded to
+    /// the minor unit of the order's `currency`. With
+    /// `convert_to`, the totals are also given in that currency, at the rate
+    /// `fx_rates` reports.
+    pub async fn compute(&self, mut order: Order) -> Result<(Order, Decimal), ComputeError> {
+        order.validate().map_err(ComputeError::Validation)?;
+        if let Some(canonical) = postal::normalize(&order.country(), &order.shipping_zip) {
+            order.shipping_zip = canonical;
+        }
+        order.shipping_address = address::normalize(&order.shipping_address);
+        order.shipping_address_parts = address::parse(&order.shipping_address);
+        if let Some(catalog) = &self.catalog {
+            catalog::reprice(catalog.as_ref(), &mut order).await?;
+        }
+        let rounding = self
+            .rounding
+            .with_override(order.rounding)
+            .in_currency(&order.currency());
+        let (mut order, rate) = self.tax(order, rounding).await?;
+        // Whatever the order was sent with is replaced, as the other
+        // computed fields are.
+        order.converted = match order.convert_to.as_deref().map(currency::normalize) {
+            Some(to) => Some(self.convert(&order, to, rounding).await?),
+            None => None,
+        };
+        Ok((order, rate))
+    }
+
+    /// Taxes a validated `order` as `compute` describes, returning the rate.
+    async fn tax(
+        &self,
+        mut order: Order,
+        rounding: RoundingStrategy,
+    ) -> Result<(Order, Decimal), ComputeError> {
+        let country = order.country();
+        let state = jurisdiction::for_address(&country, &order.shipping_zip);
+        let date = order.order_date.unwrap_or_else(|| Utc::now().date_naive());
+        self.holidays.mark(&mut order, date, state);
+        // Filled in again by `charge_vat`, for an order shipped to the EU.
+        order.vat = None;
+
+        if let Some(certificate) = &order.exemption_certificate {
+            self.exemptions.verify(certificate, &order).await?;
+            tracing::info!(certificate = %certificate.id, "tax exempt purchase");
+            order.apply_tax_rate(Decimal::ZERO, rounding);
+            order.rate_source = Some(RateSource::Exempt);
+            order.jurisdiction = state.map(str::to_owned);
+            return Ok((order, Decimal::ZERO));
+        }
+
+        if let Some(member_state) = vat::member_state(&country) {
+            return self.charge_vat(order, member_state, rounding);
+        }
+
+        let (rate, taxes, source) = if country == CANADA {
+            let province = state.unwrap_or_default();
+            let Some((rate, taxes)) = self.canada.rate(province) else {
+                tracing::warn!(province, "no Canadian taxes for the province");
+                return Err(ComputeError::TaxRateNotAvailable);
+            };
+            (rate, taxes, RateSource::Lookup)
+        } else {
+            let request = RateRequest::new(postal::zip5(&order.shipping_zip)).on(order.order_date);
+            let (rate, source) = self.find_rate(&request).await?;
+            let TaxRate { rate, components } = rate;
+            let taxes = components
+                .iter()
+                .map(|component| (TaxComponent::of(component), component.rate))
+                .collect();
+            (rate, taxes, source)
+        };
+        order.apply_tax_rates(
+            rate,
+            |category| self.categories.rate(category, state, rate),
+            rounding,
+        );
+        order.itemize(taxes, rounding);
+        order.rate_source = Some(source);
+        order.jurisdiction = state.map(str::to_owned);
+        Ok((order, rate))
+    }
+
+    /// Charges the VAT of `member_state`, or none for a reverse-charged B2B
+    /// sale, returning the standard rate charged.
+    fn charge_vat(
+        &self,
+        mut order: Order,
+        member_state: &'static str,
+        rounding: RoundingStrategy,
+    ) -> Result<(Order, Decimal), ComputeError> {
+        let vat_id = order.vat_id.as_deref().map(vat::normalize_vat_id);
+        let reverse_charge = vat_id.is_some() && self.vat.reverse_charges(member_state);
+        let rate = if reverse_charge {
+            tracing::info!(country = member_state, "reverse-charged B2B sale");
+            order.apply_tax_rate(Decimal::ZERO, rounding);
+            order.rate_source = Some(RateSource::ReverseCharge);
+            Decimal::ZERO
+        } else {
+            let Some(vat) = self.vat.get(member_state) else {
+                tracing::warn!(country = member_state, "no VAT rate for the member state");
+                return Err(ComputeError::TaxRateNotAvailable);
+            };
+            order.apply_tax_rates(
+                vat.standard,
+                |category| vat.for_category(category),
+                rounding,
+            );
+            order.rate_source = Some(RateSource::Lookup);
+            vat.standard
+        };
+        order.jurisdiction = Some(member_state.to_owned());
+        order.vat = Some(VatSummary {
+            country: member_state.to_owned(),
+            vat_id,
+            reverse_charge,
+            net_amount: order.total - order.tax_amount,
+            vat_amount: order.tax_amount,
+            gross_amount: order.total,
+        });
+        Ok((order, rate))
+    }
+
+    /// The totals of `order` in the currency `to`, rounded to its minor unit.
+    async fn convert(
+        &self,
+        order: &Order,
+        to: String,
+        rounding: RoundingStrategy,
+    ) -> Result<Conversion, ComputeError> {
+        let fx_rate = self.fx_rates.fx_rate(&order.currency(), &to).await?;
+        let rounding = rounding.in_currency(&to);
+        Ok(Conversion {
+            fx_rate,
+            subtotal: rounding.round(order.subtotal * fx_rate),
+            tax_amount: rounding.round(order.tax_amount * fx_rate),
+            total: rounding.round(order.total * fx_rate),
+            currency: to,
+        })
+    }
+
+    /// The rate `request` asks for, or the default rate when none is known.
+    async fn find_rate(
+        &self,
+        request: &RateRequest,
+    ) -> Result<(TaxRate, RateSource), ComputeError> {
+        match (
+            self.tax_rates.find_rate(request).await,
+            self.default_tax_rate,
+        ) {
+            (Ok(rate), _) => Ok((rate, RateSource::Lookup)),
+            (Err(ComputeError::TaxRateNotAvailable), Some(default)) => {
+                tracing::warn!("no sales tax rate for zip code, using DEFAULT_TAX_RATE");
+                Ok((TaxRate::from(default), RateSource::Default))
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+}
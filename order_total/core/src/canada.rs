@@ -0,0 +1,154 @@
+use crate::order::TaxComponent;
+use anyhow::{anyhow, Context};
+use models::JurisdictionLevel;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// The GST, HST and provincial sales taxes of each province and territory,
+/// as of 2025, in the format `CanadianTaxes::from_csv` reads.
+const DEFAULT_TABLE: &[u8] = include_bytes!("canada_taxes.csv");
+
+/// The code of the province (or territory) a Canadian postal code belongs
+/// to, judged by its first letter (and, in the north, the two characters
+/// after it). `None` for anything that is not a postal code like `K1A 0B1`.
+pub fn province_for(postal_code: &str) -> Option<&'static str> {
+    let code: Vec<u8> = postal_code
+        .trim()
+        .bytes()
+        .filter(|b| *b != b' ')
+        .map(|b| b.to_ascii_uppercase())
+        .collect();
+    let well_formed = code.len() == 6
+        && code.iter().enumerate().all(|(index, b)| {
+            if index % 2 == 0 {
+                b.is_ascii_uppercase() && !b"DFIOQU".contains(b)
+            } else {
+                b.is_ascii_digit()
+            }
+        });
+    if !well_formed {
+        return None;
+    }
+    Some(match code[0] {
+        b'A' => "NL",
+        b'B' => "NS",
+        b'C' => "PE",
+        b'E' => "NB",
+        b'G' | b'H' | b'J' => "QC",
+        b'K' | b'L' | b'M' | b'N' | b'P' => "ON",
+        b'R' => "MB",
+        b'S' => "SK",
+        b'T' => "AB",
+        b'V' => "BC",
+        b'X' if code[1] == b'0' && matches!(code[2], b'A' | b'B' | b'C') => "NU",
+        b'X' => "NT",
+        b'Y' => "YT",
+        _ => return None,
+    })
+}
+
+/// One of the taxes levied on a sale shipped to a province.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanadianTax {
+    /// e.g. `GST`, `HST` or `QST`.
+    pub name: String,
+    /// `federal` for the GST and HST, `province` for the others.
+    pub level: JurisdictionLevel,
+    pub rate: Decimal,
+    /// Levied on the price plus the taxes listed before it, rather than on
+    /// the price alone.
+    pub compound: bool,
+}
+
+/// The taxes of each province, applied in the order listed.
+#[derive(Debug)]
+pub struct CanadianTaxes {
+    taxes: HashMap<String, Vec<CanadianTax>>,
+}
+
+impl CanadianTaxes {
+    /// Loads the table from the CSV file named by `CANADA_TAX_TABLE`, or
+    /// the built-in one.
+    pub fn load() -> anyhow::Result<Self> {
+        match std::env::var("CANADA_TAX_TABLE") {
+            Ok(path) => {
+                let file = std::fs::File::open(&path)
+                    .with_context(|| format!("cannot open Canadian tax table {path:?}"))?;
+                Self::from_csv(file).with_context(|| format!("invalid Canadian tax table {path:?}"))
+            }
+            Err(_) => Self::from_csv(DEFAULT_TABLE),
+        }
+    }
+
+    /// Reads `province,tax,level,rate,compound` rows, e.g.
+    /// `QC,QST,province,0.09975,false`, with `level` `federal` or
+    /// `province`.
+    pub fn from_csv(reader: impl Read) -> anyhow::Result<Self> {
+        let mut taxes: HashMap<String, Vec<CanadianTax>> = HashMap::new();
+        for (index, record) in csv::Reader::from_reader(reader).records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let field = |column: usize, name: &str| {
+                record
+                    .get(column)
+                    .map(str::trim)
+                    .ok_or_else(|| anyhow!("line {line}: missing {name}"))
+            };
+            let province = field(0, "province")?.to_ascii_uppercase();
+            let name = field(1, "tax")?.to_owned();
+            let level = match field(2, "level")? {
+                "federal" => JurisdictionLevel::Federal,
+                "province" => JurisdictionLevel::Province,
+                other => anyhow::bail!(
+                    "line {line}: unknown level {other:?}, expected federal or province"
+                ),
+            };
+            let rate = field(3, "rate")?
+                .parse::<Decimal>()
+                .map_err(|err| anyhow!("line {line}: invalid rate: {err}"))?;
+            if rate < Decimal::ZERO {
+                anyhow::bail!("line {line}: the rate must not be negative");
+            }
+            let compound = field(4, "compound")?
+                .parse::<bool>()
+                .map_err(|_| anyhow!("line {line}: compound must be true or false"))?;
+            taxes.entry(province).or_default().push(CanadianTax {
+                name,
+                level,
+                rate,
+                compound,
+            });
+        }
+        Ok(Self { taxes })
+    }
+
+    /// The combined rate of the taxes of `province`, with each tax and the
+    /// share of the combined rate it accounts for. A compound tax's share
+    /// is its rate of the price plus the taxes before it, e.g. 9.975% of
+    /// 105% for a QST compounded on a 5% GST. `None` for a province the
+    /// table does not list.
+    pub fn rate(&self, province: &str) -> Option<(Decimal, Vec<(TaxComponent, Decimal)>)> {
+        let taxes = self.taxes.get(province)?;
+        let mut combined = Decimal::ZERO;
+        let mut shares = Vec::with_capacity(taxes.len());
+        for tax in taxes {
+            let share = if tax.compound {
+                tax.rate * (Decimal::ONE + combined)
+            } else {
+                tax.rate
+            };
+            combined += share;
+            shares.push((
+                TaxComponent {
+                    level: tax.level,
+                    name: Some(tax.name.clone()),
+                    rate: tax.rate,
+                    amount: Decimal::ZERO,
+                },
+                share,
+            ));
+        }
+        Some((combined, shares))
+    }
+}
@@ -0,0 +1,140 @@
+use crate::error::{ComputeError, FieldError};
+use crate::order::{Order, MAX_AMOUNT};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use utoipa::ToSchema;
+
+/// Knows the authoritative unit price of each product, e.g. an inventory
+/// service. With one configured, `Calculator` prices orders itself rather
+/// than trusting the prices they were sent with.
+#[async_trait]
+pub trait ProductCatalog: Send + Sync {
+    /// The unit price of `product_id`, or `None` for a product the catalog
+    /// does not sell.
+    async fn unit_price(&self, product_id: i32) -> Result<Option<Decimal>, ComputeError>;
+}
+
+/// A price the order was sent with that the catalog disagrees with; the
+/// catalog's price was used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct PriceMismatch {
+    /// The field that was repriced, e.g. `line_items[1].unit_price`, or
+    /// `subtotal` for an order without line items.
+    pub field: String,
+    pub product_id: i32,
+    pub submitted: Decimal,
+    pub catalog: Decimal,
+}
+
+/// Unit prices held in memory.
+#[derive(Debug, Default)]
+pub struct CatalogTable {
+    prices: HashMap<i32, Decimal>,
+}
+
+impl CatalogTable {
+    /// Loads the catalog from the CSV file at `path`.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("cannot open product catalog {path:?}"))?;
+        Self::from_csv(file).with_context(|| format!("invalid product catalog {path:?}"))
+    }
+
+    /// Reads `product_id,unit_price` rows, e.g. `321,10.00`.
+    pub fn from_csv(reader: impl Read) -> anyhow::Result<Self> {
+        let mut prices = HashMap::new();
+        for (index, record) in csv::Reader::from_reader(reader).records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let field = |column: usize, name: &str| {
+                record
+                    .get(column)
+                    .map(str::trim)
+                    .ok_or_else(|| anyhow!("line {line}: missing {name}"))
+            };
+            let product_id: i32 = field(0, "product_id")?
+                .parse()
+                .map_err(|err| anyhow!("line {line}: invalid product_id: {err}"))?;
+            let unit_price: Decimal = field(1, "unit_price")?
+                .parse()
+                .map_err(|err| anyhow!("line {line}: invalid unit_price: {err}"))?;
+            if unit_price < Decimal::ZERO {
+                anyhow::bail!("line {line}: the unit price must not be negative");
+            }
+            if unit_price > MAX_AMOUNT {
+                anyhow::bail!("line {line}: the unit price must be at most {MAX_AMOUNT}");
+            }
+            prices.insert(product_id, unit_price);
+        }
+        Ok(Self { prices })
+    }
+}
+
+#[async_trait]
+impl ProductCatalog for CatalogTable {
+    async fn unit_price(&self, product_id: i32) -> Result<Option<Decimal>, ComputeError> {
+        Ok(self.prices.get(&product_id).copied())
+    }
+}
+
+/// Replaces the prices of `order` with the catalog's: each line item's
+/// `unit_price`, or the `subtotal` of an order without line items, which
+/// becomes the price of its `quantity` of `product_id`. The prices that
+/// differ are recorded in `price_mismatches`; a product the catalog does
+/// not sell fails validation.
+pub async fn reprice(catalog: &dyn ProductCatalog, order: &mut Order) -> Result<(), ComputeError> {
+    let mut mismatches = Vec::new();
+    let mut unknown = Vec::new();
+    if order.line_items.is_empty() {
+        match catalog.unit_price(order.product_id).await? {
+            Some(unit_price) => {
+                let subtotal = unit_price * Decimal::from(order.quantity);
+                if subtotal != order.subtotal {
+                    mismatches.push(PriceMismatch {
+                        field: "subtotal".to_owned(),
+                        product_id: order.product_id,
+                        submitted: order.subtotal,
+                        catalog: subtotal,
+                    });
+                }
+                order.subtotal = subtotal;
+            }
+            None => unknown.push(FieldError::new("product_id", "is not in the catalog")),
+        }
+    }
+    for (index, item) in order.line_items.iter_mut().enumerate() {
+        match catalog.unit_price(item.product_id).await? {
+            Some(unit_price) => {
+                if unit_price != item.unit_price {
+                    mismatches.push(PriceMismatch {
+                        field: format!("line_items[{index}].unit_price"),
+                        product_id: item.product_id,
+                        submitted: item.unit_price,
+                        catalog: unit_price,
+                    });
+                }
+                item.unit_price = unit_price;
+            }
+            None => unknown.push(FieldError::new(
+                format!("line_items[{index}].product_id"),
+                "is not in the catalog",
+            )),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(ComputeError::Validation(unknown));
+    }
+    if !mismatches.is_empty() {
+        tracing::warn!(
+            order_id = order.order_id,
+            mismatches = mismatches.len(),
+            "order sent with prices the catalog disagrees with"
+        );
+    }
+    order.price_mismatches = mismatches;
+    Ok(())
+}
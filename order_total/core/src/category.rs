@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Context};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// What kind of goods a line item is, for states that tax some kinds at a
+/// reduced rate or not at all.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaxCategory {
+    /// Taxed at the zip code's rate (the default).
+    #[default]
+    Standard,
+    Grocery,
+    Clothing,
+    /// Never taxed, unless the adjustment table says otherwise.
+    Exempt,
+}
+
+/// How the rate of a zip code is adjusted for each `TaxCategory`: the rate
+/// is multiplied by a factor, `0` exempting the category and `1` taxing it
+/// in full. A factor can be given for one jurisdiction (state) or for all
+/// of them; the jurisdiction's own wins. Categories without a factor are
+/// taxed in full, except `exempt`.
+#[derive(Debug, Default)]
+pub struct CategoryAdjustments {
+    factors: HashMap<(TaxCategory, Option<String>), Decimal>,
+}
+
+impl CategoryAdjustments {
+    /// Loads the table from the CSV file named by `TAX_CATEGORY_TABLE`, if
+    /// any; without one, only `exempt` is adjusted.
+    pub fn load() -> anyhow::Result<Self> {
+        match std::env::var("TAX_CATEGORY_TABLE") {
+            Ok(path) => {
+                let file = std::fs::File::open(&path)
+                    .with_context(|| format!("cannot open tax category table {path:?}"))?;
+                Self::from_csv(file).with_context(|| format!("invalid tax category table {path:?}"))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Reads `category,jurisdiction,factor` rows, with `*` as the
+    /// jurisdiction of a factor that applies everywhere, e.g.
+    /// `grocery,TX,0`.
+    pub fn from_csv(reader: impl Read) -> anyhow::Result<Self> {
+        let mut factors = HashMap::new();
+        for (index, record) in csv::Reader::from_reader(reader).records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let field = |column: usize, name: &str| {
+                record
+                    .get(column)
+                    .map(str::trim)
+                    .ok_or_else(|| anyhow!("line {line}: missing {name}"))
+            };
+            let category = field(0, "category")?
+                .parse::<TaxCategory>()
+                .map_err(|err| anyhow!("line {line}: {err}"))?;
+            let jurisdiction = match field(1, "jurisdiction")? {
+                "*" => None,
+                state => Some(state.to_ascii_uppercase()),
+            };
+            let factor = field(2, "factor")?
+                .parse::<Decimal>()
+                .map_err(|err| anyhow!("line {line}: invalid factor: {err}"))?;
+            if factor < Decimal::ZERO {
+                anyhow::bail!("line {line}: the factor must not be negative");
+            }
+            factors.insert((category, jurisdiction), factor);
+        }
+        Ok(Self { factors })
+    }
+
+    /// The rate goods of `category` are taxed at in `jurisdiction`, where
+    /// the zip code's rate is `rate`.
+    pub fn rate(
+        &self,
+        category: TaxCategory,
+        jurisdiction: Option<&str>,
+        rate: Decimal,
+    ) -> Decimal {
+        let own =
+            jurisdiction.and_then(|state| self.factors.get(&(category, Some(state.to_owned()))));
+        let factor = own
+            .or_else(|| self.factors.get(&(category, None)))
+            .copied()
+            .unwrap_or(match category {
+                TaxCategory::Exempt => Decimal::ZERO,
+                _ => Decimal::ONE,
+            });
+        rate * factor
+    }
+}
+
+impl FromStr for TaxCategory {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "standard" => Ok(Self::Standard),
+            "grocery" => Ok(Self::Grocery),
+            "clothing" => Ok(Self::Clothing),
+            "exempt" => Ok(Self::Exempt),
+            other => Err(anyhow!(
+                "unknown tax category {other:?}, expected standard, grocery, clothing or exempt"
+            )),
+        }
+    }
+}
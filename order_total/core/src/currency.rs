@@ -0,0 +1,148 @@
+use crate::error::ComputeError;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use utoipa::ToSchema;
+
+/// The currency of an order that does not name one.
+pub const USD: &str = "USD";
+
+/// The active ISO 4217 currency codes, sorted.
+const CURRENCIES: [&str; 155] = [
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB",
+    "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWL",
+];
+
+/// The currencies without a minor unit, such as the yen; the ones left out
+/// of these lists have cents.
+const NO_MINOR_UNIT: [&str; 16] = [
+    "BIF", "CLP", "DJF", "GNF", "ISK", "JPY", "KMF", "KRW", "PYG", "RWF", "UGX", "VND", "VUV",
+    "XAF", "XOF", "XPF",
+];
+
+/// The currencies whose minor unit is a thousandth, such as the fils of the
+/// dinars.
+const THREE_DIGIT_MINOR_UNIT: [&str; 7] = ["BHD", "IQD", "JOD", "KWD", "LYD", "OMR", "TND"];
+
+/// The digits of the minor unit of `code`, an ISO 4217 code in upper case,
+/// or `None` for a code that is not one.
+pub fn minor_units(code: &str) -> Option<u32> {
+    CURRENCIES.binary_search(&code).ok()?;
+    Some(if NO_MINOR_UNIT.contains(&code) {
+        0
+    } else if THREE_DIGIT_MINOR_UNIT.contains(&code) {
+        3
+    } else {
+        2
+    })
+}
+
+/// `code` trimmed and in upper case, the form currencies are compared in.
+pub fn normalize(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
+
+/// An order's totals in another currency than the one it was priced in.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Conversion {
+    /// The ISO 4217 code converted to.
+    pub currency: String,
+    /// What one unit of the order's currency is worth in `currency`.
+    pub fx_rate: Decimal,
+    pub subtotal: Decimal,
+    pub tax_amount: Decimal,
+    pub total: Decimal,
+}
+
+/// Tells what one unit of a currency is worth in another, e.g. from a
+/// central bank's reference rates or a market data feed. `Calculator`
+/// converts the totals of orders asking for it with the rate this gives.
+#[async_trait]
+pub trait FxRateProvider: Send + Sync {
+    /// The rate from `from` to `to`, both ISO 4217 codes in upper case;
+    /// `FxRateNotAvailable` when none is known.
+    async fn fx_rate(&self, from: &str, to: &str) -> Result<Decimal, ComputeError>;
+}
+
+/// Exchange rates held in memory. A pair given one way also converts the
+/// other way, at the inverse rate.
+#[derive(Debug, Default)]
+pub struct FxRateTable {
+    rates: HashMap<(String, String), Decimal>,
+}
+
+impl FxRateTable {
+    /// Loads the rates from the CSV file named by `FX_RATE_TABLE`, if any;
+    /// without one, orders can only be converted to their own currency.
+    pub fn load() -> anyhow::Result<Self> {
+        match std::env::var("FX_RATE_TABLE") {
+            Ok(path) => {
+                let file = std::fs::File::open(&path)
+                    .with_context(|| format!("cannot open exchange rate table {path:?}"))?;
+                Self::from_csv(file)
+                    .with_context(|| format!("invalid exchange rate table {path:?}"))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Reads `from,to,rate` rows, e.g. `USD,EUR,0.92`: one `from` is worth
+    /// `rate` of `to`.
+    pub fn from_csv(reader: impl Read) -> anyhow::Result<Self> {
+        let mut rates = HashMap::new();
+        for (index, record) in csv::Reader::from_reader(reader).records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let field = |column: usize, name: &str| {
+                record
+                    .get(column)
+                    .map(str::trim)
+                    .ok_or_else(|| anyhow!("line {line}: missing {name}"))
+            };
+            let currency = |column: usize, name: &str| {
+                let code = normalize(field(column, name)?);
+                match minor_units(&code) {
+                    Some(_) => Ok(code),
+                    None => Err(anyhow!("line {line}: unknown {name} currency {code:?}")),
+                }
+            };
+            let (from, to) = (currency(0, "from")?, currency(1, "to")?);
+            let rate: Decimal = field(2, "rate")?
+                .parse()
+                .map_err(|err| anyhow!("line {line}: invalid rate: {err}"))?;
+            if rate <= Decimal::ZERO {
+                anyhow::bail!("line {line}: the rate must be positive");
+            }
+            rates.insert((from, to), rate);
+        }
+        Ok(Self { rates })
+    }
+}
+
+#[async_trait]
+impl FxRateProvider for FxRateTable {
+    async fn fx_rate(&self, from: &str, to: &str) -> Result<Decimal, ComputeError> {
+        if from == to {
+            return Ok(Decimal::ONE);
+        }
+        let pair = |from: &str, to: &str| self.rates.get(&(from.to_owned(), to.to_owned()));
+        match (pair(from, to), pair(to, from)) {
+            (Some(rate), _) => Ok(*rate),
+            (None, Some(inverse)) => Ok(Decimal::ONE / inverse),
+            (None, None) => Err(ComputeError::FxRateNotAvailable),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::rounding::RoundingStrategy;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A discount on an order, e.g. a coupon. `amount` is filled in by the
+/// computation and ignored on input.
+///
+/// Discounts are applied in a fixed order, whatever order they are listed
+/// in: pre-tax percentages, then pre-tax fixed amounts, then the tax on
+/// what is left, then post-tax percentages and post-tax fixed amounts.
+/// Within each group they apply in the order given, each to what the ones
+/// before it left, and none takes the amount below zero.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Discount {
+    /// The caller's label for it, e.g. the coupon code; echoed back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub kind: DiscountKind,
+    /// The percentage off (`10` for 10%), or the amount off.
+    pub value: Decimal,
+    #[serde(default)]
+    pub applies: DiscountStage,
+    /// What the discount took off the order.
+    #[serde(default)]
+    pub amount: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscountKind {
+    /// `value` percent off.
+    Percentage,
+    /// `value` off.
+    Fixed,
+}
+
+/// Whether a discount lowers the amount taxed, or only the total.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscountStage {
+    /// Taken off the subtotal before tax, so less tax is due (the default).
+    #[default]
+    PreTax,
+    /// Taken off the total after tax, which stays as computed.
+    PostTax,
+}
+
+/// Applies the discounts of `stage` to `amount`, recording what each took
+/// off, and returns what is left.
+pub(crate) fn apply(
+    discounts: &mut [Discount],
+    stage: DiscountStage,
+    mut amount: Decimal,
+    rounding: RoundingStrategy,
+) -> Decimal {
+    for kind in [DiscountKind::Percentage, DiscountKind::Fixed] {
+        let group = discounts
+            .iter_mut()
+            .filter(|discount| discount.applies == stage && discount.kind == kind);
+        for discount in group {
+            let off = match kind {
+                DiscountKind::Percentage => {
+                    rounding.round(amount * discount.value / Decimal::ONE_HUNDRED)
+                }
+                DiscountKind::Fixed => discount.value,
+            };
+            discount.amount = off.min(amount);
+            amount -= discount.amount;
+        }
+    }
+    amount
+}
+
+/// The total the discounts of `stage` took off.
+pub(crate) fn total(discounts: &[Discount], stage: DiscountStage) -> Decimal {
+    discounts
+        .iter()
+        .filter(|discount| discount.applies == stage)
+        .map(|discount| discount.amount)
+        .sum()
+}
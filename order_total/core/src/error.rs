@@ -0,0 +1,466 @@
+use crate::json_error::JsonError;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Why an order could not be computed, or a request to compute one was
+/// refused. `into_parts` gives the status code and body an HTTP service
+/// answers with.
+#[derive(Debug)]
+pub enum ComputeError {
+    InvalidRequest,
+    /// The body is not JSON, or not JSON of the expected shape.
+    MalformedJson(JsonError),
+    Validation(Vec<FieldError>),
+    PayloadTooLarge(usize),
+    /// The body is sent with a `Content-Encoding` other than gzip or brotli.
+    UnsupportedEncoding,
+    /// The zip code has no known sales tax rate.
+    TaxRateNotAvailable,
+    /// No exchange rate is known to convert the order's totals with.
+    FxRateNotAvailable,
+    /// The sales tax rate service could not be reached or failed.
+    UpstreamUnavailable,
+    UpstreamTimeout,
+    /// The request's deadline (`X-Request-Deadline-Ms` or the server-wide
+    /// maximum) passed before it was answered.
+    DeadlineExceeded,
+    CircuitOpen(Duration),
+    OrderNotFound,
+    PersistenceDisabled,
+    /// No job has that id, or its result has expired.
+    JobNotFound,
+    /// The job's result was asked for before it finished.
+    JobNotFinished,
+    /// The quote token is malformed or its signature does not check out.
+    QuoteInvalid,
+    /// The quote token expired before it was finalized.
+    QuoteExpired,
+    /// The quote was already finalized once.
+    QuoteAlreadyFinalized,
+    /// The exemption verifier did not accept the order's certificate.
+    ExemptionRejected,
+    IdempotencyKeyInFlight,
+    IdempotencyKeyReused,
+    /// The bearer token is missing or invalid.
+    Unauthorized,
+    /// The `X-Api-Key` is missing or unknown.
+    InvalidApiKey,
+    /// The `X-Tenant-Id` names no configured tenant.
+    UnknownTenant,
+    /// The API key's bucket is empty; a token is back after the duration.
+    RateLimited(Quota, Duration),
+    /// The service-wide bucket is empty; a token is back after the duration.
+    Throttled(Quota, Duration),
+    /// Too many requests are in progress; retry after the duration.
+    Overloaded(Duration),
+    Unexpected(Box<dyn Error + Send + Sync + 'static>),
+}
+
+/// Lets one outcome be handed to several waiting requests. An `Unexpected`
+/// cause can only be copied as its message.
+impl Clone for ComputeError {
+    fn clone(&self) -> Self {
+        match self {
+            Self::InvalidRequest => Self::InvalidRequest,
+            Self::MalformedJson(error) => Self::MalformedJson(error.clone()),
+            Self::Validation(errors) => Self::Validation(errors.clone()),
+            Self::PayloadTooLarge(limit) => Self::PayloadTooLarge(*limit),
+            Self::UnsupportedEncoding => Self::UnsupportedEncoding,
+            Self::TaxRateNotAvailable => Self::TaxRateNotAvailable,
+            Self::FxRateNotAvailable => Self::FxRateNotAvailable,
+            Self::UpstreamUnavailable => Self::UpstreamUnavailable,
+            Self::UpstreamTimeout => Self::UpstreamTimeout,
+            Self::DeadlineExceeded => Self::DeadlineExceeded,
+            Self::CircuitOpen(wait) => Self::CircuitOpen(*wait),
+            Self::OrderNotFound => Self::OrderNotFound,
+            Self::PersistenceDisabled => Self::PersistenceDisabled,
+            Self::JobNotFound => Self::JobNotFound,
+            Self::JobNotFinished => Self::JobNotFinished,
+            Self::QuoteInvalid => Self::QuoteInvalid,
+            Self::QuoteExpired => Self::QuoteExpired,
+            Self::QuoteAlreadyFinalized => Self::QuoteAlreadyFinalized,
+            Self::ExemptionRejected => Self::ExemptionRejected,
+            Self::IdempotencyKeyInFlight => Self::IdempotencyKeyInFlight,
+            Self::IdempotencyKeyReused => Self::IdempotencyKeyReused,
+            Self::Unauthorized => Self::Unauthorized,
+            Self::InvalidApiKey => Self::InvalidApiKey,
+            Self::UnknownTenant => Self::UnknownTenant,
+            Self::RateLimited(quota, wait) => Self::RateLimited(*quota, *wait),
+            Self::Throttled(quota, wait) => Self::Throttled(*quota, *wait),
+            Self::Overloaded(wait) => Self::Overloaded(*wait),
+            Self::Unexpected(cause) => Self::Unexpected(cause.to_string().into()),
+        }
+    }
+}
+
+/// Where a client stands against its rate limit after a request, reported
+/// in the `X-RateLimit-*` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the bucket is full again.
+    pub reset: Duration,
+}
+
+impl ComputeError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidRequest | Self::MalformedJson(_) => ErrorCode::InvalidRequest,
+            Self::Validation(_) => ErrorCode::ValidationFailed,
+            Self::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            Self::UnsupportedEncoding => ErrorCode::UnsupportedEncoding,
+            Self::TaxRateNotAvailable => ErrorCode::TaxRateUnavailable,
+            Self::FxRateNotAvailable => ErrorCode::FxRateUnavailable,
+            Self::UpstreamUnavailable => ErrorCode::UpstreamUnavailable,
+            Self::UpstreamTimeout => ErrorCode::UpstreamTimeout,
+            Self::DeadlineExceeded => ErrorCode::DeadlineExceeded,
+            Self::CircuitOpen(_) => ErrorCode::CircuitOpen,
+            Self::OrderNotFound => ErrorCode::OrderNotFound,
+            Self::PersistenceDisabled => ErrorCode::PersistenceDisabled,
+            Self::JobNotFound => ErrorCode::JobNotFound,
+            Self::JobNotFinished => ErrorCode::JobNotFinished,
+            Self::QuoteInvalid => ErrorCode::QuoteInvalid,
+            Self::QuoteExpired => ErrorCode::QuoteExpired,
+            Self::QuoteAlreadyFinalized => ErrorCode::QuoteAlreadyFinalized,
+            Self::ExemptionRejected => ErrorCode::ExemptionRejected,
+            Self::IdempotencyKeyInFlight => ErrorCode::IdempotencyKeyInFlight,
+            Self::IdempotencyKeyReused => ErrorCode::IdempotencyKeyReused,
+            Self::Unauthorized => ErrorCode::Unauthorized,
+            Self::InvalidApiKey => ErrorCode::InvalidApiKey,
+            Self::UnknownTenant => ErrorCode::UnknownTenant,
+            Self::RateLimited(..) | Self::Throttled(..) => ErrorCode::RateLimited,
+            Self::Overloaded(_) => ErrorCode::Overloaded,
+            Self::Unexpected(_) => ErrorCode::InternalError,
+        }
+    }
+
+    /// The status code and body a client is answered with.
+    pub fn into_parts(self) -> (StatusCode, ErrorResponse) {
+        let code = self.code();
+        match self {
+            ComputeError::InvalidRequest => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(code, "invalid request"),
+            ),
+            ComputeError::MalformedJson(error) => {
+                let errors = error
+                    .field
+                    .iter()
+                    .map(|field| FieldError::new(field.as_str(), error.problem.as_str()))
+                    .collect();
+                (
+                    StatusCode::BAD_REQUEST,
+                    ErrorResponse::new(code, format!("invalid request: {error}"))
+                        .with_errors(errors),
+                )
+            }
+            ComputeError::Validation(errors) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorResponse::new(code, "The order failed validation.").with_errors(errors),
+            ),
+            ComputeError::PayloadTooLarge(limit) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                ErrorResponse::new(
+                    code,
+                    format!("The request body exceeds the limit of {limit} bytes."),
+                ),
+            ),
+            ComputeError::UnsupportedEncoding => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorResponse::new(
+                    code,
+                    "The request body must be sent uncompressed, or with a gzip or br Content-Encoding.",
+                ),
+            ),
+            ComputeError::TaxRateNotAvailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse::new(
+                    code,
+                    "The zip code in the order does not have a corresponding sales tax rate.",
+                ),
+            ),
+            ComputeError::FxRateNotAvailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse::new(
+                    code,
+                    "No exchange rate is available to convert the order to the requested currency.",
+                ),
+            ),
+            ComputeError::UpstreamUnavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse::new(code, "The sales tax rate service could not be reached."),
+            ),
+            ComputeError::UpstreamTimeout => (
+                StatusCode::GATEWAY_TIMEOUT,
+                ErrorResponse::new(code, "The sales tax rate service did not respond in time."),
+            ),
+            ComputeError::DeadlineExceeded => (
+                StatusCode::GATEWAY_TIMEOUT,
+                ErrorResponse::new(
+                    code,
+                    "The request deadline passed before the order could be computed.",
+                ),
+            ),
+            ComputeError::CircuitOpen(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse::new(code, "The sales tax rate service is currently unavailable."),
+            ),
+            ComputeError::OrderNotFound => (
+                StatusCode::NOT_FOUND,
+                ErrorResponse::new(code, "No computed order with that id has been stored."),
+            ),
+            ComputeError::PersistenceDisabled => (
+                StatusCode::NOT_IMPLEMENTED,
+                ErrorResponse::new(code, "Order persistence is not enabled; set DATABASE_URL."),
+            ),
+            ComputeError::JobNotFound => (
+                StatusCode::NOT_FOUND,
+                ErrorResponse::new(code, "No job with that id exists, or its result has expired."),
+            ),
+            ComputeError::JobNotFinished => (
+                StatusCode::CONFLICT,
+                ErrorResponse::new(code, "The job has not finished yet; poll its status."),
+            ),
+            ComputeError::QuoteInvalid => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorResponse::new(code, "The quote token is not a valid quote."),
+            ),
+            ComputeError::QuoteExpired => (
+                StatusCode::GONE,
+                ErrorResponse::new(code, "The quote has expired; request a new one."),
+            ),
+            ComputeError::QuoteAlreadyFinalized => (
+                StatusCode::CONFLICT,
+                ErrorResponse::new(code, "The quote has already been finalized."),
+            ),
+            ComputeError::ExemptionRejected => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorResponse::new(
+                    code,
+                    "The exemption certificate was not accepted; the order was not computed.",
+                ),
+            ),
+            ComputeError::IdempotencyKeyInFlight => (
+                StatusCode::CONFLICT,
+                ErrorResponse::new(
+                    code,
+                    "A request with this Idempotency-Key is still being processed.",
+                ),
+            ),
+            ComputeError::IdempotencyKeyReused => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorResponse::new(
+                    code,
+                    "This Idempotency-Key was already used with a different request body.",
+                ),
+            ),
+            ComputeError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                ErrorResponse::new(code, "A valid bearer token is required."),
+            ),
+            ComputeError::InvalidApiKey => (
+                StatusCode::UNAUTHORIZED,
+                ErrorResponse::new(code, "A valid X-Api-Key is required."),
+            ),
+            ComputeError::UnknownTenant => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(code, "The X-Tenant-Id names no configured tenant."),
+            ),
+            ComputeError::RateLimited(..) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorResponse::new(code, "The rate limit of this API key has been exceeded."),
+            ),
+            ComputeError::Throttled(..) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorResponse::new(code, "Too many requests; retry after the Retry-After delay."),
+            ),
+            ComputeError::Overloaded(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse::new(code, "The service is at capacity; retry shortly."),
+            ),
+            ComputeError::Unexpected(cause) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(code, format!("{}", cause)),
+            ),
+        }
+    }
+
+    /// The status code and JSON body a client is answered with, for the
+    /// adapters that write the response themselves.
+    pub fn into_json(self) -> (u16, String) {
+        let (status, body) = self.into_parts();
+        (
+            status.as_u16(),
+            serde_json::to_string(&body).expect("an ErrorResponse always serializes"),
+        )
+    }
+}
+
+/// The stable, machine-readable kind of an error, sent as the `code` of every
+/// error body. Unlike the `message`, these never change wording, so clients
+/// can branch on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The body is not a valid order (`400`).
+    InvalidRequest,
+    /// The order failed validation; see `errors` (`422`).
+    ValidationFailed,
+    /// The body exceeds the configured limit (`413`).
+    PayloadTooLarge,
+    /// The body's `Content-Encoding` is not supported (`415`).
+    UnsupportedEncoding,
+    /// The zip code has no known sales tax rate (`503`).
+    TaxRateUnavailable,
+    /// No exchange rate converts to the requested currency (`503`).
+    FxRateUnavailable,
+    /// The sales tax rate service could not be reached or failed (`503`).
+    UpstreamUnavailable,
+    /// The sales tax rate service did not answer in time (`504`).
+    UpstreamTimeout,
+    /// The request's deadline passed before it was answered (`504`).
+    DeadlineExceeded,
+    /// Lookups are suspended after repeated upstream failures (`503`).
+    CircuitOpen,
+    /// No stored order has that id (`404`).
+    OrderNotFound,
+    /// Order persistence is not configured (`501`).
+    PersistenceDisabled,
+    /// No job has that id, or its result has expired (`404`).
+    JobNotFound,
+    /// The job is still queued or running (`409`).
+    JobNotFinished,
+    /// The quote token is malformed or wrongly signed (`422`).
+    QuoteInvalid,
+    /// The quote expired before it was finalized (`410`).
+    QuoteExpired,
+    /// The quote was already finalized (`409`).
+    QuoteAlreadyFinalized,
+    /// The exemption certificate was not accepted (`422`).
+    ExemptionRejected,
+    /// A request with the same `Idempotency-Key` is still running (`409`).
+    IdempotencyKeyInFlight,
+    /// The `Idempotency-Key` was used with another body (`422`).
+    IdempotencyKeyReused,
+    /// The bearer token is missing or invalid (`401`).
+    Unauthorized,
+    /// The `X-Api-Key` is missing or unknown (`401`).
+    InvalidApiKey,
+    /// The `X-Tenant-Id` names no configured tenant (`400`).
+    UnknownTenant,
+    /// The API key's or the service's rate limit is exceeded (`429`).
+    RateLimited,
+    /// The service is at its concurrency limit (`503`).
+    Overloaded,
+    /// Anything else (`500`).
+    InternalError,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    status: String,
+    code: ErrorCode,
+    message: String,
+    /// Quote this when reporting a problem; it appears in the logs of both
+    /// services.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: impl ToString) -> Self {
+        Self {
+            status: "error".to_string(),
+            code,
+            message: message.to_string(),
+            request_id: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// The message and any field errors on one line, e.g.
+    /// `The order failed validation. (quantity: must be greater than zero)`.
+    pub fn summary(&self) -> String {
+        if self.errors.is_empty() {
+            return self.message.clone();
+        }
+        let fields: Vec<String> = self
+            .errors
+            .iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect();
+        format!("{} ({})", self.message, fields.join("; "))
+    }
+}
+
+/// What is wrong with one field of a request, e.g.
+/// `{"field": "line_items[1].quantity", "message": "must be greater than zero"}`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Where the body is at hand, `JsonError::new` also names the field.
+impl From<serde_json::Error> for ComputeError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::MalformedJson(JsonError::without_body(&err))
+    }
+}
+
+impl From<rust_decimal::Error> for ComputeError {
+    fn from(_: rust_decimal::Error) -> Self {
+        Self::TaxRateNotAvailable
+    }
+}
+
+/// A rate out of range is the rate service's fault, not the zip code's.
+impl From<models::ParseError> for ComputeError {
+    fn from(err: models::ParseError) -> Self {
+        match err {
+            models::ParseError::RateOutOfRange(_) => Self::UpstreamUnavailable,
+            _ => Self::TaxRateNotAvailable,
+        }
+    }
+}
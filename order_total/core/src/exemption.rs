@@ -0,0 +1,71 @@
+use crate::error::{ComputeError, FieldError};
+use crate::order::Order;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A purchaser's certificate to buy tax free, such as a resale or nonprofit
+/// exemption. Kept with the computed order, for audits.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ExemptionCertificate {
+    /// The certificate number: 4 to 32 letters, digits or dashes.
+    pub id: String,
+    /// The state that issued it, e.g. `TX`; it must be the state the order
+    /// ships to.
+    pub jurisdiction: String,
+    /// The exempt purchaser, as named on the certificate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holder: Option<String>,
+}
+
+impl ExemptionCertificate {
+    /// Checks the format of the certificate, and that it was issued where
+    /// the order ships to (`state`, when known). The fields are reported
+    /// under `exemption_certificate.`.
+    pub fn validate(&self, state: Option<&str>, errors: &mut Vec<FieldError>) {
+        let id = self.id.trim();
+        let well_formed = (4..=32).contains(&id.len())
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !well_formed {
+            errors.push(FieldError::new(
+                "exemption_certificate.id",
+                "must be 4 to 32 letters, digits or dashes",
+            ));
+        }
+        let jurisdiction = self.jurisdiction.trim();
+        if jurisdiction.len() != 2 || !jurisdiction.chars().all(|c| c.is_ascii_alphabetic()) {
+            errors.push(FieldError::new(
+                "exemption_certificate.jurisdiction",
+                "must be a two-letter state code",
+            ));
+        } else if state.is_some_and(|state| !state.eq_ignore_ascii_case(jurisdiction)) {
+            errors.push(FieldError::new(
+                "exemption_certificate.jurisdiction",
+                "must be the state the order ships to",
+            ));
+        }
+    }
+}
+
+/// Decides whether a well-formed certificate really exempts an order, e.g.
+/// by asking a certificate registry. `Calculator` only computes an order
+/// tax free once its verifier accepts the certificate.
+#[async_trait]
+pub trait ExemptionVerifier: Send + Sync {
+    /// `ExemptionRejected` for a certificate that does not exempt `order`.
+    async fn verify(
+        &self,
+        certificate: &ExemptionCertificate,
+        order: &Order,
+    ) -> Result<(), ComputeError>;
+}
+
+/// Accepts every certificate that passed the format checks.
+pub struct AcceptWellFormed;
+
+#[async_trait]
+impl ExemptionVerifier for AcceptWellFormed {
+    async fn verify(&self, _: &ExemptionCertificate, _: &Order) -> Result<(), ComputeError> {
+        Ok(())
+    }
+}
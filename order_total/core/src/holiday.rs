@@ -0,0 +1,143 @@
+use crate::category::TaxCategory;
+use crate::order::Order;
+use anyhow::{anyhow, Context};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::io::Read;
+
+/// A period in which some goods are sold tax free, such as a back-to-school
+/// sales tax holiday on clothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaxHoliday {
+    pub name: String,
+    /// The first and last day of the holiday, both included.
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    /// The state (or province, or member state) it is held in; `None` for
+    /// everywhere.
+    pub jurisdiction: Option<String>,
+    /// The goods it exempts; `None` for all of them.
+    pub category: Option<TaxCategory>,
+    /// Only goods priced under this much apiece are exempt.
+    pub cap: Option<Decimal>,
+}
+
+impl TaxHoliday {
+    fn exempts(
+        &self,
+        date: NaiveDate,
+        jurisdiction: Option<&str>,
+        category: TaxCategory,
+        unit_price: Decimal,
+    ) -> bool {
+        (self.start..=self.end).contains(&date)
+            && self
+                .jurisdiction
+                .as_deref()
+                .is_none_or(|held| Some(held) == jurisdiction)
+            && self.category.is_none_or(|exempt| exempt == category)
+            && self.cap.is_none_or(|cap| unit_price < cap)
+    }
+}
+
+/// The tax holidays orders are checked against.
+#[derive(Debug, Default)]
+pub struct HolidayCalendar {
+    holidays: Vec<TaxHoliday>,
+}
+
+impl HolidayCalendar {
+    /// Loads the calendar from the CSV file named by `TAX_HOLIDAY_CALENDAR`,
+    /// if any; without one, there are no holidays.
+    pub fn load() -> anyhow::Result<Self> {
+        match std::env::var("TAX_HOLIDAY_CALENDAR") {
+            Ok(path) => {
+                let file = std::fs::File::open(&path)
+                    .with_context(|| format!("cannot open tax holiday calendar {path:?}"))?;
+                Self::from_csv(file)
+                    .with_context(|| format!("invalid tax holiday calendar {path:?}"))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Reads `name,start,end,jurisdiction,category,cap` rows, e.g.
+    /// `Back to school,2025-08-08,2025-08-10,TX,clothing,100`, with `*` as
+    /// the jurisdiction or category of a holiday that applies to all of them
+    /// and an empty `cap` for no cap.
+    pub fn from_csv(reader: impl Read) -> anyhow::Result<Self> {
+        let mut holidays = Vec::new();
+        for (index, record) in csv::Reader::from_reader(reader).records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let field = |column: usize, name: &str| {
+                record
+                    .get(column)
+                    .map(str::trim)
+                    .ok_or_else(|| anyhow!("line {line}: missing {name}"))
+            };
+            let date = |column: usize, name: &str| {
+                field(column, name)?
+                    .parse::<NaiveDate>()
+                    .map_err(|err| anyhow!("line {line}: invalid {name} date: {err}"))
+            };
+            let (start, end) = (date(1, "start")?, date(2, "end")?);
+            if end < start {
+                anyhow::bail!("line {line}: the holiday ends before it starts");
+            }
+            let jurisdiction = match field(3, "jurisdiction")? {
+                "*" => None,
+                jurisdiction => Some(jurisdiction.to_ascii_uppercase()),
+            };
+            let category = match field(4, "category")? {
+                "*" => None,
+                category => Some(
+                    category
+                        .parse::<TaxCategory>()
+                        .map_err(|err| anyhow!("line {line}: {err}"))?,
+                ),
+            };
+            let cap = match field(5, "cap")? {
+                "" => None,
+                cap => Some(
+                    cap.parse::<Decimal>()
+                        .map_err(|err| anyhow!("line {line}: invalid cap: {err}"))?,
+                ),
+            };
+            holidays.push(TaxHoliday {
+                name: field(0, "name")?.to_owned(),
+                start,
+                end,
+                jurisdiction,
+                category,
+                cap,
+            });
+        }
+        Ok(Self { holidays })
+    }
+
+    /// Marks each line item of `order` that a holiday held on `date` in
+    /// `jurisdiction` exempts with the holiday's name, and clears the mark
+    /// of the others. An order without line items is one product of the
+    /// standard category, priced at its `subtotal` over its `quantity`, and
+    /// is marked itself.
+    pub fn mark(&self, order: &mut Order, date: NaiveDate, jurisdiction: Option<&str>) {
+        let exempting = |category, unit_price| {
+            self.holidays
+                .iter()
+                .find(|holiday| holiday.exempts(date, jurisdiction, category, unit_price))
+                .map(|holiday| holiday.name.clone())
+        };
+        for item in &mut order.line_items {
+            item.tax_holiday = exempting(item.tax_category, item.unit_price);
+        }
+        order.tax_holiday = if order.line_items.is_empty() {
+            order
+                .subtotal
+                .checked_div(Decimal::from(order.quantity))
+                .and_then(|unit_price| exempting(TaxCategory::Standard, unit_price))
+        } else {
+            None
+        };
+    }
+}
@@ -0,0 +1,186 @@
+//! What is wrong with a JSON body, told precisely enough to fix it: where
+//! the parser stopped, the field it was reading, and what it expected, but
+//! never the values the body holds.
+
+use std::fmt;
+
+/// A JSON body that could not be parsed, or did not fit the type it was
+/// read as.
+#[derive(Debug, Clone)]
+pub struct JsonError {
+    /// The line and column the parser stopped at, both from 1; unknown for
+    /// a body that was parsed before being read as its type.
+    pub position: Option<(usize, usize)>,
+    /// The field being read, e.g. `line_items[1].quantity`, when the body
+    /// parsed but a value did not fit.
+    pub field: Option<String>,
+    /// What went wrong, e.g. `invalid type, expected i32`.
+    pub problem: String,
+}
+
+/// One step of the way into a JSON document.
+enum Frame {
+    /// An object, with the key of the value being read.
+    Object(Option<String>),
+    /// An array, with the index of the value being read.
+    Array(usize),
+}
+
+impl JsonError {
+    /// The error `err` is for `body`, which the position it reports is
+    /// in.
+    pub fn new(err: &serde_json::Error, body: &[u8]) -> Self {
+        let mut error = Self::without_body(err);
+        if err.is_data() && err.line() > 0 {
+            error.field = field_at(body, err.line(), err.column(), missing_field(err));
+        }
+        error
+    }
+
+    /// The error `err` is, for a body it gave no position in, or that is
+    /// not at hand.
+    pub fn without_body(err: &serde_json::Error) -> Self {
+        Self {
+            position: (err.line() > 0).then(|| (err.line(), err.column())),
+            field: None,
+            problem: problem(err),
+        }
+    }
+
+    /// The same error, without the position: one in a copy of the body, not
+    /// in the body as it was sent.
+    pub fn with_field_only(mut self) -> Self {
+        self.position = None;
+        self
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.problem)?;
+        if let Some(field) = &self.field {
+            write!(f, " in {field}")?;
+        }
+        if let Some((line, column)) = self.position {
+            write!(f, " at line {line} column {column}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The message of `err`, without its position and without the values of
+/// the body it quotes: `invalid type: string "two", expected i32` becomes
+/// `invalid type, expected i32`. Syntax errors name no values.
+fn problem(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(end) if err.line() > 0 => &message[..end],
+        _ => &message,
+    };
+    if !err.is_data() {
+        return message.to_owned();
+    }
+    let kind = [
+        "invalid type",
+        "invalid value",
+        "unknown variant",
+        "unknown field",
+    ]
+    .into_iter()
+    .find(|kind| message.starts_with(kind));
+    match (kind, message.split_once(", expected ")) {
+        (Some(kind), Some((_, expected))) => format!("{kind}, expected {expected}"),
+        (Some(kind), None) => kind.to_owned(),
+        (None, _) => message.to_owned(),
+    }
+}
+
+/// The field `err` says is missing, e.g. `unit_price`.
+fn missing_field(err: &serde_json::Error) -> Option<String> {
+    let message = err.to_string();
+    let name = message.strip_prefix("missing field `")?;
+    Some(name[..name.find('`')?].to_owned())
+}
+
+/// The path of the value the parser was reading when it stopped at `line`
+/// and `column` of `body`. For a `missing` field, the parser stopped past
+/// the closing brace of the object, which the path leads into instead.
+fn field_at(body: &[u8], line: usize, column: usize, missing: Option<String>) -> Option<String> {
+    let line_start = match line {
+        1 => 0,
+        line => {
+            let (newline, _) = body
+                .iter()
+                .enumerate()
+                .filter(|(_, byte)| **byte == b'\n')
+                .nth(line - 2)?;
+            newline + 1
+        }
+    };
+    let mut end = (line_start + column).min(body.len());
+    if missing.is_some() && body[..end].ends_with(b"}") {
+        end -= 1;
+    }
+    let mut frames = frames(&body[..end]);
+    if let Some(name) = missing {
+        match frames.last_mut() {
+            Some(Frame::Object(key)) => *key = Some(name),
+            _ => frames.push(Frame::Object(Some(name))),
+        }
+    }
+    let mut path = String::new();
+    for frame in &frames {
+        match frame {
+            Frame::Object(Some(key)) if path.is_empty() => path.push_str(key),
+            Frame::Object(Some(key)) => {
+                path.push('.');
+                path.push_str(key);
+            }
+            Frame::Object(None) => {}
+            Frame::Array(index) => path.push_str(&format!("[{index}]")),
+        }
+    }
+    (!path.is_empty()).then_some(path)
+}
+
+/// The objects and arrays `json` leaves open, outermost first.
+fn frames(json: &[u8]) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    // Whether the next string in the innermost object is a key.
+    let mut at_key = false;
+    let mut bytes = json.iter().copied();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'{' => {
+                frames.push(Frame::Object(None));
+                at_key = true;
+            }
+            b'[' => frames.push(Frame::Array(0)),
+            b'}' | b']' => {
+                frames.pop();
+                at_key = false;
+            }
+            b',' => match frames.last_mut() {
+                Some(Frame::Array(index)) => *index += 1,
+                Some(Frame::Object(_)) => at_key = true,
+                None => {}
+            },
+            b'"' => {
+                let mut text = Vec::new();
+                while let Some(byte) = bytes.next() {
+                    match byte {
+                        b'"' => break,
+                        b'\\' => text.extend(bytes.next()),
+                        byte => text.push(byte),
+                    }
+                }
+                if let (true, Some(Frame::Object(key))) = (at_key, frames.last_mut()) {
+                    *key = Some(String::from_utf8_lossy(&text).into_owned());
+                    at_key = false;
+                }
+            }
+            _ => {}
+        }
+    }
+    frames
+}
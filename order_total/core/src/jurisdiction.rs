@@ -0,0 +1,95 @@
+/// Ranges of three-digit ZIP code prefixes and the state (or territory, or
+/// military postal region) they belong to, in ascending order. Narrower
+/// exceptions come before the range they are carved out of.
+const ZIP3_RANGES: &[(u16, u16, &str)] = &[
+    (5, 5, "NY"),
+    (6, 7, "PR"),
+    (8, 8, "VI"),
+    (9, 9, "PR"),
+    (10, 27, "MA"),
+    (28, 29, "RI"),
+    (30, 38, "NH"),
+    (39, 49, "ME"),
+    (55, 55, "MA"),
+    (50, 59, "VT"),
+    (60, 69, "CT"),
+    (70, 89, "NJ"),
+    (90, 99, "AE"),
+    (100, 149, "NY"),
+    (150, 196, "PA"),
+    (197, 199, "DE"),
+    (201, 201, "VA"),
+    (200, 205, "DC"),
+    (206, 219, "MD"),
+    (220, 246, "VA"),
+    (247, 268, "WV"),
+    (270, 289, "NC"),
+    (290, 299, "SC"),
+    (300, 319, "GA"),
+    (340, 340, "AA"),
+    (320, 349, "FL"),
+    (350, 369, "AL"),
+    (370, 385, "TN"),
+    (386, 397, "MS"),
+    (398, 399, "GA"),
+    (400, 427, "KY"),
+    (430, 459, "OH"),
+    (460, 479, "IN"),
+    (480, 499, "MI"),
+    (500, 528, "IA"),
+    (530, 549, "WI"),
+    (550, 567, "MN"),
+    (569, 569, "DC"),
+    (570, 577, "SD"),
+    (580, 588, "ND"),
+    (590, 599, "MT"),
+    (600, 629, "IL"),
+    (630, 658, "MO"),
+    (660, 679, "KS"),
+    (680, 693, "NE"),
+    (700, 714, "LA"),
+    (716, 729, "AR"),
+    (733, 733, "TX"),
+    (730, 749, "OK"),
+    (750, 799, "TX"),
+    (800, 816, "CO"),
+    (820, 831, "WY"),
+    (832, 838, "ID"),
+    (840, 847, "UT"),
+    (850, 865, "AZ"),
+    (870, 884, "NM"),
+    (885, 885, "TX"),
+    (889, 898, "NV"),
+    (900, 961, "CA"),
+    (962, 966, "AP"),
+    (967, 968, "HI"),
+    (969, 969, "GU"),
+    (970, 979, "OR"),
+    (980, 994, "WA"),
+    (995, 999, "AK"),
+];
+
+/// The two-letter postal code of the state a US ZIP code belongs to, judged
+/// by its first three digits. `None` for malformed or unassigned codes.
+pub fn for_zip(zip: &str) -> Option<&'static str> {
+    let digits = zip.trim().get(..3)?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let prefix: u16 = digits.parse().ok()?;
+    ZIP3_RANGES
+        .iter()
+        .find(|(start, end, _)| (*start..=*end).contains(&prefix))
+        .map(|(_, _, state)| *state)
+}
+
+/// The state, or for a Canadian order the province, a postal code of
+/// `country` belongs to; for an order shipped to the EU, the member state
+/// itself.
+pub fn for_address(country: &str, postal_code: &str) -> Option<&'static str> {
+    match country {
+        crate::order::UNITED_STATES => for_zip(postal_code),
+        crate::order::CANADA => crate::canada::province_for(postal_code),
+        _ => crate::vat::member_state(country),
+    }
+}
@@ -0,0 +1,46 @@
+//! The order total computation of the `order_total` service, without its
+//! HTTP server: the `Order` model, the tax and rounding rules, and the
+//! `ComputeError`s they fail with.
+//!
+//! A `Calculator` takes any `TaxRateProvider`, so the computation can be
+//! embedded with an in-memory `RateTable`, a `FixedRateProvider`, or a
+//! provider of one's own.
+
+pub mod address;
+pub mod calculator;
+pub mod canada;
+pub mod catalog;
+pub mod category;
+pub mod currency;
+pub mod discount;
+pub mod error;
+pub mod exemption;
+pub mod holiday;
+pub mod json_error;
+pub mod jurisdiction;
+pub mod order;
+pub mod postal;
+pub mod rate_table;
+pub mod refund;
+pub mod rounding;
+pub mod tax_rate;
+pub mod vat;
+
+pub use address::AddressParts;
+pub use calculator::Calculator;
+pub use canada::{CanadianTax, CanadianTaxes};
+pub use catalog::{CatalogTable, PriceMismatch, ProductCatalog};
+pub use category::{CategoryAdjustments, TaxCategory};
+pub use currency::{Conversion, FxRateProvider, FxRateTable};
+pub use discount::{Discount, DiscountKind, DiscountStage};
+pub use error::{ComputeError, ErrorCode, ErrorResponse, FieldError, Quota};
+pub use exemption::{AcceptWellFormed, ExemptionCertificate, ExemptionVerifier};
+pub use holiday::{HolidayCalendar, TaxHoliday};
+pub use json_error::JsonError;
+pub use models::{JurisdictionLevel, RateComponent};
+pub use order::{LineItem, Order, RateSource, TaxComponent};
+pub use rate_table::RateTable;
+pub use refund::{CreditLine, CreditMemo, ReturnedItem};
+pub use rounding::{RoundingMode, RoundingOverride, RoundingScope, RoundingStrategy};
+pub use tax_rate::{FallbackProvider, FixedRateProvider, TaxRate, TaxRateProvider};
+pub use vat::{VatRate, VatRates, VatSummary};
@@ -0,0 +1,491 @@
+use crate::address::AddressParts;
+use crate::catalog::PriceMismatch;
+use crate::category::TaxCategory;
+use crate::currency::{self, Conversion};
+use crate::discount::{self, Discount, DiscountKind, DiscountStage};
+use crate::error::FieldError;
+use crate::exemption::ExemptionCertificate;
+use crate::jurisdiction;
+use crate::postal;
+use crate::rounding::{RoundingOverride, RoundingScope, RoundingStrategy};
+use crate::vat::{self, VatSummary};
+use chrono::NaiveDate;
+use models::{JurisdictionLevel, RateComponent};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Order {
+    pub order_id: i32,
+    #[serde(default)]
+    pub product_id: i32,
+    #[serde(default)]
+    pub quantity: i32,
+    #[serde(default)]
+    pub subtotal: Decimal,
+    /// Written in a normalized form by the computation, so that orders
+    /// shipped to one address carry the same `shipping_address`.
+    pub shipping_address: String,
+    /// The street, unit, city, region and postal code of the
+    /// `shipping_address`, when they can be told apart; filled in by the
+    /// computation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shipping_address_parts: Option<AddressParts>,
+    /// The postal code shipped to: a ZIP (or ZIP+4) code, a Canadian postal
+    /// code such as `K1A 0B1`, or one of the EU country shipped to. The
+    /// computation writes it in the country's canonical form.
+    pub shipping_zip: String,
+    /// The ISO 3166 code of the country shipped to: `US` (the default),
+    /// `CA`, which computes the GST, HST and provincial sales taxes instead
+    /// of looking a sales tax rate up, or an EU member state, e.g. `DE`,
+    /// which charges its VAT.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shipping_country: Option<String>,
+    #[serde(default)]
+    pub total: Decimal,
+    /// The ISO 4217 code of the currency the prices are in, e.g. `EUR`;
+    /// `USD` when left out. Money is rounded to its minor unit, so a `JPY`
+    /// order is computed in whole yen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Also reports the totals in this currency, converted at the exchange
+    /// rate the configured provider gives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convert_to: Option<String>,
+    /// The totals in `convert_to`; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub converted: Option<Conversion>,
+    /// The day the order was placed, e.g. `2025-08-08`, which decides the
+    /// tax holidays it falls in; today when left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = Date)]
+    pub order_date: Option<NaiveDate>,
+    /// The tax holiday that made an order without line items tax free, if
+    /// any; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_holiday: Option<String>,
+    /// The sales tax rate that was applied; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_rate: Option<Decimal>,
+    /// Where `tax_rate` came from; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_source: Option<RateSource>,
+    /// The sales tax included in `total`; filled in by the computation.
+    #[serde(default)]
+    pub tax_amount: Decimal,
+    /// The state the shipping zip code belongs to, e.g. `TX`, the province
+    /// of a Canadian order, e.g. `QC`, or the member state of an order
+    /// shipped to the EU, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jurisdiction: Option<String>,
+    /// Overrides the configured rounding for this order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<RoundingOverride>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub line_items: Vec<LineItem>,
+    /// The prices the order was sent with that the product catalog, when
+    /// one is configured, replaced; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub price_mismatches: Vec<PriceMismatch>,
+    /// Applied in the order `Discount` describes, each reporting its
+    /// `amount`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub discounts: Vec<Discount>,
+    /// What the discounts took off in all; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub discount_amount: Decimal,
+    /// What `tax_amount` is made of, by jurisdiction level, when the rate's
+    /// components are known; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tax_components: Vec<TaxComponent>,
+    /// The buyer's VAT number, e.g. `DE123456789`, for a business purchase
+    /// shipped to the EU.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vat_id: Option<String>,
+    /// The VAT of an order shipped to the EU; filled in by the computation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vat: Option<VatSummary>,
+    /// Makes the order tax free, once verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exemption_certificate: Option<ExemptionCertificate>,
+    /// Computes the order as a simulation, e.g. a cart preview: it is not
+    /// stored, audited or sent to a callback. Echoed in the response.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+/// How the applied rate was obtained.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RateSource {
+    /// Looked up for the shipping zip code.
+    Lookup,
+    /// No rate is known for the zip code; `DEFAULT_TAX_RATE` was used.
+    Default,
+    /// The purchaser is exempt; no rate was looked up and no tax is due.
+    Exempt,
+    /// A B2B sale within the EU; the buyer accounts for the VAT.
+    ReverseCharge,
+}
+
+/// One product in a multi-item order. `subtotal`, `discount`, `tax_rate`,
+/// `tax_holiday`, `tax` and `total` are filled in by the computation and
+/// ignored on input.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct LineItem {
+    pub product_id: i32,
+    pub quantity: i32,
+    pub unit_price: Decimal,
+    #[serde(default)]
+    pub tax_category: TaxCategory,
+    #[serde(default)]
+    pub subtotal: Decimal,
+    /// The line's share of the order's pre-tax discounts.
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub discount: Decimal,
+    /// The rate the line was taxed at, when its `tax_category` made it
+    /// differ from the order's `tax_rate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_rate: Option<Decimal>,
+    /// The tax holiday that made the line tax free, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_holiday: Option<String>,
+    #[serde(default)]
+    pub tax: Decimal,
+    #[serde(default)]
+    pub total: Decimal,
+}
+
+/// The country shipped to when an order does not say.
+pub const UNITED_STATES: &str = "US";
+pub const CANADA: &str = "CA";
+
+/// The largest price, subtotal or fixed discount an order may name, one
+/// trillion. With `MAX_QUANTITY` it keeps the totals computed from them far
+/// from what a `Decimal` holds, whose arithmetic panics beyond it.
+pub const MAX_AMOUNT: Decimal = Decimal::from_parts(0xD4A5_1000, 0xE8, 0, false, 0);
+/// The most units of a product an order or line item may name.
+pub const MAX_QUANTITY: i32 = 1_000_000;
+
+/// The tax one jurisdiction levies on the order.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TaxComponent {
+    /// `state`, `county`, `city` or `special` (a special purpose district)
+    /// in the US; `federal` or `province` in Canada.
+    #[schema(value_type = String)]
+    pub level: JurisdictionLevel,
+    /// The tax's own name, e.g. `GST`, where it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// A compound tax's rate applies to the price plus the taxes before
+    /// it.
+    pub rate: Decimal,
+    pub amount: Decimal,
+}
+
+impl TaxComponent {
+    /// The component of a looked-up rate, before its amount is known.
+    pub fn of(component: &RateComponent) -> Self {
+        Self {
+            level: component.level,
+            name: None,
+            rate: component.rate,
+            amount: Decimal::ZERO,
+        }
+    }
+}
+
+impl Order {
+    /// The ISO 3166 code of the country shipped to, upper case.
+    pub fn country(&self) -> String {
+        self.shipping_country
+            .as_deref()
+            .map(str::trim)
+            .filter(|country| !country.is_empty())
+            .unwrap_or(UNITED_STATES)
+            .to_ascii_uppercase()
+    }
+
+    /// The ISO 4217 code of the currency of the prices, upper case.
+    pub fn currency(&self) -> String {
+        self.currency
+            .as_deref()
+            .map(str::trim)
+            .filter(|currency| !currency.is_empty())
+            .map_or_else(|| currency::USD.to_owned(), currency::normalize)
+    }
+
+    /// Checks the fields a total depends on, reporting every problem found
+    /// rather than only the first one.
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        let country = self.country();
+        let member_state = vat::member_state(&country);
+        let ships_to = country == UNITED_STATES || country == CANADA || member_state.is_some();
+        if self.shipping_zip.trim().is_empty() {
+            errors.push(FieldError::new("shipping_zip", "must not be empty"));
+        } else if ships_to && postal::normalize(&country, &self.shipping_zip).is_none() {
+            let example = postal::example(&country).unwrap_or_default();
+            errors.push(FieldError::new(
+                "shipping_zip",
+                format!("must be a postal code of {country}, e.g. {example}"),
+            ));
+        }
+        if !ships_to {
+            errors.push(FieldError::new(
+                "shipping_country",
+                "must be US, CA or an EU member state",
+            ));
+        }
+        if let Some(vat_id) = &self.vat_id {
+            match member_state {
+                Some(member_state)
+                    if !vat::is_vat_id_of(member_state, &vat::normalize_vat_id(vat_id)) =>
+                {
+                    errors.push(FieldError::new(
+                        "vat_id",
+                        format!("must be a VAT number of {member_state}"),
+                    ));
+                }
+                Some(_) => {}
+                None => errors.push(FieldError::new(
+                    "vat_id",
+                    "only applies to orders shipped to the EU",
+                )),
+            }
+        }
+        if self.shipping_address.trim().is_empty() {
+            errors.push(FieldError::new("shipping_address", "must not be empty"));
+        }
+        if currency::minor_units(&self.currency()).is_none() {
+            errors.push(FieldError::new(
+                "currency",
+                "must be an ISO 4217 currency code, e.g. EUR",
+            ));
+        }
+        if let Some(convert_to) = &self.convert_to {
+            if currency::minor_units(&currency::normalize(convert_to)).is_none() {
+                errors.push(FieldError::new(
+                    "convert_to",
+                    "must be an ISO 4217 currency code, e.g. EUR",
+                ));
+            }
+        }
+
+        if self.line_items.is_empty() {
+            if self.quantity <= 0 {
+                errors.push(FieldError::new("quantity", "must be greater than zero"));
+            } else if self.quantity > MAX_QUANTITY {
+                errors.push(too_large("quantity", MAX_QUANTITY));
+            }
+            if self.subtotal < Decimal::ZERO {
+                errors.push(FieldError::new("subtotal", "must not be negative"));
+            } else if self.subtotal > MAX_AMOUNT {
+                errors.push(too_large("subtotal", MAX_AMOUNT));
+            }
+        }
+        for (index, item) in self.line_items.iter().enumerate() {
+            if item.quantity <= 0 {
+                errors.push(FieldError::new(
+                    format!("line_items[{index}].quantity"),
+                    "must be greater than zero",
+                ));
+            } else if item.quantity > MAX_QUANTITY {
+                errors.push(too_large(
+                    format!("line_items[{index}].quantity"),
+                    MAX_QUANTITY,
+                ));
+            }
+            if item.unit_price < Decimal::ZERO {
+                errors.push(FieldError::new(
+                    format!("line_items[{index}].unit_price"),
+                    "must not be negative",
+                ));
+            } else if item.unit_price > MAX_AMOUNT {
+                errors.push(too_large(
+                    format!("line_items[{index}].unit_price"),
+                    MAX_AMOUNT,
+                ));
+            }
+        }
+        if let Some(certificate) = &self.exemption_certificate {
+            let state = jurisdiction::for_address(&country, &self.shipping_zip);
+            certificate.validate(state, &mut errors);
+        }
+        for (index, discount) in self.discounts.iter().enumerate() {
+            if discount.value < Decimal::ZERO {
+                errors.push(FieldError::new(
+                    format!("discounts[{index}].value"),
+                    "must not be negative",
+                ));
+            } else if discount.value > MAX_AMOUNT {
+                errors.push(too_large(format!("discounts[{index}].value"), MAX_AMOUNT));
+            } else if discount.kind == DiscountKind::Percentage
+                && discount.value > Decimal::ONE_HUNDRED
+            {
+                errors.push(FieldError::new(
+                    format!("discounts[{index}].value"),
+                    "must be at most 100 percent",
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Computes the order total for the given sales tax rate, rounding money
+    /// as `rounding` says.
+    ///
+    /// Orders with line items are taxed line by line and the order
+    /// `subtotal` becomes the sum of the line subtotals. Orders without line
+    /// items keep the single-product behavior and tax the order `subtotal`.
+    /// With `RoundingScope::PerOrder`, the tax of a multi-item order is
+    /// rounded once for the whole order rather than line by line.
+    ///
+    /// Pre-tax discounts lower the amount taxed; with line items, they are
+    /// shared between the lines in proportion to their subtotals. Post-tax
+    /// discounts come off the taxed total. Either way `subtotal` stays the
+    /// amount before discounts, `tax_rate` records the rate and `tax_amount`
+    /// the tax included in the total.
+    pub fn apply_tax_rate(&mut self, rate: Decimal, rounding: RoundingStrategy) {
+        self.apply_tax_rates(rate, |_| rate, rounding);
+    }
+
+    /// Like `apply_tax_rate`, but taxes each line item at `line_rate` of its
+    /// `tax_category`, e.g. less for groceries where they are taxed less,
+    /// and lines marked with a `tax_holiday` not at all. `rate` is the
+    /// order's own rate, applied to orders without line items, unless the
+    /// order is marked with a `tax_holiday` itself.
+    pub fn apply_tax_rates(
+        &mut self,
+        rate: Decimal,
+        line_rate: impl Fn(TaxCategory) -> Decimal,
+        rounding: RoundingStrategy,
+    ) {
+        self.tax_rate = Some(rate);
+        if self.line_items.is_empty() {
+            let base = discount::apply(
+                &mut self.discounts,
+                DiscountStage::PreTax,
+                self.subtotal,
+                rounding,
+            );
+            let rate = match self.tax_holiday {
+                Some(_) => Decimal::ZERO,
+                None => rate,
+            };
+            let taxed = rounding.round(base * (Decimal::ONE + rate));
+            self.tax_amount = taxed - base;
+            self.finish_discounts(taxed, rounding);
+            return;
+        }
+
+        for item in &mut self.line_items {
+            item.subtotal = rounding.round(item.unit_price * Decimal::from(item.quantity));
+        }
+        self.subtotal = self.line_items.iter().map(|item| item.subtotal).sum();
+        let base = discount::apply(
+            &mut self.discounts,
+            DiscountStage::PreTax,
+            self.subtotal,
+            rounding,
+        );
+        self.share_discount(self.subtotal - base, rounding);
+        let mut unrounded = Decimal::ZERO;
+        for item in &mut self.line_items {
+            let item_rate = match item.tax_holiday {
+                Some(_) => Decimal::ZERO,
+                None => line_rate(item.tax_category),
+            };
+            item.tax_rate = (item_rate != rate).then_some(item_rate);
+            let tax = (item.subtotal - item.discount) * item_rate;
+            unrounded += tax;
+            item.tax = rounding.round(tax);
+            item.total = item.subtotal - item.discount + item.tax;
+        }
+        self.tax_amount = match rounding.scope {
+            RoundingScope::PerLine => self.line_items.iter().map(|item| item.tax).sum(),
+            RoundingScope::PerOrder => rounding.round(unrounded),
+        };
+        self.finish_discounts(base + self.tax_amount, rounding);
+    }
+
+    /// Splits a pre-tax discount between the line items by subtotal, the
+    /// last line taking what rounding left over.
+    fn share_discount(&mut self, discount: Decimal, rounding: RoundingStrategy) {
+        let subtotal = self.subtotal;
+        let mut left = discount;
+        let last = self.line_items.len() - 1;
+        for (index, item) in self.line_items.iter_mut().enumerate() {
+            item.discount = if index == last || left.is_zero() {
+                left
+            } else {
+                rounding
+                    .round(discount * item.subtotal / subtotal)
+                    .min(left)
+            };
+            left -= item.discount;
+        }
+    }
+
+    /// Breaks `tax_amount` down into `taxes`, each getting the amount in
+    /// proportion to its share of the rate; the last takes what rounding
+    /// left over.
+    pub fn itemize(&mut self, taxes: Vec<(TaxComponent, Decimal)>, rounding: RoundingStrategy) {
+        let combined: Decimal = taxes.iter().map(|(_, share)| share).sum();
+        self.tax_components.clear();
+        if combined.is_zero() {
+            return;
+        }
+        let last = taxes.len() - 1;
+        let mut left = self.tax_amount;
+        for (index, (mut tax, share)) in taxes.into_iter().enumerate() {
+            tax.amount = if index == last {
+                left
+            } else {
+                rounding.round(self.tax_amount * share / combined)
+            };
+            left -= tax.amount;
+            self.tax_components.push(tax);
+        }
+    }
+
+    /// Takes the post-tax discounts off `taxed` for the `total`.
+    fn finish_discounts(&mut self, taxed: Decimal, rounding: RoundingStrategy) {
+        self.total = discount::apply(&mut self.discounts, DiscountStage::PostTax, taxed, rounding);
+        self.discount_amount = self.discounts.iter().map(|discount| discount.amount).sum();
+    }
+}
+
+fn too_large(field: impl Into<String>, max: impl std::fmt::Display) -> FieldError {
+    FieldError::new(field, format!("must be at most {max}"))
+}
+
+/*
+impl Order {
+    fn new(
+        order_id: i32,
+        product_id: i32,
+        quantity: i32,
+        subtotal: Decimal,
+        shipping_address: String,
+        shipping_zip: String,
+        total: Decimal,
+    ) -> Self {
+        Self {
+            order_id,
+            product_id,
+            quantity,
+            subtotal,
+            shipping_address,
+            shipping_zip,
+            total,
+        }
+    }
+}
+*/
@@ -0,0 +1,136 @@
+use crate::canada;
+
+/// How the postal codes of one country are written: in one of `patterns`,
+/// where `9` is a digit, `A` a letter and `X` either, and spaces and dashes
+/// stand for themselves.
+struct Format {
+    country: &'static str,
+    patterns: &'static [&'static str],
+    /// Written before the code by some senders, e.g. `LV-1050`; dropped.
+    prefix: &'static str,
+    example: &'static str,
+}
+
+const fn format(
+    country: &'static str,
+    patterns: &'static [&'static str],
+    example: &'static str,
+) -> Format {
+    Format {
+        country,
+        patterns,
+        prefix: "",
+        example,
+    }
+}
+
+/// The postal code formats of the countries an order can ship to, see
+/// `Order::validate`: the United States, Canada and the EU member states.
+/// Canadian codes are checked further by `canada::province_for`. The
+/// United Kingdom is left out with the rest of the world: there is no VAT
+/// table for it to be computed with.
+const FORMATS: &[Format] = &[
+    format("US", &["99999", "99999-9999"], "78701 or 78701-1234"),
+    format("CA", &["A9A 9A9"], "K1A 0B1"),
+    format("AT", &["9999"], "1010"),
+    format("BE", &["9999"], "1000"),
+    format("BG", &["9999"], "1000"),
+    format("CY", &["9999"], "1010"),
+    format("CZ", &["999 99"], "110 00"),
+    format("DE", &["99999"], "10115"),
+    format("DK", &["9999"], "1050"),
+    format("EE", &["99999"], "10111"),
+    format("ES", &["99999"], "28001"),
+    format("FI", &["99999"], "00100"),
+    format("FR", &["99999"], "75001"),
+    format("GR", &["999 99"], "105 57"),
+    format("HR", &["99999"], "10000"),
+    format("HU", &["9999"], "1011"),
+    format("IE", &["A9X XXXX"], "D02 X285"),
+    format("IT", &["99999"], "00118"),
+    Format {
+        prefix: "LT",
+        ..format("LT", &["99999"], "01100")
+    },
+    Format {
+        prefix: "L",
+        ..format("LU", &["9999"], "1009")
+    },
+    Format {
+        prefix: "LV",
+        ..format("LV", &["9999"], "1050")
+    },
+    format("MT", &["AAA 9999", "AA 9999"], "VLT 1117"),
+    format("NL", &["9999 AA"], "1012 AB"),
+    format("PL", &["99-999"], "00-001"),
+    format("PT", &["9999-999"], "1000-001"),
+    format("RO", &["999999"], "010011"),
+    format("SE", &["999 99"], "111 20"),
+    format("SI", &["9999"], "1000"),
+    format("SK", &["999 99"], "811 01"),
+];
+
+/// A postal code of `country` (an ISO 3166 code in upper case) in its
+/// canonical form: in upper case and with the usual spacing, e.g.
+/// `78701-1234`, `K1A 0B1` or `1012 AB`, however it was spaced or dashed.
+/// `None` for a code that is not written like one of the country's; codes
+/// of countries with no known format are only trimmed.
+pub fn normalize(country: &str, code: &str) -> Option<String> {
+    let Some(format) = FORMATS.iter().find(|format| format.country == country) else {
+        return Some(code.trim().to_owned());
+    };
+    let compact: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let unprefixed = compact
+        .strip_prefix(format.prefix)
+        .filter(|_| !format.prefix.is_empty());
+    let canonical = format.patterns.iter().find_map(|pattern| {
+        unprefixed
+            .and_then(|code| write_as(pattern, code))
+            .or_else(|| write_as(pattern, &compact))
+    })?;
+    if country == "CA" && canada::province_for(&canonical).is_none() {
+        return None;
+    }
+    Some(canonical)
+}
+
+/// The five-digit ZIP code of a canonical US postal code, which rates are
+/// looked up by.
+pub fn zip5(code: &str) -> &str {
+    code.split('-').next().unwrap_or(code)
+}
+
+/// An example of a postal code of `country`, for error messages.
+pub fn example(country: &str) -> Option<&'static str> {
+    FORMATS
+        .iter()
+        .find(|format| format.country == country)
+        .map(|format| format.example)
+}
+
+/// `code`, without separators, written as `pattern` if it fits it.
+fn write_as(pattern: &str, code: &str) -> Option<String> {
+    let mut chars = code.chars();
+    let mut written = String::with_capacity(pattern.len());
+    for expected in pattern.chars() {
+        if expected == ' ' || expected == '-' {
+            written.push(expected);
+            continue;
+        }
+        let c = chars.next()?;
+        let fits = match expected {
+            '9' => c.is_ascii_digit(),
+            'A' => c.is_ascii_uppercase(),
+            _ => c.is_ascii_alphanumeric(),
+        };
+        if !fits {
+            return None;
+        }
+        written.push(c);
+    }
+    chars.next().is_none().then_some(written)
+}
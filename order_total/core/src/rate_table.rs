@@ -0,0 +1,63 @@
+use crate::tax_rate::TaxRate;
+use anyhow::{anyhow, Context};
+use chrono::NaiveDate;
+use models::RateRow;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// A zip code to sales tax rate table held in memory, in the same
+/// `zip,rate[,state,county,city,special[,effective_from]]` CSV format the
+/// sales tax rate service uses, with every rate a zip code has had.
+#[derive(Debug, Default)]
+pub struct RateTable {
+    rates: HashMap<String, Vec<RateRow>>,
+}
+
+impl RateTable {
+    /// Loads the table from the CSV file named by `TAX_RATE_TABLE`, or from
+    /// the sales tax rate service's own table (`models::RATES_BY_ZIPCODE_CSV`)
+    /// compiled into the binary.
+    pub fn load() -> anyhow::Result<Self> {
+        match std::env::var("TAX_RATE_TABLE") {
+            Ok(path) => {
+                let file = std::fs::File::open(&path)
+                    .with_context(|| format!("cannot open tax rate table {path:?}"))?;
+                Self::from_csv(file).with_context(|| format!("invalid tax rate table {path:?}"))
+            }
+            Err(_) => Self::from_csv(models::RATES_BY_ZIPCODE_CSV),
+        }
+    }
+
+    pub fn from_csv(reader: impl Read) -> anyhow::Result<Self> {
+        let mut rates: HashMap<String, Vec<RateRow>> = HashMap::new();
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+        for (index, record) in reader.records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let zip = record
+                .get(0)
+                .ok_or_else(|| anyhow!("line {line}: missing zip"))?;
+            if record.get(1).is_none() {
+                anyhow::bail!("line {line}: missing rate");
+            }
+            let row = RateRow::from_csv_fields(record.iter().skip(1))
+                .map_err(|err| anyhow!("line {line}: {err}"))?;
+            rates.entry(zip.trim().to_owned()).or_default().push(row);
+        }
+        Ok(Self { rates })
+    }
+
+    /// The rate of `zip` in effect on `date`.
+    pub fn get(&self, zip: &str, date: NaiveDate) -> Option<TaxRate> {
+        let rows = self.rates.get(zip.trim())?;
+        RateRow::in_effect(rows, date).map(|row| row.rate.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.rates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+}
@@ -0,0 +1,188 @@
+use crate::discount::{self, DiscountStage};
+use crate::error::{ComputeError, FieldError};
+use crate::order::Order;
+use crate::rounding::{RoundingScope, RoundingStrategy};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Some of a product the customer gave back.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ReturnedItem {
+    pub product_id: i32,
+    pub quantity: i32,
+}
+
+/// What to credit the customer for a return: the returned lines with the
+/// tax to reverse on each, at the rate the order was computed with.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreditMemo {
+    pub order_id: i32,
+    pub tax_rate: Decimal,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jurisdiction: Option<String>,
+    pub lines: Vec<CreditLine>,
+    pub subtotal: Decimal,
+    /// The sales tax to reverse.
+    pub tax_amount: Decimal,
+    /// The order's post-tax discounts, in proportion to what was returned.
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub discount_amount: Decimal,
+    /// The whole amount to credit, tax included.
+    pub total: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreditLine {
+    pub product_id: i32,
+    pub quantity: i32,
+    pub subtotal: Decimal,
+    pub tax: Decimal,
+    pub total: Decimal,
+}
+
+/// One line as it was charged, after pre-tax discounts: an order without
+/// line items is one line of its product.
+struct Charged {
+    product_id: i32,
+    quantity: i32,
+    subtotal: Decimal,
+    rate: Decimal,
+    tax: Decimal,
+}
+
+impl Order {
+    /// The credit memo for returning `returned` from this computed order,
+    /// rounding as `rounding` says (the strategy the order was computed
+    /// with).
+    ///
+    /// A line returned in full is credited what was charged for it; part of
+    /// a line is credited its share of the line subtotal after pre-tax
+    /// discounts, taxed at the rate the line was taxed at. Returning the whole order
+    /// reverses exactly its `tax_amount` and credits its `total`.
+    pub fn refund(
+        &self,
+        returned: &[ReturnedItem],
+        rounding: RoundingStrategy,
+    ) -> Result<CreditMemo, ComputeError> {
+        let Some(rate) = self.tax_rate else {
+            return Err(ComputeError::Validation(vec![FieldError::new(
+                "tax_rate",
+                "must be the rate the order was computed with",
+            )]));
+        };
+        let charged = self.charged(rate);
+        let mut quantities = vec![0; charged.len()];
+        let mut errors = Vec::new();
+        if returned.is_empty() {
+            errors.push(FieldError::new("returned_items", "must not be empty"));
+        }
+        for (index, item) in returned.iter().enumerate() {
+            let Some(line) = charged
+                .iter()
+                .position(|line| line.product_id == item.product_id)
+            else {
+                errors.push(FieldError::new(
+                    format!("returned_items[{index}].product_id"),
+                    "is not in the order",
+                ));
+                continue;
+            };
+            if item.quantity <= 0 {
+                errors.push(FieldError::new(
+                    format!("returned_items[{index}].quantity"),
+                    "must be greater than zero",
+                ));
+                continue;
+            }
+            quantities[line] += item.quantity;
+            if quantities[line] > charged[line].quantity {
+                errors.push(FieldError::new(
+                    format!("returned_items[{index}].quantity"),
+                    "is more than was ordered",
+                ));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ComputeError::Validation(errors));
+        }
+
+        let mut unrounded = Decimal::ZERO;
+        let lines: Vec<CreditLine> = charged
+            .iter()
+            .zip(&quantities)
+            .filter(|(_, quantity)| **quantity > 0)
+            .map(|(line, &quantity)| {
+                let (subtotal, tax) = if quantity == line.quantity {
+                    (line.subtotal, line.tax)
+                } else {
+                    let share = Decimal::from(quantity) / Decimal::from(line.quantity);
+                    let subtotal = rounding.round(line.subtotal * share);
+                    (subtotal, rounding.round(subtotal * line.rate))
+                };
+                unrounded += subtotal * line.rate;
+                CreditLine {
+                    product_id: line.product_id,
+                    quantity,
+                    subtotal,
+                    tax,
+                    total: subtotal + tax,
+                }
+            })
+            .collect();
+
+        let subtotal: Decimal = lines.iter().map(|line| line.subtotal).sum();
+        let everything = charged
+            .iter()
+            .zip(&quantities)
+            .all(|(line, quantity)| line.quantity == *quantity);
+        let post_tax = discount::total(&self.discounts, DiscountStage::PostTax);
+        let (tax_amount, discount_amount) = if everything {
+            (self.tax_amount, post_tax)
+        } else {
+            let tax_amount = match rounding.scope {
+                RoundingScope::PerLine => lines.iter().map(|line| line.tax).sum(),
+                RoundingScope::PerOrder => rounding.round(unrounded),
+            };
+            let charged: Decimal = charged.iter().map(|line| line.subtotal).sum();
+            let share = if charged.is_zero() {
+                Decimal::ZERO
+            } else {
+                rounding.round(post_tax * subtotal / charged)
+            };
+            (tax_amount, share)
+        };
+        Ok(CreditMemo {
+            order_id: self.order_id,
+            tax_rate: rate,
+            jurisdiction: self.jurisdiction.clone(),
+            lines,
+            subtotal,
+            tax_amount,
+            discount_amount,
+            total: subtotal + tax_amount - discount_amount,
+        })
+    }
+
+    fn charged(&self, rate: Decimal) -> Vec<Charged> {
+        if self.line_items.is_empty() {
+            return vec![Charged {
+                product_id: self.product_id,
+                quantity: self.quantity,
+                subtotal: self.subtotal - discount::total(&self.discounts, DiscountStage::PreTax),
+                rate,
+                tax: self.tax_amount,
+            }];
+        }
+        self.line_items
+            .iter()
+            .map(|item| Charged {
+                product_id: item.product_id,
+                quantity: item.quantity,
+                subtotal: item.subtotal - item.discount,
+                rate: item.tax_rate.unwrap_or(rate),
+                tax: item.tax,
+            })
+            .collect()
+    }
+}
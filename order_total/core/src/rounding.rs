@@ -0,0 +1,131 @@
+use crate::currency;
+use anyhow::anyhow;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// How a half cent (or any fraction of one) is resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// Halves away from zero, the usual commercial rounding (the default).
+    HalfUp,
+    /// Halves to the even cent ("banker's rounding").
+    HalfEven,
+    /// Any fraction up to the next cent.
+    Up,
+    /// Any fraction dropped.
+    Down,
+}
+
+/// Where tax is rounded in an order with line items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingScope {
+    /// Each line's tax is rounded and the rounded amounts are added up (the
+    /// default).
+    PerLine,
+    /// The tax on the order subtotal is rounded once. Line taxes are still
+    /// shown rounded, but may not add up to the order's `tax_amount`.
+    PerOrder,
+}
+
+/// The rounding applied to money in the total computation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundingStrategy {
+    pub mode: RoundingMode,
+    pub scope: RoundingScope,
+    /// The decimal digits money is rounded to: 2 for cents, unless the
+    /// currency of the order says otherwise.
+    pub minor_units: u32,
+}
+
+/// A per-request choice of rounding; parts left out use the configured
+/// default.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+pub struct RoundingOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<RoundingMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<RoundingScope>,
+}
+
+impl RoundingStrategy {
+    /// Reads the default strategy from `ROUNDING_MODE` (`half_up`,
+    /// `half_even`, `up` or `down`; default `half_up`) and `ROUNDING_SCOPE`
+    /// (`per_line` or `per_order`; default `per_line`).
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match std::env::var("ROUNDING_MODE") {
+            Ok(mode) => mode.parse()?,
+            Err(_) => RoundingMode::HalfUp,
+        };
+        let scope = match std::env::var("ROUNDING_SCOPE") {
+            Ok(scope) => scope.parse()?,
+            Err(_) => RoundingScope::PerLine,
+        };
+        Ok(Self {
+            mode,
+            scope,
+            minor_units: 2,
+        })
+    }
+
+    pub fn with_override(self, choice: Option<RoundingOverride>) -> Self {
+        let choice = choice.unwrap_or_default();
+        Self {
+            mode: choice.mode.unwrap_or(self.mode),
+            scope: choice.scope.unwrap_or(self.scope),
+            ..self
+        }
+    }
+
+    /// Rounds to the minor unit of `currency`, e.g. to whole yen for `JPY`.
+    pub fn in_currency(self, currency: &str) -> Self {
+        Self {
+            minor_units: currency::minor_units(currency).unwrap_or(2),
+            ..self
+        }
+    }
+
+    /// Rounds `amount` to the minor unit, cents by default.
+    pub fn round(self, amount: Decimal) -> Decimal {
+        let strategy = match self.mode {
+            RoundingMode::HalfUp => rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::HalfEven => rust_decimal::RoundingStrategy::MidpointNearestEven,
+            RoundingMode::Up => rust_decimal::RoundingStrategy::AwayFromZero,
+            RoundingMode::Down => rust_decimal::RoundingStrategy::ToZero,
+        };
+        amount.round_dp_with_strategy(self.minor_units, strategy)
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "half_up" => Ok(Self::HalfUp),
+            "half_even" | "bankers" => Ok(Self::HalfEven),
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            other => Err(anyhow!(
+                "invalid ROUNDING_MODE {other:?}: expected half_up, half_even, up or down"
+            )),
+        }
+    }
+}
+
+impl FromStr for RoundingScope {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "per_line" => Ok(Self::PerLine),
+            "per_order" => Ok(Self::PerOrder),
+            other => Err(anyhow!(
+                "invalid ROUNDING_SCOPE {other:?}: expected per_line or per_order"
+            )),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::error::ComputeError;
+use crate::rate_table::RateTable;
+use async_trait::async_trait;
+use models::RateRequest;
+use rust_decimal::Decimal;
+
+/// The rate of a zip code, with the components it is made of where the
+/// provider knows them.
+pub type TaxRate = models::RateResponse;
+
+/// Looks up the sales tax rate that applies to a zip code, as in effect on
+/// the day the request asks for (today, unless it names one).
+///
+/// The computation only talks to this trait, so it can run against the HTTP
+/// service, an in-memory table, a fixed rate, or any provider a user brings
+/// along.
+#[async_trait]
+pub trait TaxRateProvider: Send + Sync {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError>;
+}
+
+#[async_trait]
+impl TaxRateProvider for RateTable {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        self.get(&request.zip, request.effective_date())
+            .ok_or(ComputeError::TaxRateNotAvailable)
+    }
+}
+
+/// Applies the same rate to every zip code, on every day.
+pub struct FixedRateProvider(pub Decimal);
+
+#[async_trait]
+impl TaxRateProvider for FixedRateProvider {
+    async fn find_rate(&self, _request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        Ok(self.0.into())
+    }
+}
+
+/// Answers from `fallback` whenever `primary` fails.
+pub struct FallbackProvider<P, F> {
+    pub primary: P,
+    pub fallback: F,
+}
+
+#[async_trait]
+impl<P: TaxRateProvider, F: TaxRateProvider> TaxRateProvider for FallbackProvider<P, F> {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        match self.primary.find_rate(request).await {
+            Ok(rate) => Ok(rate),
+            Err(err) => match self.fallback.find_rate(request).await {
+                Ok(rate) => {
+                    tracing::warn!("using the fallback tax rate after the primary lookup failed");
+                    Ok(rate)
+                }
+                Err(_) => Err(err),
+            },
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::category::TaxCategory;
+use anyhow::{anyhow, Context};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use utoipa::ToSchema;
+
+/// The standard and reduced VAT rates of each member state, as of 2025, in
+/// the format `VatRates::from_csv` reads.
+const DEFAULT_TABLE: &[u8] = include_bytes!("vat_rates.csv");
+
+/// The member states of the EU, whose orders are charged VAT, with the
+/// prefix of their VAT numbers and the formats of the rest: `9` stands for
+/// a digit, `A` for a letter and `X` for either.
+const MEMBER_STATES: [(&str, &str, &[&str]); 27] = [
+    ("AT", "AT", &["U99999999"]),
+    ("BE", "BE", &["9999999999"]),
+    ("BG", "BG", &["999999999", "9999999999"]),
+    ("CY", "CY", &["99999999A"]),
+    ("CZ", "CZ", &["99999999", "999999999", "9999999999"]),
+    ("DE", "DE", &["999999999"]),
+    ("DK", "DK", &["99999999"]),
+    ("EE", "EE", &["999999999"]),
+    ("ES", "ES", &["X9999999X"]),
+    ("FI", "FI", &["99999999"]),
+    ("FR", "FR", &["XX999999999"]),
+    ("GR", "EL", &["999999999"]),
+    ("HR", "HR", &["99999999999"]),
+    ("HU", "HU", &["99999999"]),
+    ("IE", "IE", &["9999999A", "9999999AA", "9X99999A"]),
+    ("IT", "IT", &["99999999999"]),
+    ("LT", "LT", &["999999999", "999999999999"]),
+    ("LU", "LU", &["99999999"]),
+    ("LV", "LV", &["99999999999"]),
+    ("MT", "MT", &["99999999"]),
+    ("NL", "NL", &["999999999B99"]),
+    ("PL", "PL", &["9999999999"]),
+    ("PT", "PT", &["999999999"]),
+    (
+        "RO",
+        "RO",
+        &[
+            "99",
+            "999",
+            "9999",
+            "99999",
+            "999999",
+            "9999999",
+            "99999999",
+            "999999999",
+            "9999999999",
+        ],
+    ),
+    ("SE", "SE", &["999999999999"]),
+    ("SI", "SI", &["99999999"]),
+    ("SK", "SK", &["9999999999"]),
+];
+
+/// The code of `country` as listed, when it is an EU member state.
+pub fn member_state(country: &str) -> Option<&'static str> {
+    MEMBER_STATES
+        .iter()
+        .find(|(code, _, _)| *code == country)
+        .map(|(code, _, _)| *code)
+}
+
+/// Strips the spaces, dots and dashes VAT numbers are often written with,
+/// e.g. `de 123.456.789` becomes `DE123456789`.
+pub fn normalize_vat_id(vat_id: &str) -> String {
+    vat_id
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '.' && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Whether the normalized `vat_id` is formatted as a VAT number of
+/// `country`, its prefix included. Only the format is checked, not that the
+/// number was issued.
+pub fn is_vat_id_of(country: &str, vat_id: &str) -> bool {
+    let Some((_, prefix, formats)) = MEMBER_STATES.iter().find(|(code, _, _)| *code == country)
+    else {
+        return false;
+    };
+    let Some(number) = vat_id.strip_prefix(prefix) else {
+        return false;
+    };
+    formats.iter().any(|format| {
+        format.len() == number.len()
+            && format.bytes().zip(number.bytes()).all(|(f, c)| match f {
+                b'9' => c.is_ascii_digit(),
+                b'A' => c.is_ascii_uppercase(),
+                b'X' => c.is_ascii_uppercase() || c.is_ascii_digit(),
+                literal => c == literal,
+            })
+    })
+}
+
+/// The VAT rates of one member state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VatRate {
+    pub standard: Decimal,
+    /// What `grocery` line items are charged.
+    pub reduced: Decimal,
+}
+
+impl VatRate {
+    /// The rate a line item of `category` is charged: the reduced rate for
+    /// groceries, nothing for `exempt` and the standard rate otherwise.
+    pub fn for_category(&self, category: TaxCategory) -> Decimal {
+        match category {
+            TaxCategory::Grocery => self.reduced,
+            TaxCategory::Exempt => Decimal::ZERO,
+            TaxCategory::Standard | TaxCategory::Clothing => self.standard,
+        }
+    }
+}
+
+/// The VAT rates of the member states, and where the seller is registered
+/// for VAT, for a B2B sale to be reverse charged.
+#[derive(Debug)]
+pub struct VatRates {
+    rates: HashMap<String, VatRate>,
+    /// A B2B sale to a business in another member state is reverse charged;
+    /// unset, every B2B sale is.
+    pub seller_country: Option<String>,
+}
+
+impl VatRates {
+    /// Loads the rates from the CSV file named by `VAT_RATE_TABLE`, or the
+    /// built-in ones, and the seller's country from `VAT_SELLER_COUNTRY`.
+    pub fn load() -> anyhow::Result<Self> {
+        let mut rates = match std::env::var("VAT_RATE_TABLE") {
+            Ok(path) => {
+                let file = std::fs::File::open(&path)
+                    .with_context(|| format!("cannot open VAT rate table {path:?}"))?;
+                Self::from_csv(file).with_context(|| format!("invalid VAT rate table {path:?}"))?
+            }
+            Err(_) => Self::from_csv(DEFAULT_TABLE)?,
+        };
+        if let Ok(country) = std::env::var("VAT_SELLER_COUNTRY") {
+            let country = country.trim().to_ascii_uppercase();
+            if member_state(&country).is_none() {
+                anyhow::bail!("VAT_SELLER_COUNTRY {country:?} is not an EU member state");
+            }
+            rates.seller_country = Some(country);
+        }
+        Ok(rates)
+    }
+
+    /// Reads `country,standard,reduced` rows, e.g. `DE,0.19,0.07`.
+    pub fn from_csv(reader: impl Read) -> anyhow::Result<Self> {
+        let mut rates = HashMap::new();
+        for (index, record) in csv::Reader::from_reader(reader).records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let field = |column: usize, name: &str| {
+                record
+                    .get(column)
+                    .map(str::trim)
+                    .ok_or_else(|| anyhow!("line {line}: missing {name}"))
+            };
+            let country = field(0, "country")?.to_ascii_uppercase();
+            if member_state(&country).is_none() {
+                anyhow::bail!("line {line}: {country:?} is not an EU member state");
+            }
+            let rate = |column: usize, name: &str| {
+                let rate = field(column, name)?
+                    .parse::<Decimal>()
+                    .map_err(|err| anyhow!("line {line}: invalid {name} rate: {err}"))?;
+                if rate < Decimal::ZERO {
+                    anyhow::bail!("line {line}: the {name} rate must not be negative");
+                }
+                Ok(rate)
+            };
+            let rate = VatRate {
+                standard: rate(1, "standard")?,
+                reduced: rate(2, "reduced")?,
+            };
+            rates.insert(country, rate);
+        }
+        Ok(Self {
+            rates,
+            seller_country: None,
+        })
+    }
+
+    pub fn get(&self, country: &str) -> Option<VatRate> {
+        self.rates.get(country).copied()
+    }
+
+    /// Whether a B2B sale to a business in `country` is reverse charged,
+    /// the buyer accounting for the VAT instead of paying it.
+    pub fn reverse_charges(&self, country: &str) -> bool {
+        self.seller_country.as_deref() != Some(country)
+    }
+}
+
+/// How the VAT of an order shipped to the EU came about; filled in by the
+/// computation.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct VatSummary {
+    /// The member state shipped to, whose VAT was charged.
+    pub country: String,
+    /// The buyer's VAT number, normalized, for a B2B sale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vat_id: Option<String>,
+    /// No VAT was charged: the buyer accounts for it in their own country.
+    pub reverse_charge: bool,
+    /// The order total without VAT.
+    pub net_amount: Decimal,
+    pub vat_amount: Decimal,
+    /// The order total with VAT, the same as `total`.
+    pub gross_amount: Decimal,
+}
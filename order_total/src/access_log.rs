@@ -0,0 +1,115 @@
+//! One log line per request answered, for following the traffic: its
+//! route, status, size and latency, with the share of the latency spent
+//! waiting on the sales tax rate service.
+
+use crate::body::{Body, HttpBody};
+use crate::config::env_or;
+use hyper::{Method, Response};
+use rand::Rng;
+use std::cell::Cell;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    static UPSTREAM: Cell<Duration>;
+}
+
+/// The latency buckets by their upper bound in milliseconds; each line
+/// names the first bucket its latency falls in, so that lines group by
+/// latency without a histogram.
+const BUCKETS: [(u64, &str); 10] = [
+    (5, "le_5ms"),
+    (10, "le_10ms"),
+    (25, "le_25ms"),
+    (50, "le_50ms"),
+    (100, "le_100ms"),
+    (250, "le_250ms"),
+    (500, "le_500ms"),
+    (1000, "le_1s"),
+    (2500, "le_2.5s"),
+    (5000, "le_5s"),
+];
+
+/// Writes the access log under the `access_log` target, so `RUST_LOG` can
+/// route or silence it apart from the other logs.
+///
+/// * `ACCESS_LOG` - log a line per request (default `true`)
+/// * `ACCESS_LOG_SAMPLE_RATE` - the share of requests logged, from 0 to 1, for high-volume deployments (default 1); `5xx` responses are always logged
+pub struct AccessLog {
+    sample_rate: f64,
+}
+
+/// What is known of a request before it is handled.
+pub struct Started {
+    method: Method,
+    at: Instant,
+}
+
+impl AccessLog {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let sample_rate = if env_or("ACCESS_LOG", true)? {
+            env_or("ACCESS_LOG_SAMPLE_RATE", 1.0f64)?.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        Ok(Self { sample_rate })
+    }
+
+    pub fn start(&self, method: &Method) -> Started {
+        Started {
+            method: method.clone(),
+            at: Instant::now(),
+        }
+    }
+
+    /// Logs the line of a request to `path` answered with `response`.
+    /// The size is that of the body when known up front; streamed bodies
+    /// have none.
+    pub fn record(&self, started: Started, path: &str, response: &Response<Body>) {
+        let status = response.status();
+        let sampled = self.sample_rate >= 1.0
+            || (self.sample_rate > 0.0 && rand::thread_rng().gen_bool(self.sample_rate));
+        if !sampled && !status.is_server_error() {
+            return;
+        }
+        let latency = started.at.elapsed();
+        let latency_ms = latency.as_millis() as u64;
+        let upstream_ms = UPSTREAM
+            .try_with(|upstream| upstream.get().as_millis() as u64)
+            .unwrap_or_default();
+        tracing::info!(
+            target: "access_log",
+            method = %started.method,
+            path,
+            route = %crate::api::resolve(path).template(),
+            status = status.as_u16(),
+            bytes = response.body().size_hint().exact(),
+            latency_ms,
+            latency_bucket = bucket(latency_ms),
+            upstream_ms,
+            request_id = crate::request_id::current(),
+            tenant = crate::tenant::current(),
+            "request"
+        );
+    }
+}
+
+/// The bucket `latency_ms` falls in, e.g. `le_25ms`.
+fn bucket(latency_ms: u64) -> &'static str {
+    BUCKETS
+        .iter()
+        .find(|(bound, _)| latency_ms <= *bound)
+        .map_or("gt_5s", |(_, name)| name)
+}
+
+/// Runs the handling of a request, adding up the time its lookups of the
+/// sales tax rate service take, see `upstream`.
+pub async fn scope<F: Future>(future: F) -> F::Output {
+    UPSTREAM.scope(Cell::new(Duration::ZERO), future).await
+}
+
+/// Counts `elapsed` waiting on the sales tax rate service towards the
+/// current request.
+pub fn add_upstream(elapsed: Duration) {
+    let _ = UPSTREAM.try_with(|upstream| upstream.set(upstream.get() + elapsed));
+}
@@ -0,0 +1,232 @@
+use crate::audit::{AuditFilter, AuditLog};
+use crate::body::Body;
+use crate::error::{self, ComputeError, FieldError};
+use crate::usage::UsageRange;
+use crate::{body, json_result, logging, App};
+use hyper::header::AUTHORIZATION;
+use hyper::{HeaderMap, Method, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Operator endpoints under `/admin`, outside the versioned API and its
+/// authentication. They exist only with `ADMIN_TOKEN` set, and require it
+/// as a bearer token:
+///
+/// * `GET /admin/cache/stats` - size, hit ratio and age of the rate cache
+/// * `DELETE /admin/cache/{zip}` - forget the cached rate of one zip code
+/// * `DELETE /admin/cache` - forget every cached rate
+/// * `GET /admin/shadow/stats` - how the shadow rate service's answers
+///   compared, see `shadow::Shadowed`
+/// * `GET /admin/audit` - the audit log entries that match the query, see
+///   `audit::AuditFilter`
+/// * `GET /admin/audit/verify` - whether the audit log's hash chain is intact
+/// * `POST /admin/reload` - read the computation settings anew, see
+///   `App::reload`; SIGHUP does the same
+/// * `GET /admin/usage` - the requests and orders of every tenant and client
+///   over the hours from `from` to `to` (RFC 3339, both optional), see
+///   `usage::Usage`
+/// * `GET /admin/log_level` - the log filter in force
+/// * `PUT /admin/log_level` - replace the log filter, e.g. with
+///   `{"filter": "order_total=debug,info", "revert_after_secs": 600}`, see
+///   `LogLevel`
+///
+/// The audit routes exist only with `AUDIT_LOG` set.
+///
+/// Invalidation reaches this replica's memory and the shared Redis; other
+/// replicas keep what they hold in memory until `RATE_CACHE_SECS` runs out.
+pub struct Admin {
+    token: String,
+}
+
+#[derive(Serialize)]
+struct Invalidated {
+    removed: usize,
+}
+
+#[derive(Serialize)]
+struct Reloaded {
+    reloaded: bool,
+}
+
+/// The body of `PUT /admin/log_level`: the new filter, in `RUST_LOG`
+/// syntax, and optionally how long it lasts before the one it replaced is
+/// back.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogLevel {
+    filter: String,
+    revert_after_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct LogFilter {
+    filter: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<String>,
+}
+
+impl Admin {
+    pub fn from_env() -> Option<Self> {
+        std::env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .map(|token| Self { token })
+    }
+
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), ComputeError> {
+        let presented = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if constant_time_eq(presented.trim().as_bytes(), self.token.as_bytes()) {
+            Ok(())
+        } else {
+            Err(ComputeError::Unauthorized)
+        }
+    }
+}
+
+/// Serves a request to `/admin/...`, or `404` for what is not an admin
+/// route.
+pub async fn handle(req: Request<Body>, app: &App, path: &str) -> Response<Body> {
+    let Some(admin) = &app.admin else {
+        return not_found();
+    };
+    let cache = app.rate_cache();
+    let zip = path
+        .strip_prefix("/admin/cache/")
+        .filter(|zip| !zip.is_empty());
+    let route = match (req.method(), path, zip) {
+        (&Method::GET, "/admin/cache/stats", _) => Route::Stats,
+        (&Method::DELETE, "/admin/cache", _) => Route::Clear,
+        (&Method::DELETE, _, Some(zip)) => Route::Invalidate(zip),
+        (&Method::GET, "/admin/shadow/stats", _) => Route::Shadow,
+        (&Method::POST, "/admin/reload", _) => Route::Reload,
+        (&Method::GET, "/admin/usage", _) => Route::Usage,
+        (&Method::GET, "/admin/log_level", _) => Route::LogLevel,
+        (&Method::PUT, "/admin/log_level", _) => Route::SetLogLevel,
+        (&Method::GET, "/admin/audit" | "/admin/audit/verify", _) => match &app.audit {
+            Some(audit) if path.ends_with("/verify") => Route::VerifyAudit(audit),
+            Some(audit) => Route::Audit(audit),
+            None => return not_found(),
+        },
+        _ => return not_found(),
+    };
+    if let Err(err) = admin.authorize(req.headers()) {
+        return error::response(err);
+    }
+    let result = match route {
+        Route::Stats => to_json(&cache.stats()),
+        Route::Clear => match cache.clear().await {
+            Ok(removed) => {
+                tracing::info!(removed, "rate cache cleared");
+                to_json(&Invalidated { removed })
+            }
+            Err(err) => Err(unexpected(err)),
+        },
+        Route::Invalidate(zip) => match cache.invalidate(zip).await {
+            Ok(removed) => {
+                tracing::info!(zip, "cached rate invalidated");
+                to_json(&Invalidated {
+                    removed: removed.into(),
+                })
+            }
+            Err(err) => Err(unexpected(err)),
+        },
+        Route::Shadow => to_json(&app.shadow.report()),
+        Route::Reload => match app.reload() {
+            Ok(()) => to_json(&Reloaded { reloaded: true }),
+            Err(err) => Err(ComputeError::Unexpected(
+                format!("could not reload the configuration, the old one stays in force: {err:#}")
+                    .into(),
+            )),
+        },
+        Route::Usage => serde_urlencoded::from_str::<UsageRange>(req.uri().query().unwrap_or(""))
+            .map_err(|_| ComputeError::InvalidRequest)
+            .and_then(|range| to_json(&app.usage.report(&range))),
+        Route::Audit(audit) => {
+            serde_urlencoded::from_str::<AuditFilter>(req.uri().query().unwrap_or(""))
+                .map_err(|_| ComputeError::InvalidRequest)
+                .and_then(|filter| audit.query(&filter).map_err(unreadable))
+                .and_then(|page| to_json(&page))
+        }
+        Route::VerifyAudit(audit) => audit
+            .verify()
+            .map_err(unreadable)
+            .and_then(|verification| to_json(&verification)),
+        Route::LogLevel => match logging::filter() {
+            Some(filter) => to_json(&LogFilter {
+                filter,
+                previous: None,
+            }),
+            None => Err(no_logging()),
+        },
+        Route::SetLogLevel => set_log_level(req, app).await,
+    };
+    json_result(result)
+}
+
+fn not_found() -> Response<Body> {
+    let mut response = Response::default();
+    *response.status_mut() = StatusCode::NOT_FOUND;
+    response
+}
+
+enum Route<'a> {
+    Stats,
+    Clear,
+    Invalidate(&'a str),
+    Shadow,
+    Reload,
+    Usage,
+    Audit(&'a AuditLog),
+    VerifyAudit(&'a AuditLog),
+    LogLevel,
+    SetLogLevel,
+}
+
+async fn set_log_level(req: Request<Body>, app: &App) -> Result<String, ComputeError> {
+    let bytes = body::to_bytes_limited(req, app.max_body_bytes).await?;
+    let level: LogLevel =
+        serde_json::from_slice(&bytes).map_err(|_| ComputeError::InvalidRequest)?;
+    if logging::filter().is_none() {
+        return Err(no_logging());
+    }
+    let revert_after = level.revert_after_secs.map(Duration::from_secs);
+    let previous = logging::set_filter(&level.filter, revert_after).map_err(|err| {
+        ComputeError::Validation(vec![FieldError::new(
+            "filter",
+            format!("is not a valid log filter: {err}"),
+        )])
+    })?;
+    to_json(&LogFilter {
+        filter: level.filter,
+        previous: Some(previous),
+    })
+}
+
+fn no_logging() -> ComputeError {
+    ComputeError::Unexpected("logging is not initialized in this process".into())
+}
+
+fn unreadable(err: anyhow::Error) -> ComputeError {
+    ComputeError::Unexpected(err.context("could not read the audit log").into())
+}
+
+/// Compares without returning early, so the time taken does not tell how
+/// much of the token a guess got right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn unexpected(err: anyhow::Error) -> ComputeError {
+    ComputeError::Unexpected(
+        err.context("could not invalidate the shared rate cache")
+            .into(),
+    )
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, ComputeError> {
+    crate::pretty::to_string(value).map_err(|err| ComputeError::Unexpected(Box::new(err)))
+}
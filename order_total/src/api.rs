@@ -0,0 +1,125 @@
+use crate::body::Body;
+use hyper::header::{HeaderValue, LINK};
+use hyper::Response;
+
+/// API versions served side by side. Each one gets a path prefix, and the
+/// original unprefixed paths remain as deprecated aliases of `V1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+}
+
+impl ApiVersion {
+    const ALL: [ApiVersion; 1] = [ApiVersion::V1];
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+        }
+    }
+}
+
+/// A request path split into the API version it addresses and the path
+/// within that version.
+#[derive(Debug, Clone, Copy)]
+pub struct Route<'a> {
+    pub version: ApiVersion,
+    pub path: &'a str,
+    /// Reached through an unversioned alias such as `/compute`.
+    pub deprecated: bool,
+}
+
+/// Paths that live outside the versioned API. GraphQL evolves its schema
+/// in place rather than by path.
+const UNVERSIONED: [&str; 7] = [
+    "/",
+    "/openapi.json",
+    "/docs",
+    "/docs/",
+    "/schema.graphql",
+    "/graphql",
+    VERSION,
+];
+/// Where the build information is served, see `version`.
+pub const VERSION: &str = "/version";
+/// Where the operator endpoints live, outside the versioned API.
+const ADMIN_PREFIX: &str = "/admin";
+/// The unversioned paths that document the API rather than being part of it.
+const DOCUMENTATION: [&str; 5] = ["/", "/openapi.json", "/docs", "/docs/", "/schema.graphql"];
+
+pub fn resolve(path: &str) -> Route<'_> {
+    for version in ApiVersion::ALL {
+        if let Some(rest) = path.strip_prefix(version.prefix()) {
+            if rest.starts_with('/') {
+                return Route {
+                    version,
+                    path: rest,
+                    deprecated: false,
+                };
+            }
+        }
+    }
+    Route {
+        version: ApiVersion::V1,
+        path,
+        deprecated: !UNVERSIONED.contains(&path) && !is_admin(path),
+    }
+}
+
+/// Whether `path` is one of the operator endpoints, see `admin`.
+pub fn is_admin(path: &str) -> bool {
+    path.strip_prefix(ADMIN_PREFIX)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+impl Route<'_> {
+    /// Whether the path is part of the API proper, as opposed to the landing
+    /// page, the API documentation, the build information and the operator
+    /// endpoints.
+    pub fn is_api(&self) -> bool {
+        !self.is_documentation() && self.path != VERSION && !is_admin(self.path)
+    }
+
+    /// Whether the path is the landing page or part of the API documentation.
+    pub fn is_documentation(&self) -> bool {
+        DOCUMENTATION.contains(&self.path)
+    }
+
+    /// The route with its ids replaced by placeholders, e.g.
+    /// `/v1/orders/{order_id}`, which groups the requests of one route.
+    pub fn template(&self) -> String {
+        let path = match self.path {
+            path if path.starts_with("/jobs/") && path.ends_with("/result") => {
+                "/jobs/{job_id}/result"
+            }
+            path if path.starts_with("/jobs/") => "/jobs/{job_id}",
+            path if path.starts_with("/orders/") => "/orders/{order_id}",
+            "/admin/cache/stats" => "/admin/cache/stats",
+            path if path.starts_with("/admin/cache/") => "/admin/cache/{zip}",
+            path => path,
+        };
+        if UNVERSIONED.contains(&self.path) || is_admin(self.path) {
+            path.to_owned()
+        } else {
+            format!("{}{path}", self.version.prefix())
+        }
+    }
+
+    /// Flags responses served through a deprecated alias and points clients
+    /// at the versioned path.
+    pub fn annotate(&self, response: &mut Response<Body>) {
+        if !self.deprecated {
+            return;
+        }
+        let headers = response.headers_mut();
+        headers.insert("Deprecation", HeaderValue::from_static("true"));
+        let successor = format!(
+            "<{}{}>; rel=\"successor-version\"",
+            self.version.prefix(),
+            self.path
+        );
+        if let Ok(value) = HeaderValue::from_str(&successor) {
+            headers.insert(LINK, value);
+        }
+    }
+}
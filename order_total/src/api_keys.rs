@@ -0,0 +1,152 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use anyhow::{anyhow, Context};
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+pub use order_total_core::Quota;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const HEADER: &str = "x-api-key";
+
+/// The partner keys accepted in `X-Api-Key`, each with its own token bucket.
+///
+/// * `API_KEYS_FILE` - CSV with `key,client` columns and an optional
+///   `requests_per_minute` column
+/// * `API_KEY_RATE_LIMIT` - requests per minute for keys without their own
+///   limit (default 60)
+///
+/// A bucket holds a minute's worth of requests, so a client may burst up to
+/// its limit and is then held to the steady rate.
+pub struct ApiKeys {
+    keys: HashMap<String, Client>,
+}
+
+struct Client {
+    name: String,
+    per_minute: u32,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl ApiKeys {
+    /// `None` when `API_KEYS_FILE` is unset, i.e. API keys are not in use.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(path) = std::env::var("API_KEYS_FILE") else {
+            return Ok(None);
+        };
+        let default_limit = env_or("API_KEY_RATE_LIMIT", 60u32)?;
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("cannot open API_KEYS_FILE {path:?}"))?;
+        let keys = Self::from_csv(file, default_limit)
+            .with_context(|| format!("invalid API_KEYS_FILE {path:?}"))?;
+        tracing::info!(keys = keys.keys.len(), "API keys loaded");
+        Ok(Some(keys))
+    }
+
+    fn from_csv(reader: impl std::io::Read, default_limit: u32) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        for (index, record) in reader.records().enumerate() {
+            let record = record?;
+            let line = index + 2;
+            let key = record
+                .get(0)
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| anyhow!("line {line}: missing key"))?;
+            let name = record
+                .get(1)
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| anyhow!("line {line}: missing client"))?;
+            let per_minute = match record.get(2).filter(|limit| !limit.is_empty()) {
+                Some(limit) => limit
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&limit| limit > 0)
+                    .ok_or_else(|| anyhow!("line {line}: invalid requests_per_minute {limit:?}"))?,
+                None => default_limit.max(1),
+            };
+            let client = Client {
+                name: name.to_owned(),
+                per_minute,
+                bucket: Mutex::new(Bucket {
+                    tokens: f64::from(per_minute),
+                    updated: Instant::now(),
+                }),
+            };
+            if keys.insert(key.to_owned(), client).is_some() {
+                anyhow::bail!("line {line}: duplicate key");
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    /// Whether the request chose to authenticate with an API key.
+    pub fn present(headers: &HeaderMap) -> bool {
+        headers.contains_key(HEADER)
+    }
+
+    /// Checks the request's `X-Api-Key` and takes a token from its bucket,
+    /// returning the client name and its remaining quota.
+    pub fn admit(&self, headers: &HeaderMap) -> Result<(&str, Quota), ComputeError> {
+        let client = headers
+            .get(HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|key| self.keys.get(key.trim()))
+            .ok_or(ComputeError::InvalidApiKey)?;
+        let quota = client.take()?;
+        Ok((&client.name, quota))
+    }
+}
+
+impl Client {
+    fn take(&self) -> Result<Quota, ComputeError> {
+        let capacity = f64::from(self.per_minute);
+        let per_sec = capacity / 60.0;
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.updated = now;
+
+        let admitted = bucket.tokens >= 1.0;
+        if admitted {
+            bucket.tokens -= 1.0;
+        }
+        let quota = Quota {
+            limit: self.per_minute,
+            remaining: bucket.tokens as u32,
+            reset: Duration::from_secs_f64((capacity - bucket.tokens) / per_sec),
+        };
+        if admitted {
+            Ok(quota)
+        } else {
+            let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec);
+            Err(ComputeError::RateLimited(quota, wait))
+        }
+    }
+}
+
+/// Reports `quota` in the `X-RateLimit-*` headers of a response (or in the
+/// metadata of a gRPC status).
+pub fn annotate(quota: &Quota, headers: &mut HeaderMap) {
+    headers.insert("X-RateLimit-Limit", quota.limit.into());
+    headers.insert("X-RateLimit-Remaining", quota.remaining.into());
+    headers.insert(
+        "X-RateLimit-Reset",
+        HeaderValue::from(ceil_secs(quota.reset)),
+    );
+}
+
+/// Whole seconds, rounded up, as `Retry-After` and `X-RateLimit-Reset` want.
+pub fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
@@ -0,0 +1,467 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use crate::request_id;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use order_total_core::{ErrorCode, Order, RateSource};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write as _};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// The `prev_hash` of the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// An append-only record of every computation, for finance to trace how
+/// each total was derived:
+///
+/// * `AUDIT_LOG` - a JSON lines file to append to, or `sqlite://audit.db`
+///   (or `sqlite::memory:`) for a table, which needs the `sqlite` feature;
+///   unset, nothing is recorded
+/// * `AUDIT_LOG_MAX_BYTES` - a file this large is rotated to `{file}.1`,
+///   `{file}.2` and so on, oldest first, and never deleted (default 64 MiB)
+///
+/// Each entry carries the SHA-256 of the one before it, and its own over
+/// that and its content, so an entry edited, removed or inserted later
+/// breaks the chain, see `GET /admin/audit/verify`.
+pub struct AuditLog {
+    sink: Box<dyn AuditSink>,
+    /// The `seq` and `hash` of the last entry, which the next one chains
+    /// to; held while appending, so the chain follows the order entries
+    /// are written in.
+    last: Mutex<(u64, String)>,
+}
+
+/// Where audit entries are kept.
+trait AuditSink: Send + Sync {
+    fn append(&self, entry: &AuditEntry) -> anyhow::Result<()>;
+
+    /// Every entry, oldest first.
+    fn entries(&self) -> anyhow::Result<Vec<AuditEntry>>;
+
+    /// The last entry written, if any.
+    fn last(&self) -> anyhow::Result<Option<AuditEntry>>;
+}
+
+/// How one order was computed, or why it was not.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    /// The `hash` of the entry before this one.
+    pub prev_hash: String,
+    /// The hex SHA-256 of `prev_hash` followed by the entry's other fields
+    /// as JSON.
+    pub hash: String,
+}
+
+/// The content of an audit entry. Amounts and orders are kept as they were
+/// written, so the hash of an entry read back is the one it was written
+/// with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Counts the entries from 1, without gaps.
+    pub seq: u64,
+    pub recorded_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub order_id: i32,
+    /// The order as it was submitted.
+    pub input: serde_json::Value,
+    /// The sales tax rate that was applied, e.g. `"0.0825"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_rate: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_source: Option<RateSource>,
+    /// The computed order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    /// Why the order could not be computed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorCode>,
+}
+
+impl AuditRecord {
+    fn hash(&self, prev_hash: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(prev_hash.as_bytes());
+        hasher.update(serde_json::to_vec(self).unwrap());
+        let mut hex = String::with_capacity(64);
+        for byte in hasher.finalize() {
+            write!(hex, "{byte:02x}").unwrap();
+        }
+        hex
+    }
+}
+
+/// The query parameters of `GET /admin/audit`.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    order_id: Option<i32>,
+    request_id: Option<String>,
+    /// Only entries recorded at or after this time (RFC 3339).
+    since: Option<DateTime<Utc>>,
+    /// Only entries recorded before this time (RFC 3339).
+    until: Option<DateTime<Utc>>,
+    /// Only failed (`true`) or successful (`false`) computations.
+    failed: Option<bool>,
+    /// How many entries to return, most recent first (default 100, at most
+    /// 1000).
+    limit: Option<usize>,
+}
+
+impl AuditFilter {
+    fn admits(&self, record: &AuditRecord) -> bool {
+        self.order_id.is_none_or(|id| record.order_id == id)
+            && self
+                .request_id
+                .as_deref()
+                .is_none_or(|id| record.request_id.as_deref() == Some(id))
+            && self.since.is_none_or(|since| record.recorded_at >= since)
+            && self.until.is_none_or(|until| record.recorded_at < until)
+            && self
+                .failed
+                .is_none_or(|failed| record.error.is_some() == failed)
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    pub limit: usize,
+}
+
+/// The outcome of checking the chain of every entry.
+#[derive(Serialize)]
+pub struct Verification {
+    pub entries: u64,
+    pub intact: bool,
+    /// The first entry whose `seq`, `prev_hash` or `hash` does not follow
+    /// from the ones before it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub broken_at: Option<u64>,
+}
+
+impl AuditLog {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(target) = std::env::var("AUDIT_LOG")
+            .ok()
+            .filter(|target| !target.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let sink: Box<dyn AuditSink> = if target.starts_with("sqlite:") {
+            open_database(&target)?
+        } else {
+            Box::new(FileSink::open(
+                PathBuf::from(&target),
+                env_or("AUDIT_LOG_MAX_BYTES", 64 * 1024 * 1024)?,
+            )?)
+        };
+        let last = match sink.last()? {
+            Some(entry) => (entry.record.seq, entry.hash),
+            None => (0, GENESIS.to_owned()),
+        };
+        tracing::info!(target, entries = last.0, "recording an audit log");
+        Ok(Some(Self {
+            sink,
+            last: Mutex::new(last),
+        }))
+    }
+
+    /// Appends the computation of `input` (the order as submitted) and its
+    /// outcome. A computation that cannot be recorded fails, rather than
+    /// going unaudited.
+    pub fn record(
+        &self,
+        input: serde_json::Value,
+        result: &Result<(Order, Decimal), ComputeError>,
+    ) -> Result<(), ComputeError> {
+        let order_id = input["order_id"].as_i64().unwrap_or_default() as i32;
+        let mut last = self.last.lock().unwrap();
+        let mut record = AuditRecord {
+            seq: last.0 + 1,
+            recorded_at: Utc::now(),
+            request_id: request_id::current(),
+            order_id,
+            input,
+            tax_rate: None,
+            rate_source: None,
+            result: None,
+            error: None,
+        };
+        match result {
+            Ok((order, tax_rate)) => {
+                record.tax_rate = Some(tax_rate.to_string());
+                record.rate_source = order.rate_source;
+                record.result = Some(serde_json::to_value(order).map_err(unexpected_json)?);
+            }
+            Err(err) => record.error = Some(err.code()),
+        }
+        let prev_hash = last.1.clone();
+        let hash = record.hash(&prev_hash);
+        let entry = AuditEntry {
+            record,
+            prev_hash,
+            hash,
+        };
+        self.sink.append(&entry).map_err(|err| {
+            ComputeError::Unexpected(err.context("could not append to the audit log").into())
+        })?;
+        *last = (entry.record.seq, entry.hash);
+        Ok(())
+    }
+
+    /// The entries `filter` admits, most recent first.
+    pub fn query(&self, filter: &AuditFilter) -> anyhow::Result<AuditPage> {
+        let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+        let entries = self
+            .sink
+            .entries()?
+            .into_iter()
+            .rev()
+            .filter(|entry| filter.admits(&entry.record))
+            .take(limit)
+            .collect();
+        Ok(AuditPage { entries, limit })
+    }
+
+    /// Recomputes the chain from the first entry.
+    pub fn verify(&self) -> anyhow::Result<Verification> {
+        let entries = self.sink.entries()?;
+        let mut prev_hash = GENESIS.to_owned();
+        for (index, entry) in entries.iter().enumerate() {
+            let seq = index as u64 + 1;
+            if entry.record.seq != seq
+                || entry.prev_hash != prev_hash
+                || entry.record.hash(&prev_hash) != entry.hash
+            {
+                return Ok(Verification {
+                    entries: entries.len() as u64,
+                    intact: false,
+                    broken_at: Some(seq),
+                });
+            }
+            prev_hash.clone_from(&entry.hash);
+        }
+        Ok(Verification {
+            entries: entries.len() as u64,
+            intact: true,
+            broken_at: None,
+        })
+    }
+}
+
+fn unexpected_json(err: serde_json::Error) -> ComputeError {
+    ComputeError::Unexpected(Box::new(err))
+}
+
+/// Entries as JSON lines, rotated by size.
+struct FileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    fn open(path: PathBuf, max_bytes: u64) -> anyhow::Result<Self> {
+        let file = append_to(&path)?;
+        Ok(Self {
+            path,
+            max_bytes,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// `{path}.{n}`, the `n`th rotated file.
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{n}"));
+        name.into()
+    }
+
+    /// The rotated files, oldest first, then the current one.
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = (1..)
+            .map(|n| self.rotated(n))
+            .take_while(|path| path.exists())
+            .collect();
+        files.push(self.path.clone());
+        files
+    }
+}
+
+fn append_to(path: &PathBuf) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("cannot open audit log {path:?}"))
+}
+
+fn read_entries(path: &PathBuf) -> anyhow::Result<Vec<AuditEntry>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("cannot read {path:?}")),
+    };
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(&line?)
+                .with_context(|| format!("{path:?} line {}: invalid audit entry", index + 1))
+        })
+        .collect()
+}
+
+impl AuditSink for FileSink {
+    fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+        let size = file.metadata()?.len();
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            let rotated = self.rotated(self.files().len());
+            std::fs::rename(&self.path, &rotated)
+                .with_context(|| format!("cannot rotate the audit log to {rotated:?}"))?;
+            *file = append_to(&self.path)?;
+            tracing::info!(file = ?rotated, "audit log rotated");
+        }
+        file.write_all(&line)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<AuditEntry>> {
+        let _file = self.file.lock().unwrap();
+        let mut entries = Vec::new();
+        for path in self.files() {
+            entries.extend(read_entries(&path)?);
+        }
+        Ok(entries)
+    }
+
+    fn last(&self) -> anyhow::Result<Option<AuditEntry>> {
+        for path in self.files().iter().rev() {
+            if let Some(entry) = read_entries(path)?.pop() {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_database(url: &str) -> anyhow::Result<Box<dyn AuditSink>> {
+    Ok(Box::new(sqlite::SqliteSink::open(url)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_database(_url: &str) -> anyhow::Result<Box<dyn AuditSink>> {
+    anyhow::bail!(
+        "AUDIT_LOG names a database but order_total was built without the `sqlite` feature"
+    )
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{AuditEntry, AuditSink};
+    use anyhow::Context;
+    use chrono::SecondsFormat;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use std::sync::Mutex;
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS audit_log (
+            seq INTEGER PRIMARY KEY,
+            recorded_at TEXT NOT NULL,
+            order_id INTEGER NOT NULL,
+            request_id TEXT,
+            hash TEXT NOT NULL,
+            entry TEXT NOT NULL
+        );
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+            BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+    ";
+
+    /// `entry` keeps the whole entry as JSON; the other columns are there
+    /// to query by from outside the service. Triggers refuse updates and
+    /// deletes.
+    pub struct SqliteSink {
+        conn: Mutex<Connection>,
+    }
+
+    impl SqliteSink {
+        pub fn open(url: &str) -> anyhow::Result<Self> {
+            let conn = match url.strip_prefix("sqlite:").unwrap_or(url) {
+                ":memory:" => Connection::open_in_memory(),
+                path => Connection::open(path.trim_start_matches("//")),
+            }
+            .with_context(|| format!("cannot open database {url:?}"))?;
+            conn.execute_batch(SCHEMA)
+                .context("cannot create the audit_log table")?;
+            Ok(Self {
+                conn: Mutex::new(conn),
+            })
+        }
+
+        fn query(&self, sql: &str) -> anyhow::Result<Vec<AuditEntry>> {
+            let conn = self.conn.lock().unwrap();
+            let mut statement = conn.prepare(sql)?;
+            let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+            rows.map(|entry| Ok(serde_json::from_str(&entry?)?))
+                .collect()
+        }
+    }
+
+    impl AuditSink for SqliteSink {
+        fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+            let record = &entry.record;
+            self.conn.lock().unwrap().execute(
+                "INSERT INTO audit_log (seq, recorded_at, order_id, request_id, hash, entry)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    record.seq,
+                    record
+                        .recorded_at
+                        .to_rfc3339_opts(SecondsFormat::Millis, true),
+                    record.order_id,
+                    record.request_id,
+                    entry.hash,
+                    serde_json::to_string(entry)?,
+                ],
+            )?;
+            Ok(())
+        }
+
+        fn entries(&self) -> anyhow::Result<Vec<AuditEntry>> {
+            self.query("SELECT entry FROM audit_log ORDER BY seq")
+        }
+
+        fn last(&self) -> anyhow::Result<Option<AuditEntry>> {
+            let entry: Option<String> = self
+                .conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT entry FROM audit_log ORDER BY seq DESC LIMIT 1",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(entry
+                .map(|entry| serde_json::from_str(&entry))
+                .transpose()?)
+        }
+    }
+}
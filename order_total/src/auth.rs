@@ -0,0 +1,180 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use anyhow::{anyhow, Context};
+use hyper::header::AUTHORIZATION;
+use hyper::HeaderMap;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{DecodingKey, Validation};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+/// How long a fetched JWKS is trusted before it is fetched again.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// A token signed with a key the cached JWKS does not have triggers a refetch
+/// (keys get rotated), but no more often than this.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+
+/// The claims of a validated token that handlers get to see.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+}
+
+/// Bearer token validation, enabled by configuring a signing key:
+///
+/// * `JWT_SECRET` - shared secret for HS256/HS384/HS512 tokens
+/// * `JWT_PUBLIC_KEY_PATH` - PEM file of an RSA, EC or Ed25519 public key
+/// * `JWT_JWKS_URL` - the issuer's JSON Web Key Set, selected by the token's `kid`
+/// * `JWT_JWKS_CONNECT_TIMEOUT_MS` - bound on connecting to fetch the JWKS (default 1000)
+/// * `JWT_JWKS_TIMEOUT_MS` - bound on a whole fetch of the JWKS (default 2000)
+/// * `JWT_ISSUER` / `JWT_AUDIENCE` - required `iss` / `aud` values, if set
+pub struct JwtAuth {
+    keys: Keys,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+enum Keys {
+    Static(DecodingKey),
+    Jwks {
+        url: String,
+        client: reqwest::Client,
+        cache: RwLock<Option<(Instant, JwkSet)>>,
+        /// Held by the one request fetching the set; the others wait for
+        /// it rather than fetch the set again.
+        fetching: Mutex<()>,
+    },
+}
+
+impl JwtAuth {
+    /// `None` when no signing key is configured, i.e. authentication is off.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let secret = std::env::var("JWT_SECRET").ok();
+        let key_path = std::env::var("JWT_PUBLIC_KEY_PATH").ok();
+        let jwks_url = std::env::var("JWT_JWKS_URL").ok();
+        let keys = match (secret, key_path, jwks_url) {
+            (None, None, None) => return Ok(None),
+            (Some(secret), None, None) => Keys::Static(DecodingKey::from_secret(secret.as_bytes())),
+            (None, Some(path), None) => Keys::Static(load_public_key(&path)?),
+            (None, None, Some(url)) => Keys::Jwks {
+                url,
+                client: reqwest::Client::builder()
+                    .connect_timeout(Duration::from_millis(env_or(
+                        "JWT_JWKS_CONNECT_TIMEOUT_MS",
+                        1000,
+                    )?))
+                    .timeout(Duration::from_millis(env_or("JWT_JWKS_TIMEOUT_MS", 2000)?))
+                    .build()?,
+                cache: RwLock::new(None),
+                fetching: Mutex::new(()),
+            },
+            _ => anyhow::bail!("set only one of JWT_SECRET, JWT_PUBLIC_KEY_PATH and JWT_JWKS_URL"),
+        };
+        Ok(Some(Self {
+            keys,
+            issuer: std::env::var("JWT_ISSUER").ok(),
+            audience: std::env::var("JWT_AUDIENCE").ok(),
+        }))
+    }
+
+    /// Validates the `Authorization: Bearer` token of a request. The reason
+    /// a token is refused is logged, not returned to the client.
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Result<Claims, ComputeError> {
+        let token = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or(ComputeError::Unauthorized)?;
+        self.validate(token).await.map_err(|err| {
+            tracing::info!("bearer token rejected: {err:#}");
+            ComputeError::Unauthorized
+        })
+    }
+
+    async fn validate(&self, token: &str) -> anyhow::Result<Claims> {
+        let key = match &self.keys {
+            Keys::Static(key) => key.clone(),
+            Keys::Jwks { .. } => {
+                let header = jsonwebtoken::decode_header(token)?;
+                let kid = header.kid.ok_or_else(|| anyhow!("token has no kid"))?;
+                self.jwk(&kid).await?
+            }
+        };
+
+        let mut validation = Validation::new_for_family(key.family());
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        Ok(jsonwebtoken::decode::<Claims>(token, &key, &validation)?.claims)
+    }
+
+    /// The key `kid` from the JWKS, fetching the set when it is missing,
+    /// stale, or lacks that key. The cache is not locked during the fetch,
+    /// so requests whose key is cached are not held up by it.
+    async fn jwk(&self, kid: &str) -> anyhow::Result<DecodingKey> {
+        let Keys::Jwks {
+            url,
+            client,
+            cache,
+            fetching,
+        } = &self.keys
+        else {
+            unreachable!("only called with a JWKS");
+        };
+        if let Some(key) = cached(&*cache.read().await, kid) {
+            return key;
+        }
+
+        // Whoever fetched before may have just fetched the set.
+        let _fetching = fetching.lock().await;
+        if let Some(key) = cached(&*cache.read().await, kid) {
+            return key;
+        }
+        let set: JwkSet = client
+            .get(url.as_str())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("cannot fetch JWKS from {url}"))?;
+        let key = set
+            .find(kid)
+            .map(DecodingKey::from_jwk)
+            .transpose()?
+            .ok_or_else(|| anyhow!("unknown kid {kid:?}"));
+        *cache.write().await = Some((Instant::now(), set));
+        key
+    }
+}
+
+/// The answer the cached set gives for `kid`, or `None` when it has to be
+/// fetched: when there is none yet, or it is stale, or it lacks `kid` and
+/// was fetched long enough ago to try again.
+fn cached(cache: &Option<(Instant, JwkSet)>, kid: &str) -> Option<anyhow::Result<DecodingKey>> {
+    let (fetched, set) = cache.as_ref()?;
+    match set.find(kid) {
+        Some(jwk) if fetched.elapsed() < JWKS_MAX_AGE => {
+            Some(DecodingKey::from_jwk(jwk).map_err(Into::into))
+        }
+        None if fetched.elapsed() < JWKS_MIN_REFRESH => Some(Err(anyhow!("unknown kid {kid:?}"))),
+        _ => None,
+    }
+}
+
+fn load_public_key(path: &str) -> anyhow::Result<DecodingKey> {
+    let pem =
+        std::fs::read(path).with_context(|| format!("cannot read JWT_PUBLIC_KEY_PATH {path:?}"))?;
+    DecodingKey::from_rsa_pem(&pem)
+        .or_else(|_| DecodingKey::from_ec_pem(&pem))
+        .or_else(|_| DecodingKey::from_ed_pem(&pem))
+        .map_err(|_| {
+            anyhow!("JWT_PUBLIC_KEY_PATH {path:?} is not an RSA, EC or Ed25519 public key")
+        })
+}
@@ -0,0 +1,225 @@
+//! Request and response bodies, and reading them. The WasmEdge build uses
+//! hyper 0.14's `Body`; the native build, on hyper 1.x, uses the one here,
+//! which boxes any http-body-util body behind the same constructors
+//! (`empty`, `from`, `wrap_stream` and `channel`), so that the handlers are
+//! written once for both.
+
+use crate::compression;
+use crate::error::ComputeError;
+use hyper::body::Bytes;
+use hyper::Request;
+
+#[cfg(feature = "native")]
+pub use http_body::Body as HttpBody;
+#[cfg(feature = "wasmedge")]
+pub use hyper::body::HttpBody;
+#[cfg(feature = "wasmedge")]
+pub use hyper::{Body, Error};
+#[cfg(feature = "native")]
+pub use native::{data, to_bytes, Body, Error};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The next chunk of `body`, or `None` at its end.
+#[cfg(feature = "wasmedge")]
+pub async fn data(body: &mut Body) -> Option<Result<Bytes, Error>> {
+    body.data().await
+}
+
+/// Reads the whole of `body`.
+#[cfg(feature = "wasmedge")]
+pub async fn to_bytes<B: HttpBody>(body: B) -> Result<Bytes, B::Error> {
+    hyper::body::to_bytes(body).await
+}
+
+/// Reads the whole request body like `to_bytes`, but gives up
+/// with `PayloadTooLarge` as soon as more than `limit` bytes have arrived
+/// (or a larger `Content-Length` is announced) instead of buffering them.
+/// A body that fails to decompress is an `InvalidRequest`, one that
+/// decompresses past `limit` a `PayloadTooLarge`.
+pub async fn to_bytes_limited(req: Request<Body>, limit: usize) -> Result<Bytes, ComputeError> {
+    let announced = req
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if announced.is_some_and(|length| length > limit as u64) {
+        return Err(ComputeError::PayloadTooLarge(limit));
+    }
+
+    let mut body = req.into_body();
+    let mut buf = Vec::with_capacity(announced.map_or(0, |length| length as usize));
+    while let Some(chunk) = data(&mut body).await {
+        let chunk = chunk.map_err(|err| {
+            if compression::is_too_large(&err) {
+                ComputeError::PayloadTooLarge(limit)
+            } else if compression::is_invalid_encoding(&err) {
+                ComputeError::InvalidRequest
+            } else {
+                ComputeError::Unexpected(err.into())
+            }
+        })?;
+        if buf.len() + chunk.len() > limit {
+            return Err(ComputeError::PayloadTooLarge(limit));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf.into())
+}
+
+#[cfg(feature = "native")]
+mod native {
+    use super::BoxError;
+    use futures_util::{Stream, TryStreamExt};
+    use http_body::{Frame, SizeHint};
+    use http_body_util::channel::Channel;
+    use http_body_util::combinators::UnsyncBoxBody;
+    use http_body_util::{BodyExt, Empty, Full, StreamBody};
+    use hyper::body::Bytes;
+    use std::fmt;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// A request or response body: what the connection receives, or what a
+    /// handler answers with.
+    pub struct Body(UnsyncBoxBody<Bytes, Error>);
+
+    /// Why a body could not be read. Like hyper 0.14's, its `source` is the
+    /// error of the connection or of the stream behind the body.
+    #[derive(Debug)]
+    pub struct Error(BoxError);
+
+    /// Sends the chunks of a `Body::channel` body.
+    pub type Sender = http_body_util::channel::Sender<Bytes, Error>;
+
+    impl Body {
+        /// Boxes `body`, such as one of another framework's.
+        pub fn new<B>(body: B) -> Self
+        where
+            B: http_body::Body<Data = Bytes> + Send + 'static,
+            B::Error: Into<BoxError>,
+        {
+            Self(body.map_err(|err| Error(err.into())).boxed_unsync())
+        }
+
+        pub fn empty() -> Self {
+            Self::new(Empty::new())
+        }
+
+        /// The body of the chunks `stream` yields.
+        pub fn wrap_stream<S, O, E>(stream: S) -> Self
+        where
+            S: Stream<Item = Result<O, E>> + Send + 'static,
+            O: Into<Bytes> + 'static,
+            E: Into<BoxError> + 'static,
+        {
+            let frames = stream.map_ok(|chunk| Frame::data(chunk.into()));
+            Self::new(StreamBody::new(frames))
+        }
+
+        /// A body that is sent as it is written to its `Sender`.
+        pub fn channel() -> (Sender, Self) {
+            let (tx, body) = Channel::new(1);
+            (tx, Self::new(body))
+        }
+    }
+
+    /// The next chunk of `body`, or `None` at its end.
+    pub async fn data(body: &mut Body) -> Option<Result<Bytes, Error>> {
+        loop {
+            match body.frame().await? {
+                Ok(frame) => match frame.into_data() {
+                    Ok(data) => return Some(Ok(data)),
+                    // Trailers; hyper 0.14's `data` skipped them as well.
+                    Err(_) => continue,
+                },
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    /// Reads the whole of `body`.
+    pub async fn to_bytes<B: http_body::Body>(body: B) -> Result<Bytes, B::Error> {
+        Ok(body.collect().await?.to_bytes())
+    }
+
+    impl Default for Body {
+        fn default() -> Self {
+            Self::empty()
+        }
+    }
+
+    impl fmt::Debug for Body {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Body").finish_non_exhaustive()
+        }
+    }
+
+    impl http_body::Body for Body {
+        type Data = Bytes;
+        type Error = Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+            Pin::new(&mut self.0).poll_frame(cx)
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.0.is_end_stream()
+        }
+
+        fn size_hint(&self) -> SizeHint {
+            self.0.size_hint()
+        }
+    }
+
+    impl From<hyper::body::Incoming> for Body {
+        fn from(body: hyper::body::Incoming) -> Self {
+            Self::new(body)
+        }
+    }
+
+    impl From<Bytes> for Body {
+        fn from(bytes: Bytes) -> Self {
+            Self::new(Full::new(bytes))
+        }
+    }
+
+    impl From<Vec<u8>> for Body {
+        fn from(bytes: Vec<u8>) -> Self {
+            Bytes::from(bytes).into()
+        }
+    }
+
+    impl From<String> for Body {
+        fn from(text: String) -> Self {
+            Bytes::from(text).into()
+        }
+    }
+
+    impl From<&'static str> for Body {
+        fn from(text: &'static str) -> Self {
+            Bytes::from_static(text.as_bytes()).into()
+        }
+    }
+
+    impl From<&'static [u8]> for Body {
+        fn from(bytes: &'static [u8]) -> Self {
+            Bytes::from_static(bytes).into()
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl std::error::Error for Error {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&*self.0)
+        }
+    }
+}
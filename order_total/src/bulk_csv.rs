@@ -0,0 +1,184 @@
+use crate::body::Body;
+use crate::error::{ComputeError, FieldError};
+use crate::{body, response_build, with_content_type, App};
+use hyper::{Request, Response, StatusCode};
+use order_total_core::Order;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// Columns every upload must have, like the required fields of a JSON order.
+const REQUIRED: [&str; 3] = ["order_id", "shipping_address", "shipping_zip"];
+/// Columns appended to the output, and left out of the echoed input.
+const RESULT_COLUMNS: [&str; 3] = ["total", "tax_rate", "error"];
+
+/// Computes every row of a CSV upload whose columns are the `Order` fields
+/// (`order_id`, `product_id`, `quantity`, `subtotal`, `shipping_address`,
+/// `shipping_zip`, optionally `shipping_country` and `currency`), and answers with the same rows plus `total`, `tax_rate`
+/// and `error` columns. A row that fails only fills in `error`; the rest of
+/// the upload is still computed.
+#[utoipa::path(
+    post,
+    path = "/v1/compute_csv",
+    request_body(content = String, description = "A header row followed by one order per row", content_type = "text/csv"),
+    responses(
+        (status = 200, description = "The rows with `total`, `tax_rate` and `error` columns added", body = String, content_type = "text/csv"),
+        (status = 413, description = "The upload exceeds `MAX_CSV_BYTES`", body = ErrorResponse),
+        (status = 422, description = "A required column is missing", body = ErrorResponse),
+    )
+)]
+pub async fn compute_csv(req: Request<Body>, app: &App) -> Response<Body> {
+    match compute(req, app).await {
+        Ok(csv) => with_content_type(
+            response_build(StatusCode::OK, &csv),
+            "text/csv; charset=utf-8",
+        ),
+        Err(err) => crate::error::response(err),
+    }
+}
+
+async fn compute(req: Request<Body>, app: &App) -> Result<String, ComputeError> {
+    let bytes = body::to_bytes_limited(req, app.max_csv_bytes).await?;
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(&bytes[..]);
+    let headers = reader
+        .headers()
+        .map_err(|_| ComputeError::InvalidRequest)?
+        .clone();
+
+    let missing: Vec<FieldError> = REQUIRED
+        .iter()
+        .filter(|column| !headers.iter().any(|header| header == **column))
+        .map(|column| FieldError::new(*column, "missing column"))
+        .collect();
+    if !missing.is_empty() {
+        return Err(ComputeError::Validation(missing));
+    }
+
+    let echoed: Vec<usize> = (0..headers.len())
+        .filter(|&index| !RESULT_COLUMNS.contains(&&headers[index]))
+        .collect();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let output_headers = echoed
+        .iter()
+        .map(|&index| &headers[index])
+        .chain(RESULT_COLUMNS);
+    writer.write_record(output_headers).map_err(unexpected)?;
+
+    for record in reader.records() {
+        // A row that is not valid CSV (or UTF-8) is reported as invalid.
+        let (record, result) = match record {
+            Ok(record) => {
+                let result = match parse_row(&headers, &record) {
+                    Ok(order) => crate::process(order, app).await,
+                    Err(errors) => Err(ComputeError::Validation(errors)),
+                };
+                (record, result)
+            }
+            Err(_) => (csv::StringRecord::new(), Err(ComputeError::InvalidRequest)),
+        };
+        let (total, tax_rate, error) = match result {
+            Ok(order) => (
+                order.total.to_string(),
+                order
+                    .tax_rate
+                    .map(|rate| rate.to_string())
+                    .unwrap_or_default(),
+                String::new(),
+            ),
+            Err(err) => (String::new(), String::new(), err.into_parts().1.summary()),
+        };
+        let row = echoed
+            .iter()
+            .map(|&index| record.get(index).unwrap_or_default())
+            .chain([total.as_str(), tax_rate.as_str(), error.as_str()]);
+        writer.write_record(row).map_err(unexpected)?;
+    }
+
+    let csv = writer
+        .into_inner()
+        .map_err(|err| unexpected(err.into_error()))?;
+    String::from_utf8(csv).map_err(unexpected)
+}
+
+/// Builds the order in one row, collecting every cell that does not parse.
+/// Empty cells take the default, as missing fields of a JSON order do.
+fn parse_row(
+    headers: &csv::StringRecord,
+    record: &csv::StringRecord,
+) -> Result<Order, Vec<FieldError>> {
+    let mut row = Row {
+        headers,
+        record,
+        errors: Vec::new(),
+    };
+    let order = Order {
+        order_id: row.parse("order_id", "an integer"),
+        product_id: row.parse("product_id", "an integer"),
+        quantity: row.parse("quantity", "an integer"),
+        subtotal: row.parse("subtotal", "a number"),
+        shipping_address: row.text("shipping_address").to_owned(),
+        shipping_address_parts: None,
+        shipping_zip: row.text("shipping_zip").to_owned(),
+        shipping_country: Some(row.text("shipping_country").to_owned())
+            .filter(|country| !country.is_empty()),
+        total: Decimal::ZERO,
+        currency: Some(row.text("currency").to_owned()).filter(|currency| !currency.is_empty()),
+        convert_to: None,
+        converted: None,
+        order_date: None,
+        tax_holiday: None,
+        tax_rate: None,
+        rate_source: None,
+        tax_amount: Decimal::ZERO,
+        jurisdiction: None,
+        rounding: None,
+        line_items: Vec::new(),
+        price_mismatches: Vec::new(),
+        discounts: Vec::new(),
+        discount_amount: Decimal::ZERO,
+        tax_components: Vec::new(),
+        vat_id: None,
+        vat: None,
+        exemption_certificate: None,
+        dry_run: false,
+    };
+    if row.errors.is_empty() {
+        Ok(order)
+    } else {
+        Err(row.errors)
+    }
+}
+
+struct Row<'a> {
+    headers: &'a csv::StringRecord,
+    record: &'a csv::StringRecord,
+    errors: Vec<FieldError>,
+}
+
+impl<'a> Row<'a> {
+    fn text(&self, column: &str) -> &'a str {
+        self.headers
+            .iter()
+            .position(|header| header == column)
+            .and_then(|index| self.record.get(index))
+            .unwrap_or_default()
+    }
+
+    fn parse<T: FromStr + Default>(&mut self, column: &str, expected: &str) -> T {
+        let value = self.text(column);
+        if value.is_empty() {
+            return T::default();
+        }
+        value.parse().unwrap_or_else(|_| {
+            self.errors
+                .push(FieldError::new(column, format!("must be {expected}")));
+            T::default()
+        })
+    }
+}
+
+fn unexpected(err: impl std::error::Error + Send + Sync + 'static) -> ComputeError {
+    ComputeError::Unexpected(Box::new(err))
+}
@@ -0,0 +1,99 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use crate::request_id;
+use async_trait::async_trait;
+use order_total_core::order::MAX_AMOUNT;
+use order_total_core::{CatalogTable, ProductCatalog};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The product catalog orders are priced by, if any:
+///
+/// * `PRODUCT_CATALOG_URL` - GET `{url}/{product_id}` for each product, answered with `{"unit_price": ...}`, or `404` for a product not sold
+/// * `PRODUCT_CATALOG_TABLE` - a `product_id,unit_price` CSV to price by instead
+/// * `PRODUCT_CATALOG_TIMEOUT_MS` - bound on one catalog lookup (default 2000)
+///
+/// With neither set, the prices orders are sent with are trusted.
+pub fn from_env() -> anyhow::Result<Option<Arc<dyn ProductCatalog>>> {
+    let setting = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+    match (
+        setting("PRODUCT_CATALOG_URL"),
+        setting("PRODUCT_CATALOG_TABLE"),
+    ) {
+        (Some(_), Some(_)) => {
+            anyhow::bail!("set PRODUCT_CATALOG_URL or PRODUCT_CATALOG_TABLE, not both")
+        }
+        (None, Some(path)) => Ok(Some(Arc::new(CatalogTable::load(&path)?))),
+        (Some(url), None) => {
+            let base = reqwest::Url::parse(&url)
+                .map_err(|err| anyhow::anyhow!("invalid PRODUCT_CATALOG_URL: {err}"))?;
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_millis(env_or(
+                    "PRODUCT_CATALOG_TIMEOUT_MS",
+                    2000,
+                )?))
+                .build()?;
+            Ok(Some(Arc::new(HttpCatalog { client, base })))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Asks an external catalog service for each product's price.
+struct HttpCatalog {
+    client: reqwest::Client,
+    base: reqwest::Url,
+}
+
+/// What the catalog answers about a product.
+#[derive(Deserialize)]
+struct Product {
+    unit_price: Decimal,
+}
+
+#[async_trait]
+impl ProductCatalog for HttpCatalog {
+    async fn unit_price(&self, product_id: i32) -> Result<Option<Decimal>, ComputeError> {
+        let url = format!("{}/{product_id}", self.base.as_str().trim_end_matches('/'));
+        let mut request = self.client.get(url);
+        if let Some(id) = request_id::current() {
+            request = request.header(request_id::HEADER, id);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|err| unavailable(err.to_string()))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(unavailable(format!("answered {status}")));
+        }
+        let product: Product = response
+            .json()
+            .await
+            .map_err(|err| unavailable(err.to_string()))?;
+        // A price no order could be sent with is the catalog's mistake.
+        if !(Decimal::ZERO..=MAX_AMOUNT).contains(&product.unit_price) {
+            return Err(unavailable(format!(
+                "priced product {product_id} at {}",
+                product.unit_price
+            )));
+        }
+        Ok(Some(product.unit_price))
+    }
+}
+
+/// The catalog did not give a price; the order is not computed at a price
+/// it was sent with instead.
+fn unavailable(reason: String) -> ComputeError {
+    ComputeError::Unexpected(format!("the product catalog failed: {reason}").into())
+}
@@ -0,0 +1,80 @@
+use crate::config::env_or;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Stops calling the sales tax rate service after it has failed repeatedly,
+/// so that `/compute` fails fast instead of waiting on a dead upstream.
+///
+/// After `failure_threshold` consecutive failures the circuit opens for
+/// `open_duration`. The first call after that is let through as a probe:
+/// if it succeeds the circuit closes again, otherwise it re-opens.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { since: Instant },
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Reads `CIRCUIT_BREAKER_THRESHOLD` and `CIRCUIT_BREAKER_OPEN_SECS`,
+    /// defaulting to 5 failures and 30 seconds.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let threshold = env_or("CIRCUIT_BREAKER_THRESHOLD", 5)?;
+        let open_secs = env_or("CIRCUIT_BREAKER_OPEN_SECS", 30)?;
+        Ok(Self::new(threshold, Duration::from_secs(open_secs)))
+    }
+
+    /// Asks permission to call the upstream. Returns how long the caller
+    /// should wait before trying again if the circuit is open.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(until - now),
+            State::HalfOpen { since } if now < since + self.open_duration => {
+                Err(since + self.open_duration - now)
+            }
+            // Either the open period elapsed or the previous probe never
+            // reported back; let this call through as the probe.
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = State::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::Open { .. } | State::HalfOpen { .. } => self.failure_threshold,
+        };
+        *state = if failures >= self.failure_threshold {
+            State::Open {
+                until: Instant::now() + self.open_duration,
+            }
+        } else {
+            State::Closed { failures }
+        };
+    }
+}
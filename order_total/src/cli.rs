@@ -0,0 +1,315 @@
+//! The command line of the `order_total` binary. Without a subcommand it
+//! serves the API, as `serve` does; the other subcommands use the same
+//! configuration to do one job and exit.
+
+use crate::codec::Format;
+use crate::error::ComputeError;
+use crate::ndjson::{encode_line, Lines};
+use crate::{config, config_file, logging, shutdown, App, RuntimeFlavor};
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use hyper::body::Bytes;
+use serde_json::Value;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Computes order totals with sales tax. Every flag stands for an
+/// environment variable, which it overrides; see the README.
+#[derive(Debug, Parser)]
+#[command(name = "order_total", version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The flags of `serve`, which runs without a subcommand
+    #[command(flatten)]
+    serve: ServeArgs,
+    /// TOML file of further settings (`CONFIG_FILE`); the environment overrides it
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// `current_thread` or `multi_thread` (`RUNTIME_FLAVOR`)
+    #[arg(long, global = true, value_name = "FLAVOR")]
+    runtime: Option<String>,
+    /// Worker threads of the `multi_thread` runtime (`WORKER_THREADS`)
+    #[arg(long, global = true, value_name = "N")]
+    worker_threads: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the API until asked to shut down (the default)
+    Serve(ServeArgs),
+    /// Compute the orders of a JSON file, one order or an array of them,
+    /// without a server, printing the results
+    Compute {
+        /// The JSON file of orders
+        #[arg(long, value_name = "PATH")]
+        file: PathBuf,
+        /// Compute without storing or auditing the orders
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Compute newline-delimited JSON orders from standard input with the
+    /// embedded rate table, writing one result line per order line to
+    /// standard output; never touches the network
+    Pipe {
+        /// Compute without storing or auditing the orders
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the configuration and exit
+    CheckConfig,
+    /// Look up the rates of `CACHE_WARM_ZIPS` into the shared Redis cache
+    /// and exit
+    WarmCache,
+    /// Answer one HTTP request handed over as CGI, as Spin's `wagi`
+    /// executor does: the request in the environment and on standard
+    /// input, the response on standard output
+    Wagi,
+}
+
+#[derive(Debug, Default, Args)]
+struct ServeArgs {
+    /// Listen address, which may include a port (`BIND_ADDR`)
+    #[arg(long, value_name = "ADDR")]
+    bind: Option<String>,
+    /// Listen port (`PORT`)
+    #[arg(long)]
+    port: Option<u16>,
+    /// `http` or `kafka` (`RUN_MODE`)
+    #[arg(long, value_name = "MODE")]
+    mode: Option<String>,
+}
+
+impl Cli {
+    /// Sets the environment variables the flags stand for, then loads the
+    /// `--config` file, which only fills in those still unset: flags win
+    /// over the environment, which wins over the file.
+    ///
+    /// It changes the process environment, so it runs before the runtime
+    /// starts any threads.
+    pub fn configure(&mut self) -> anyhow::Result<()> {
+        let serve = match &self.command {
+            Some(Command::Serve(serve)) => serve,
+            _ => &self.serve,
+        };
+        let flags = [
+            ("BIND_ADDR", serve.bind.clone()),
+            ("PORT", serve.port.map(|port| port.to_string())),
+            ("RUN_MODE", serve.mode.clone()),
+            ("RUNTIME_FLAVOR", self.runtime.clone()),
+            (
+                "WORKER_THREADS",
+                self.worker_threads.map(|workers| workers.to_string()),
+            ),
+        ];
+        for (name, value) in flags {
+            if let Some(value) = value {
+                std::env::set_var(name, value);
+            }
+        }
+        config_file::load(self.config.take())?;
+        if matches!(self.command, Some(Command::Pipe { .. })) {
+            offline()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the command on the runtime configured as `flavor`. Configuration
+    /// errors end the process with exit code 2; `compute` and `warm-cache`
+    /// exit with 1 when an order or a zip code failed.
+    pub async fn run(
+        self,
+        flavor: RuntimeFlavor,
+    ) -> Result<ExitCode, Box<dyn std::error::Error + Send + Sync>> {
+        let result = match self.command {
+            None | Some(Command::Serve(_)) => {
+                return crate::run(flavor).await.map(|()| ExitCode::SUCCESS)
+            }
+            Some(Command::Compute { file, dry_run }) => {
+                logging::init_to_stderr();
+                compute_file(&file, dry_run).await
+            }
+            Some(Command::Pipe { dry_run }) => {
+                logging::init_to_stderr();
+                pipe(dry_run).await
+            }
+            Some(Command::CheckConfig) => {
+                logging::init_to_stderr();
+                check_config().map(|()| {
+                    println!("configuration is valid");
+                    ExitCode::SUCCESS
+                })
+            }
+            Some(Command::WarmCache) => {
+                logging::init_to_stderr();
+                warm_cache().await
+            }
+            Some(Command::Wagi) => {
+                logging::init_to_stderr();
+                crate::wagi::run().await
+            }
+        };
+        Ok(result.unwrap_or_else(|err| {
+            eprintln!("{err:#}");
+            ExitCode::from(2)
+        }))
+    }
+}
+
+/// Computes the order, or each order of the array, in `path` and prints the
+/// computed order or error body for each, in the shape of the input.
+async fn compute_file(path: &Path, dry_run: bool) -> anyhow::Result<ExitCode> {
+    let input =
+        std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    let app = App::from_env()?;
+    let (batch, orders) = match serde_json::from_slice::<Value>(&input) {
+        Ok(Value::Array(orders)) => (
+            true,
+            orders
+                .iter()
+                .map(|order| Bytes::from(serde_json::to_vec(order).unwrap()))
+                .collect(),
+        ),
+        _ => (false, vec![Bytes::from(input)]),
+    };
+
+    let mut failed = false;
+    let mut results = Vec::with_capacity(orders.len());
+    for order in &orders {
+        let result = match crate::compute(order, Format::Json, app.schema, dry_run, &app).await {
+            Ok(order) => serde_json::to_value(order)?,
+            Err(err) => {
+                failed = true;
+                serde_json::to_value(crate::error::parts(err).1)?
+            }
+        };
+        results.push(result);
+    }
+    let output = if batch {
+        Value::Array(results)
+    } else {
+        results.remove(0)
+    };
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Settings that reach the network and would change the results, which
+/// `pipe` refuses.
+const ONLINE_SETTINGS: [&str; 2] = ["PRODUCT_CATALOG_URL", "EXEMPTION_VERIFIER_URL"];
+/// Settings that reach the network without changing the results, which
+/// `pipe` leaves out.
+const OFFLINE_DROPPED: [&str; 2] = ["REDIS_URL", "OTEL_EXPORTER_OTLP_ENDPOINT"];
+
+/// Configures `pipe` to compute without the network: rates come from the
+/// rate table.
+fn offline() -> anyhow::Result<()> {
+    if let Some(name) = ONLINE_SETTINGS
+        .into_iter()
+        .find(|name| std::env::var_os(name).is_some())
+    {
+        anyhow::bail!("pipe computes offline, so {name} cannot be set");
+    }
+    std::env::set_var("TAX_RATE_SOURCE", "embedded");
+    for name in OFFLINE_DROPPED {
+        std::env::remove_var(name);
+    }
+    Ok(())
+}
+
+/// Reads order lines from standard input until it ends, answering each on
+/// standard output as soon as it is computed. A reader that goes away ends
+/// the pipe quietly, as it does other Unix filters.
+async fn pipe(dry_run: bool) -> anyhow::Result<ExitCode> {
+    let app = App::from_env()?;
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    let mut lines = Lines::new(app.max_body_bytes);
+    let mut chunk = vec![0; 64 * 1024];
+    let mut failed = false;
+    loop {
+        let read = match stdin.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => anyhow::bail!("could not read standard input: {err}"),
+        };
+        for line in lines.push(&chunk[..read]) {
+            if !answer(line, dry_run, &app, &mut stdout, &mut failed).await? {
+                return Ok(ExitCode::SUCCESS);
+            }
+        }
+    }
+    if let Some(line) = lines.finish() {
+        answer(line, dry_run, &app, &mut stdout, &mut failed).await?;
+    }
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Computes one order line and writes its result line; `false` when the
+/// reader of standard output has gone away.
+async fn answer(
+    line: Result<Bytes, ComputeError>,
+    dry_run: bool,
+    app: &App,
+    out: &mut impl Write,
+    failed: &mut bool,
+) -> anyhow::Result<bool> {
+    let result = match line {
+        Ok(line) => crate::compute(&line, Format::Json, app.schema, dry_run, app).await,
+        Err(err) => Err(err),
+    };
+    *failed |= result.is_err();
+    match out
+        .write_all(&encode_line(result))
+        .and_then(|()| out.flush())
+    {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(false),
+        Err(err) => anyhow::bail!("could not write standard output: {err}"),
+    }
+}
+
+/// Builds everything `serve` would from the configuration, without
+/// listening.
+fn check_config() -> anyhow::Result<()> {
+    App::from_env()?;
+    config::run_mode()?;
+    #[cfg(feature = "server")]
+    crate::server::check_config()?;
+    config::nats_url()?;
+    shutdown::grace()?;
+    Ok(())
+}
+
+/// Warms the shared cache: the in-memory one ends with the process, so it
+/// needs `REDIS_URL`.
+async fn warm_cache() -> anyhow::Result<ExitCode> {
+    let app = App::from_env()?;
+    if !app.rate_cache.stats().shared {
+        anyhow::bail!("warm-cache fills the Redis cache of REDIS_URL, which is not set");
+    }
+    let warmed = app.warm_up.run(&*app.calculator().tax_rates).await;
+    if warmed.zip_codes == 0 {
+        anyhow::bail!("no zip codes to warm: set CACHE_WARM_ZIPS or CACHE_WARM_ZIPS_FILE");
+    }
+    println!(
+        "warmed {} zip codes, {} failed",
+        warmed.zip_codes - warmed.failed as usize,
+        warmed.failed
+    );
+    Ok(if warmed.failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
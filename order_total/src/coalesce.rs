@@ -0,0 +1,81 @@
+use crate::error::ComputeError;
+use crate::tax_rate::{self, TaxRate, TaxRateProvider};
+use async_trait::async_trait;
+use models::RateRequest;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+type Outcome = Option<Result<TaxRate, ComputeError>>;
+
+/// Shares one lookup between concurrent requests for the same zip code (and
+/// day): the
+/// first caller asks `inner`, and everyone who asks while that lookup is in
+/// flight waits for its result instead of issuing their own.
+pub struct Coalescing<P> {
+    inner: P,
+    in_flight: Mutex<HashMap<String, watch::Receiver<Outcome>>>,
+}
+
+impl<P> Coalescing<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Unregisters the lookup when the leading request finishes or is dropped.
+struct Flight<'a> {
+    in_flight: &'a Mutex<HashMap<String, watch::Receiver<Outcome>>>,
+    key: &'a str,
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(self.key);
+    }
+}
+
+#[async_trait]
+impl<P: TaxRateProvider> TaxRateProvider for Coalescing<P> {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        let key = tax_rate::key(request);
+        let waiting = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(rx) => Err(rx.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    in_flight.insert(key.clone(), rx);
+                    Ok(tx)
+                }
+            }
+        };
+
+        let mut rx = match waiting {
+            Ok(tx) => {
+                let flight = Flight {
+                    in_flight: &self.in_flight,
+                    key: &key,
+                };
+                let result = self.inner.find_rate(request).await;
+                drop(flight);
+                let _ = tx.send(Some(result.clone()));
+                return result;
+            }
+            Err(rx) => rx,
+        };
+        loop {
+            if let Some(result) = rx.borrow_and_update().clone() {
+                return result;
+            }
+            // The leading request went away before finishing; look it up
+            // ourselves rather than fail.
+            if rx.changed().await.is_err() {
+                return self.inner.find_rate(request).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::error::ComputeError;
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, ACCEPT, CONTENT_TYPE};
+use order_total_core::JsonError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The body formats `/compute` speaks. JSON is the default; MessagePack and
+/// CBOR are used when asked for by media type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.trim().to_ascii_lowercase().as_str() {
+            "application/json" => Some(Self::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            "application/cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// The format of the request body. Anything other than MessagePack or
+    /// CBOR is read as JSON, as before, since clients such as `curl -d` send
+    /// a form content type with JSON bodies.
+    pub fn of_request(headers: &HeaderMap) -> Self {
+        headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Self::from_media_type(value.split(';').next().unwrap_or_default()))
+            .unwrap_or(Self::Json)
+    }
+
+    /// The format to answer in: the supported type the `Accept` header
+    /// prefers (by q-value, then by position), otherwise `request`'s format.
+    pub fn of_response(headers: &HeaderMap, request: Self) -> Self {
+        let Some(accept) = headers.get(ACCEPT).and_then(|value| value.to_str().ok()) else {
+            return request;
+        };
+        let mut best: Option<(Self, f32)> = None;
+        for range in accept.split(',') {
+            let mut parts = range.split(';');
+            let Some(format) = Self::from_media_type(parts.next().unwrap_or_default()) else {
+                continue;
+            };
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((format, quality));
+            }
+        }
+        best.map_or(request, |(format, _)| format)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::MessagePack => "application/msgpack",
+            Self::Cbor => "application/cbor",
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, body: &[u8]) -> Result<T, ComputeError> {
+        match self {
+            Self::Json => serde_json::from_slice(body)
+                .map_err(|err| ComputeError::MalformedJson(JsonError::new(&err, body))),
+            Self::MessagePack => {
+                rmp_serde::from_slice(body).map_err(|_| ComputeError::InvalidRequest)
+            }
+            Self::Cbor => ciborium::from_reader(body).map_err(|_| ComputeError::InvalidRequest),
+        }
+    }
+
+    /// JSON is laid out as the request asks, see `pretty`; MessagePack maps
+    /// keep their field names so that the output mirrors the JSON one.
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Bytes, ComputeError> {
+        let unexpected =
+            |err: Box<dyn std::error::Error + Send + Sync>| ComputeError::Unexpected(err);
+        let body = match self {
+            Self::Json => crate::pretty::to_vec(value).map_err(|err| unexpected(err.into()))?,
+            Self::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|err| unexpected(err.into()))?
+            }
+            Self::Cbor => {
+                let mut body = Vec::new();
+                ciborium::into_writer(value, &mut body).map_err(|err| unexpected(err.into()))?;
+                body
+            }
+        };
+        Ok(body.into())
+    }
+}
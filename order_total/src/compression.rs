@@ -0,0 +1,309 @@
+use crate::body::{self, Body, BoxError, HttpBody};
+use crate::config::env_or;
+use crate::error::ComputeError;
+use brotli::{CompressorWriter, DecompressorWriter};
+use flate2::write::{GzDecoder, GzEncoder};
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, VARY,
+};
+use hyper::{Request, Response, StatusCode};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Brotli quality and window; a fast setting, since responses are compressed
+/// on the fly.
+const BROTLI_QUALITY: u32 = 4;
+const BROTLI_WINDOW: u32 = 22;
+const BUFFER_SIZE: usize = 4096;
+
+/// The content codings understood in both directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "br" => Some(Self::Brotli),
+            _ => None,
+        }
+    }
+
+    /// The coding to compress a response with: the one `Accept-Encoding`
+    /// gives the highest weight, brotli on a tie. `*` stands for gzip.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        let mut best: Option<(Self, f32)> = None;
+        let values = headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok());
+        for item in values.flat_map(|value| value.split(',')) {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let weight = match params.find_map(|param| param.trim().strip_prefix("q=")) {
+                Some(q) => q.trim().parse::<f32>().unwrap_or(0.0),
+                None => 1.0,
+            };
+            let encoding = match name {
+                "*" => Self::Gzip,
+                name => match Self::from_name(name) {
+                    Some(encoding) => encoding,
+                    None => continue,
+                },
+            };
+            let better = match best {
+                None => true,
+                Some((current, q)) => {
+                    weight > q || (weight == q && encoding == Self::Brotli && current != encoding)
+                }
+            };
+            if weight > 0.0 && better {
+                best = Some((encoding, weight));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+/// Whether responses are compressed, `COMPRESS_RESPONSES` (default `true`),
+/// and the body size below which they are not, `COMPRESS_MIN_BYTES`
+/// (default 1024).
+pub struct ResponseCompression {
+    enabled: bool,
+    min_bytes: u64,
+}
+
+impl ResponseCompression {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            enabled: env_or("COMPRESS_RESPONSES", true)?,
+            min_bytes: env_or("COMPRESS_MIN_BYTES", 1024)?,
+        })
+    }
+
+    /// The coding to compress the response to a request with `headers`, if
+    /// any.
+    pub fn negotiate(&self, headers: &HeaderMap) -> Option<Encoding> {
+        Encoding::negotiate(headers).filter(|_| self.enabled)
+    }
+
+    pub fn apply(&self, response: Response<Body>, encoding: Encoding) -> Response<Body> {
+        compress(response, encoding, self.min_bytes)
+    }
+}
+
+/// Compresses `response` with `encoding`, unless it is already encoded, has
+/// no body, or its body is known to be shorter than `min_bytes`. Streamed
+/// bodies are compressed chunk by chunk, each chunk flushed as it goes, so
+/// `/v1/compute_stream` lines still arrive as they are computed.
+fn compress(mut response: Response<Body>, encoding: Encoding, min_bytes: u64) -> Response<Body> {
+    let headers = response.headers_mut();
+    headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    let status = response.status();
+    let small = response
+        .body()
+        .size_hint()
+        .exact()
+        .is_some_and(|length| length < min_bytes);
+    if small
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || response.headers().contains_key(CONTENT_ENCODING)
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    let coder = match encoding {
+        Encoding::Gzip => {
+            Coder::GzipEncoder(GzEncoder::new(Vec::new(), flate2::Compression::default()))
+        }
+        Encoding::Brotli => Coder::BrotliEncoder(Box::new(CompressorWriter::new(
+            Vec::new(),
+            BUFFER_SIZE,
+            BROTLI_QUALITY,
+            BROTLI_WINDOW,
+        ))),
+    };
+    Response::from_parts(parts, transcode(body, coder))
+}
+
+/// Decodes a body sent with `Content-Encoding: gzip` or `br` as it is read,
+/// so that the body size limits apply to the decoded bytes. A corrupt body
+/// surfaces when it is read, see `is_invalid_encoding`, and so does a chunk
+/// that decodes to more than `limit` bytes, see `is_too_large`: the decoder
+/// stops there rather than inflating it all.
+pub fn decompress(req: Request<Body>, limit: usize) -> Result<Request<Body>, ComputeError> {
+    let Some(value) = req.headers().get(CONTENT_ENCODING) else {
+        return Ok(req);
+    };
+    let name = value
+        .to_str()
+        .map_err(|_| ComputeError::UnsupportedEncoding)?
+        .trim();
+    if name.eq_ignore_ascii_case("identity") {
+        return Ok(req);
+    }
+    let coder = match Encoding::from_name(name).ok_or(ComputeError::UnsupportedEncoding)? {
+        Encoding::Gzip => Coder::GzipDecoder(GzDecoder::new(Bounded::new(limit))),
+        Encoding::Brotli => Coder::BrotliDecoder(Box::new(DecompressorWriter::new(
+            Bounded::new(limit),
+            BUFFER_SIZE,
+        ))),
+    };
+
+    let (mut parts, body) = req.into_parts();
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+    Ok(Request::from_parts(parts, transcode(body, coder)))
+}
+
+/// Whether reading a body failed because it was not validly encoded, as
+/// opposed to the connection failing.
+pub fn is_invalid_encoding(err: &body::Error) -> bool {
+    std::error::Error::source(err).is_some_and(|source| source.is::<InvalidEncoding>())
+}
+
+/// Whether reading a body failed because a chunk of it decoded to more than
+/// the limit given to `decompress`.
+pub fn is_too_large(err: &body::Error) -> bool {
+    std::error::Error::source(err).is_some_and(|source| source.is::<TooLarge>())
+}
+
+#[derive(Debug)]
+struct InvalidEncoding(io::Error);
+
+impl fmt::Display for InvalidEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid request body encoding: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidEncoding {}
+
+#[derive(Debug)]
+struct TooLarge;
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request body decodes past the size limit")
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// The output buffer of a decoder, refusing to grow past `limit` bytes
+/// between two drains.
+struct Bounded {
+    output: Vec<u8>,
+    limit: usize,
+}
+
+impl Bounded {
+    fn new(limit: usize) -> Self {
+        Self {
+            output: Vec::new(),
+            limit,
+        }
+    }
+}
+
+impl Write for Bounded {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.output.len() + buf.len() > self.limit {
+            return Err(io::Error::other(TooLarge));
+        }
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A streaming encoder or decoder writing into a buffer that is drained
+/// after every chunk.
+enum Coder {
+    GzipEncoder(GzEncoder<Vec<u8>>),
+    BrotliEncoder(Box<CompressorWriter<Vec<u8>>>),
+    GzipDecoder(GzDecoder<Bounded>),
+    BrotliDecoder(Box<DecompressorWriter<Bounded>>),
+}
+
+impl Coder {
+    /// Feeds `chunk` through and returns the output it produced.
+    fn push(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let output = match self {
+            Self::GzipEncoder(coder) => write_flush(coder, chunk)?.get_mut(),
+            Self::BrotliEncoder(coder) => write_flush(coder.as_mut(), chunk)?.get_mut(),
+            Self::GzipDecoder(coder) => &mut write_flush(coder, chunk)?.get_mut().output,
+            Self::BrotliDecoder(coder) => &mut write_flush(coder.as_mut(), chunk)?.get_mut().output,
+        };
+        Ok(std::mem::take(output).into())
+    }
+
+    /// Ends the stream and returns the remaining output.
+    fn finish(self) -> io::Result<Bytes> {
+        let output = match self {
+            Self::GzipEncoder(coder) => coder.finish()?,
+            Self::BrotliEncoder(coder) => coder.into_inner(),
+            Self::GzipDecoder(coder) => coder.finish()?.output,
+            Self::BrotliDecoder(coder) => {
+                coder
+                    .into_inner()
+                    .map_err(|_| {
+                        io::Error::new(io::ErrorKind::UnexpectedEof, "truncated brotli stream")
+                    })?
+                    .output
+            }
+        };
+        Ok(output.into())
+    }
+}
+
+fn write_flush<'a, W: Write>(writer: &'a mut W, chunk: &[u8]) -> io::Result<&'a mut W> {
+    writer.write_all(chunk)?;
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// Runs every chunk of `body` through `coder`.
+fn transcode(body: Body, coder: Coder) -> Body {
+    let stream = futures_util::stream::unfold(Some((body, coder)), |state| async move {
+        let (mut body, mut coder) = state?;
+        let (output, next) = match body::data(&mut body).await {
+            Some(Ok(chunk)) => match coder.push(&chunk) {
+                Ok(output) => (Ok(output), Some((body, coder))),
+                Err(err) => (Err(coding_error(err)), None),
+            },
+            Some(Err(err)) => (Err(err.into()), None),
+            None => (coder.finish().map_err(coding_error), None),
+        };
+        Some((output, next))
+    });
+    Body::wrap_stream::<_, Bytes, BoxError>(stream)
+}
+
+fn coding_error(err: io::Error) -> BoxError {
+    if err.get_ref().is_some_and(|inner| inner.is::<TooLarge>()) {
+        TooLarge.into()
+    } else {
+        InvalidEncoding(err).into()
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::anyhow;
+use rust_decimal::Decimal;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// Reads and parses an environment variable, falling back to `default` when
+/// it is unset or blank. A value that is set but does not parse is an error
+/// naming the variable, rather than quietly standing for the default.
+pub fn env_or<T>(name: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    Ok(env_opt(name)?.unwrap_or(default))
+}
+
+/// Reads and parses an environment variable that has no default, see
+/// `env_or`.
+pub fn env_opt<T>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|err| anyhow!("invalid {name} {value:?}: {err}")),
+        _ => Ok(None),
+    }
+}
+
+/// Resolves the address the server listens on from the `BIND_ADDR`/`PORT`
+/// environment variables (which the `--bind`/`--port` command line flags
+/// set, see `cli`), defaulting to `0.0.0.0:8002`. `BIND_ADDR` may also carry
+/// a port (`127.0.0.1:9000`), in which case it is only overridden by an
+/// explicit port setting.
+#[cfg(feature = "server")]
+pub fn listen_addr() -> anyhow::Result<SocketAddr> {
+    let bind = std::env::var("BIND_ADDR").ok();
+    let port = std::env::var("PORT").ok();
+
+    let mut addr = match bind {
+        Some(bind) => parse_bind_addr(&bind)?,
+        None => SocketAddr::from(([0, 0, 0, 0], DEFAULT_PORT)),
+    };
+    if let Some(port) = port {
+        let port = port
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| anyhow!("invalid port {port:?}: expected a number between 0 and 65535"))?;
+        addr.set_port(port);
+    }
+    Ok(addr)
+}
+
+const DEFAULT_PORT: u16 = 8002;
+
+/// The address of the gRPC listener: `GRPC_PORT` on the IP of the HTTP
+/// listener `listen`, or `None` when `GRPC_PORT` is unset and gRPC is off.
+#[cfg(feature = "server")]
+pub fn grpc_addr(listen: SocketAddr) -> anyhow::Result<Option<SocketAddr>> {
+    let Ok(port) = std::env::var("GRPC_PORT") else {
+        return Ok(None);
+    };
+    let port = port.trim().parse::<u16>().map_err(|_| {
+        anyhow!("invalid GRPC_PORT {port:?}: expected a number between 0 and 65535")
+    })?;
+    Ok(Some(SocketAddr::new(listen.ip(), port)))
+}
+
+pub(crate) fn parse_bind_addr(value: &str) -> anyhow::Result<SocketAddr> {
+    let value = value.trim();
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, DEFAULT_PORT))
+        .map_err(|_| {
+            anyhow!("invalid bind address {value:?}: expected an IP address such as 0.0.0.0 or 127.0.0.1:8002")
+        })
+}
+
+/// The Tokio runtime the server runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeFlavor {
+    /// Everything on the main thread; the only choice for WASI, which has no
+    /// threads.
+    CurrentThread,
+    /// A work-stealing pool; `workers` defaults to one per CPU core.
+    MultiThread { workers: Option<usize> },
+}
+
+/// Resolves the runtime from the `RUNTIME_FLAVOR`/`WORKER_THREADS`
+/// environment variables (or the `--runtime`/`--worker-threads` flags).
+/// Native builds default to `multi_thread`, WASI builds to `current_thread`;
+/// setting a worker count implies `multi_thread`.
+pub fn runtime_flavor() -> anyhow::Result<RuntimeFlavor> {
+    let flavor = std::env::var("RUNTIME_FLAVOR").ok();
+    let workers = std::env::var("WORKER_THREADS").ok();
+    let workers = match workers {
+        Some(workers) => Some(
+            workers
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| {
+                    anyhow!("invalid worker thread count {workers:?}: expected a positive number")
+                })?,
+        ),
+        None => None,
+    };
+
+    let flavor = match flavor.as_deref().map(str::trim) {
+        Some("current_thread") if workers.is_some() => {
+            anyhow::bail!("a worker thread count needs the multi_thread runtime")
+        }
+        Some("current_thread") => RuntimeFlavor::CurrentThread,
+        Some("multi_thread") => RuntimeFlavor::MultiThread { workers },
+        Some(other) => anyhow::bail!(
+            "invalid runtime flavor {other:?}: expected current_thread or multi_thread"
+        ),
+        None if workers.is_none() && cfg!(target_os = "wasi") => RuntimeFlavor::CurrentThread,
+        None => RuntimeFlavor::MultiThread { workers },
+    };
+    if cfg!(target_os = "wasi") && flavor != RuntimeFlavor::CurrentThread {
+        anyhow::bail!("WASI has no threads, so only the current_thread runtime is available");
+    }
+    Ok(flavor)
+}
+
+impl RuntimeFlavor {
+    pub fn build(self) -> std::io::Result<tokio::runtime::Runtime> {
+        match self {
+            Self::CurrentThread => tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build(),
+            #[cfg(not(target_os = "wasi"))]
+            Self::MultiThread { workers } => {
+                let mut builder = tokio::runtime::Builder::new_multi_thread();
+                if let Some(workers) = workers {
+                    builder.worker_threads(workers);
+                }
+                builder.enable_all().build()
+            }
+            #[cfg(target_os = "wasi")]
+            Self::MultiThread { .. } => Err(std::io::Error::other("WASI has no threads")),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CurrentThread => f.write_str("current_thread"),
+            Self::MultiThread { workers: None } => f.write_str("multi_thread"),
+            Self::MultiThread {
+                workers: Some(workers),
+            } => write!(f, "multi_thread ({workers} workers)"),
+        }
+    }
+}
+
+/// What the process does with the orders it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMode {
+    /// Serve the HTTP (and gRPC) API.
+    Http,
+    /// Consume orders from a Kafka topic, see `kafka`.
+    Kafka,
+}
+
+/// Resolves the mode from the `RUN_MODE` environment variable (or the
+/// `--mode` flag): `http` (the default) or `kafka`, which needs a build with
+/// the `kafka` feature.
+pub fn run_mode() -> anyhow::Result<RunMode> {
+    let mode = std::env::var("RUN_MODE").ok();
+    match mode.as_deref().map(str::trim) {
+        None | Some("http") => Ok(RunMode::Http),
+        Some("kafka") if cfg!(feature = "kafka") => Ok(RunMode::Kafka),
+        Some("kafka") => {
+            anyhow::bail!("this build has no kafka run mode; enable the kafka feature")
+        }
+        Some(other) => anyhow::bail!("invalid run mode {other:?}: expected http or kafka"),
+    }
+}
+
+/// Whether the server listens on TCP, `LISTEN_TCP` (default `true`). Turning
+/// it off needs a Unix socket to listen on instead, and leaves no listener
+/// for TLS.
+#[cfg(feature = "server")]
+pub fn listen_tcp(socket: bool, tls: bool) -> anyhow::Result<bool> {
+    let tcp = env_or("LISTEN_TCP", true)?;
+    if !tcp && !socket {
+        anyhow::bail!("LISTEN_TCP=false needs LISTEN_SOCKET, or nothing would be served");
+    }
+    if !tcp && tls {
+        anyhow::bail!("TLS is served over TCP, which LISTEN_TCP=false turns off");
+    }
+    Ok(tcp)
+}
+
+/// The NATS server(s) to answer compute requests from, `NATS_URL`, e.g.
+/// `nats://localhost:4222`. Unset, there is no NATS transport; it needs a
+/// build with the `nats` feature.
+pub fn nats_url() -> anyhow::Result<Option<String>> {
+    match std::env::var("NATS_URL") {
+        Ok(url) if !url.trim().is_empty() && cfg!(feature = "nats") => Ok(Some(url)),
+        Ok(url) if !url.trim().is_empty() => {
+            anyhow::bail!(
+                "NATS_URL is set, but this build has no NATS transport; enable the nats feature"
+            )
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Where `/compute` gets sales tax rates from, selected by `TAX_RATE_SOURCE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaxRateSource {
+    /// Ask the sales tax rate service (the default).
+    Service,
+    /// Use the embedded rate table only; no network calls.
+    Embedded,
+    /// Ask the service, answering from the embedded table when it fails.
+    Fallback,
+    /// Apply `FIXED_TAX_RATE` to every order, e.g. for local testing.
+    Fixed(Decimal),
+}
+
+impl FromStr for TaxRateSource {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "service" => Ok(Self::Service),
+            "embedded" => Ok(Self::Embedded),
+            "fallback" => Ok(Self::Fallback),
+            "fixed" => {
+                let rate = std::env::var("FIXED_TAX_RATE")
+                    .map_err(|_| anyhow!("TAX_RATE_SOURCE=fixed requires FIXED_TAX_RATE"))?;
+                let rate = rate.trim().parse().map_err(|_| {
+                    anyhow!("invalid FIXED_TAX_RATE {rate:?}: expected a decimal such as 0.0825")
+                })?;
+                Ok(Self::Fixed(rate))
+            }
+            other => Err(anyhow!(
+                "invalid TAX_RATE_SOURCE {other:?}: expected service, embedded, fallback or fixed"
+            )),
+        }
+    }
+}
+
+pub fn tax_rate_source() -> anyhow::Result<TaxRateSource> {
+    match std::env::var("TAX_RATE_SOURCE") {
+        Ok(value) => value.parse(),
+        Err(_) => Ok(TaxRateSource::Service),
+    }
+}
+
+/// The rate applied to zip codes no rate is known for, from
+/// `DEFAULT_TAX_RATE`. Without it such orders are rejected.
+pub fn default_tax_rate() -> anyhow::Result<Option<Decimal>> {
+    match std::env::var("DEFAULT_TAX_RATE") {
+        Ok(rate) => rate.trim().parse().map(Some).map_err(|_| {
+            anyhow!("invalid DEFAULT_TAX_RATE {rate:?}: expected a decimal such as 0.0825")
+        }),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,589 @@
+//! The `--config` file: a TOML file of `[server]`, `[upstream]`, `[cache]`,
+//! `[auth]`, `[logging]` and `[features]` settings, each standing for one of
+//! the environment variables the service is otherwise configured by. A variable
+//! set in the environment wins over the file.
+
+use crate::flags::Flag;
+use crate::schema_mode::SchemaMode;
+use anyhow::{anyhow, bail};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The settings of a configuration file. Every setting is optional; unknown
+/// ones are refused, so a misspelt key is not silently ignored.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub server: Server,
+    #[serde(default)]
+    pub upstream: Upstream,
+    #[serde(default)]
+    pub cache: Cache,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub logging: Logging,
+    #[serde(default)]
+    pub features: Features,
+}
+
+/// Where and how the service listens.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Server {
+    /// `RUN_MODE`
+    pub run_mode: Option<RunMode>,
+    /// `BIND_ADDR`, which may carry a port
+    pub bind_addr: Option<String>,
+    /// `PORT`
+    pub port: Option<u16>,
+    /// `GRPC_PORT`
+    pub grpc_port: Option<u16>,
+    /// `RUNTIME_FLAVOR`
+    pub runtime_flavor: Option<Flavor>,
+    /// `WORKER_THREADS`
+    pub worker_threads: Option<usize>,
+    /// `MAX_BODY_BYTES`
+    pub max_body_bytes: Option<usize>,
+    /// `MAX_IN_FLIGHT`
+    pub max_in_flight: Option<usize>,
+    /// `REQUEST_DEADLINE_MS`
+    pub request_deadline_ms: Option<u64>,
+    /// `REQUEST_TIMEOUT_MS`
+    pub request_timeout_ms: Option<u64>,
+    /// `SCHEMA_MODE`
+    pub schema_mode: Option<SchemaMode>,
+    /// `NUMERIC_STRINGS`
+    pub numeric_strings: Option<bool>,
+    /// `SHUTDOWN_GRACE_SECS`
+    pub shutdown_grace_secs: Option<u64>,
+    /// `TLS_CERT_PATH`
+    pub tls_cert_path: Option<PathBuf>,
+    /// `TLS_KEY_PATH`
+    pub tls_key_path: Option<PathBuf>,
+    /// `TLS_REDIRECT_PORT`
+    pub tls_redirect_port: Option<u16>,
+}
+
+/// Where tax rates come from, and how the rate service is called.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Upstream {
+    /// `SALES_TAX_RATE_SERVICE`, the replicas in the order they are tried
+    #[serde(default)]
+    pub urls: Vec<String>,
+    /// `SHADOW_TAX_RATE_SERVICE`, the replicas of the service compared with
+    #[serde(default)]
+    pub shadow_urls: Vec<String>,
+    /// `TAX_RATE_SOURCE`
+    pub tax_rate_source: Option<TaxRateSource>,
+    /// `FIXED_TAX_RATE`
+    pub fixed_tax_rate: Option<Decimal>,
+    /// `DEFAULT_TAX_RATE`
+    pub default_tax_rate: Option<Decimal>,
+    /// `TAX_RATE_TABLE`
+    pub rate_table: Option<PathBuf>,
+    /// `TAX_SERVICE_MAX_ATTEMPTS`
+    pub max_attempts: Option<u32>,
+    /// `TAX_SERVICE_RETRY_BASE_MS`
+    pub retry_base_ms: Option<u64>,
+    /// `TAX_SERVICE_RETRY_MAX_MS`
+    pub retry_max_ms: Option<u64>,
+    /// `TAX_SERVICE_TIMEOUT_MS`
+    pub timeout_ms: Option<u64>,
+    /// `TAX_SERVICE_CONNECT_TIMEOUT_MS`
+    pub connect_timeout_ms: Option<u64>,
+    /// `TAX_SERVICE_POOL_MAX_IDLE`
+    pub pool_max_idle: Option<usize>,
+    /// `TAX_SERVICE_POOL_IDLE_SECS`
+    pub pool_idle_secs: Option<u64>,
+    /// `TAX_SERVICE_TCP_KEEPALIVE_SECS`
+    pub tcp_keepalive_secs: Option<u64>,
+    /// `TAX_SERVICE_HEDGE_PERCENTILE`
+    pub hedge_percentile: Option<f64>,
+    /// `TAX_SERVICE_HEDGE_MIN_MS`
+    pub hedge_min_ms: Option<u64>,
+    /// `TAX_SERVICE_CA_BUNDLE`
+    pub ca_bundle: Option<PathBuf>,
+    /// `TAX_SERVICE_CLIENT_CERT`
+    pub client_cert: Option<PathBuf>,
+    /// `TAX_SERVICE_CLIENT_KEY`
+    pub client_key: Option<PathBuf>,
+    /// `CIRCUIT_BREAKER_THRESHOLD`
+    pub circuit_breaker_threshold: Option<u32>,
+    /// `CIRCUIT_BREAKER_OPEN_SECS`
+    pub circuit_breaker_open_secs: Option<u64>,
+}
+
+/// The rate caches and their warm-up.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cache {
+    /// `RATE_CACHE_SECS`
+    pub secs: Option<u64>,
+    /// `RATE_CACHE_MAX`
+    pub max_entries: Option<usize>,
+    /// `REDIS_URL`
+    pub redis_url: Option<String>,
+    /// `REDIS_KEY_PREFIX`
+    pub redis_key_prefix: Option<String>,
+    /// `REDIS_TIMEOUT_MS`
+    pub redis_timeout_ms: Option<u64>,
+    /// `CACHE_WARM_ZIPS`
+    #[serde(default)]
+    pub warm_zips: Vec<String>,
+    /// `CACHE_WARM_ZIPS_FILE`
+    pub warm_zips_file: Option<PathBuf>,
+    /// `CACHE_WARM_CONCURRENCY`
+    pub warm_concurrency: Option<usize>,
+    /// `UNKNOWN_ZIP_CACHE_SECS`
+    pub unknown_zip_secs: Option<u64>,
+    /// `UNKNOWN_ZIP_CACHE_MAX`
+    pub unknown_zip_max: Option<usize>,
+}
+
+/// Who may call the API and the admin endpoints.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Auth {
+    /// `ADMIN_TOKEN`
+    pub admin_token: Option<String>,
+    /// `JWT_SECRET`
+    pub jwt_secret: Option<String>,
+    /// `JWT_PUBLIC_KEY_PATH`
+    pub jwt_public_key_path: Option<PathBuf>,
+    /// `JWT_JWKS_URL`
+    pub jwt_jwks_url: Option<String>,
+    /// `JWT_JWKS_CONNECT_TIMEOUT_MS`
+    pub jwt_jwks_connect_timeout_ms: Option<u64>,
+    /// `JWT_JWKS_TIMEOUT_MS`
+    pub jwt_jwks_timeout_ms: Option<u64>,
+    /// `JWT_ISSUER`
+    pub jwt_issuer: Option<String>,
+    /// `JWT_AUDIENCE`
+    pub jwt_audience: Option<String>,
+    /// `API_KEYS_FILE`
+    pub api_keys_file: Option<PathBuf>,
+    /// `API_KEY_RATE_LIMIT`
+    pub api_key_rate_limit: Option<u32>,
+}
+
+/// Logs and traces.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Logging {
+    /// `RUST_LOG`
+    pub level: Option<String>,
+    /// `LOG_FORMAT`
+    pub format: Option<LogFormat>,
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT`
+    pub otlp_endpoint: Option<String>,
+    /// `OTEL_SERVICE_NAME`
+    pub service_name: Option<String>,
+    /// `ACCESS_LOG`
+    pub access_log: Option<bool>,
+    /// `ACCESS_LOG_SAMPLE_RATE`
+    pub access_log_sample_rate: Option<f64>,
+    /// `SENTRY_DSN`
+    pub sentry_dsn: Option<String>,
+    /// `SENTRY_ENVIRONMENT`
+    pub sentry_environment: Option<String>,
+}
+
+/// Feature flags, which together stand for `FEATURE_FLAGS`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Features {
+    /// Whether each flag is on for the whole service, e.g.
+    /// `flags = { batch = false }`
+    #[serde(default)]
+    pub flags: BTreeMap<String, bool>,
+    /// The flags of a tenant, which win over `flags`, e.g.
+    /// `[features.tenants.acme]` with `batch = true`
+    #[serde(default)]
+    pub tenants: BTreeMap<String, BTreeMap<String, bool>>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    Http,
+    Kafka,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flavor {
+    CurrentThread,
+    MultiThread,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaxRateSource {
+    Service,
+    Embedded,
+    Fallback,
+    Fixed,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl Config {
+    /// Reads and validates the file at `path`. Errors name the file and
+    /// the offending setting.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("could not read {}: {err}", path.display()))?;
+        let config: Self =
+            toml::from_str(&text).map_err(|err| anyhow!("{}: {err}", path.display()))?;
+        config
+            .validate()
+            .map_err(|err| anyhow!("{}: {err}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Checks what the types of the settings cannot: ranges, URLs, and
+    /// settings that only make sense together.
+    fn validate(&self) -> anyhow::Result<()> {
+        let Self {
+            server,
+            upstream,
+            cache,
+            auth,
+            logging,
+            features,
+        } = self;
+
+        if let Some(bind) = &server.bind_addr {
+            crate::config::parse_bind_addr(bind)
+                .map_err(|err| anyhow!("server.bind_addr: {err}"))?;
+        }
+        if server.worker_threads == Some(0) {
+            bail!("server.worker_threads must be a positive number");
+        }
+        if matches!(server.runtime_flavor, Some(Flavor::CurrentThread))
+            && server.worker_threads.is_some()
+        {
+            bail!("server.worker_threads needs runtime_flavor = \"multi_thread\"");
+        }
+        if server.tls_cert_path.is_some() != server.tls_key_path.is_some() {
+            bail!("server.tls_cert_path and server.tls_key_path must be set together");
+        }
+
+        for (index, url) in upstream.urls.iter().enumerate() {
+            check_url(url).map_err(|err| anyhow!("upstream.urls[{index}]: {err}"))?;
+        }
+        for (index, url) in upstream.shadow_urls.iter().enumerate() {
+            check_url(url).map_err(|err| anyhow!("upstream.shadow_urls[{index}]: {err}"))?;
+        }
+        if matches!(upstream.tax_rate_source, Some(TaxRateSource::Fixed))
+            && upstream.fixed_tax_rate.is_none()
+        {
+            bail!("upstream.tax_rate_source = \"fixed\" needs upstream.fixed_tax_rate");
+        }
+        for (name, rate) in [
+            ("fixed_tax_rate", upstream.fixed_tax_rate),
+            ("default_tax_rate", upstream.default_tax_rate),
+        ] {
+            if rate.is_some_and(|rate| rate < Decimal::ZERO || rate >= Decimal::ONE) {
+                bail!("upstream.{name} must be a fraction between 0 and 1, e.g. 0.0825");
+            }
+        }
+        if upstream.max_attempts == Some(0) {
+            bail!("upstream.max_attempts must be at least 1");
+        }
+        if let (Some(base), Some(max)) = (upstream.retry_base_ms, upstream.retry_max_ms) {
+            if base > max {
+                bail!("upstream.retry_base_ms ({base}) exceeds upstream.retry_max_ms ({max})");
+            }
+        }
+        if upstream
+            .hedge_percentile
+            .is_some_and(|percentile| !(percentile > 0.0 && percentile < 100.0))
+        {
+            bail!("upstream.hedge_percentile must be between 0 and 100, e.g. 95");
+        }
+        if upstream.client_cert.is_some() != upstream.client_key.is_some() {
+            bail!("upstream.client_cert and upstream.client_key must be set together");
+        }
+
+        if let Some(url) = &cache.redis_url {
+            check_url(url).map_err(|err| anyhow!("cache.redis_url: {err}"))?;
+        }
+        if cache.warm_concurrency == Some(0) {
+            bail!("cache.warm_concurrency must be at least 1");
+        }
+
+        let verifiers = [
+            auth.jwt_secret.is_some(),
+            auth.jwt_public_key_path.is_some(),
+            auth.jwt_jwks_url.is_some(),
+        ];
+        if verifiers.into_iter().filter(|&set| set).count() > 1 {
+            bail!("set one of auth.jwt_secret, auth.jwt_public_key_path and auth.jwt_jwks_url");
+        }
+        if let Some(url) = &auth.jwt_jwks_url {
+            check_url(url).map_err(|err| anyhow!("auth.jwt_jwks_url: {err}"))?;
+        }
+
+        if let Some(level) = &logging.level {
+            tracing_subscriber::EnvFilter::try_new(level)
+                .map_err(|err| anyhow!("logging.level: invalid filter {level:?}: {err}"))?;
+        }
+        if let Some(url) = &logging.otlp_endpoint {
+            check_url(url).map_err(|err| anyhow!("logging.otlp_endpoint: {err}"))?;
+        }
+        if logging
+            .access_log_sample_rate
+            .is_some_and(|rate| !(0.0..=1.0).contains(&rate))
+        {
+            bail!("logging.access_log_sample_rate must be between 0 and 1, e.g. 0.1");
+        }
+
+        for name in features.flags.keys() {
+            name.parse::<Flag>()
+                .map_err(|err| anyhow!("features.flags: {err}"))?;
+        }
+        for (tenant, flags) in &features.tenants {
+            for name in flags.keys() {
+                name.parse::<Flag>()
+                    .map_err(|err| anyhow!("features.tenants.{tenant}: {err}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The environment variables the file stands for, with their values.
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        let Self {
+            server,
+            upstream,
+            cache,
+            auth,
+            logging,
+            features,
+        } = self;
+        let mut vars = Vars::default();
+
+        vars.set(
+            "RUN_MODE",
+            server.run_mode.map(|mode| match mode {
+                RunMode::Http => "http",
+                RunMode::Kafka => "kafka",
+            }),
+        );
+        vars.set("BIND_ADDR", server.bind_addr.as_ref());
+        vars.set("PORT", server.port);
+        vars.set("GRPC_PORT", server.grpc_port);
+        vars.set(
+            "RUNTIME_FLAVOR",
+            server.runtime_flavor.map(|flavor| match flavor {
+                Flavor::CurrentThread => "current_thread",
+                Flavor::MultiThread => "multi_thread",
+            }),
+        );
+        vars.set("WORKER_THREADS", server.worker_threads);
+        vars.set("MAX_BODY_BYTES", server.max_body_bytes);
+        vars.set("MAX_IN_FLIGHT", server.max_in_flight);
+        vars.set("REQUEST_DEADLINE_MS", server.request_deadline_ms);
+        vars.set("REQUEST_TIMEOUT_MS", server.request_timeout_ms);
+        vars.set("SCHEMA_MODE", server.schema_mode.map(SchemaMode::name));
+        vars.set("NUMERIC_STRINGS", server.numeric_strings);
+        vars.set("SHUTDOWN_GRACE_SECS", server.shutdown_grace_secs);
+        vars.path("TLS_CERT_PATH", &server.tls_cert_path);
+        vars.path("TLS_KEY_PATH", &server.tls_key_path);
+        vars.set("TLS_REDIRECT_PORT", server.tls_redirect_port);
+
+        vars.list("SALES_TAX_RATE_SERVICE", &upstream.urls);
+        vars.list("SHADOW_TAX_RATE_SERVICE", &upstream.shadow_urls);
+        vars.set(
+            "TAX_RATE_SOURCE",
+            upstream.tax_rate_source.map(|source| match source {
+                TaxRateSource::Service => "service",
+                TaxRateSource::Embedded => "embedded",
+                TaxRateSource::Fallback => "fallback",
+                TaxRateSource::Fixed => "fixed",
+            }),
+        );
+        vars.set("FIXED_TAX_RATE", upstream.fixed_tax_rate);
+        vars.set("DEFAULT_TAX_RATE", upstream.default_tax_rate);
+        vars.path("TAX_RATE_TABLE", &upstream.rate_table);
+        vars.set("TAX_SERVICE_MAX_ATTEMPTS", upstream.max_attempts);
+        vars.set("TAX_SERVICE_RETRY_BASE_MS", upstream.retry_base_ms);
+        vars.set("TAX_SERVICE_RETRY_MAX_MS", upstream.retry_max_ms);
+        vars.set("TAX_SERVICE_TIMEOUT_MS", upstream.timeout_ms);
+        vars.set(
+            "TAX_SERVICE_CONNECT_TIMEOUT_MS",
+            upstream.connect_timeout_ms,
+        );
+        vars.set("TAX_SERVICE_POOL_MAX_IDLE", upstream.pool_max_idle);
+        vars.set("TAX_SERVICE_POOL_IDLE_SECS", upstream.pool_idle_secs);
+        vars.set(
+            "TAX_SERVICE_TCP_KEEPALIVE_SECS",
+            upstream.tcp_keepalive_secs,
+        );
+        vars.set("TAX_SERVICE_HEDGE_PERCENTILE", upstream.hedge_percentile);
+        vars.set("TAX_SERVICE_HEDGE_MIN_MS", upstream.hedge_min_ms);
+        vars.path("TAX_SERVICE_CA_BUNDLE", &upstream.ca_bundle);
+        vars.path("TAX_SERVICE_CLIENT_CERT", &upstream.client_cert);
+        vars.path("TAX_SERVICE_CLIENT_KEY", &upstream.client_key);
+        vars.set(
+            "CIRCUIT_BREAKER_THRESHOLD",
+            upstream.circuit_breaker_threshold,
+        );
+        vars.set(
+            "CIRCUIT_BREAKER_OPEN_SECS",
+            upstream.circuit_breaker_open_secs,
+        );
+
+        vars.set("RATE_CACHE_SECS", cache.secs);
+        vars.set("RATE_CACHE_MAX", cache.max_entries);
+        vars.set("REDIS_URL", cache.redis_url.as_ref());
+        vars.set("REDIS_KEY_PREFIX", cache.redis_key_prefix.as_ref());
+        vars.set("REDIS_TIMEOUT_MS", cache.redis_timeout_ms);
+        vars.list("CACHE_WARM_ZIPS", &cache.warm_zips);
+        vars.path("CACHE_WARM_ZIPS_FILE", &cache.warm_zips_file);
+        vars.set("CACHE_WARM_CONCURRENCY", cache.warm_concurrency);
+        vars.set("UNKNOWN_ZIP_CACHE_SECS", cache.unknown_zip_secs);
+        vars.set("UNKNOWN_ZIP_CACHE_MAX", cache.unknown_zip_max);
+
+        vars.set("ADMIN_TOKEN", auth.admin_token.as_ref());
+        vars.set("JWT_SECRET", auth.jwt_secret.as_ref());
+        vars.path("JWT_PUBLIC_KEY_PATH", &auth.jwt_public_key_path);
+        vars.set("JWT_JWKS_URL", auth.jwt_jwks_url.as_ref());
+        vars.set(
+            "JWT_JWKS_CONNECT_TIMEOUT_MS",
+            auth.jwt_jwks_connect_timeout_ms,
+        );
+        vars.set("JWT_JWKS_TIMEOUT_MS", auth.jwt_jwks_timeout_ms);
+        vars.set("JWT_ISSUER", auth.jwt_issuer.as_ref());
+        vars.set("JWT_AUDIENCE", auth.jwt_audience.as_ref());
+        vars.path("API_KEYS_FILE", &auth.api_keys_file);
+        vars.set("API_KEY_RATE_LIMIT", auth.api_key_rate_limit);
+
+        vars.set("RUST_LOG", logging.level.as_ref());
+        vars.set(
+            "LOG_FORMAT",
+            logging.format.map(|format| match format {
+                LogFormat::Text => "text",
+                LogFormat::Json => "json",
+            }),
+        );
+        vars.set(
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            logging.otlp_endpoint.as_ref(),
+        );
+        vars.set("OTEL_SERVICE_NAME", logging.service_name.as_ref());
+        vars.set("ACCESS_LOG", logging.access_log);
+        vars.set("ACCESS_LOG_SAMPLE_RATE", logging.access_log_sample_rate);
+        vars.set("SENTRY_DSN", logging.sentry_dsn.as_ref());
+        vars.set("SENTRY_ENVIRONMENT", logging.sentry_environment.as_ref());
+
+        let setting = |name: &str, on: bool| format!("{name}={}", if on { "on" } else { "off" });
+        let flags: Vec<String> = features
+            .flags
+            .iter()
+            .map(|(name, &on)| setting(name, on))
+            .chain(features.tenants.iter().flat_map(|(tenant, flags)| {
+                flags
+                    .iter()
+                    .map(move |(name, &on)| format!("{tenant}:{}", setting(name, on)))
+            }))
+            .collect();
+        vars.list("FEATURE_FLAGS", &flags);
+        vars.0
+    }
+}
+
+#[derive(Default)]
+struct Vars(Vec<(&'static str, String)>);
+
+impl Vars {
+    fn set(&mut self, name: &'static str, value: Option<impl Display>) {
+        if let Some(value) = value {
+            self.0.push((name, value.to_string()));
+        }
+    }
+
+    fn path(&mut self, name: &'static str, value: &Option<PathBuf>) {
+        self.set(name, value.as_ref().map(|path| path.display()));
+    }
+
+    fn list(&mut self, name: &'static str, values: &[String]) {
+        if !values.is_empty() {
+            self.0.push((name, values.join(",")));
+        }
+    }
+}
+
+fn check_url(url: &str) -> anyhow::Result<()> {
+    reqwest::Url::parse(url)
+        .map(drop)
+        .map_err(|err| anyhow!("invalid URL {url:?}: {err}"))
+}
+
+/// The file in force and the variables it set, which a reload may change;
+/// the variables the environment set itself are never touched.
+static LOADED: Mutex<Option<(PathBuf, Vec<&'static str>)>> = Mutex::new(None);
+
+/// Loads the file named by the `--config` command line flag (`flag`) or,
+/// failing that, the `CONFIG_FILE` environment variable, and sets the
+/// variables it stands for that the environment does not set. Without
+/// either, there is no configuration file.
+///
+/// It changes the process environment, so it runs before the runtime starts
+/// any threads.
+pub(crate) fn load(flag: Option<PathBuf>) -> anyhow::Result<()> {
+    let path = flag.or_else(|| {
+        std::env::var_os("CONFIG_FILE")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let config = Config::load(&path)?;
+    let set = apply(&config);
+    *LOADED.lock().unwrap() = Some((path, set));
+    Ok(())
+}
+
+/// Reads the configuration file in force again, if there is one: the
+/// variables it set before are replaced by the ones it sets now. An invalid
+/// file changes nothing.
+pub(crate) fn reload() -> anyhow::Result<()> {
+    let mut loaded = LOADED.lock().unwrap();
+    let Some((path, previous)) = loaded.as_mut() else {
+        return Ok(());
+    };
+    let config = Config::load(path)?;
+    for name in previous.iter() {
+        std::env::remove_var(name);
+    }
+    *previous = apply(&config);
+    Ok(())
+}
+
+/// Sets the variables of `config` the environment does not set; returns the
+/// names it set.
+fn apply(config: &Config) -> Vec<&'static str> {
+    let mut set = Vec::new();
+    for (name, value) in config.vars() {
+        if std::env::var_os(name).is_none() {
+            std::env::set_var(name, value);
+            set.push(name);
+        }
+    }
+    set
+}
@@ -0,0 +1,369 @@
+use crate::body::{Body, BoxError, HttpBody};
+use crate::config::env_or;
+use anyhow::Context as _;
+use futures_util::{Stream, StreamExt};
+use hyper::{Request, Response};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::{Instant, Sleep};
+
+/// The connections a listener accepts, as `serve` takes them.
+pub type Connections<C> = Pin<Box<dyn Stream<Item = io::Result<C>> + Send>>;
+
+/// What a server speaks on its connections: HTTP/1.1 and HTTP/2 as
+/// `ConnectionSettings` say, or HTTP/2 alone, as gRPC does.
+#[derive(Clone, Copy)]
+pub enum Protocols<'a> {
+    Http(&'a ConnectionSettings),
+    Http2Only,
+}
+
+/// Listens on `addr`, returning the address actually bound (port 0 picks a
+/// free one) and the connections accepted there. Accepting a connection
+/// that is gone before it is picked up is skipped; any other failure, such
+/// as running out of file descriptors, is logged and retried a second later.
+#[cfg(feature = "wasmedge")]
+pub fn listen(addr: &SocketAddr) -> anyhow::Result<(SocketAddr, Connections<TcpStream>)> {
+    use hyper::server::accept::Accept;
+    use hyper::server::conn::{AddrIncoming, AddrStream};
+
+    let mut incoming =
+        AddrIncoming::bind(addr).with_context(|| format!("cannot listen on {addr}"))?;
+    let local_addr = incoming.local_addr();
+    let connections = futures_util::stream::poll_fn(move |cx| {
+        Pin::new(&mut incoming)
+            .poll_accept(cx)
+            .map(|accepted| accepted.map(|conn| conn.map(AddrStream::into_inner)))
+    });
+    Ok((local_addr, Box::pin(connections)))
+}
+
+/// Listens on `addr`, returning the address actually bound (port 0 picks a
+/// free one) and the connections accepted there. Accepting a connection
+/// that is gone before it is picked up is skipped; any other failure, such
+/// as running out of file descriptors, is logged and retried a second later.
+#[cfg(feature = "native")]
+pub fn listen(addr: &SocketAddr) -> anyhow::Result<(SocketAddr, Connections<TcpStream>)> {
+    let listener = std::net::TcpListener::bind(addr)
+        .and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            tokio::net::TcpListener::from_std(listener)
+        })
+        .with_context(|| format!("cannot listen on {addr}"))?;
+    let local_addr = listener.local_addr()?;
+    let connections = futures_util::stream::unfold(listener, |listener| async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => return Some((Ok(stream), listener)),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::ConnectionRefused
+                            | io::ErrorKind::ConnectionAborted
+                            | io::ErrorKind::ConnectionReset
+                    ) => {}
+                Err(err) => {
+                    tracing::error!(error = %err, "accept error");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+    Ok((local_addr, Box::pin(connections)))
+}
+
+/// Serves `connections` with `protocols` until `shutdown` resolves, then
+/// stops accepting and waits for the connections in progress to finish.
+/// `handler` makes what answers the requests of each connection.
+#[cfg(feature = "wasmedge")]
+pub async fn serve<C, H, F, E, B>(
+    connections: Connections<C>,
+    protocols: Protocols<'_>,
+    mut handler: impl FnMut(&C) -> H + Send + 'static,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    H: Fn(Request<Body>) -> F + Send + 'static,
+    F: Future<Output = Result<Response<B>, E>> + Send + 'static,
+    E: Into<BoxError>,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    use hyper::server::{accept, Server};
+    use hyper::service::{make_service_fn, service_fn};
+    use std::convert::Infallible;
+
+    let builder = Server::builder(accept::from_stream(connections));
+    let builder = match protocols {
+        Protocols::Http(settings) => settings.apply(builder),
+        Protocols::Http2Only => builder.http2_only(true),
+    };
+    let make_svc = make_service_fn(move |conn: &C| {
+        let handler = handler(conn);
+        async move { Ok::<_, Infallible>(service_fn(handler)) }
+    });
+    builder
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
+
+/// Serves `connections` with `protocols` until `shutdown` resolves, then
+/// stops accepting and waits for the connections in progress to finish.
+/// `handler` makes what answers the requests of each connection.
+#[cfg(feature = "native")]
+pub async fn serve<C, H, F, E, B>(
+    mut connections: Connections<C>,
+    protocols: Protocols<'_>,
+    mut handler: impl FnMut(&C) -> H + Send + 'static,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    H: Fn(Request<Body>) -> F + Send + 'static,
+    F: Future<Output = Result<Response<B>, E>> + Send + 'static,
+    E: Into<BoxError>,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    use hyper::body::Incoming;
+    use hyper::service::service_fn;
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use hyper_util::server::conn::auto;
+    use hyper_util::server::graceful::GracefulShutdown;
+
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    match protocols {
+        Protocols::Http(settings) => settings.apply(&mut builder),
+        Protocols::Http2Only => builder = builder.http2_only(),
+    }
+    let graceful = GracefulShutdown::new();
+    let mut shutdown = std::pin::pin!(shutdown);
+    loop {
+        let conn = tokio::select! {
+            conn = connections.next() => conn,
+            () = &mut shutdown => break,
+        };
+        let Some(conn) = conn.transpose()? else {
+            break;
+        };
+        let handler = handler(&conn);
+        let service = service_fn(move |req: Request<Incoming>| handler(req.map(Body::from)));
+        let conn = builder
+            .serve_connection(TokioIo::new(conn), service)
+            .into_owned();
+        let conn = graceful.watch(conn);
+        tokio::spawn(async move {
+            if let Err(err) = conn.await {
+                tracing::debug!(error = %err, "connection error");
+            }
+        });
+    }
+    drop(connections);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// How the server treats the connections it accepts. HTTP/2 is served
+/// alongside HTTP/1.1: negotiated with ALPN over TLS, or with prior
+/// knowledge (h2c) over plain connections.
+///
+/// * `HTTP1_KEEPALIVE` - keep HTTP/1.1 connections open between requests (default `true`)
+/// * `HTTP_IDLE_TIMEOUT_SECS` - close a connection that has been quiet this long: nothing read or written, and no request in progress (unset: no limit)
+/// * `HTTP2_MAX_CONCURRENT_STREAMS` - requests one HTTP/2 connection may have in progress at once (default 200)
+/// * `HTTP2_KEEPALIVE_INTERVAL_SECS` - ping idle HTTP/2 connections this often (unset: no pings)
+/// * `HTTP2_KEEPALIVE_TIMEOUT_SECS` - close an HTTP/2 connection whose ping is not answered within this time (default 20)
+#[derive(Clone)]
+pub struct ConnectionSettings {
+    http1_keepalive: bool,
+    idle_timeout: Option<Duration>,
+    http2_max_concurrent_streams: u32,
+    http2_keepalive_interval: Option<Duration>,
+    http2_keepalive_timeout: Duration,
+}
+
+impl ConnectionSettings {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let secs = |name: &str| -> anyhow::Result<_> {
+            Ok(Some(env_or(name, 0u64)?)
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs))
+        };
+        Ok(Self {
+            http1_keepalive: env_or("HTTP1_KEEPALIVE", true)?,
+            idle_timeout: secs("HTTP_IDLE_TIMEOUT_SECS")?,
+            http2_max_concurrent_streams: env_or("HTTP2_MAX_CONCURRENT_STREAMS", 200u32)?.max(1),
+            http2_keepalive_interval: secs("HTTP2_KEEPALIVE_INTERVAL_SECS")?,
+            http2_keepalive_timeout: Duration::from_secs(env_or(
+                "HTTP2_KEEPALIVE_TIMEOUT_SECS",
+                20,
+            )?),
+        })
+    }
+
+    #[cfg(feature = "wasmedge")]
+    fn apply<I>(&self, builder: hyper::server::Builder<I>) -> hyper::server::Builder<I> {
+        builder
+            .http1_keepalive(self.http1_keepalive)
+            .http2_max_concurrent_streams(self.http2_max_concurrent_streams)
+            .http2_keep_alive_interval(self.http2_keepalive_interval)
+            .http2_keep_alive_timeout(self.http2_keepalive_timeout)
+    }
+
+    #[cfg(feature = "native")]
+    fn apply(
+        &self,
+        builder: &mut hyper_util::server::conn::auto::Builder<hyper_util::rt::TokioExecutor>,
+    ) {
+        builder.http1().keep_alive(self.http1_keepalive);
+        builder
+            .http2()
+            .timer(hyper_util::rt::TokioTimer::new())
+            .max_concurrent_streams(self.http2_max_concurrent_streams)
+            .keep_alive_interval(self.http2_keepalive_interval)
+            .keep_alive_timeout(self.http2_keepalive_timeout);
+    }
+
+    /// Wraps `connections`, so that they close once idle for
+    /// `HTTP_IDLE_TIMEOUT_SECS`.
+    pub fn track<C: Send + 'static>(&self, connections: Connections<C>) -> Connections<Tracked<C>> {
+        let timeout = self.idle_timeout;
+        Box::pin(connections.map(move |conn| conn.map(|conn| Tracked::new(conn, timeout))))
+    }
+}
+
+/// A connection that knows when it was last used. Once idle for its
+/// timeout it reads as closed, which ends it the way a client hanging up
+/// does.
+pub struct Tracked<T> {
+    io: T,
+    idle: Option<(Duration, Pin<Box<Sleep>>)>,
+    in_progress: Arc<AtomicUsize>,
+}
+
+/// Marks a request in progress on a connection, which is not idle until
+/// it is dropped.
+pub struct InProgress(Arc<AtomicUsize>);
+
+impl<T> Tracked<T> {
+    fn new(io: T, timeout: Option<Duration>) -> Self {
+        Self {
+            io,
+            idle: timeout.map(|timeout| (timeout, Box::pin(tokio::time::sleep(timeout)))),
+            in_progress: Arc::default(),
+        }
+    }
+
+    /// What marks the connection's requests in progress.
+    pub fn requests(&self) -> Requests {
+        Requests(self.in_progress.clone())
+    }
+
+    fn touch(&mut self) {
+        if let Some((timeout, sleep)) = &mut self.idle {
+            sleep.as_mut().reset(Instant::now() + *timeout);
+        }
+    }
+
+    /// Whether the connection has been idle for its timeout; otherwise the
+    /// timer wakes `cx` when it might have.
+    fn expired(&mut self, cx: &mut Context<'_>) -> bool {
+        let Some((timeout, sleep)) = &mut self.idle else {
+            return false;
+        };
+        while sleep.as_mut().poll(cx).is_ready() {
+            if self.in_progress.load(Ordering::Relaxed) == 0 {
+                return true;
+            }
+            sleep.as_mut().reset(Instant::now() + *timeout);
+        }
+        false
+    }
+}
+
+/// Hands out an `InProgress` for each request of a connection.
+#[derive(Clone)]
+pub struct Requests(Arc<AtomicUsize>);
+
+impl Requests {
+    pub fn start(&self) -> InProgress {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        InProgress(self.0.clone())
+    }
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Tracked<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.io).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                this.touch();
+                Poll::Ready(result)
+            }
+            Poll::Pending if this.expired(cx) => Poll::Ready(Ok(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Tracked<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.io).poll_write(cx, buf);
+        if result.is_ready() {
+            this.touch();
+        }
+        result
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.io).poll_write_vectored(cx, bufs);
+        if result.is_ready() {
+            this.touch();
+        }
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,90 @@
+use crate::body::Body;
+use crate::config::{env_opt, env_or};
+use hyper::header::{self, HeaderValue};
+use hyper::Response;
+
+const DEFAULT_ALLOWED_HEADERS: &str =
+    "api,Keep-Alive,User-Agent,Content-Type,Idempotency-Key,X-Request-Id,X-Request-Deadline-Ms,X-Schema-Mode,Authorization,X-Api-Key,X-Tenant-Id";
+
+/// Which browser origins may call the API, applied to every response.
+///
+/// * `CORS_ALLOWED_ORIGINS` - comma separated origins, or `*` for any (default `*`)
+/// * `CORS_ALLOWED_HEADERS` - request headers a preflight may ask for
+/// * `CORS_MAX_AGE_SECS` - how long browsers may cache a preflight (unset by default)
+/// * `CORS_ALLOW_CREDENTIALS` - allow cookies and auth headers (default false)
+pub struct CorsPolicy {
+    /// `None` allows any origin.
+    origins: Option<Vec<String>>,
+    allowed_headers: String,
+    max_age: Option<u64>,
+    credentials: bool,
+}
+
+impl CorsPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let origins = std::env::var("CORS_ALLOWED_ORIGINS").unwrap_or_else(|_| "*".into());
+        let origins: Vec<String> = origins
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/').to_owned())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+        Ok(Self {
+            origins: (!origins.iter().any(|origin| origin == "*")).then_some(origins),
+            allowed_headers: std::env::var("CORS_ALLOWED_HEADERS")
+                .unwrap_or_else(|_| DEFAULT_ALLOWED_HEADERS.into()),
+            max_age: env_opt("CORS_MAX_AGE_SECS")?,
+            credentials: env_or("CORS_ALLOW_CREDENTIALS", false)?,
+        })
+    }
+
+    /// Adds the CORS headers for a request from `origin` to `response`. The
+    /// allowed methods, headers and max-age are only sent in answer to a
+    /// preflight.
+    pub fn apply(
+        &self,
+        origin: Option<&HeaderValue>,
+        preflight: bool,
+        response: &mut Response<Body>,
+    ) {
+        let headers = response.headers_mut();
+        let allow_origin = match (&self.origins, origin) {
+            // A wildcard cannot be combined with credentials, so the origin
+            // is echoed instead.
+            (None, Some(origin)) if self.credentials => Some(origin.clone()),
+            (None, _) => Some(HeaderValue::from_static("*")),
+            (Some(allowed), Some(origin)) => origin
+                .to_str()
+                .ok()
+                .filter(|origin| allowed.iter().any(|allowed| allowed == origin))
+                .map(|_| origin.clone()),
+            (Some(_), None) => None,
+        };
+        if self.origins.is_some() || self.credentials {
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        let Some(allow_origin) = allow_origin else {
+            return;
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        if !preflight {
+            return;
+        }
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, OPTIONS"),
+        );
+        if let Ok(value) = HeaderValue::from_str(&self.allowed_headers) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, value);
+        }
+        if let Some(secs) = self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, secs.into());
+        }
+    }
+}
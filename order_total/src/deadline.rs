@@ -0,0 +1,94 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use hyper::HeaderMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The header a caller gives its remaining budget in, in milliseconds.
+pub const HEADER: &str = "x-request-deadline-ms";
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// How long a request may take: what its `X-Request-Deadline-Ms` allows,
+/// capped by the server-wide maximum. Within that, each lookup of the sales
+/// tax rate service is given the time that is left as its timeout.
+///
+/// Whatever the deadline, no request is handled for longer than the
+/// overall timeout, so that a stalled body or handler cannot hold its
+/// connection forever.
+///
+/// * `REQUEST_DEADLINE_MS` - the longest any API request may take (unset by default)
+/// * `REQUEST_TIMEOUT_MS` - the longest any request may take to be answered, reading its body and building the response included (default 10000, `0`: no limit)
+pub struct Deadlines {
+    max: Option<Duration>,
+    timeout: Option<Duration>,
+}
+
+impl Deadlines {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let millis = |name: &str, default: u64| -> anyhow::Result<_> {
+            Ok(Some(env_or(name, default)?)
+                .filter(|&millis| millis > 0)
+                .map(Duration::from_millis))
+        };
+        Ok(Self {
+            max: millis("REQUEST_DEADLINE_MS", 0)?,
+            timeout: millis("REQUEST_TIMEOUT_MS", 10_000)?,
+        })
+    }
+
+    /// Runs the whole handling of a request for at most the overall
+    /// timeout; `DeadlineExceeded` if it is not done by then.
+    pub async fn bound<F: Future>(&self, handler: F) -> Result<F::Output, ComputeError> {
+        let Some(timeout) = self.timeout else {
+            return Ok(handler.await);
+        };
+        tokio::time::timeout(timeout, handler).await.map_err(|_| {
+            tracing::warn!(timeout_ms = timeout.as_millis() as u64, "request timed out");
+            ComputeError::DeadlineExceeded
+        })
+    }
+
+    /// The deadline of a request with `headers`, arriving now. A header
+    /// that is not a number of milliseconds is ignored.
+    pub fn of_request(&self, headers: &HeaderMap) -> Option<Instant> {
+        let requested = headers
+            .get(HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_millis);
+        let budget = match (requested, self.max) {
+            (Some(requested), Some(max)) => requested.min(max),
+            (budget, None) | (None, budget) => budget?,
+        };
+        Some(Instant::now() + budget)
+    }
+}
+
+/// Runs `future` until `deadline`, with it as the current deadline;
+/// `DeadlineExceeded` if it is not done by then.
+pub async fn scope<F: Future>(
+    deadline: Option<Instant>,
+    future: F,
+) -> Result<F::Output, ComputeError> {
+    match deadline {
+        Some(deadline) => {
+            let bounded = tokio::time::timeout_at(deadline, future);
+            DEADLINE
+                .scope(deadline, bounded)
+                .await
+                .map_err(|_| ComputeError::DeadlineExceeded)
+        }
+        None => Ok(future.await),
+    }
+}
+
+/// The time left before the current request's deadline, if it has one.
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
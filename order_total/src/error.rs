@@ -0,0 +1,93 @@
+use crate::api_keys::{self, ceil_secs, Quota};
+use crate::body::Body;
+use crate::error_report::{self, Event, Level};
+use crate::rate_limit;
+use crate::response_build;
+use crate::upstream::FetchError;
+use hyper::{Response, StatusCode};
+pub use order_total_core::error::{ComputeError, ErrorCode, ErrorResponse, FieldError};
+
+/// The response a client gets for `err`, with `Retry-After`, the rate limit
+/// quota (the service-wide `RateLimit-*` or the API key's `X-RateLimit-*`)
+/// and `WWW-Authenticate` where they apply. An unexpected error is
+/// reported, see `error_report`.
+pub fn response(err: ComputeError) -> Response<Body> {
+    if let ComputeError::Unexpected(cause) = &err {
+        error_report::capture(Event::new(Level::Error, "Unexpected", format!("{cause:#}")));
+    }
+    unreported(err)
+}
+
+/// `response`, for an error that has been reported already.
+pub fn unreported(err: ComputeError) -> Response<Body> {
+    let (retry_after, quota) = retry_hints(&err);
+    let unauthorized = matches!(err, ComputeError::Unauthorized);
+    let throttled = match &err {
+        ComputeError::Throttled(quota, _) => Some(*quota),
+        _ => None,
+    };
+    let (code, body) = parts(err);
+    let body = crate::pretty::to_string(&body).unwrap();
+    let mut response = response_build(code, &body);
+    if let Some(secs) = retry_after {
+        response
+            .headers_mut()
+            .insert(hyper::header::RETRY_AFTER, secs.into());
+    }
+    if let Some(quota) = quota {
+        api_keys::annotate(&quota, response.headers_mut());
+    }
+    if let Some(quota) = throttled {
+        rate_limit::annotate(&quota, response.headers_mut());
+    }
+    if unauthorized {
+        response.headers_mut().insert(
+            hyper::header::WWW_AUTHENTICATE,
+            hyper::header::HeaderValue::from_static("Bearer"),
+        );
+    }
+    response
+}
+
+/// The `Retry-After` seconds and rate limit quota to report with `err`, if
+/// any.
+pub fn retry_hints(err: &ComputeError) -> (Option<u64>, Option<Quota>) {
+    match err {
+        ComputeError::CircuitOpen(wait)
+        | ComputeError::Overloaded(wait)
+        | ComputeError::Throttled(_, wait) => (Some(ceil_secs(*wait)), None),
+        ComputeError::RateLimited(quota, wait) => (Some(ceil_secs(*wait)), Some(*quota)),
+        _ => (None, None),
+    }
+}
+
+/// `ComputeError::into_parts`, with the body carrying the id of the request
+/// being answered.
+pub fn parts(err: ComputeError) -> (StatusCode, ErrorResponse) {
+    let (code, body) = err.into_parts();
+    // order_total_core answers with the `http` types of hyper 0.14.
+    let code = StatusCode::from_u16(code.as_u16()).expect("a valid status code");
+    (code, body.with_request_id(crate::request_id::current()))
+}
+
+/// The code as it is spelled in JSON error bodies, e.g. `VALIDATION_FAILED`.
+pub fn code_name(code: ErrorCode) -> String {
+    match serde_json::to_value(code) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => unreachable!("error codes serialize as strings"),
+    }
+}
+
+impl From<FetchError> for ComputeError {
+    fn from(value: FetchError) -> Self {
+        if let FetchError::DeadlineExceeded = value {
+            Self::DeadlineExceeded
+        } else if value.is_timeout() {
+            Self::UpstreamTimeout
+        } else if value.status() == Some(StatusCode::NOT_FOUND) {
+            Self::TaxRateNotAvailable
+        } else {
+            Self::UpstreamUnavailable
+        }
+    }
+}
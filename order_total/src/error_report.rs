@@ -0,0 +1,236 @@
+//! Reporting errors to Sentry as they happen, so that they are seen before
+//! customers complain: unexpected errors, panics and failures of the sales
+//! tax rate service, each with the request it happened in.
+//!
+//! Events are sent as envelopes over the same HTTP client as webhooks, in
+//! the background; a failure to send is logged and otherwise ignored.
+
+use anyhow::{anyhow, Context};
+use hyper::Method;
+use serde_json::{json, Map, Value};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Events sent at once, beyond which new ones are dropped rather than
+/// queued: an outage of Sentry must not pile up work here.
+const MAX_IN_FLIGHT: usize = 16;
+
+tokio::task_local! {
+    static CURRENT: RequestScope;
+}
+
+/// Reports to the project of a Sentry DSN.
+///
+/// * `SENTRY_DSN` - the project to report to, e.g. `https://key@o1.ingest.sentry.io/42`; unset, nothing is reported
+/// * `SENTRY_ENVIRONMENT` - the environment events are filed under (default `production`)
+/// * `SENTRY_RELEASE` - the release events are filed under (default `order_total@{version}`)
+#[derive(Clone)]
+pub struct ErrorReporter {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    dsn: String,
+    envelope_url: String,
+    auth: String,
+    environment: String,
+    release: String,
+    client: reqwest::Client,
+    in_flight: AtomicUsize,
+}
+
+/// The request being handled, attached to what it reports.
+struct RequestScope {
+    reporter: ErrorReporter,
+    method: Method,
+    path: String,
+}
+
+#[derive(Clone, Copy)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+/// What went wrong: a kind to group by, e.g. `panic`, and the message.
+pub struct Event {
+    level: Level,
+    kind: &'static str,
+    message: String,
+    extra: Map<String, Value>,
+}
+
+impl ErrorReporter {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(dsn) = std::env::var("SENTRY_DSN")
+            .ok()
+            .filter(|dsn| !dsn.is_empty())
+        else {
+            return Ok(None);
+        };
+        let (envelope_url, key) = parse_dsn(&dsn).context("SENTRY_DSN is not a valid DSN")?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()?;
+        Ok(Some(Self {
+            inner: Arc::new(Inner {
+                auth: format!(
+                    "Sentry sentry_version=7, sentry_client=order_total/{}, sentry_key={key}",
+                    env!("CARGO_PKG_VERSION")
+                ),
+                dsn,
+                envelope_url,
+                environment: std::env::var("SENTRY_ENVIRONMENT")
+                    .unwrap_or_else(|_| "production".to_owned()),
+                release: std::env::var("SENTRY_RELEASE")
+                    .unwrap_or_else(|_| format!("order_total@{}", env!("CARGO_PKG_VERSION"))),
+                client,
+                in_flight: AtomicUsize::new(0),
+            }),
+        }))
+    }
+
+    fn send(&self, event: Event, method: &Method, path: &str) {
+        let inner = &self.inner;
+        if inner.in_flight.fetch_add(1, Ordering::Relaxed) >= MAX_IN_FLIGHT {
+            inner.in_flight.fetch_sub(1, Ordering::Relaxed);
+            tracing::debug!(
+                kind = event.kind,
+                "too many error reports in flight, dropped"
+            );
+            return;
+        }
+        let event_id = uuid::Uuid::new_v4().simple().to_string();
+        let body = envelope(
+            inner,
+            &event_id,
+            event.into_json(inner, &event_id, method, path),
+        );
+        let reporter = self.clone();
+        tokio::spawn(async move {
+            let inner = &reporter.inner;
+            let sent = inner
+                .client
+                .post(&inner.envelope_url)
+                .header(hyper::header::CONTENT_TYPE, "application/x-sentry-envelope")
+                .header("x-sentry-auth", &inner.auth)
+                .body(body)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            inner.in_flight.fetch_sub(1, Ordering::Relaxed);
+            if let Err(err) = sent {
+                tracing::warn!(%event_id, error = %err, "could not report an error to Sentry");
+            }
+        });
+    }
+}
+
+impl Event {
+    pub fn new(level: Level, kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            kind,
+            message: message.into(),
+            extra: Map::new(),
+        }
+    }
+
+    /// Attaches `value` to the event under `key`, e.g. a backtrace.
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.extra.insert(key.to_owned(), value.into());
+        self
+    }
+
+    fn into_json(self, inner: &Inner, event_id: &str, method: &Method, path: &str) -> Value {
+        let mut tags = Map::new();
+        tags.insert("git_sha".into(), env!("ORDER_TOTAL_GIT_SHA").into());
+        tags.insert("route".into(), crate::api::resolve(path).template().into());
+        if let Some(id) = crate::request_id::current() {
+            tags.insert("request_id".into(), id.into());
+        }
+        json!({
+            "event_id": event_id,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "platform": "native",
+            "logger": "order_total",
+            "level": match self.level {
+                Level::Error => "error",
+                Level::Warning => "warning",
+            },
+            "environment": inner.environment,
+            "release": inner.release,
+            "exception": {"values": [{"type": self.kind, "value": self.message}]},
+            "request": {"method": method.as_str(), "url": path},
+            "tags": tags,
+            "extra": self.extra,
+        })
+    }
+}
+
+/// Runs the handling of a `method` request to `path`, reporting what goes
+/// wrong in it to `reporter`, if there is one.
+pub async fn scope<F: Future>(
+    reporter: Option<&ErrorReporter>,
+    method: &Method,
+    path: &str,
+    future: F,
+) -> F::Output {
+    match reporter {
+        Some(reporter) => {
+            let scope = RequestScope {
+                reporter: reporter.clone(),
+                method: method.clone(),
+                path: path.to_owned(),
+            };
+            CURRENT.scope(scope, future).await
+        }
+        None => future.await,
+    }
+}
+
+/// Reports `event` with the request being handled; outside of a request,
+/// or without `SENTRY_DSN`, it is dropped.
+pub fn capture(event: Event) {
+    let _ = CURRENT.try_with(|scope| scope.reporter.send(event, &scope.method, &scope.path));
+}
+
+/// The envelope endpoint of a DSN, `{scheme}://{key}@{host}{/path}/{project}`,
+/// and its public key.
+fn parse_dsn(dsn: &str) -> anyhow::Result<(String, String)> {
+    let url = reqwest::Url::parse(dsn)?;
+    let key = url.username();
+    if key.is_empty() {
+        return Err(anyhow!("it has no public key"));
+    }
+    let path = url.path().trim_end_matches('/');
+    let (prefix, project) = path.rsplit_once('/').unwrap_or_default();
+    if project.is_empty() {
+        return Err(anyhow!("it has no project id"));
+    }
+    let host = url.host_str().unwrap_or_default();
+    let port = url
+        .port()
+        .map(|port| format!(":{port}"))
+        .unwrap_or_default();
+    Ok((
+        format!(
+            "{}://{host}{port}{prefix}/api/{project}/envelope/",
+            url.scheme()
+        ),
+        key.to_owned(),
+    ))
+}
+
+/// An envelope carrying one event: its header, the item's and the event.
+fn envelope(inner: &Inner, event_id: &str, event: Value) -> Vec<u8> {
+    let header = json!({"event_id": event_id, "dsn": inner.dsn});
+    let item = event.to_string();
+    format!(
+        "{header}\n{}\n{item}\n",
+        json!({"type": "event", "length": item.len()})
+    )
+    .into_bytes()
+}
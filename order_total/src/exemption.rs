@@ -0,0 +1,81 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use crate::request_id;
+use async_trait::async_trait;
+use order_total_core::{AcceptWellFormed, ExemptionCertificate, ExemptionVerifier, Order};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The verifier of exemption certificates:
+///
+/// * `EXEMPTION_VERIFIER_URL` - POST each certificate here, a `2xx` accepting it and a `4xx` rejecting it; unset, every well-formed certificate is accepted
+/// * `EXEMPTION_VERIFIER_TIMEOUT_MS` - bound on one verification (default 2000)
+pub fn from_env() -> anyhow::Result<Arc<dyn ExemptionVerifier>> {
+    let Some(url) = std::env::var("EXEMPTION_VERIFIER_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+    else {
+        return Ok(Arc::new(AcceptWellFormed));
+    };
+    let url = reqwest::Url::parse(url.trim())
+        .map_err(|err| anyhow::anyhow!("invalid EXEMPTION_VERIFIER_URL: {err}"))?;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(env_or(
+            "EXEMPTION_VERIFIER_TIMEOUT_MS",
+            2000,
+        )?))
+        .build()?;
+    Ok(Arc::new(HttpVerifier { client, url }))
+}
+
+/// Asks an external certificate registry about each certificate.
+struct HttpVerifier {
+    client: reqwest::Client,
+    url: reqwest::Url,
+}
+
+/// What the verifier is sent.
+#[derive(Serialize)]
+struct Verification<'a> {
+    certificate: &'a ExemptionCertificate,
+    order_id: i32,
+    shipping_zip: &'a str,
+}
+
+#[async_trait]
+impl ExemptionVerifier for HttpVerifier {
+    async fn verify(
+        &self,
+        certificate: &ExemptionCertificate,
+        order: &Order,
+    ) -> Result<(), ComputeError> {
+        let mut request = self.client.post(self.url.clone()).json(&Verification {
+            certificate,
+            order_id: order.order_id,
+            shipping_zip: &order.shipping_zip,
+        });
+        if let Some(id) = request_id::current() {
+            request = request.header(request_id::HEADER, id);
+        }
+        let status = request
+            .send()
+            .await
+            .map_err(|err| unavailable(err.to_string()))?
+            .status();
+        if status.is_success() {
+            Ok(())
+        } else if status.is_client_error() {
+            tracing::warn!(certificate = %certificate.id, %status, "exemption certificate rejected");
+            Err(ComputeError::ExemptionRejected)
+        } else {
+            Err(unavailable(format!("answered {status}")))
+        }
+    }
+}
+
+/// The verifier did not give an answer; the order is not computed tax free
+/// on a guess.
+fn unavailable(reason: String) -> ComputeError {
+    ComputeError::Unexpected(format!("the exemption verifier failed: {reason}").into())
+}
@@ -0,0 +1,148 @@
+//! Feature flags, to roll a behavior out gradually: off for the whole
+//! service while it is being proven, then on for one tenant after another.
+
+use crate::error::{ComputeError, FieldError};
+use crate::tenant;
+use anyhow::{anyhow, bail};
+use order_total_core::Order;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// A behavior a flag switches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Flag {
+    /// The batch endpoints: `POST /v1/jobs`, `/v1/compute_csv` and
+    /// `/v1/compute_stream`, which answer `404` when it is off.
+    Batch,
+    /// Orders choosing their own `rounding`.
+    OrderRounding,
+    /// Orders converted to another currency with `convert_to`.
+    CurrencyConversion,
+}
+
+impl Flag {
+    pub const ALL: [Self; 3] = [Self::Batch, Self::OrderRounding, Self::CurrencyConversion];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Batch => "batch",
+            Self::OrderRounding => "order_rounding",
+            Self::CurrencyConversion => "currency_conversion",
+        }
+    }
+}
+
+impl FromStr for Flag {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|flag| flag.name() == name)
+            .ok_or_else(|| {
+                let known: Vec<_> = Self::ALL.iter().map(|flag| flag.name()).collect();
+                anyhow!(
+                    "unknown feature flag {name:?}, expected one of {}",
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// The flags `FEATURE_FLAGS` sets, a comma-separated list of `flag=on` or
+/// `flag=off` (`true` and `false` will do too), each for the whole service,
+/// or for one tenant of `TENANTS_FILE` as `tenant:flag=on`, e.g.
+/// `order_rounding=off,acme:order_rounding=on`. A tenant's setting wins over
+/// the service's, and a flag set nowhere is on.
+///
+/// A reload reads the flags anew, see `replace`, so a rollout needs no
+/// restart.
+pub struct FeatureFlags {
+    settings: RwLock<Settings>,
+}
+
+#[derive(Default)]
+struct Settings {
+    service: HashMap<Flag, bool>,
+    tenants: HashMap<String, HashMap<Flag, bool>>,
+}
+
+impl FeatureFlags {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            settings: RwLock::new(Settings::from_env()?),
+        })
+    }
+
+    /// Takes the flags of `flags`, `FEATURE_FLAGS` as `from_env` read it
+    /// again.
+    pub fn replace(&self, flags: Self) {
+        *self.settings.write().unwrap() = flags.settings.into_inner().unwrap();
+    }
+
+    /// Whether `flag` is on for the request's tenant, see `tenant`.
+    pub fn enabled(&self, flag: Flag) -> bool {
+        let settings = self.settings.read().unwrap();
+        tenant::current()
+            .and_then(|tenant| settings.tenants.get(&tenant)?.get(&flag).copied())
+            .or_else(|| settings.service.get(&flag).copied())
+            .unwrap_or(true)
+    }
+
+    /// Refuses an order asking for what is switched off.
+    pub fn admit(&self, order: &Order) -> Result<(), ComputeError> {
+        let gated = [
+            ("rounding", order.rounding.is_some(), Flag::OrderRounding),
+            (
+                "convert_to",
+                order.convert_to.is_some(),
+                Flag::CurrencyConversion,
+            ),
+        ];
+        let errors: Vec<_> = gated
+            .into_iter()
+            .filter(|&(_, asked, flag)| asked && !self.enabled(flag))
+            .map(|(field, _, _)| FieldError::new(field, "is not enabled"))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ComputeError::Validation(errors))
+        }
+    }
+}
+
+impl Settings {
+    fn from_env() -> anyhow::Result<Self> {
+        let spec = std::env::var("FEATURE_FLAGS").unwrap_or_default();
+        Self::parse(&spec).map_err(|err| anyhow!("invalid FEATURE_FLAGS {spec:?}: {err}"))
+    }
+
+    fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut settings = Self::default();
+        for entry in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let Some((name, value)) = entry.split_once('=') else {
+                bail!("{entry:?} is not flag=on or flag=off");
+            };
+            let on = match value.trim() {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                other => bail!("{other:?} is not on or off"),
+            };
+            let (flags, name) = match name.split_once(':') {
+                Some((tenant, name)) => {
+                    let tenant = tenant.trim().to_owned();
+                    (settings.tenants.entry(tenant).or_default(), name)
+                }
+                None => (&mut settings.service, name),
+            };
+            flags.insert(name.trim().parse()?, on);
+        }
+        Ok(settings)
+    }
+}
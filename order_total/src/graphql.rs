@@ -0,0 +1,346 @@
+//! The GraphQL API at `POST /graphql`: a `computeOrder` mutation, computed
+//! like `POST /v1/compute`, and `order` and `orders` queries over the stored
+//! orders. The schema is served at `GET /schema.graphql`.
+
+use crate::body::Body;
+use crate::error::{self, ComputeError};
+use crate::store::StoredOrder;
+use crate::{body, orders, process, response_build, with_content_type, App};
+use async_graphql::{
+    BatchRequest, Context, EmptySubscription, Enum, ErrorExtensions, InputObject, Object, Schema,
+    SimpleObject,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use hyper::{Request, Response, StatusCode};
+use order_total_core::{LineItem, Order, RoundingOverride};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+type OrderSchema = Schema<Query, Mutation, EmptySubscription>;
+
+lazy_static! {
+    static ref SCHEMA: OrderSchema = Schema::build(Query, Mutation, EmptySubscription)
+        .limit_depth(8)
+        .finish();
+    pub static ref SDL: String = SCHEMA.sdl();
+}
+
+/// Runs the query (or batch of queries) in the request body. GraphQL errors,
+/// such as a failed computation, are answered with `200` and an `errors`
+/// list; only a body that is not a GraphQL request gets an error status.
+pub async fn handle(req: Request<Body>, app: Arc<App>) -> Response<Body> {
+    let bytes = match body::to_bytes_limited(req, app.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(err) => return error::response(err),
+    };
+    let request: BatchRequest = match serde_json::from_slice(&bytes) {
+        Ok(request) => request,
+        Err(_) => return error::response(ComputeError::InvalidRequest),
+    };
+    let response = SCHEMA.execute_batch(request.data(app)).await;
+    let body = crate::pretty::to_string(&response).unwrap();
+    with_content_type(response_build(StatusCode::OK, &body), "application/json")
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The latest stored computation of an order, or null when there is none.
+    async fn order(
+        &self,
+        ctx: &Context<'_>,
+        order_id: i32,
+    ) -> async_graphql::Result<Option<OrderOutput>> {
+        let order = orders::find(app(ctx), order_id).map_err(graphql_error)?;
+        Ok(order.map(OrderOutput::stored))
+    }
+
+    /// Stored orders, most recently computed first: `limit` of them (default
+    /// 50, at most 500) after skipping the `offset` most recent ones.
+    async fn orders(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> async_graphql::Result<Vec<OrderOutput>> {
+        let page = orders::page(app(ctx), limit, offset).map_err(graphql_error)?;
+        Ok(page.orders.into_iter().map(OrderOutput::stored).collect())
+    }
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    /// Computes the totals of an order, and stores it when persistence is on.
+    async fn compute_order(
+        &self,
+        ctx: &Context<'_>,
+        order: OrderInput,
+    ) -> async_graphql::Result<OrderOutput> {
+        let app = app(ctx);
+        let _permit = app.in_flight.try_acquire().map_err(graphql_error)?;
+        let order = process(order.into(), app).await.map_err(graphql_error)?;
+        Ok(OrderOutput::computed(order, None))
+    }
+}
+
+fn app<'a>(ctx: &Context<'a>) -> &'a App {
+    ctx.data_unchecked::<Arc<App>>()
+}
+
+/// A GraphQL error for `err`, whose extensions carry what the REST error body
+/// would: the `code`, the `requestId`, any field `errors`, and `retryAfter`
+/// seconds where it applies.
+fn graphql_error(err: ComputeError) -> async_graphql::Error {
+    let (retry_after, _) = error::retry_hints(&err);
+    let (_, body) = error::parts(err);
+    async_graphql::Error::new(body.message()).extend_with(|_, extensions| {
+        extensions.set("code", error::code_name(body.code()));
+        if let Some(id) = body.request_id() {
+            extensions.set("requestId", id);
+        }
+        if !body.errors().is_empty() {
+            let errors = serde_json::to_value(body.errors()).unwrap();
+            extensions.set("errors", async_graphql::Value::from_json(errors).unwrap());
+        }
+        if let Some(secs) = retry_after {
+            extensions.set("retryAfter", secs);
+        }
+    })
+}
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+#[graphql(name = "RoundingMode", remote = "order_total_core::RoundingMode")]
+enum RoundingModeValue {
+    HalfUp,
+    HalfEven,
+    Up,
+    Down,
+}
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+#[graphql(name = "RoundingScope", remote = "order_total_core::RoundingScope")]
+enum RoundingScopeValue {
+    PerLine,
+    PerOrder,
+}
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+#[graphql(name = "RateSource", remote = "order_total_core::RateSource")]
+enum RateSourceValue {
+    /// Looked up for the shipping zip code.
+    Lookup,
+    /// No rate is known for the zip code; `DEFAULT_TAX_RATE` was used.
+    Default,
+    /// The purchaser is exempt; no rate was looked up.
+    Exempt,
+    /// A B2B sale within the EU; the buyer accounts for the VAT.
+    ReverseCharge,
+}
+
+#[derive(Enum, Copy, Clone, Default, PartialEq, Eq)]
+#[graphql(name = "TaxCategory", remote = "order_total_core::TaxCategory")]
+enum TaxCategoryValue {
+    /// Taxed at the zip code's rate.
+    #[default]
+    Standard,
+    Grocery,
+    Clothing,
+    /// Never taxed, unless `TAX_CATEGORY_TABLE` says otherwise.
+    Exempt,
+}
+
+/// An order to compute. Money amounts are `Decimal` strings (or numbers).
+#[derive(InputObject)]
+#[graphql(name = "OrderInput")]
+struct OrderInput {
+    order_id: i32,
+    #[graphql(default)]
+    product_id: i32,
+    #[graphql(default)]
+    quantity: i32,
+    #[graphql(default)]
+    subtotal: Decimal,
+    shipping_address: String,
+    shipping_zip: String,
+    /// `US` (the default), `CA` or an EU member state.
+    shipping_country: Option<String>,
+    /// The ISO 4217 code of the currency of the prices; `USD` when left out.
+    currency: Option<String>,
+    /// The day the order was placed; today when left out.
+    order_date: Option<NaiveDate>,
+    /// Overrides the configured rounding for this order.
+    rounding: Option<RoundingInput>,
+    #[graphql(default)]
+    line_items: Vec<LineItemInput>,
+    /// Computes the order without storing, auditing or announcing it.
+    #[graphql(default)]
+    dry_run: bool,
+}
+
+/// Parts left out use the configured default.
+#[derive(InputObject)]
+#[graphql(name = "RoundingInput")]
+struct RoundingInput {
+    mode: Option<RoundingModeValue>,
+    scope: Option<RoundingScopeValue>,
+}
+
+#[derive(InputObject)]
+#[graphql(name = "LineItemInput")]
+struct LineItemInput {
+    product_id: i32,
+    quantity: i32,
+    unit_price: Decimal,
+    #[graphql(default)]
+    tax_category: TaxCategoryValue,
+}
+
+impl From<OrderInput> for Order {
+    fn from(input: OrderInput) -> Self {
+        Order {
+            order_id: input.order_id,
+            product_id: input.product_id,
+            quantity: input.quantity,
+            subtotal: input.subtotal,
+            shipping_address: input.shipping_address,
+            shipping_address_parts: None,
+            shipping_zip: input.shipping_zip,
+            shipping_country: input.shipping_country,
+            total: Decimal::ZERO,
+            currency: input.currency,
+            convert_to: None,
+            converted: None,
+            order_date: input.order_date,
+            tax_holiday: None,
+            tax_rate: None,
+            rate_source: None,
+            tax_amount: Decimal::ZERO,
+            jurisdiction: None,
+            rounding: input.rounding.map(|rounding| RoundingOverride {
+                mode: rounding.mode.map(Into::into),
+                scope: rounding.scope.map(Into::into),
+            }),
+            line_items: input
+                .line_items
+                .into_iter()
+                .map(|item| LineItem {
+                    product_id: item.product_id,
+                    quantity: item.quantity,
+                    unit_price: item.unit_price,
+                    tax_category: item.tax_category.into(),
+                    subtotal: Decimal::ZERO,
+                    discount: Decimal::ZERO,
+                    tax_rate: None,
+                    tax_holiday: None,
+                    tax: Decimal::ZERO,
+                    total: Decimal::ZERO,
+                })
+                .collect(),
+            price_mismatches: Vec::new(),
+            discounts: Vec::new(),
+            discount_amount: Decimal::ZERO,
+            tax_components: Vec::new(),
+            vat_id: None,
+            vat: None,
+            exemption_certificate: None,
+            dry_run: input.dry_run,
+        }
+    }
+}
+
+/// A computed order, as returned by `computeOrder` or read back from the
+/// store.
+#[derive(SimpleObject)]
+#[graphql(name = "Order")]
+struct OrderOutput {
+    order_id: i32,
+    product_id: i32,
+    quantity: i32,
+    subtotal: Decimal,
+    shipping_address: String,
+    shipping_zip: String,
+    shipping_country: Option<String>,
+    currency: Option<String>,
+    order_date: Option<NaiveDate>,
+    /// The tax holiday that made an order without line items tax free, if
+    /// any.
+    tax_holiday: Option<String>,
+    total: Decimal,
+    /// The sales tax rate that was applied.
+    tax_rate: Option<Decimal>,
+    /// Where `taxRate` came from.
+    rate_source: Option<RateSourceValue>,
+    /// The sales tax included in `total`.
+    tax_amount: Decimal,
+    /// The state the shipping zip code belongs to, e.g. `TX`, or the
+    /// province of a Canadian order, when known.
+    jurisdiction: Option<String>,
+    line_items: Vec<LineItemOutput>,
+    /// When the order was stored; null for a `computeOrder` result.
+    computed_at: Option<DateTime<Utc>>,
+    /// The order was computed as a simulation and not recorded.
+    dry_run: bool,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "LineItem")]
+struct LineItemOutput {
+    product_id: i32,
+    quantity: i32,
+    unit_price: Decimal,
+    tax_category: TaxCategoryValue,
+    subtotal: Decimal,
+    /// The rate the line was taxed at, when its category made it differ
+    /// from the order's.
+    tax_rate: Option<Decimal>,
+    /// The tax holiday that made the line tax free, if any.
+    tax_holiday: Option<String>,
+    tax: Decimal,
+    total: Decimal,
+}
+
+impl OrderOutput {
+    fn stored(stored: StoredOrder) -> Self {
+        Self::computed(stored.order, Some(stored.computed_at))
+    }
+
+    fn computed(order: Order, computed_at: Option<DateTime<Utc>>) -> Self {
+        Self {
+            order_id: order.order_id,
+            product_id: order.product_id,
+            quantity: order.quantity,
+            subtotal: order.subtotal,
+            shipping_address: order.shipping_address,
+            shipping_zip: order.shipping_zip,
+            shipping_country: order.shipping_country,
+            currency: order.currency,
+            order_date: order.order_date,
+            tax_holiday: order.tax_holiday,
+            total: order.total,
+            tax_rate: order.tax_rate,
+            rate_source: order.rate_source.map(Into::into),
+            tax_amount: order.tax_amount,
+            jurisdiction: order.jurisdiction,
+            line_items: order
+                .line_items
+                .into_iter()
+                .map(|item| LineItemOutput {
+                    product_id: item.product_id,
+                    quantity: item.quantity,
+                    unit_price: item.unit_price,
+                    tax_category: item.tax_category.into(),
+                    subtotal: item.subtotal,
+                    tax_rate: item.tax_rate,
+                    tax_holiday: item.tax_holiday,
+                    tax: item.tax,
+                    total: item.total,
+                })
+                .collect(),
+            computed_at,
+            dry_run: order.dry_run,
+        }
+    }
+}
@@ -0,0 +1,369 @@
+//! The gRPC interface of `proto/order_total.proto`, served on `GRPC_PORT`.
+//! tonic's own transport does not build for WASI, so the generated service is
+//! run by a second, HTTP/2-only hyper server instead. Requests are logged,
+//! authenticated and computed like those to `POST /v1/compute`.
+
+use crate::api_keys;
+use crate::body::Body;
+use crate::connection::{self, Protocols};
+use crate::error::{self, ComputeError, ErrorCode, FieldError};
+use crate::{authenticate, logging, process, usage, App};
+use hyper::HeaderMap;
+use order_total_core::{
+    LineItem, Order, RateSource, RoundingMode, RoundingOverride, RoundingScope, TaxCategory,
+};
+use prost::Message;
+use rust_decimal::Decimal;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use tonic::body::BoxBody;
+use tonic::codegen::Service;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Status};
+
+pub mod proto {
+    tonic::include_proto!("order_total.v1");
+}
+
+use proto::order_total_server::{OrderTotal, OrderTotalServer};
+
+/// Serves the gRPC interface of `app` on `addr` until `shutdown` resolves,
+/// and returns the address actually bound along with the server to await.
+pub fn bind(
+    app: Arc<App>,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<(SocketAddr, impl Future<Output = anyhow::Result<()>>)> {
+    let (local_addr, connections) = connection::listen(&addr)?;
+    let grpc = OrderTotalServer::new(GrpcApi { app: app.clone() })
+        .max_decoding_message_size(app.max_body_bytes);
+    let handler = move |_: &_| {
+        let app = app.clone();
+        let grpc = grpc.clone();
+        move |req| {
+            let app = app.clone();
+            let grpc = grpc.clone();
+            logging::traced(req, |req| handle(req, app, grpc))
+        }
+    };
+    let server = connection::serve(connections, Protocols::Http2Only, handler, shutdown);
+    Ok((local_addr, server))
+}
+
+/// Checks the credentials in the request metadata, as the `middleware` stack does
+/// for the REST API, before handing the call to the generated service, and
+/// counts what the call computes in the caller's usage.
+async fn handle(
+    req: hyper::Request<Body>,
+    app: Arc<App>,
+    grpc: OrderTotalServer<GrpcApi>,
+) -> Result<hyper::Response<BoxBody>, Infallible> {
+    let (response, batch) = usage::measure(None, call(req, &app, grpc)).await;
+    app.usage.record(&batch);
+    response
+}
+
+async fn call(
+    mut req: hyper::Request<Body>,
+    app: &App,
+    mut grpc: OrderTotalServer<GrpcApi>,
+) -> Result<hyper::Response<BoxBody>, Infallible> {
+    let quota = match authenticate(&mut req, app).await {
+        Ok(quota) => quota,
+        Err(err) => return Ok(into_http(status(err))),
+    };
+    let mut response = grpc.call(req).await?;
+    if let Some(quota) = quota {
+        api_keys::annotate(&quota, response.headers_mut());
+    }
+    Ok(response)
+}
+
+struct GrpcApi {
+    app: Arc<App>,
+}
+
+#[tonic::async_trait]
+impl OrderTotal for GrpcApi {
+    async fn compute_order(
+        &self,
+        request: tonic::Request<proto::ComputeOrderRequest>,
+    ) -> Result<tonic::Response<proto::ComputeOrderResponse>, Status> {
+        let _permit = self.app.in_flight.try_acquire().map_err(status)?;
+        let order = request
+            .into_inner()
+            .order
+            .ok_or(ComputeError::InvalidRequest)
+            .and_then(from_proto)
+            .map_err(status)?;
+        let order = process(order, &self.app).await.map_err(status)?;
+        Ok(tonic::Response::new(proto::ComputeOrderResponse {
+            order: Some(to_proto(order)),
+        }))
+    }
+}
+
+/// The status a gRPC client gets for `err`. Its details are the encoded
+/// `ErrorDetail`, and `retry-after` and the rate limit quota are sent as
+/// metadata where they apply, like the headers of `error::response`.
+fn status(err: ComputeError) -> Status {
+    let (retry_after, quota) = error::retry_hints(&err);
+    let (_, body) = error::parts(err);
+    let detail = proto::ErrorDetail {
+        code: error::code_name(body.code()),
+        message: body.message().to_owned(),
+        request_id: body.request_id().unwrap_or_default().to_owned(),
+        errors: body
+            .errors()
+            .iter()
+            .map(|error| proto::FieldError {
+                field: error.field.clone(),
+                message: error.message.clone(),
+            })
+            .collect(),
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Some(secs) = retry_after {
+        headers.insert(hyper::header::RETRY_AFTER, secs.into());
+    }
+    if let Some(quota) = quota {
+        api_keys::annotate(&quota, &mut headers);
+    }
+    Status::with_details_and_metadata(
+        status_code(body.code()),
+        body.summary(),
+        detail.encode_to_vec().into(),
+        MetadataMap::from_headers(headers),
+    )
+}
+
+/// The response carrying `status`.
+#[cfg(feature = "wasmedge")]
+fn into_http(status: Status) -> hyper::Response<BoxBody> {
+    status.to_http()
+}
+
+/// The response carrying `status`.
+#[cfg(feature = "native")]
+fn into_http(status: Status) -> hyper::Response<BoxBody> {
+    status.into_http()
+}
+
+/// The gRPC counterpart of each error's HTTP status.
+fn status_code(code: ErrorCode) -> Code {
+    match code {
+        ErrorCode::InvalidRequest
+        | ErrorCode::ValidationFailed
+        | ErrorCode::UnsupportedEncoding
+        | ErrorCode::QuoteInvalid
+        | ErrorCode::ExemptionRejected
+        | ErrorCode::UnknownTenant => Code::InvalidArgument,
+        ErrorCode::PayloadTooLarge | ErrorCode::RateLimited => Code::ResourceExhausted,
+        ErrorCode::TaxRateUnavailable
+        | ErrorCode::FxRateUnavailable
+        | ErrorCode::IdempotencyKeyReused => Code::FailedPrecondition,
+        ErrorCode::UpstreamUnavailable | ErrorCode::CircuitOpen | ErrorCode::Overloaded => {
+            Code::Unavailable
+        }
+        ErrorCode::UpstreamTimeout | ErrorCode::DeadlineExceeded => Code::DeadlineExceeded,
+        ErrorCode::OrderNotFound | ErrorCode::JobNotFound => Code::NotFound,
+        ErrorCode::PersistenceDisabled => Code::Unimplemented,
+        ErrorCode::IdempotencyKeyInFlight => Code::Aborted,
+        ErrorCode::JobNotFinished | ErrorCode::QuoteExpired => Code::FailedPrecondition,
+        ErrorCode::QuoteAlreadyFinalized => Code::AlreadyExists,
+        ErrorCode::Unauthorized | ErrorCode::InvalidApiKey => Code::Unauthenticated,
+        ErrorCode::InternalError => Code::Internal,
+    }
+}
+
+/// Reads the input fields of an order. Decimal strings that do not parse
+/// are reported together, as a validation failure; the computed fields are
+/// ignored.
+fn from_proto(order: proto::Order) -> Result<Order, ComputeError> {
+    let mut errors = Vec::new();
+    let subtotal = decimal("subtotal", &order.subtotal, &mut errors);
+    let order_date = match order.order_date.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(date) => date
+            .parse()
+            .map_err(|_| {
+                errors.push(FieldError::new(
+                    "order_date",
+                    "must be a date such as 2025-08-08",
+                ))
+            })
+            .ok(),
+    };
+    let line_items = order
+        .line_items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| LineItem {
+            product_id: item.product_id,
+            quantity: item.quantity,
+            unit_price: decimal(
+                format!("line_items[{index}].unit_price"),
+                &item.unit_price,
+                &mut errors,
+            ),
+            tax_category: match proto::TaxCategory::try_from(item.tax_category) {
+                Ok(proto::TaxCategory::Unspecified | proto::TaxCategory::Standard) => {
+                    TaxCategory::Standard
+                }
+                Ok(proto::TaxCategory::Grocery) => TaxCategory::Grocery,
+                Ok(proto::TaxCategory::Clothing) => TaxCategory::Clothing,
+                Ok(proto::TaxCategory::Exempt) => TaxCategory::Exempt,
+                Err(_) => {
+                    errors.push(FieldError::new(
+                        format!("line_items[{index}].tax_category"),
+                        "unknown tax category",
+                    ));
+                    TaxCategory::Standard
+                }
+            },
+            subtotal: Decimal::ZERO,
+            discount: Decimal::ZERO,
+            tax_rate: None,
+            tax_holiday: None,
+            tax: Decimal::ZERO,
+            total: Decimal::ZERO,
+        })
+        .collect();
+    let rounding = order.rounding.map(|rounding| RoundingOverride {
+        mode: match proto::RoundingMode::try_from(rounding.mode) {
+            Ok(proto::RoundingMode::Unspecified) => None,
+            Ok(proto::RoundingMode::HalfUp) => Some(RoundingMode::HalfUp),
+            Ok(proto::RoundingMode::HalfEven) => Some(RoundingMode::HalfEven),
+            Ok(proto::RoundingMode::Up) => Some(RoundingMode::Up),
+            Ok(proto::RoundingMode::Down) => Some(RoundingMode::Down),
+            Err(_) => {
+                errors.push(FieldError::new("rounding.mode", "unknown rounding mode"));
+                None
+            }
+        },
+        scope: match proto::RoundingScope::try_from(rounding.scope) {
+            Ok(proto::RoundingScope::Unspecified) => None,
+            Ok(proto::RoundingScope::PerLine) => Some(RoundingScope::PerLine),
+            Ok(proto::RoundingScope::PerOrder) => Some(RoundingScope::PerOrder),
+            Err(_) => {
+                errors.push(FieldError::new("rounding.scope", "unknown rounding scope"));
+                None
+            }
+        },
+    });
+    if !errors.is_empty() {
+        return Err(ComputeError::Validation(errors));
+    }
+
+    Ok(Order {
+        order_id: order.order_id,
+        product_id: order.product_id,
+        quantity: order.quantity,
+        subtotal,
+        shipping_address: order.shipping_address,
+        shipping_address_parts: None,
+        shipping_zip: order.shipping_zip,
+        shipping_country: order.shipping_country,
+        total: Decimal::ZERO,
+        currency: order.currency,
+        convert_to: None,
+        converted: None,
+        order_date,
+        tax_holiday: None,
+        tax_rate: None,
+        rate_source: None,
+        tax_amount: Decimal::ZERO,
+        jurisdiction: None,
+        rounding,
+        line_items,
+        price_mismatches: Vec::new(),
+        discounts: Vec::new(),
+        discount_amount: Decimal::ZERO,
+        tax_components: Vec::new(),
+        vat_id: None,
+        vat: None,
+        exemption_certificate: None,
+        dry_run: order.dry_run,
+    })
+}
+
+/// An empty string is zero.
+fn decimal(field: impl Into<String>, value: &str, errors: &mut Vec<FieldError>) -> Decimal {
+    let value = value.trim();
+    if value.is_empty() {
+        return Decimal::ZERO;
+    }
+    Decimal::from_str(value).unwrap_or_else(|_| {
+        errors.push(FieldError::new(field, "must be a decimal number"));
+        Decimal::ZERO
+    })
+}
+
+fn to_proto(order: Order) -> proto::Order {
+    proto::Order {
+        order_id: order.order_id,
+        product_id: order.product_id,
+        quantity: order.quantity,
+        subtotal: order.subtotal.to_string(),
+        shipping_address: order.shipping_address,
+        shipping_zip: order.shipping_zip,
+        shipping_country: order.shipping_country,
+        order_date: order.order_date.map(|date| date.to_string()),
+        tax_holiday: order.tax_holiday,
+        currency: order.currency,
+        line_items: order
+            .line_items
+            .into_iter()
+            .map(|item| proto::LineItem {
+                product_id: item.product_id,
+                quantity: item.quantity,
+                unit_price: item.unit_price.to_string(),
+                subtotal: item.subtotal.to_string(),
+                tax: item.tax.to_string(),
+                total: item.total.to_string(),
+                tax_category: match item.tax_category {
+                    TaxCategory::Standard => proto::TaxCategory::Standard,
+                    TaxCategory::Grocery => proto::TaxCategory::Grocery,
+                    TaxCategory::Clothing => proto::TaxCategory::Clothing,
+                    TaxCategory::Exempt => proto::TaxCategory::Exempt,
+                }
+                .into(),
+                tax_rate: item.tax_rate.map(|rate| rate.to_string()),
+                tax_holiday: item.tax_holiday,
+            })
+            .collect(),
+        rounding: order.rounding.map(|rounding| proto::Rounding {
+            mode: match rounding.mode {
+                None => proto::RoundingMode::Unspecified,
+                Some(RoundingMode::HalfUp) => proto::RoundingMode::HalfUp,
+                Some(RoundingMode::HalfEven) => proto::RoundingMode::HalfEven,
+                Some(RoundingMode::Up) => proto::RoundingMode::Up,
+                Some(RoundingMode::Down) => proto::RoundingMode::Down,
+            }
+            .into(),
+            scope: match rounding.scope {
+                None => proto::RoundingScope::Unspecified,
+                Some(RoundingScope::PerLine) => proto::RoundingScope::PerLine,
+                Some(RoundingScope::PerOrder) => proto::RoundingScope::PerOrder,
+            }
+            .into(),
+        }),
+        total: order.total.to_string(),
+        tax_amount: order.tax_amount.to_string(),
+        tax_rate: order.tax_rate.map(|rate| rate.to_string()),
+        rate_source: match order.rate_source {
+            None => proto::RateSource::Unspecified,
+            Some(RateSource::Lookup) => proto::RateSource::Lookup,
+            Some(RateSource::Default) => proto::RateSource::Default,
+            Some(RateSource::Exempt) => proto::RateSource::Exempt,
+            Some(RateSource::ReverseCharge) => proto::RateSource::ReverseCharge,
+        }
+        .into(),
+        jurisdiction: order.jurisdiction,
+        dry_run: order.dry_run,
+    }
+}
@@ -0,0 +1,101 @@
+use crate::config::env_or;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Lookups timed to estimate the percentile from.
+const WINDOW: usize = 200;
+/// Until this many lookups have been timed, `min_delay` is used as is.
+const MIN_SAMPLES: usize = 20;
+
+/// Sends a second, hedged request when the first has not been answered
+/// within the recent latency percentile, and takes whichever answer comes
+/// first. Only the slowest few percent of lookups are hedged, which trims
+/// the tail latency for little extra upstream traffic.
+///
+/// * `TAX_SERVICE_HEDGE_PERCENTILE` - the percentile to hedge after, e.g. 95; unset, lookups are not hedged
+/// * `TAX_SERVICE_HEDGE_MIN_MS` - least delay before hedging, and the delay until enough lookups are timed (default 50)
+pub struct Hedging {
+    percentile: f64,
+    min_delay: Duration,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl Hedging {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let percentile: f64 = env_or("TAX_SERVICE_HEDGE_PERCENTILE", 0.0)?;
+        if percentile <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            percentile: percentile.min(100.0),
+            min_delay: Duration::from_millis(env_or("TAX_SERVICE_HEDGE_MIN_MS", 50)?),
+            latencies: Mutex::new(VecDeque::with_capacity(WINDOW)),
+        }))
+    }
+
+    /// How long the first request is given before the hedge is sent.
+    pub fn delay(&self) -> Duration {
+        let latencies = self.latencies.lock().unwrap();
+        if latencies.len() < MIN_SAMPLES {
+            return self.min_delay;
+        }
+        let mut sorted: Vec<Duration> = latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (self.percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1].max(self.min_delay)
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == WINDOW {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    /// Runs `request(0)`, and `request(1)` as well once `delay` passes
+    /// without an answer. The first result `is_answer` accepts wins; when
+    /// one request fails otherwise, the other one's result is returned.
+    /// Each request answered with `Ok` is timed from its own start.
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        request: F,
+        is_answer: impl Fn(&Result<T, E>) -> bool,
+    ) -> Result<T, E>
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let timed = |index: usize| {
+            let request = request(index);
+            async move {
+                let started = Instant::now();
+                let result = request.await;
+                if result.is_ok() {
+                    self.record(started.elapsed());
+                }
+                result
+            }
+        };
+
+        let first = timed(0);
+        tokio::pin!(first);
+        tokio::select! {
+            result = &mut first => return result,
+            _ = tokio::time::sleep(self.delay()) => {}
+        }
+        tracing::debug!("sales tax rate lookup is slow, sending a hedged request");
+        let second = timed(1);
+        tokio::pin!(second);
+        tokio::select! {
+            result = &mut first => {
+                if is_answer(&result) { result } else { second.await }
+            }
+            result = &mut second => {
+                if is_answer(&result) { result } else { first.await }
+            }
+        }
+    }
+}
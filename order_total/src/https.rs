@@ -0,0 +1,163 @@
+//! Serving HTTPS, and the plain HTTP port that redirects to it.
+
+use crate::body::Body;
+use crate::connection::{self, Connections, Protocols};
+use crate::tls::{load_certs, load_key, Compat};
+use anyhow::{anyhow, Context};
+use futures_util::StreamExt;
+use hyper::{Request, Response, StatusCode};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// A client that has not finished the handshake by then is dropped, so it
+/// cannot hold on to a connection for free.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Handshaken connections waiting for the server to pick them up.
+const BACKLOG: usize = 64;
+
+/// Serving HTTPS, enabled by `TLS_CERT_PATH` and `TLS_KEY_PATH` (PEM files:
+/// the certificate chain, leaf first, and its private key).
+/// `TLS_REDIRECT_PORT` additionally serves a plain HTTP port that redirects
+/// every request to HTTPS.
+pub struct Tls {
+    acceptor: TlsAcceptor,
+    pub redirect_port: Option<u16>,
+}
+
+/// A connection as handed to hyper.
+pub type Conn = Compat<TlsStream<Compat<TcpStream>>>;
+
+impl Tls {
+    /// `None` when neither path is set, i.e. the listener speaks plain HTTP.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let cert_path = std::env::var("TLS_CERT_PATH").ok();
+        let key_path = std::env::var("TLS_KEY_PATH").ok();
+        let (cert_path, key_path) = match (cert_path, key_path) {
+            (None, None) => return Ok(None),
+            (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+            _ => anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+        };
+        let redirect_port = match std::env::var("TLS_REDIRECT_PORT") {
+            Ok(port) => Some(port.trim().parse::<u16>().map_err(|_| {
+                anyhow!("invalid TLS_REDIRECT_PORT {port:?}: expected a number between 0 and 65535")
+            })?),
+            Err(_) => None,
+        };
+
+        let certs = load_certs("TLS_CERT_PATH", &cert_path)?;
+        let key = load_key("TLS_KEY_PATH", &key_path)?;
+        let provider = Arc::new(rustls_rustcrypto::provider());
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("TLS_KEY_PATH does not match the certificate in TLS_CERT_PATH")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Some(Self {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            redirect_port,
+        }))
+    }
+
+    /// Listens on `addr`, handing hyper the connections that completed a
+    /// TLS handshake. Handshakes run concurrently, so a slow client does not
+    /// hold up the others.
+    pub fn incoming(&self, addr: &SocketAddr) -> anyhow::Result<Connections<Conn>> {
+        let (_, mut incoming) = connection::listen(addr)?;
+        let (tx, rx) = mpsc::channel(BACKLOG);
+        let acceptor = self.acceptor.clone();
+        tokio::spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    _ = tx.closed() => return,
+                    stream = incoming.next() => stream,
+                };
+                let stream = match stream {
+                    Some(Ok(stream)) => stream,
+                    Some(Err(err)) => {
+                        tracing::warn!(error = %err, "failed to accept a connection");
+                        continue;
+                    }
+                    None => return,
+                };
+                let Ok(peer) = stream.peer_addr() else {
+                    continue;
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let handshake = acceptor.accept(Compat(stream));
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send(Compat(stream)).await;
+                        }
+                        Ok(Err(err)) => {
+                            tracing::debug!(%peer, error = %err, "TLS handshake failed")
+                        }
+                        Err(_) => tracing::debug!(%peer, "TLS handshake timed out"),
+                    }
+                });
+            }
+        });
+        Ok(Box::pin(futures_util::stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|conn| (Ok(conn), rx)) },
+        )))
+    }
+}
+
+/// Serves `addr` with plain HTTP, answering every request with a permanent
+/// redirect to the same URL on the HTTPS port.
+pub fn redirect(
+    addr: SocketAddr,
+    https_port: u16,
+    settings: connection::ConnectionSettings,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
+    let (_, connections) = connection::listen(&addr)?;
+    let handler = move |_: &TcpStream| {
+        move |req: Request<Body>| async move { Ok::<_, Infallible>(redirect_response(&req, https_port)) }
+    };
+    tracing::info!(%addr, "redirecting HTTP to HTTPS");
+    Ok(async move {
+        connection::serve(connections, Protocols::Http(&settings), handler, shutdown).await
+    })
+}
+
+fn redirect_response(req: &Request<Body>, https_port: u16) -> Response<Body> {
+    let host = req
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<hyper::http::uri::Authority>().ok());
+    let Some(host) = host else {
+        let mut response = Response::new(Body::from("A Host header is required."));
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        return response;
+    };
+    let port = match https_port {
+        443 => String::new(),
+        port => format!(":{port}"),
+    };
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    Response::builder()
+        .status(StatusCode::PERMANENT_REDIRECT)
+        .header(
+            hyper::header::LOCATION,
+            format!("https://{}{port}{path}", host.host()),
+        )
+        .body(Body::empty())
+        .unwrap()
+}
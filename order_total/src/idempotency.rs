@@ -0,0 +1,133 @@
+use crate::codec::Format;
+use crate::config::env_or;
+use crate::error::ComputeError;
+use hyper::body::Bytes;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Remembers the response to each `Idempotency-Key` for a while, so a
+/// client retrying a `/compute` call gets the original result back instead
+/// of a second computation (and a second stored order). Each client, the
+/// API key or bearer token subject it authenticated as, has keys of its
+/// own within each tenant, see `tenant`, so that one cannot replay the
+/// responses of another by guessing its keys.
+pub struct IdempotencyStore {
+    ttl: Duration,
+    max_keys: usize,
+    entries: Mutex<HashMap<Key, Entry>>,
+}
+
+/// The tenant, the client and the `Idempotency-Key`.
+type Key = (Option<String>, Option<String>, String);
+
+struct Entry {
+    created: Instant,
+    /// Hash of the request body the key was first used with.
+    fingerprint: u64,
+    /// `None` while the first request is still being computed.
+    response: Option<(Format, Bytes)>,
+}
+
+/// What to do with a request carrying an idempotency key.
+pub enum Reservation<'a> {
+    /// First use of the key: compute, then `complete` the reservation.
+    Fresh(Pending<'a>),
+    /// The key was already used with the same body; return this response,
+    /// in the format it was first answered in.
+    Replay(Format, Bytes),
+}
+
+/// A reserved key whose response is being computed. Dropping it without
+/// calling `complete` (the computation failed or the client went away)
+/// frees the key for a retry.
+pub struct Pending<'a> {
+    store: &'a IdempotencyStore,
+    key: Key,
+    completed: bool,
+}
+
+impl Pending<'_> {
+    pub fn complete(mut self, format: Format, response: &Bytes) {
+        if let Some(entry) = self.store.entries.lock().unwrap().get_mut(&self.key) {
+            entry.response = Some((format, response.clone()));
+        }
+        self.completed = true;
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.store.entries.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+impl IdempotencyStore {
+    /// Reads `IDEMPOTENCY_TTL_SECS` (default 24 hours) and
+    /// `IDEMPOTENCY_MAX_KEYS` (default 10000).
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            ttl: Duration::from_secs(env_or("IDEMPOTENCY_TTL_SECS", 24 * 60 * 60)?),
+            max_keys: env_or("IDEMPOTENCY_MAX_KEYS", 10_000)?.max(1),
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn begin(&self, key: &str, body: &[u8]) -> Result<Reservation<'_>, ComputeError> {
+        if key.is_empty() || key.len() > 255 {
+            return Err(ComputeError::InvalidRequest);
+        }
+        let key = (
+            crate::tenant::current(),
+            crate::usage::client(),
+            key.to_owned(),
+        );
+        let fingerprint = fingerprint(body);
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| now.duration_since(entry.created) < self.ttl);
+
+        if let Some(entry) = entries.get(&key) {
+            if entry.fingerprint != fingerprint {
+                return Err(ComputeError::IdempotencyKeyReused);
+            }
+            return match &entry.response {
+                Some((format, response)) => Ok(Reservation::Replay(*format, response.clone())),
+                None => Err(ComputeError::IdempotencyKeyInFlight),
+            };
+        }
+
+        if entries.len() >= self.max_keys {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.created)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key.clone(),
+            Entry {
+                created: now,
+                fingerprint,
+                response: None,
+            },
+        );
+        Ok(Reservation::Fresh(Pending {
+            store: self,
+            key,
+            completed: false,
+        }))
+    }
+}
+
+fn fingerprint(body: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
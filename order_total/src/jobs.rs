@@ -0,0 +1,282 @@
+use crate::body::Body;
+use crate::config::env_or;
+use crate::error::{self, ComputeError};
+use crate::schema_mode::OrderSchema;
+use crate::{body, process, request_id, response_build, tenant, usage, with_content_type, App};
+use chrono::{DateTime, Utc};
+use hyper::header::{HeaderValue, LOCATION};
+use hyper::{Request, Response, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{Instrument, Span};
+use utoipa::ToSchema;
+
+/// Batches computed in the background, for those that take too long to wait
+/// on: `POST /v1/jobs` answers with a job id right away, and the orders are
+/// computed by a pool of workers, one batch per worker.
+///
+/// * `JOB_WORKERS` - batches computed at once, the others wait in line (default 2)
+/// * `JOB_RETENTION_SECS` - how long a finished job's result is kept (default 3600)
+/// * `MAX_JOB_BYTES` - largest accepted batch (default 8 MiB)
+pub struct JobQueue {
+    workers: Semaphore,
+    retention: Duration,
+    max_bytes: usize,
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+struct Job {
+    created_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<(DateTime<Utc>, Instant)>,
+    total: usize,
+    failed: usize,
+    /// The computed order or error body of each order done so far.
+    results: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a free worker.
+    Queued,
+    Running,
+    /// Every order has been computed; the result is available.
+    Finished,
+}
+
+/// The progress of a job.
+#[derive(Serialize, ToSchema)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub status: JobState,
+    /// How many orders the batch holds.
+    pub total: usize,
+    /// How many of them are done, successfully or not.
+    pub completed: usize,
+    /// How many of those failed; their result is an error body.
+    pub failed: usize,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// The outcome of a finished job.
+#[derive(Serialize, ToSchema)]
+pub struct JobResult {
+    pub job_id: String,
+    /// One entry per order of the batch, in the same order: the computed
+    /// order, or the error body of an order that failed.
+    #[schema(value_type = Vec<Object>)]
+    pub results: Vec<serde_json::Value>,
+}
+
+impl JobQueue {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            workers: Semaphore::new(env_or("JOB_WORKERS", 2usize)?.max(1)),
+            retention: Duration::from_secs(env_or("JOB_RETENTION_SECS", 3600)?),
+            max_bytes: env_or("MAX_JOB_BYTES", 8 * 1024 * 1024)?,
+            jobs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Registers a job of `total` orders, dropping the finished jobs past
+    /// their retention on the way.
+    fn insert(&self, id: &str, total: usize) -> JobStatus {
+        let job = Job {
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            total,
+            failed: 0,
+            results: Vec::with_capacity(total),
+        };
+        let status = job.status(id);
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| !job.expired(self.retention));
+        jobs.insert(id.to_owned(), job);
+        status
+    }
+
+    fn update(&self, id: &str, change: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            change(job);
+        }
+    }
+
+    fn read<T>(&self, id: &str, read: impl FnOnce(&Job) -> T) -> Result<T, ComputeError> {
+        let jobs = self.jobs.lock().unwrap();
+        match jobs.get(id) {
+            Some(job) if !job.expired(self.retention) => Ok(read(job)),
+            _ => Err(ComputeError::JobNotFound),
+        }
+    }
+}
+
+impl Job {
+    fn status(&self, id: &str) -> JobStatus {
+        let status = match (self.started_at, self.finished_at) {
+            (_, Some(_)) => JobState::Finished,
+            (Some(_), None) => JobState::Running,
+            (None, None) => JobState::Queued,
+        };
+        JobStatus {
+            job_id: id.to_owned(),
+            status,
+            total: self.total,
+            completed: self.results.len(),
+            failed: self.failed,
+            created_at: self.created_at,
+            started_at: self.started_at,
+            finished_at: self.finished_at.map(|(at, _)| at),
+        }
+    }
+
+    fn expired(&self, retention: Duration) -> bool {
+        self.finished_at
+            .is_some_and(|(_, finished)| finished.elapsed() >= retention)
+    }
+}
+
+/// Queues a batch of orders, sent as a JSON array, and answers `202` with
+/// the job's status and its URL in `Location`. A malformed order fails only
+/// its own entry of the result. With a `callback_url` query parameter, the
+/// result is also POSTed there once the job finishes.
+#[utoipa::path(
+    post,
+    path = "/v1/jobs",
+    request_body(content = Vec<Order>, description = "The orders of the batch, as a JSON array"),
+    params(("callback_url" = Option<String>, Query, description = "POST the job's result to this URL when it finishes, signed with `WEBHOOK_SECRET`")),
+    responses(
+        (status = 202, description = "The job was queued; poll the `Location` for its status", body = JobStatus),
+        (status = 400, description = "The body is not a JSON array", body = ErrorResponse),
+        (status = 413, description = "The body exceeds `MAX_JOB_BYTES`", body = ErrorResponse),
+        (status = 422, description = "The `callback_url` is invalid, or callbacks are not enabled", body = ErrorResponse),
+    )
+)]
+pub async fn submit(req: Request<Body>, app: Arc<App>) -> Response<Body> {
+    match queue(req, app).await {
+        Ok(status) => {
+            let location = format!("/v1/jobs/{}", status.job_id);
+            let body = crate::pretty::to_string(&status).unwrap();
+            let mut response = with_content_type(
+                response_build(StatusCode::ACCEPTED, &body),
+                "application/json",
+            );
+            response
+                .headers_mut()
+                .insert(LOCATION, HeaderValue::from_str(&location).unwrap());
+            response
+        }
+        Err(err) => error::response(err),
+    }
+}
+
+async fn queue(req: Request<Body>, app: Arc<App>) -> Result<JobStatus, ComputeError> {
+    let callback = app.webhooks.callback_url(req.uri().query())?;
+    let schema = app.schema.of_request(req.headers())?;
+    let bytes = body::to_bytes_limited(req, app.jobs.max_bytes).await?;
+    let orders: Vec<serde_json::Value> = serde_json::from_slice(&bytes)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let status = app.jobs.insert(&id, orders.len());
+    tracing::info!(job_id = %id, orders = orders.len(), "job queued");
+
+    let request_id = request_id::current().unwrap_or_default();
+    let job = run(id, orders, schema, callback, usage::client(), app);
+    let task = tenant::scope(tenant::current(), job);
+    tokio::spawn(request_id::scope(request_id, task).instrument(Span::current()));
+    Ok(status)
+}
+
+/// Computes the orders of a job one after the other, once a worker is free,
+/// then sends the result to the job's callback, if any. The orders count as
+/// one batch of `client`, see `usage`.
+async fn run(
+    id: String,
+    orders: Vec<serde_json::Value>,
+    schema: OrderSchema,
+    callback: Option<String>,
+    client: Option<String>,
+    app: Arc<App>,
+) {
+    let _worker = app.jobs.workers.acquire().await.expect("never closed");
+    app.jobs
+        .update(&id, |job| job.started_at = Some(Utc::now()));
+    let computing = async {
+        for order in orders {
+            let result = match schema.order(order) {
+                Ok(order) => process(order, &app).await,
+                Err(err) => Err(err),
+            };
+            let (result, failed) = match result {
+                Ok(order) => (serde_json::to_value(&order).unwrap(), false),
+                Err(err) => (serde_json::to_value(error::parts(err).1).unwrap(), true),
+            };
+            app.jobs.update(&id, |job| {
+                job.results.push(result);
+                job.failed += usize::from(failed);
+            });
+        }
+    };
+    let ((), batch) = usage::measure(client, computing).await;
+    app.usage.record(&batch);
+    let mut results = Vec::new();
+    app.jobs.update(&id, |job| {
+        job.finished_at = Some((Utc::now(), Instant::now()));
+        tracing::info!(job_id = %id, failed = job.failed, "job finished");
+        if callback.is_some() {
+            results = job.results.clone();
+        }
+    });
+    if let Some(url) = callback {
+        let result = JobResult {
+            job_id: id,
+            results,
+        };
+        app.webhooks
+            .spawn(url, "job.finished", serde_json::to_vec(&result).unwrap());
+    }
+}
+
+/// The progress of a job.
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{job_id}",
+    params(("job_id" = String, Path, description = "The id `POST /v1/jobs` answered with")),
+    responses(
+        (status = 200, description = "The job's status", body = JobStatus),
+        (status = 404, description = "No such job, or its result has expired", body = ErrorResponse),
+    )
+)]
+pub fn status(app: &App, job_id: &str) -> Result<String, ComputeError> {
+    let status = app.jobs.read(job_id, |job| job.status(job_id))?;
+    Ok(crate::pretty::to_string(&status).unwrap())
+}
+
+/// The results of a finished job.
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{job_id}/result",
+    params(("job_id" = String, Path, description = "The id `POST /v1/jobs` answered with")),
+    responses(
+        (status = 200, description = "One computed order or error body per order of the batch", body = JobResult),
+        (status = 404, description = "No such job, or its result has expired", body = ErrorResponse),
+        (status = 409, description = "The job has not finished yet", body = ErrorResponse),
+    )
+)]
+pub fn result(app: &App, job_id: &str) -> Result<String, ComputeError> {
+    let results = app
+        .jobs
+        .read(job_id, |job| job.finished_at.map(|_| job.results.clone()))?;
+    let result = JobResult {
+        job_id: job_id.to_owned(),
+        results: results.ok_or(ComputeError::JobNotFinished)?,
+    };
+    Ok(crate::pretty::to_string(&result).unwrap())
+}
@@ -0,0 +1,332 @@
+use crate::codec::Format;
+use crate::config::env_or;
+use crate::{compute, error, request_id, App};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use rskafka::client::consumer::{StartOffset, StreamConsumerBuilder};
+use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+use rskafka::client::{Client, ClientBuilder};
+use rskafka::record::Record;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tracing::Instrument;
+
+/// The header of a result record telling a computed order (`order`) from an
+/// error body (`error`).
+const RESULT_HEADER: &str = "result";
+
+/// The Kafka run mode: orders are consumed from one topic and their results
+/// published to another, computed exactly like `POST /v1/compute`.
+///
+/// * `KAFKA_BROKERS` - bootstrap brokers, comma separated (required)
+/// * `KAFKA_INPUT_TOPIC` - the topic of JSON orders (default `orders`)
+/// * `KAFKA_OUTPUT_TOPIC` - the topic results are published to (default `order-totals`)
+/// * `KAFKA_ERROR_TOPIC` - the topic error bodies are published to (default the output topic)
+/// * `KAFKA_START_OFFSET` - where consumption starts, `latest` (default) or `earliest`
+struct KafkaConfig {
+    brokers: Vec<String>,
+    input_topic: String,
+    output_topic: String,
+    error_topic: Option<String>,
+    start_offset: StartOffset,
+}
+
+impl KafkaConfig {
+    fn from_env() -> anyhow::Result<Self> {
+        let brokers: Vec<String> = std::env::var("KAFKA_BROKERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|broker| broker.trim().to_owned())
+            .filter(|broker| !broker.is_empty())
+            .collect();
+        if brokers.is_empty() {
+            anyhow::bail!("the kafka run mode needs KAFKA_BROKERS");
+        }
+        let start_offset = match std::env::var("KAFKA_START_OFFSET")
+            .as_deref()
+            .map(str::trim)
+        {
+            Err(_) | Ok("latest") => StartOffset::Latest,
+            Ok("earliest") => StartOffset::Earliest,
+            Ok(other) => {
+                anyhow::bail!("invalid KAFKA_START_OFFSET {other:?}: expected latest or earliest")
+            }
+        };
+        Ok(Self {
+            brokers,
+            input_topic: env_or("KAFKA_INPUT_TOPIC", "orders".to_owned())?,
+            output_topic: env_or("KAFKA_OUTPUT_TOPIC", "order-totals".to_owned())?,
+            error_topic: std::env::var("KAFKA_ERROR_TOPIC")
+                .ok()
+                .map(|topic| topic.trim().to_owned())
+                .filter(|topic| !topic.is_empty()),
+            start_offset,
+        })
+    }
+}
+
+/// Consumes every partition of the input topic until `shutdown` resolves or
+/// a connection fails for good. Each result goes to the output (or error)
+/// partition matching its input partition, so orders of one partition stay
+/// in order.
+///
+/// Offsets are committed in memory only, see `PartitionConsumer`: the
+/// consumers start at `KAFKA_START_OFFSET` every time the service starts.
+pub async fn run(app: Arc<App>, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+    let config = KafkaConfig::from_env()?;
+    let client = ClientBuilder::new(config.brokers.clone())
+        .client_id("order_total")
+        .build()
+        .await
+        .context("could not connect to KAFKA_BROKERS")?;
+
+    let outputs = topic_clients(&client, &config.output_topic).await?;
+    let errors = match &config.error_topic {
+        Some(topic) => topic_clients(&client, topic).await?,
+        None => outputs.clone(),
+    };
+
+    let mut consumers = JoinSet::new();
+    for partition in partitions(&client, &config.input_topic).await? {
+        let input = partition_client(&client, &config.input_topic, partition).await?;
+        let publisher = Partitions {
+            output: outputs[partition as usize % outputs.len()].clone(),
+            errors: errors[partition as usize % errors.len()].clone(),
+        };
+        let consumer = PartitionConsumer::new(app.clone(), publisher, config.start_offset);
+        let task = consume(input, consumer);
+        consumers.spawn(task.instrument(tracing::info_span!("kafka_consumer", partition)));
+    }
+    tracing::info!(
+        brokers = %config.brokers.join(","),
+        input = %config.input_topic,
+        output = %config.output_topic,
+        errors = %config.error_topic.as_ref().unwrap_or(&config.output_topic),
+        partitions = consumers.len(),
+        "kafka consumer started"
+    );
+
+    tokio::select! {
+        Some(result) = consumers.join_next() => {
+            result.context("kafka consumer panicked")??;
+            Err(anyhow!("a kafka consumer stopped"))
+        }
+        _ = shutdown => {
+            consumers.shutdown().await;
+            Ok(())
+        }
+    }
+}
+
+async fn partitions(client: &Client, topic: &str) -> anyhow::Result<Vec<i32>> {
+    let topics = client
+        .list_topics()
+        .await
+        .context("could not list the Kafka topics")?;
+    match topics.into_iter().find(|candidate| candidate.name == topic) {
+        Some(topic) if !topic.partitions.is_empty() => Ok(topic.partitions.into_iter().collect()),
+        _ => Err(anyhow!("the Kafka topic {topic:?} does not exist")),
+    }
+}
+
+async fn topic_clients(client: &Client, topic: &str) -> anyhow::Result<Vec<Arc<PartitionClient>>> {
+    let mut clients = Vec::new();
+    for partition in partitions(client, topic).await? {
+        clients.push(partition_client(client, topic, partition).await?);
+    }
+    Ok(clients)
+}
+
+async fn partition_client(
+    client: &Client,
+    topic: &str,
+    partition: i32,
+) -> anyhow::Result<Arc<PartitionClient>> {
+    let client = client
+        .partition_client(topic, partition, UnknownTopicHandling::Retry)
+        .await
+        .with_context(|| format!("could not open partition {partition} of {topic:?}"))?;
+    Ok(Arc::new(client))
+}
+
+/// Feeds the records of `input` to `consumer`. When one cannot be fetched
+/// or its result cannot be published, consumption starts over after a pause
+/// from the first record not committed, so none is skipped.
+async fn consume(
+    input: Arc<PartitionClient>,
+    mut consumer: PartitionConsumer<Partitions>,
+) -> anyhow::Result<()> {
+    loop {
+        let mut records = StreamConsumerBuilder::new(input.clone(), consumer.resume_from())
+            .with_max_wait_ms(500)
+            .build();
+        let failure = loop {
+            match records.next().await {
+                Some(Ok((record, _high_watermark))) => {
+                    if let Err(err) = consumer.handle(record.offset, record.record).await {
+                        break err;
+                    }
+                }
+                Some(Err(err)) => break anyhow!(err).context("could not fetch orders"),
+                None => return Ok(()),
+            }
+        };
+        tracing::warn!(
+            error = %format!("{failure:#}"),
+            committed = consumer.committed(),
+            "kafka consumer resuming from the last committed offset"
+        );
+        tokio::time::sleep(RESUME_DELAY).await;
+    }
+}
+
+/// How long a consumer pauses before consuming again after a failure.
+const RESUME_DELAY: Duration = Duration::from_secs(1);
+
+/// The topics a result record is published to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    /// `KAFKA_OUTPUT_TOPIC`, for computed orders.
+    Output,
+    /// `KAFKA_ERROR_TOPIC`, for error bodies.
+    Errors,
+}
+
+/// Publishes the result records of one input partition.
+#[async_trait]
+pub trait Publish: Send + Sync {
+    async fn publish(&self, destination: Destination, record: Record) -> anyhow::Result<()>;
+}
+
+/// The output and error partitions matching an input partition.
+struct Partitions {
+    output: Arc<PartitionClient>,
+    errors: Arc<PartitionClient>,
+}
+
+#[async_trait]
+impl Publish for Partitions {
+    async fn publish(&self, destination: Destination, record: Record) -> anyhow::Result<()> {
+        let partition = match destination {
+            Destination::Output => &self.output,
+            Destination::Errors => &self.errors,
+        };
+        partition
+            .produce(vec![record], Compression::NoCompression)
+            .await
+            .context("could not publish a result")?;
+        Ok(())
+    }
+}
+
+/// An order as an input record carries it.
+#[derive(Debug)]
+pub struct Message {
+    pub key: Option<Vec<u8>>,
+    /// The JSON order, empty (and so invalid) when the record has no value.
+    pub order: Vec<u8>,
+    /// The record's well-formed `x-request-id` header, or a new id.
+    pub request_id: String,
+}
+
+impl Message {
+    pub fn decode(record: Record) -> Self {
+        let request_id = record
+            .headers
+            .get(request_id::HEADER)
+            .and_then(|id| std::str::from_utf8(id).ok())
+            .and_then(request_id::parse)
+            .map_or_else(request_id::generate, str::to_owned);
+        Self {
+            key: record.key,
+            order: record.value.unwrap_or_default(),
+            request_id,
+        }
+    }
+}
+
+/// Computes the orders of one input partition in offset order, publishing
+/// each result through `P` before it commits the record's offset. A result
+/// that cannot be published leaves the offset uncommitted, for the record to
+/// be consumed again.
+pub struct PartitionConsumer<P> {
+    app: Arc<App>,
+    publisher: P,
+    start_offset: StartOffset,
+    committed: Option<i64>,
+}
+
+impl<P: Publish> PartitionConsumer<P> {
+    pub fn new(app: Arc<App>, publisher: P, start_offset: StartOffset) -> Self {
+        Self {
+            app,
+            publisher,
+            start_offset,
+            committed: None,
+        }
+    }
+
+    /// The offset of the next record to consume, once one was committed.
+    pub fn committed(&self) -> Option<i64> {
+        self.committed
+    }
+
+    /// Where consuming starts: past the last committed record, or at the
+    /// configured start offset before any.
+    pub fn resume_from(&self) -> StartOffset {
+        self.committed.map_or(self.start_offset, StartOffset::At)
+    }
+
+    /// Computes the order of `record`, found at `offset`, publishes its
+    /// result, and commits the offset.
+    pub async fn handle(&mut self, offset: i64, record: Record) -> anyhow::Result<()> {
+        let message = Message::decode(record);
+        let span = tracing::info_span!(
+            "kafka_order",
+            offset,
+            request_id = %message.request_id,
+            zip = tracing::field::Empty,
+        );
+        let id = message.request_id.clone();
+        let (destination, result) = request_id::scope(id, result_record(message, &self.app))
+            .instrument(span)
+            .await;
+        self.publisher.publish(destination, result).await?;
+        self.committed = Some(offset + 1);
+        Ok(())
+    }
+}
+
+/// Computes the order in `message` and builds the record of its result: the
+/// computed order, or the error body, under the same key.
+async fn result_record(message: Message, app: &App) -> (Destination, Record) {
+    let order = message.order.into();
+    let (destination, result, value) =
+        match compute(&order, Format::Json, app.schema, false, app).await {
+            Ok(order) => (
+                Destination::Output,
+                "order",
+                serde_json::to_vec(&order).unwrap(),
+            ),
+            Err(err) => {
+                tracing::warn!(code = ?err.code(), "order could not be computed");
+                let body = serde_json::to_vec(&error::parts(err).1).unwrap();
+                (Destination::Errors, "error", body)
+            }
+        };
+    let mut headers = BTreeMap::from([(RESULT_HEADER.to_owned(), result.as_bytes().to_vec())]);
+    if let Some(id) = request_id::current() {
+        headers.insert(request_id::HEADER.to_owned(), id.into_bytes());
+    }
+    let record = Record {
+        key: message.key,
+        value: Some(value),
+        headers,
+        timestamp: chrono::Utc::now(),
+    };
+    (destination, record)
+}
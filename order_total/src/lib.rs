@@ -0,0 +1,733 @@
+//! The `order_total` service: the HTTP API around `order_total_core`. The
+//! binary calls `run`; `App::from_env` and `bind` (and `grpc::bind` for the
+//! gRPC interface) start the service inside another program, such as the
+//! integration tests, and `OrderTotalService` mounts it in a router of its
+//! own. The socket servers, `bind` and `grpc::bind` among them, are only
+//! built with the `server` feature.
+
+#[macro_use]
+extern crate lazy_static;
+// The WasmEdge build keeps the tonic and prost of hyper 0.14; the generated
+// gRPC code names them `tonic` and `prost`.
+#[cfg(feature = "wasmedge")]
+extern crate prost_0_12 as prost;
+#[cfg(feature = "wasmedge")]
+extern crate tonic_0_11 as tonic;
+
+#[cfg(all(feature = "wasmedge", feature = "native"))]
+compile_error!("enable only one of the `wasmedge` and `native` features");
+#[cfg(not(any(feature = "wasmedge", feature = "native")))]
+compile_error!("enable the `wasmedge` feature (WasmEdge) or the `native` feature");
+
+mod access_log;
+mod admin;
+mod api;
+mod api_keys;
+mod audit;
+mod auth;
+mod body;
+mod bulk_csv;
+mod catalog;
+mod circuit_breaker;
+mod cli;
+mod coalesce;
+mod codec;
+mod compression;
+mod config;
+mod config_file;
+#[cfg(feature = "server")]
+mod connection;
+mod cors;
+mod deadline;
+mod error;
+mod error_report;
+mod exemption;
+mod flags;
+mod graphql;
+#[cfg(feature = "server")]
+pub mod grpc;
+mod hedge;
+#[cfg(feature = "server")]
+mod https;
+mod idempotency;
+mod jobs;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+mod load_shed;
+mod logging;
+mod middleware;
+#[cfg(feature = "nats")]
+pub mod nats;
+mod ndjson;
+mod negative_cache;
+mod openapi;
+mod orders;
+mod pretty;
+mod quote;
+mod rate_cache;
+mod rate_limit;
+mod recover;
+mod redis;
+mod refund;
+mod reload;
+mod request_id;
+mod response_cache;
+mod retry;
+mod schema_mode;
+mod security_headers;
+#[cfg(feature = "server")]
+mod server;
+mod service;
+mod shadow;
+// The socket servers use all of it; the Kafka run mode only waits for the
+// request.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+mod shutdown;
+mod store;
+mod tax_rate;
+mod telemetry;
+mod tenant;
+mod tls;
+#[cfg(feature = "server")]
+mod unix_socket;
+mod upstream;
+mod usage;
+mod version;
+mod wagi;
+mod webhook;
+
+use access_log::AccessLog;
+use admin::Admin;
+use api::{ApiVersion, Route};
+use api_keys::{ApiKeys, Quota};
+use audit::AuditLog;
+use auth::JwtAuth;
+use codec::Format;
+use compression::ResponseCompression;
+#[cfg(feature = "server")]
+use connection::ConnectionSettings;
+use cors::CorsPolicy;
+use deadline::Deadlines;
+use error::ComputeError;
+use error_report::ErrorReporter;
+use flags::{FeatureFlags, Flag};
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use idempotency::{IdempotencyStore, Reservation};
+use jobs::JobQueue;
+use load_shed::ConcurrencyLimit;
+use order_total_core::{
+    Calculator, CanadianTaxes, CategoryAdjustments, FxRateTable, HolidayCalendar, Order,
+    RoundingStrategy, VatRates,
+};
+use quote::Quotes;
+use rate_cache::{CachedRates, WarmUp};
+use rate_limit::RateLimit;
+use response_cache::ResponseCache;
+use rust_decimal::Decimal;
+use schema_mode::OrderSchema;
+use security_headers::SecurityHeaders;
+use serde::Deserialize;
+use shadow::ShadowStats;
+use std::sync::{Arc, RwLock};
+use store::OrderStore;
+use tenant::{Tenant, Tenants};
+use usage::Usage;
+use webhook::Webhooks;
+
+pub use body::Body;
+pub use cli::Cli;
+pub use config::{runtime_flavor, RuntimeFlavor};
+pub use config_file::Config;
+#[cfg(feature = "server")]
+pub use server::bind;
+#[cfg(all(feature = "server", unix))]
+pub use server::bind_unix;
+pub use service::OrderTotalService;
+
+lazy_static! {
+    static ref OPENAPI_JSON: String = openapi::json();
+}
+
+/// Everything the request handlers share, built once at startup.
+pub struct App {
+    /// Replaced as a whole by `reload`; a computation keeps the one it
+    /// started with.
+    calculator: RwLock<Arc<Calculator>>,
+    /// The largest request body, `MAX_BODY_BYTES`, and the largest CSV
+    /// upload, `MAX_CSV_BYTES`, after decompression.
+    max_body_bytes: usize,
+    max_csv_bytes: usize,
+    store: Option<Arc<dyn OrderStore>>,
+    audit: Option<AuditLog>,
+    idempotency: IdempotencyStore,
+    responses: ResponseCache,
+    cors: CorsPolicy,
+    compression: ResponseCompression,
+    auth: Option<JwtAuth>,
+    api_keys: Option<ApiKeys>,
+    in_flight: ConcurrencyLimit,
+    rate_limit: RateLimit,
+    deadlines: Deadlines,
+    jobs: JobQueue,
+    quotes: Quotes,
+    webhooks: Webhooks,
+    usage: Usage,
+    rate_cache: Arc<CachedRates>,
+    shadow: Arc<ShadowStats>,
+    tenants: Option<Tenants>,
+    warm_up: WarmUp,
+    admin: Option<Admin>,
+    #[cfg(feature = "server")]
+    connections: ConnectionSettings,
+    security_headers: SecurityHeaders,
+    access_log: AccessLog,
+    error_reporter: Option<ErrorReporter>,
+    schema: OrderSchema,
+    flags: FeatureFlags,
+}
+
+impl App {
+    /// Builds the app from the environment variables listed in the README.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let rate_cache = Arc::new(CachedRates::from_env()?);
+        let shadow = Arc::new(ShadowStats::default());
+        let webhooks = Webhooks::from_env()?;
+        Ok(Self {
+            calculator: RwLock::new(Arc::new(calculator_from_env(&rate_cache, &shadow, None)?)),
+            max_body_bytes: config::env_or("MAX_BODY_BYTES", 256 * 1024)?,
+            max_csv_bytes: config::env_or("MAX_CSV_BYTES", 8 * 1024 * 1024)?,
+            store: store::from_env()?,
+            audit: AuditLog::from_env()?,
+            idempotency: IdempotencyStore::from_env()?,
+            responses: ResponseCache::from_env()?,
+            cors: CorsPolicy::from_env()?,
+            compression: ResponseCompression::from_env()?,
+            auth: JwtAuth::from_env()?,
+            api_keys: ApiKeys::from_env()?,
+            in_flight: ConcurrencyLimit::from_env()?,
+            rate_limit: RateLimit::from_env()?,
+            deadlines: Deadlines::from_env()?,
+            jobs: JobQueue::from_env()?,
+            quotes: Quotes::from_env()?,
+            usage: Usage::from_env(&webhooks)?,
+            webhooks,
+            warm_up: WarmUp::from_env(&rate_cache)?,
+            rate_cache,
+            tenants: Tenants::from_env(|settings, cache| {
+                calculator_from_env(cache, &shadow, Some(settings))
+            })?,
+            shadow,
+            admin: Admin::from_env(),
+            #[cfg(feature = "server")]
+            connections: ConnectionSettings::from_env()?,
+            security_headers: SecurityHeaders::from_env()?,
+            access_log: AccessLog::from_env()?,
+            error_reporter: ErrorReporter::from_env()?,
+            schema: OrderSchema::from_env()?,
+            flags: FeatureFlags::from_env()?,
+        })
+    }
+
+    /// Fills the rate cache with the zip codes of `CACHE_WARM_ZIPS`, see
+    /// `rate_cache::WarmUp`; `run` does so before serving.
+    pub async fn warm_up(&self) {
+        self.warm_up.run(&*self.calculator().tax_rates).await;
+    }
+
+    /// Sends the usage counted since the last report to `USAGE_SINK_URL`,
+    /// see `usage::Usage`; `run` does so every `USAGE_REPORT_SECS`.
+    pub fn report_usage(&self) {
+        self.usage.send_report();
+    }
+
+    /// Reads the computation settings anew: where rates come from (the
+    /// rate service URLs, `TAX_RATE_SOURCE`, the rate table and the shadow
+    /// service), the default rate, rounding, the tax, VAT, holiday, exchange
+    /// rate and catalog tables, the rate cache's `RATE_CACHE_SECS` and
+    /// `RATE_CACHE_MAX`, and the feature flags.
+    /// Computations already under way finish with the old settings. When
+    /// the new ones are invalid, the old ones stay in force.
+    ///
+    /// The rate service's circuit breaker, the memory of unknown zip codes
+    /// and the response cache start over; cached rates are kept. The
+    /// tenants of `TENANTS_FILE` are built anew on the new settings.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        config_file::reload()?;
+        let calculator = calculator_from_env(&self.rate_cache, &self.shadow, None)?;
+        let flags = FeatureFlags::from_env()?;
+        let limits = self.rate_cache.limits()?;
+        // The last that can fail, and all or nothing itself.
+        if let Some(tenants) = &self.tenants {
+            tenants.reload(|settings, cache| {
+                calculator_from_env(cache, &self.shadow, Some(settings))
+            })?;
+        }
+        self.flags.replace(flags);
+        self.rate_cache.reconfigure(limits);
+        *self.calculator.write().unwrap() = Arc::new(calculator);
+        self.responses.clear();
+        tracing::info!("configuration reloaded");
+        Ok(())
+    }
+
+    /// The calculator of the request's tenant, see `tenant`, or the
+    /// service's.
+    fn calculator(&self) -> Arc<Calculator> {
+        match self.tenant() {
+            Some(tenant) => tenant.calculator(),
+            None => self.calculator.read().unwrap().clone(),
+        }
+    }
+
+    /// The rate cache of the request's tenant, or the service's.
+    fn rate_cache(&self) -> &Arc<CachedRates> {
+        self.tenant()
+            .map_or(&self.rate_cache, |tenant| &tenant.rate_cache)
+    }
+
+    /// The configured tenant the request names in `X-Tenant-Id`, if any.
+    fn tenant(&self) -> Option<&Tenant> {
+        self.tenants.as_ref()?.get(&tenant::current()?)
+    }
+}
+
+/// The calculator as the environment configures it, with the overrides of
+/// `tenant`; `SALES_TAX_RATE_SERVICE` defaults to
+/// `http://localhost:8001/find_rate`.
+fn calculator_from_env(
+    rate_cache: &Arc<CachedRates>,
+    shadow: &Arc<ShadowStats>,
+    tenant: Option<&tenant::Settings>,
+) -> anyhow::Result<Calculator> {
+    let service_url = match tenant.and_then(|tenant| tenant.rate_service_url.clone()) {
+        Some(url) => url,
+        None => std::env::var("SALES_TAX_RATE_SERVICE")
+            .unwrap_or_else(|_| format!("http://localhost:8001{}", models::FIND_RATE_PATH)),
+    };
+    let mut calculator = Calculator {
+        tax_rates: shadow::from_env(tax_rate::from_env(&service_url, rate_cache)?, shadow)?,
+        rounding: RoundingStrategy::from_env()?,
+        default_tax_rate: config::default_tax_rate()?,
+        categories: CategoryAdjustments::load()?,
+        exemptions: exemption::from_env()?,
+        canada: CanadianTaxes::load()?,
+        vat: VatRates::load()?,
+        holidays: HolidayCalendar::load()?,
+        fx_rates: Arc::new(FxRateTable::load()?),
+        catalog: catalog::from_env()?,
+    };
+    if let Some(tenant) = tenant {
+        tenant.apply(&mut calculator);
+    }
+    Ok(calculator)
+}
+
+/// Routes a request to its handler; `None` for a path nothing is served at.
+async fn dispatch(req: Request<Body>, route: Route<'_>, app: &Arc<App>) -> Option<Response<Body>> {
+    let response = match (req.method(), route.version, route.path) {
+        // CORS OPTIONS
+        (&Method::OPTIONS, ApiVersion::V1, "/compute") => response_build(StatusCode::OK, ""),
+        (&Method::OPTIONS, _, "/graphql") => response_build(StatusCode::OK, ""),
+
+        // Serve some instructions at /
+        (&Method::GET, _, "/") => Response::new(Body::from(
+            "Try POSTing data to /v1/compute such as: `curl localhost:8002/v1/compute -XPOST -d '...'`",
+        )),
+
+        (&Method::GET, _, "/openapi.json") => {
+            with_content_type(response_build(StatusCode::OK, &OPENAPI_JSON), "application/json")
+        }
+
+        // Interactive Swagger UI for the document above
+        (&Method::GET, _, "/docs" | "/docs/") => with_content_type(
+            response_build(StatusCode::OK, include_str!("docs/index.html")),
+            "text/html; charset=utf-8",
+        ),
+
+        // Which build is running
+        (&Method::GET, _, api::VERSION) => {
+            with_content_type(response_build(StatusCode::OK, &version::json()), "application/json")
+        }
+
+        (&Method::GET, _, "/schema.graphql") => with_content_type(
+            response_build(StatusCode::OK, &graphql::SDL),
+            "text/plain; charset=utf-8",
+        ),
+
+        (&Method::POST, ApiVersion::V1, "/compute") => compute_request(req, app).await,
+
+        (&Method::POST, _, "/graphql") => graphql::handle(req, app.clone()).await,
+
+        // The batch endpoints are not served with the `batch` flag off
+        (&Method::POST, ApiVersion::V1, "/compute_csv") if app.flags.enabled(Flag::Batch) => {
+            bulk_csv::compute_csv(req, app).await
+        }
+
+        (&Method::POST, ApiVersion::V1, "/compute_stream") if app.flags.enabled(Flag::Batch) => {
+            ndjson::compute_stream(req, app.clone())
+        }
+
+        (&Method::POST, ApiVersion::V1, "/quote") => quote::quote(req, app).await,
+
+        (&Method::POST, ApiVersion::V1, "/finalize") => quote::finalize(req, app).await,
+
+        (&Method::POST, ApiVersion::V1, "/refund") => refund::refund(req, app).await,
+
+        (&Method::POST, ApiVersion::V1, "/jobs") if app.flags.enabled(Flag::Batch) => {
+            jobs::submit(req, app.clone()).await
+        }
+
+        (&Method::GET, ApiVersion::V1, path) if path.starts_with("/jobs/") => {
+            let job_id = &path["/jobs/".len()..];
+            match job_id.strip_suffix("/result") {
+                Some(job_id) => json_result(jobs::result(app, job_id)),
+                None => json_result(jobs::status(app, job_id)),
+            }
+        }
+
+        (&Method::GET, ApiVersion::V1, "/orders") => json_result(orders::list(app, req.uri().query())),
+
+        (&Method::GET, ApiVersion::V1, path) if path.starts_with("/orders/") => {
+            json_result(orders::get(app, &path["/orders/".len()..]))
+        }
+
+        (_, _, path) if api::is_admin(path) => admin::handle(req, app, path).await,
+
+        // Return the 404 Not Found for other routes.
+        _ => return None,
+    };
+    Some(response)
+}
+
+/// Checks the credentials of a request to an API route. An `X-Api-Key` is
+/// taken against the key's rate limit, whose quota is returned for the
+/// response headers; it is required when API keys are the only mechanism
+/// configured. Otherwise a bearer token is required when JWT authentication
+/// is on, and its claims are put in the request extensions for the handlers.
+async fn authenticate<B>(req: &mut Request<B>, app: &App) -> Result<Option<Quota>, ComputeError> {
+    let span = tracing::Span::current();
+    if let Some(keys) = &app.api_keys {
+        if app.auth.is_none() || ApiKeys::present(req.headers()) {
+            let (client, quota) = keys.admit(req.headers())?;
+            span.record("subject", client);
+            usage::identify(client);
+            return Ok(Some(quota));
+        }
+    }
+    if let Some(auth) = &app.auth {
+        let claims = auth.authenticate(req.headers()).await?;
+        span.record("subject", claims.sub.as_str());
+        usage::identify(&claims.sub);
+        req.extensions_mut().insert(claims);
+    }
+    Ok(None)
+}
+
+/// Reads the order from the request body and computes it, honoring an
+/// `Idempotency-Key` header: a retried request with the same key and body is
+/// answered with the stored response (flagged `Idempotent-Replayed: true`).
+///
+/// The order may be sent and answered as JSON, MessagePack or CBOR, as
+/// selected by the `Content-Type` and `Accept` headers. Beyond
+/// `MAX_IN_FLIGHT` concurrent requests, it is shed with a `503`.
+///
+/// With a `callback_url` query parameter, the computed order is also POSTed
+/// there as JSON, see `webhook`. With `dry_run=true` (or `"dry_run": true`
+/// in the order), it is only computed, see `Order::dry_run`. Without an
+/// `Idempotency-Key`, a repeated order may be answered from the response
+/// cache, see `compute_previewed`.
+async fn compute_request(req: Request<Body>, app: &App) -> Response<Body> {
+    let _permit = match app.in_flight.try_acquire() {
+        Ok(permit) => permit,
+        Err(err) => return error::response(err),
+    };
+    let callback = match app.webhooks.callback_url(req.uri().query()) {
+        Ok(callback) => callback,
+        Err(err) => return error::response(err),
+    };
+    let dry_run = match dry_run(req.uri().query()) {
+        Ok(dry_run) => dry_run,
+        Err(err) => return error::response(err),
+    };
+    let schema = match app.schema.of_request(req.headers()) {
+        Ok(schema) => schema,
+        Err(err) => return error::response(err),
+    };
+    let request_format = Format::of_request(req.headers());
+    let response_format = Format::of_response(req.headers(), request_format);
+    let key = req
+        .headers()
+        .get("Idempotency-Key")
+        .map(|value| value.to_str().map(str::to_owned));
+    let bytes = match body::to_bytes_limited(req, app.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(err) => return error::response(err),
+    };
+    let key = match key {
+        None => {
+            let formats = (request_format, response_format);
+            return compute_previewed(&bytes, formats, schema, dry_run, callback, app).await;
+        }
+        Some(Ok(key)) => key,
+        Some(Err(_)) => return error::response(ComputeError::InvalidRequest),
+    };
+
+    match app.idempotency.begin(&key, &bytes) {
+        Ok(Reservation::Replay(format, body)) => {
+            let mut response = encoded_response(format, body);
+            response.headers_mut().insert(
+                "Idempotent-Replayed",
+                hyper::header::HeaderValue::from_static("true"),
+            );
+            response
+        }
+        Ok(Reservation::Fresh(pending)) => {
+            let result = compute(&bytes, request_format, schema, dry_run, app).await;
+            notify(app, callback, &result);
+            let result = result.and_then(|order| response_format.encode(&order));
+            match result {
+                Ok(body) => {
+                    pending.complete(response_format, &body);
+                    encoded_response(response_format, body)
+                }
+                Err(err) => error::response(err),
+            }
+        }
+        Err(err) => error::response(err),
+    }
+}
+
+/// Computes an order, answering it from the response cache when the same
+/// order was answered in the same format within `RESPONSE_CACHE_SECS`
+/// (flagged `X-Cache: hit`, and `X-Cache: miss` when it was computed).
+///
+/// Only an order without side effects is cached: one without a callback
+/// that is a dry run, or that would be neither stored nor audited.
+async fn compute_previewed(
+    bytes: &Bytes,
+    (request_format, response_format): (Format, Format),
+    schema: OrderSchema,
+    dry_run: bool,
+    callback: Option<String>,
+    app: &App,
+) -> Response<Body> {
+    let mut order = match schema.decode(request_format, bytes) {
+        Ok(order) => order,
+        Err(err) => return error::response(err),
+    };
+    order.dry_run |= dry_run;
+    let unrecorded = order.dry_run || (app.store.is_none() && app.audit.is_none());
+    let key = match callback {
+        None if unrecorded => app.responses.key(&order, response_format),
+        _ => None,
+    };
+    let Some(key) = key else {
+        let result = process(order, app).await;
+        notify(app, callback, &result);
+        return encoded_result(result, response_format);
+    };
+    if let Some(body) = app.responses.get(&key) {
+        usage::count();
+        return with_cache_status(encoded_response(response_format, body), "hit");
+    }
+    match process(order, app)
+        .await
+        .and_then(|order| response_format.encode(&order))
+    {
+        Ok(body) => {
+            app.responses.insert(key, &body);
+            with_cache_status(encoded_response(response_format, body), "miss")
+        }
+        Err(err) => error::response(err),
+    }
+}
+
+fn with_cache_status(mut response: Response<Body>, status: &'static str) -> Response<Body> {
+    response
+        .headers_mut()
+        .insert("X-Cache", hyper::header::HeaderValue::from_static(status));
+    response
+}
+
+#[derive(Deserialize)]
+struct DryRunParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Whether the query asks for a dry run, `dry_run=true`.
+fn dry_run(query: Option<&str>) -> Result<bool, ComputeError> {
+    serde_urlencoded::from_str::<DryRunParams>(query.unwrap_or(""))
+        .map(|params| params.dry_run)
+        .map_err(|_| ComputeError::InvalidRequest)
+}
+
+/// Sends a successfully computed order to the request's callback, if any,
+/// unless it was a dry run.
+fn notify(app: &App, callback: Option<String>, result: &Result<Order, ComputeError>) {
+    if let (Some(url), Ok(order)) = (callback, result) {
+        if order.dry_run {
+            return;
+        }
+        let body = serde_json::to_vec(order).unwrap();
+        app.webhooks.spawn(url, "order.computed", body);
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/compute",
+    request_body(
+        content = Order,
+        description = "The order, as JSON or as `application/msgpack` or `application/cbor` with a matching `Content-Type`"
+    ),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when a request is retried with the same key and body"),
+        ("X-Request-Deadline-Ms" = Option<u64>, Header, description = "How many milliseconds the caller is willing to wait, capped by `REQUEST_DEADLINE_MS`"),
+        ("X-Schema-Mode" = Option<String>, Header, description = "`strict` refuses unknown fields and single-item orders without `product_id`, `quantity` or `subtotal`; `lenient` ignores unknown fields. `SCHEMA_MODE` when left out"),
+        ("callback_url" = Option<String>, Query, description = "Also POST the computed order to this URL, signed with `WEBHOOK_SECRET`"),
+        ("dry_run" = Option<bool>, Query, description = "Only compute the order, as if it had `\"dry_run\": true`: it is not stored, audited or sent to `callback_url`"),
+    ),
+    responses(
+        (status = 200, description = "The order with its total computed, in the format the `Accept` header asks for", body = Order,
+            content_type = ["application/json", "application/msgpack", "application/cbor"]),
+        (status = 400, description = "The body is not a valid order", body = ErrorResponse),
+        (status = 401, description = "Authentication is on and the bearer token or API key is missing or invalid", body = ErrorResponse),
+        (status = 409, description = "A request with the same Idempotency-Key is in progress", body = ErrorResponse),
+        (status = 413, description = "The body exceeds `MAX_BODY_BYTES`", body = ErrorResponse),
+        (status = 422, description = "The order failed validation, or the Idempotency-Key was used with another body", body = ErrorResponse),
+        (status = 429, description = "The API key's rate limit or `RATE_LIMIT_PER_SEC` is exceeded; see `Retry-After`", body = ErrorResponse),
+        (status = 500, description = "The request could not be answered because of an internal error", body = Problem,
+            content_type = "application/problem+json"),
+        (status = 503, description = "No sales tax rate is available for the zip code, or the service is at `MAX_IN_FLIGHT`", body = ErrorResponse),
+        (status = 504, description = "The sales tax rate service timed out, or the request's deadline passed", body = ErrorResponse),
+    )
+)]
+async fn compute(
+    byte_stream: &Bytes,
+    format: Format,
+    schema: OrderSchema,
+    dry_run: bool,
+    app: &App,
+) -> Result<Order, ComputeError> {
+    let mut order = schema.decode(format, byte_stream)?;
+    order.dry_run |= dry_run;
+    process(order, app).await
+}
+
+/// Validates and computes a parsed order, storing it when persistence is on
+/// and the order is not a dry run.
+async fn process(order: Order, app: &App) -> Result<Order, ComputeError> {
+    tracing::Span::current().record("zip", order.shipping_zip.as_str());
+
+    let (order, tax_rate) = calculate(order, app).await?;
+    if let Some(store) = app.store.as_ref().filter(|_| !order.dry_run) {
+        store
+            .save(&order, tax_rate, chrono::Utc::now())
+            .map_err(|err| ComputeError::Unexpected(err.into()))?;
+    }
+
+    Ok(order)
+}
+
+/// Computes an order and its applied rate, recording how in the audit log
+/// when one is kept and the order is not a dry run, and counting it in the
+/// usage of the request once computed, see `usage`. An order without a
+/// `currency` is in that of its tenant, if the tenant has one, and one
+/// asking for what a feature flag switches off is refused, see `flags`.
+async fn calculate(mut order: Order, app: &App) -> Result<(Order, Decimal), ComputeError> {
+    if let Some(currency) = app
+        .tenant()
+        .and_then(|tenant| tenant.settings.currency.as_ref())
+    {
+        order.currency.get_or_insert_with(|| currency.clone());
+    }
+    app.flags.admit(&order)?;
+    let result = match app.audit.as_ref().filter(|_| !order.dry_run) {
+        None => app.calculator().compute(order).await,
+        Some(audit) => {
+            let input = serde_json::to_value(&order)
+                .map_err(|err| ComputeError::Unexpected(Box::new(err)))?;
+            let result = app.calculator().compute(order).await;
+            audit.record(input, &result)?;
+            result
+        }
+    };
+    if result.is_ok() {
+        usage::count();
+    }
+    result
+}
+
+// CORS headers are added to every response by `middleware::stack`, see `cors`.
+fn response_build(status: StatusCode, body: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(body.to_owned()))
+        .unwrap()
+}
+
+fn json_result(result: Result<String, ComputeError>) -> Response<Body> {
+    match result {
+        Ok(body) => response_build(StatusCode::OK, &body),
+        Err(err) => error::response(err),
+    }
+}
+
+fn encoded_result(result: Result<Order, ComputeError>, format: Format) -> Response<Body> {
+    match result.and_then(|order| format.encode(&order)) {
+        Ok(body) => encoded_response(format, body),
+        Err(err) => error::response(err),
+    }
+}
+
+fn encoded_response(format: Format, body: Bytes) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static(format.content_type()),
+    );
+    response
+}
+
+fn with_content_type(mut response: Response<Body>, content_type: &'static str) -> Response<Body> {
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static(content_type),
+    );
+    response
+}
+
+/// Startup configuration errors are logged and end the process, rather than
+/// being printed as a `Debug` dump by `main`.
+fn or_exit<T>(result: anyhow::Result<T>) -> T {
+    result.unwrap_or_else(|err| {
+        tracing::error!("{err:#}");
+        std::process::exit(2);
+    })
+}
+
+/// Runs the service as configured by the environment until it is asked to
+/// shut down. Startup configuration errors end the process.
+pub async fn run(flavor: RuntimeFlavor) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    logging::init();
+
+    let app = Arc::new(or_exit(App::from_env()));
+    app.warm_up().await;
+    reload::on_hangup(app.clone());
+    usage::report_periodically(app.clone());
+    if or_exit(config::run_mode()) == config::RunMode::Kafka {
+        #[cfg(feature = "kafka")]
+        or_exit(kafka::run(app, or_exit(shutdown::Shutdown::listen()).requested()).await);
+        return Ok(());
+    }
+    #[cfg(feature = "server")]
+    server::run(app, flavor).await;
+    #[cfg(not(feature = "server"))]
+    or_exit::<()>(Err(anyhow::anyhow!(
+        "built without the `server` feature, so there is no {flavor} server to run; \
+         answer requests with `order_total wagi` or `pipe`, or set RUN_MODE=kafka"
+    )));
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Bounds the number of orders computed at once. A request beyond the bound
+/// is turned away with `503` and a `Retry-After` straight away, so a burst
+/// cannot queue up work until every request times out.
+///
+/// * `MAX_IN_FLIGHT` - most `/compute` requests in progress, unset or 0 for no limit
+/// * `LOAD_SHED_RETRY_AFTER_SECS` - the `Retry-After` given to shed requests (default 1)
+pub struct ConcurrencyLimit {
+    permits: Option<Semaphore>,
+    retry_after: Duration,
+}
+
+impl ConcurrencyLimit {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let max = env_or("MAX_IN_FLIGHT", 0usize)?;
+        Ok(Self {
+            permits: (max > 0).then(|| Semaphore::new(max)),
+            retry_after: Duration::from_secs(env_or("LOAD_SHED_RETRY_AFTER_SECS", 1)?),
+        })
+    }
+
+    /// A slot for one request, held until the permit is dropped; `None` when
+    /// there is no limit.
+    pub fn try_acquire(&self) -> Result<Option<SemaphorePermit<'_>>, ComputeError> {
+        let Some(permits) = &self.permits else {
+            return Ok(None);
+        };
+        match permits.try_acquire() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => {
+                tracing::debug!("at MAX_IN_FLIGHT, shedding request");
+                Err(ComputeError::Overloaded(self.retry_after))
+            }
+        }
+    }
+}
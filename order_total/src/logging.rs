@@ -0,0 +1,92 @@
+use crate::{request_id, telemetry};
+use hyper::header::HeaderValue;
+use hyper::{Request, Response};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+/// What swaps the filter of the subscriber `init` installed.
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+/// Counts the filter changes, so that a temporary change reverts only if
+/// nothing replaced it in the meantime.
+static FILTER_CHANGES: AtomicU64 = AtomicU64::new(0);
+
+/// Installs the global tracing subscriber. The filter comes from `RUST_LOG`
+/// (default `info`), and `set_filter` changes it at runtime; `LOG_FORMAT=json` switches to one JSON object per line
+/// for log aggregators. Spans are also exported to an OpenTelemetry collector
+/// when one is configured (see `telemetry::layer`).
+pub fn init() {
+    init_with(std::io::stdout)
+}
+
+/// As `init`, logging to standard error instead, for the commands that
+/// print their results on standard output (see `cli`).
+pub fn init_to_stderr() {
+    init_with(std::io::stderr)
+}
+
+fn init_with<W>(writer: W)
+where
+    W: for<'w> fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let (filter, handle) = reload::Layer::new(models::logging::env_filter());
+    let _ = FILTER.set(handle);
+    models::logging::init(filter, telemetry::layer(), writer);
+}
+
+/// The log filter in force, e.g. `info`; `None` before `init`.
+pub fn filter() -> Option<String> {
+    FILTER.get()?.with_current(ToString::to_string).ok()
+}
+
+/// Replaces the log filter with `directives`, in `RUST_LOG` syntax, and
+/// returns the one it replaces. With `revert_after`, the old filter comes
+/// back after that long, unless the filter was changed again in between.
+pub fn set_filter(directives: &str, revert_after: Option<Duration>) -> anyhow::Result<String> {
+    let handle = FILTER
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("logging is not initialized"))?;
+    let filter = EnvFilter::try_new(directives)?;
+    let previous = handle.with_current(ToString::to_string)?;
+    handle.reload(filter)?;
+    let change = FILTER_CHANGES.fetch_add(1, Ordering::SeqCst) + 1;
+    tracing::info!(filter = directives, previous, "log filter changed");
+    if let Some(after) = revert_after {
+        let previous = previous.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            if FILTER_CHANGES.load(Ordering::SeqCst) == change {
+                if let Err(err) = set_filter(&previous, None) {
+                    tracing::warn!(error = %err, "could not restore the log filter");
+                }
+            }
+        });
+    }
+    Ok(previous)
+}
+
+/// Runs `handler` inside a `request` span, see `models::logging::traced`,
+/// which a W3C `traceparent` header makes part of the caller's trace.
+/// Handlers can fill in the `subject`, `tenant` and `zip` fields with
+/// `Span::current().record(..)`.
+///
+/// The request id is also available to the handler through
+/// `request_id::current()` and is echoed in the `X-Request-Id` response
+/// header. The REST API and the gRPC service (see `grpc`) both go through
+/// here, whatever their body types.
+pub async fn traced<B, R, E, F, Fut>(req: Request<B>, handler: F) -> Result<Response<R>, E>
+where
+    F: FnOnce(Request<B>) -> Fut,
+    Fut: Future<Output = Result<Response<R>, E>>,
+    E: Display,
+{
+    let id = request_id::from_request(&req);
+    let header = HeaderValue::from_str(&id).ok();
+    models::logging::traced(req, header, telemetry::set_parent, |req| {
+        request_id::scope(id, handler(req))
+    })
+    .await
+}
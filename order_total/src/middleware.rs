@@ -0,0 +1,362 @@
+//! The layers every HTTP request passes through on its way to `dispatch`,
+//! as tower `Layer`s around a `Service`: tracing, the tenant, the access
+//! log, error reports, the layout of JSON bodies, the security headers, the overall
+//! timeout, CORS, the rate limit, the request's deadline, usage metering,
+//! authentication and compression.
+//! A cross-cutting concern is one more layer in `stack`, rather than another
+//! edit of the handler of every route.
+//!
+//! A layer is an async function of the app, the request and the rest of the
+//! stack, which it hands the request on to, see `around`.
+
+use crate::body::Body;
+use crate::error::ComputeError;
+use crate::{
+    access_log, api, api_keys, compression, deadline, error, error_report, logging, pretty,
+    rate_limit, recover, tenant, usage, App,
+};
+use hyper::header::ORIGIN;
+use hyper::{Method, Request, Response, StatusCode};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::{Identity, Layer, Stack};
+use tower_service::Service;
+
+type Answer = Result<Response<Body>, anyhow::Error>;
+
+/// What the services of the stack answer with.
+pub type BoxFuture = Pin<Box<dyn Future<Output = Answer> + Send>>;
+
+/// Serves `app`: the layers, outermost first, then the routes.
+///
+/// * every request is traced and logged in the access log, with the tenant
+///   it names, see `tenant`
+/// * what goes wrong is reported, see `error_report`
+/// * JSON bodies are compact unless the request asks otherwise, see `pretty`
+/// * every response, errors and `404`s included, carries the security
+///   headers
+/// * a panic is answered `500`, see `recover`, and a request not answered
+///   within `REQUEST_TIMEOUT_MS` is answered `504`
+/// * CORS headers are added to every response, see `cors`
+/// * API requests beyond `RATE_LIMIT_PER_SEC` are answered `429`, see
+///   `rate_limit`
+/// * an API request is answered `504` once its deadline passes, see
+///   `deadline`
+/// * an API request naming an unknown tenant is answered `400`
+/// * the orders an API request computes are counted for its tenant and
+///   client, see `usage`
+/// * API routes require a valid bearer token or API key, see
+///   `crate::authenticate`
+/// * request bodies may be gzip or brotli compressed, and responses are
+///   compressed as `Accept-Encoding` allows, see `compression`
+pub fn stack(
+    app: &Arc<App>,
+) -> impl Service<Request<Body>, Response = Response<Body>, Error = anyhow::Error, Future = BoxFuture>
+       + Clone
+       + Send
+       + 'static {
+    Layers::new()
+        .layer(around(app, trace))
+        .layer(around(app, identify_tenant))
+        .layer(around(app, log_access))
+        .layer(around(app, report_errors))
+        .layer(around(app, lay_out_json))
+        .layer(around(app, secure))
+        .layer(around(app, contain_failures))
+        .layer(around(app, allow_origins))
+        .layer(around(app, throttle))
+        .layer(around(app, bound_by_deadline))
+        .layer(around(app, admit_tenant))
+        .layer(around(app, meter))
+        .layer(around(app, authenticate))
+        .layer(around(app, compress))
+        .service(Router { app: app.clone() })
+}
+
+/// Layers in the order a request passes through them, outermost first, as
+/// tower's `ServiceBuilder` stacks them.
+struct Layers<L>(L);
+
+impl Layers<Identity> {
+    fn new() -> Self {
+        Self(Identity::new())
+    }
+}
+
+impl<L> Layers<L> {
+    /// Adds `layer` inside the ones so far.
+    fn layer<T>(self, layer: T) -> Layers<Stack<T, L>> {
+        Layers(Stack::new(layer, self.0))
+    }
+
+    fn service<S>(self, service: S) -> L::Service
+    where
+        L: Layer<S>,
+    {
+        self.0.layer(service)
+    }
+}
+
+/// The rest of the stack, which a layer hands the request on to.
+struct Next(Box<dyn FnOnce(Request<Body>) -> BoxFuture + Send>);
+
+impl Next {
+    async fn run(self, req: Request<Body>) -> Answer {
+        (self.0)(req).await
+    }
+}
+
+/// The layer of `f`, an async function of the app, the request and the rest
+/// of the stack.
+fn around<F>(app: &Arc<App>, f: F) -> Around<F> {
+    Around {
+        app: app.clone(),
+        f,
+    }
+}
+
+#[derive(Clone)]
+struct Around<F> {
+    app: Arc<App>,
+    f: F,
+}
+
+impl<F: Clone, S> Layer<S> for Around<F> {
+    type Service = AroundService<F, S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AroundService {
+            app: self.app.clone(),
+            f: self.f.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AroundService<F, S> {
+    app: Arc<App>,
+    f: F,
+    inner: S,
+}
+
+/// Every service of the stack is always ready: limits are layers that
+/// answer rather than wait.
+impl<F, Fut, S> Service<Request<Body>> for AroundService<F, S>
+where
+    F: Fn(Arc<App>, Request<Body>, Next) -> Fut,
+    Fut: Future<Output = Answer> + Send + 'static,
+    S: Service<Request<Body>, Response = Response<Body>, Error = anyhow::Error>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = anyhow::Error;
+    type Future = BoxFuture;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> BoxFuture {
+        let mut inner = self.inner.clone();
+        let next = Next(Box::new(move |req| Box::pin(inner.call(req))));
+        Box::pin((self.f)(self.app.clone(), req, next))
+    }
+}
+
+/// The innermost service: routes a request to its handler, see `dispatch`,
+/// and answers `404` for a path nothing is served at.
+#[derive(Clone)]
+struct Router {
+    app: Arc<App>,
+}
+
+impl Service<Request<Body>> for Router {
+    type Response = Response<Body>;
+    type Error = anyhow::Error;
+    type Future = BoxFuture;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> BoxFuture {
+        let app = self.app.clone();
+        Box::pin(async move {
+            let path = req.uri().path().to_owned();
+            let route = api::resolve(&path);
+            Ok(match crate::dispatch(req, route, &app).await {
+                Some(mut response) => {
+                    route.annotate(&mut response);
+                    response
+                }
+                None => {
+                    let mut not_found = Response::default();
+                    *not_found.status_mut() = StatusCode::NOT_FOUND;
+                    not_found
+                }
+            })
+        })
+    }
+}
+
+/// Whether `req` is to the API proper, and not a CORS preflight, which
+/// carries no credentials.
+fn is_api_request<B>(req: &Request<B>) -> bool {
+    api::resolve(req.uri().path()).is_api() && req.method() != Method::OPTIONS
+}
+
+/// A `request` span and the request id, see `logging::traced`.
+async fn trace(_: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    logging::traced(req, |req| next.run(req)).await
+}
+
+/// The tenant the request names, for the rest of the stack and the
+/// request's span; `X-Tenant-Id` is ignored without `TENANTS_FILE`.
+async fn identify_tenant(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    let tenant = app
+        .tenants
+        .as_ref()
+        .and_then(|_| tenant::from_request(&req));
+    if let Some(tenant) = &tenant {
+        tracing::Span::current().record("tenant", tenant.as_str());
+    }
+    tenant::scope(tenant, next.run(req)).await
+}
+
+/// A line of the access log per request answered, see `access_log`.
+async fn log_access(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    access_log::scope(async {
+        let path = req.uri().path().to_owned();
+        let started = app.access_log.start(req.method());
+        let response = next.run(req).await?;
+        app.access_log.record(started, &path, &response);
+        Ok(response)
+    })
+    .await
+}
+
+async fn report_errors(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    let method = req.method().clone();
+    let path = req.uri().path().to_owned();
+    error_report::scope(app.error_reporter.as_ref(), &method, &path, next.run(req)).await
+}
+
+async fn lay_out_json(_: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    pretty::scope(pretty::from_request(&req), next.run(req)).await
+}
+
+async fn secure(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    let path = req.uri().path().to_owned();
+    let mut response = next.run(req).await?;
+    app.security_headers
+        .apply(&api::resolve(&path), &mut response);
+    Ok(response)
+}
+
+/// Answers a panic `500` and a request past `REQUEST_TIMEOUT_MS` `504`.
+async fn contain_failures(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    match app.deadlines.bound(recover::catch(next.run(req))).await {
+        Ok(response) => response,
+        Err(err) => Ok(error::response(err)),
+    }
+}
+
+async fn allow_origins(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    let origin = req.headers().get(ORIGIN).cloned();
+    let preflight = req.method() == Method::OPTIONS;
+    let mut response = next.run(req).await?;
+    app.cors.apply(origin.as_ref(), preflight, &mut response);
+    Ok(response)
+}
+
+/// Takes a token of the service-wide rate limit for an API request, and
+/// reports what is left in the response.
+async fn throttle(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    if !is_api_request(&req) {
+        return next.run(req).await;
+    }
+    let quota = match app.rate_limit.take() {
+        Ok(Some(quota)) => quota,
+        Ok(None) => return next.run(req).await,
+        Err(err) => return Ok(error::response(err)),
+    };
+    let mut response = next.run(req).await?;
+    rate_limit::annotate(&quota, response.headers_mut());
+    Ok(response)
+}
+
+/// Runs an API request with its deadline, which starts as it arrives.
+async fn bound_by_deadline(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    let deadline = if is_api_request(&req) {
+        app.deadlines.of_request(req.headers())
+    } else {
+        None
+    };
+    let path = req.uri().path().to_owned();
+    match deadline::scope(deadline, next.run(req)).await {
+        Ok(response) => response,
+        Err(err) => {
+            let mut response = error::response(err);
+            api::resolve(&path).annotate(&mut response);
+            Ok(response)
+        }
+    }
+}
+
+/// Refuses an API request naming a tenant that is not configured, rather
+/// than computing it with the service's settings.
+async fn admit_tenant(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    if is_api_request(&req) && tenant::current().is_some() && app.tenant().is_none() {
+        return Ok(error::response(ComputeError::UnknownTenant));
+    }
+    next.run(req).await
+}
+
+/// Counts the orders an API request computes as one batch, for the tenant
+/// and the client `authenticate` identifies.
+async fn meter(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    if !is_api_request(&req) {
+        return next.run(req).await;
+    }
+    let (response, batch) = usage::measure(None, next.run(req)).await;
+    app.usage.record(&batch);
+    response
+}
+
+/// Checks the credentials of an API request, and adds the quota of its API
+/// key, if any, to the response.
+async fn authenticate(app: Arc<App>, mut req: Request<Body>, next: Next) -> Answer {
+    if !is_api_request(&req) {
+        return next.run(req).await;
+    }
+    let quota = match crate::authenticate(&mut req, &app).await {
+        Ok(quota) => quota,
+        Err(err) => return Ok(error::response(err)),
+    };
+    let mut response = next.run(req).await?;
+    if let Some(quota) = quota {
+        api_keys::annotate(&quota, response.headers_mut());
+    }
+    Ok(response)
+}
+
+async fn compress(app: Arc<App>, req: Request<Body>, next: Next) -> Answer {
+    let encoding = app.compression.negotiate(req.headers());
+    // No chunk may decode to more than the largest body any route accepts.
+    let limit = app.max_body_bytes.max(app.max_csv_bytes);
+    let req = match compression::decompress(req, limit) {
+        Ok(req) => req,
+        Err(err) => return Ok(error::response(err)),
+    };
+    let response = next.run(req).await?;
+    Ok(match encoding {
+        Some(encoding) => app.compression.apply(response, encoding),
+        None => response,
+    })
+}
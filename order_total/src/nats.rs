@@ -0,0 +1,149 @@
+use crate::codec::Format;
+use crate::config::env_or;
+use crate::error::{self, ComputeError};
+use crate::{api_keys, authenticate, compute, request_id, usage, App};
+use anyhow::Context;
+use async_nats::{Client, HeaderMap, Message};
+use futures_util::StreamExt;
+use hyper::body::Bytes;
+use hyper::Request;
+use order_total_core::Order;
+use std::future::Future;
+use std::sync::Arc;
+use tracing::Instrument;
+
+/// Answers compute requests sent over NATS request-reply, alongside the
+/// HTTP server. The request payload is a JSON order and the reply the
+/// computed order or the error body, as `POST /v1/compute` would answer;
+/// replicas share the subject through a queue group.
+///
+/// * `NATS_URL` - the server(s), comma separated (see `config::nats_url`)
+/// * `NATS_SUBJECT` - the subject requests arrive on (default `order_total.compute`)
+/// * `NATS_QUEUE_GROUP` - the queue group replicas share (default `order_total`)
+pub async fn subscribe(
+    app: Arc<App>,
+    url: &str,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<impl Future<Output = ()>> {
+    let subject = env_or("NATS_SUBJECT", "order_total.compute".to_owned())?;
+    let group = env_or("NATS_QUEUE_GROUP", "order_total".to_owned())?;
+    let client = async_nats::ConnectOptions::new()
+        .name("order_total")
+        .connect(url)
+        .await
+        .context("could not connect to NATS_URL")?;
+    let mut requests = client
+        .queue_subscribe(subject.clone(), group.clone())
+        .await
+        .with_context(|| format!("could not subscribe to {subject:?}"))?;
+    tracing::info!(%subject, %group, "NATS responder started");
+
+    Ok(async move {
+        let mut shutdown = std::pin::pin!(shutdown);
+        let mut draining = false;
+        loop {
+            // Once draining, the requests already delivered are still
+            // answered, then the subscription ends.
+            let message = tokio::select! {
+                message = requests.next() => message,
+                _ = &mut shutdown, if !draining => {
+                    draining = true;
+                    if let Err(err) = requests.drain().await {
+                        tracing::warn!(error = %err, "could not drain the NATS subscription");
+                    }
+                    continue;
+                }
+            };
+            let Some(message) = message else { break };
+            tokio::spawn(respond(client.clone(), message, app.clone()));
+        }
+    })
+}
+
+async fn respond(client: Client, message: Message, app: Arc<App>) {
+    let Some(reply) = message.reply.clone() else {
+        tracing::debug!(subject = %message.subject, "NATS message without a reply subject, ignored");
+        return;
+    };
+    let mut req = Request::new(());
+    if let Some(headers) = &message.headers {
+        for (name, values) in headers.iter() {
+            for value in values {
+                let (Ok(name), Ok(value)) = (
+                    hyper::header::HeaderName::from_bytes(AsRef::<[u8]>::as_ref(name)),
+                    hyper::header::HeaderValue::from_str(value.as_str()),
+                ) else {
+                    continue;
+                };
+                req.headers_mut().append(name, value);
+            }
+        }
+    }
+    let id = request_id::from_request(&req);
+    let span = tracing::info_span!(
+        "nats_request",
+        otel.kind = "server",
+        nats_subject = %message.subject,
+        request_id = %id,
+        subject = tracing::field::Empty,
+        zip = tracing::field::Empty,
+    );
+
+    let reply_to = async {
+        let mut headers = hyper::HeaderMap::new();
+        let answered = answer(&mut req, &message.payload, &app, &mut headers);
+        let (answered, batch) = usage::measure(None, answered).await;
+        app.usage.record(&batch);
+        let (result, value) = match answered {
+            Ok(order) => ("order", serde_json::to_vec(&order).unwrap()),
+            Err(err) => {
+                let (retry_after, quota) = error::retry_hints(&err);
+                if let Some(quota) = quota {
+                    api_keys::annotate(&quota, &mut headers);
+                }
+                if let Some(secs) = retry_after {
+                    headers.insert(hyper::header::RETRY_AFTER, secs.into());
+                }
+                tracing::info!(code = ?err.code(), "NATS request failed");
+                ("error", serde_json::to_vec(&error::parts(err).1).unwrap())
+            }
+        };
+        let mut reply_headers = HeaderMap::new();
+        reply_headers.insert("result", result);
+        if let Some(id) = request_id::current() {
+            reply_headers.insert(request_id::HEADER, id);
+        }
+        for (name, value) in &headers {
+            if let Ok(value) = value.to_str() {
+                reply_headers.insert(name.as_str(), value);
+            }
+        }
+        if let Err(err) = client
+            .publish_with_headers(reply, reply_headers, value.into())
+            .await
+        {
+            tracing::warn!(error = %err, "could not publish the NATS reply");
+        }
+    };
+    request_id::scope(id, reply_to).instrument(span).await;
+}
+
+/// Authenticates the request by its headers, like an HTTP request, and
+/// computes the order in `payload`. The rate limit quota of an API key goes
+/// to `headers`.
+async fn answer(
+    req: &mut Request<()>,
+    payload: &Bytes,
+    app: &App,
+    headers: &mut hyper::HeaderMap,
+) -> Result<Order, ComputeError> {
+    if let Some(quota) = authenticate(req, app).await? {
+        api_keys::annotate(&quota, headers);
+    }
+    if payload.len() > app.max_body_bytes {
+        return Err(ComputeError::PayloadTooLarge(app.max_body_bytes));
+    }
+    let schema = app.schema.of_request(req.headers())?;
+    let _permit = app.in_flight.try_acquire()?;
+    compute(payload, Format::Json, schema, false, app).await
+}
@@ -0,0 +1,142 @@
+use crate::body::{self, Body};
+use crate::codec::Format;
+use crate::error::ComputeError;
+use crate::schema_mode::OrderSchema;
+use crate::{request_id, tenant, usage, App};
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Request, Response};
+use order_total_core::Order;
+use std::sync::Arc;
+use tracing::{Instrument, Span};
+
+/// Computes a batch of newline-delimited JSON orders as they arrive, writing
+/// one result line per order line as soon as it is ready: the computed order,
+/// or the error body for that line. Only one line is buffered at a time, and
+/// `MAX_BODY_BYTES` bounds a line rather than the whole upload. The orders
+/// of the stream count as one batch, see `usage`.
+#[utoipa::path(
+    post,
+    path = "/v1/compute_stream",
+    request_body(content = Order, description = "One JSON order per line", content_type = "application/x-ndjson"),
+    responses(
+        (status = 200, description = "One computed order or error body per input line, streamed", body = Order, content_type = "application/x-ndjson"),
+    )
+)]
+pub fn compute_stream(req: Request<Body>, app: Arc<App>) -> Response<Body> {
+    let schema = match app.schema.of_request(req.headers()) {
+        Ok(schema) => schema,
+        Err(err) => return crate::error::response(err),
+    };
+    let (mut tx, body) = Body::channel();
+    let mut input = req.into_body();
+    let meter = app.clone();
+    let task = async move {
+        let mut lines = Lines::new(app.max_body_bytes);
+        loop {
+            let chunk = match body::data(&mut input).await {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(err)) => {
+                    tracing::warn!(error = %err, "order stream aborted by the client");
+                    return;
+                }
+                None => break,
+            };
+            for line in lines.push(&chunk) {
+                if tx
+                    .send_data(result_line(line, schema, &app).await)
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+        if let Some(line) = lines.finish() {
+            let _ = tx.send_data(result_line(line, schema, &app).await).await;
+        }
+    };
+    let batch = usage::measure(usage::client(), task);
+    let task = async move {
+        let ((), batch) = batch.await;
+        meter.usage.record(&batch);
+    };
+    let id = request_id::current().unwrap_or_default();
+    let task = tenant::scope(tenant::current(), request_id::scope(id, task));
+    tokio::spawn(task.instrument(Span::current()));
+
+    let mut response = Response::new(body);
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    response
+}
+
+async fn result_line(line: Result<Bytes, ComputeError>, schema: OrderSchema, app: &App) -> Bytes {
+    let result = match line {
+        Ok(line) => crate::compute(&line, Format::Json, schema, false, app).await,
+        Err(err) => Err(err),
+    };
+    encode_line(result)
+}
+
+/// The line answering an order line: the computed order, or the error body.
+pub(crate) fn encode_line(result: Result<Order, ComputeError>) -> Bytes {
+    let mut out = match result {
+        Ok(order) => serde_json::to_vec(&order).unwrap(),
+        Err(err) => serde_json::to_vec(&crate::error::parts(err).1).unwrap(),
+    };
+    out.push(b'\n');
+    out.into()
+}
+
+/// Splits the incoming chunks into lines, skipping blank ones. A line longer
+/// than `limit` is dropped as it arrives and reported as `PayloadTooLarge`.
+pub(crate) struct Lines {
+    limit: usize,
+    buf: Vec<u8>,
+    overflowed: bool,
+}
+
+impl Lines {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            buf: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<Result<Bytes, ComputeError>> {
+        let mut lines = Vec::new();
+        let mut pieces = chunk.split(|&b| b == b'\n').peekable();
+        while let Some(piece) = pieces.next() {
+            if !self.overflowed {
+                if self.buf.len() + piece.len() > self.limit {
+                    self.overflowed = true;
+                    self.buf = Vec::new();
+                } else {
+                    self.buf.extend_from_slice(piece);
+                }
+            }
+            // Every piece but the last one was followed by a newline.
+            if pieces.peek().is_some() {
+                lines.extend(self.take());
+            }
+        }
+        lines
+    }
+
+    pub(crate) fn finish(mut self) -> Option<Result<Bytes, ComputeError>> {
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<Result<Bytes, ComputeError>> {
+        if std::mem::take(&mut self.overflowed) {
+            return Some(Err(ComputeError::PayloadTooLarge(self.limit)));
+        }
+        let line = std::mem::take(&mut self.buf);
+        (!line.trim_ascii().is_empty()).then(|| Ok(line.into()))
+    }
+}
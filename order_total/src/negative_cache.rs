@@ -0,0 +1,62 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use crate::tax_rate::{self, TaxRate, TaxRateProvider};
+use async_trait::async_trait;
+use models::RateRequest;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Remembers for a while which zip codes `inner` has no rate for, so a flood
+/// of orders with a bad zip code is answered without asking the upstream
+/// each time. Only "not found" is cached; failures are left to the retry
+/// policy and circuit breaker.
+///
+/// * `UNKNOWN_ZIP_CACHE_SECS` - how long a miss is remembered, 0 to disable (default 60)
+/// * `UNKNOWN_ZIP_CACHE_MAX` - most zip codes remembered at once (default 10000)
+pub struct NegativeCache<P> {
+    inner: P,
+    ttl: Duration,
+    max_entries: usize,
+    misses: Mutex<HashMap<String, Instant>>,
+}
+
+impl<P> NegativeCache<P> {
+    pub fn from_env(inner: P) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner,
+            ttl: Duration::from_secs(env_or("UNKNOWN_ZIP_CACHE_SECS", 60)?),
+            max_entries: env_or("UNKNOWN_ZIP_CACHE_MAX", 10_000)?,
+            misses: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+#[async_trait]
+impl<P: TaxRateProvider> TaxRateProvider for NegativeCache<P> {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        if self.ttl.is_zero() {
+            return self.inner.find_rate(request).await;
+        }
+        let key = tax_rate::key(request);
+        let cached = self
+            .misses
+            .lock()
+            .unwrap()
+            .get(&key)
+            .is_some_and(|cached| cached.elapsed() < self.ttl);
+        if cached {
+            return Err(ComputeError::TaxRateNotAvailable);
+        }
+
+        let result = self.inner.find_rate(request).await;
+        if let Err(ComputeError::TaxRateNotAvailable) = result {
+            let mut misses = self.misses.lock().unwrap();
+            misses.retain(|_, cached| cached.elapsed() < self.ttl);
+            if misses.len() < self.max_entries {
+                misses.insert(key, Instant::now());
+            }
+        }
+        result
+    }
+}
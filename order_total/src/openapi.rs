@@ -0,0 +1,75 @@
+use crate::error::{ErrorCode, ErrorResponse, FieldError};
+use crate::jobs::{JobResult, JobState, JobStatus};
+use crate::orders::OrderList;
+use crate::quote::{FinalizeRequest, Quote};
+use crate::recover::Problem;
+use crate::refund::RefundRequest;
+use crate::store::StoredOrder;
+use order_total_core::{
+    AddressParts, Conversion, CreditLine, CreditMemo, Discount, DiscountKind, DiscountStage,
+    ExemptionCertificate, LineItem, Order, PriceMismatch, RateSource, ReturnedItem, RoundingMode,
+    RoundingOverride, RoundingScope, TaxCategory, TaxComponent, VatSummary,
+};
+use utoipa::OpenApi;
+
+/// The OpenAPI 3 description of the service, served at `/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "order_total",
+        description = "Computes order totals including sales tax."
+    ),
+    paths(
+        crate::compute,
+        crate::ndjson::compute_stream,
+        crate::bulk_csv::compute_csv,
+        crate::orders::list,
+        crate::orders::get,
+        crate::jobs::submit,
+        crate::jobs::status,
+        crate::jobs::result,
+        crate::quote::quote,
+        crate::quote::finalize,
+        crate::refund::refund
+    ),
+    components(schemas(
+        Order,
+        AddressParts,
+        LineItem,
+        TaxCategory,
+        Discount,
+        ExemptionCertificate,
+        PriceMismatch,
+        DiscountKind,
+        DiscountStage,
+        RateSource,
+        TaxComponent,
+        VatSummary,
+        Conversion,
+        RoundingOverride,
+        RoundingMode,
+        RoundingScope,
+        ErrorResponse,
+        ErrorCode,
+        FieldError,
+        Problem,
+        StoredOrder,
+        OrderList,
+        JobStatus,
+        JobState,
+        JobResult,
+        Quote,
+        FinalizeRequest,
+        RefundRequest,
+        ReturnedItem,
+        CreditMemo,
+        CreditLine
+    ))
+)]
+pub struct ApiDoc;
+
+pub fn json() -> String {
+    ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("the OpenAPI document serializes")
+}
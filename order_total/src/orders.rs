@@ -0,0 +1,96 @@
+use crate::error::ComputeError;
+use crate::store::{OrderStore, StoredOrder};
+use crate::App;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    /// How many orders to return (default 50, at most 500).
+    limit: Option<u32>,
+    /// How many of the most recent orders to skip.
+    offset: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderList {
+    pub orders: Vec<StoredOrder>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+/// Lists stored orders, most recently computed first.
+#[utoipa::path(
+    get,
+    path = "/v1/orders",
+    params(Pagination),
+    responses(
+        (status = 200, description = "A page of stored orders", body = OrderList),
+        (status = 400, description = "Invalid pagination parameters", body = ErrorResponse),
+        (status = 501, description = "Order persistence is not enabled", body = ErrorResponse),
+    )
+)]
+pub fn list(app: &App, query: Option<&str>) -> Result<String, ComputeError> {
+    let pagination: Pagination = serde_urlencoded::from_str(query.unwrap_or(""))
+        .map_err(|_| ComputeError::InvalidRequest)?;
+    to_json(&page(app, pagination.limit, pagination.offset)?)
+}
+
+/// A page of stored orders, most recent first, with the defaults and the
+/// cap of `GET /v1/orders` applied to `limit`.
+pub fn page(app: &App, limit: Option<u32>, offset: Option<u32>) -> Result<OrderList, ComputeError> {
+    let store = store(app)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let offset = offset.unwrap_or(0);
+
+    let orders = store.list(limit, offset).map_err(unexpected)?;
+    Ok(OrderList {
+        orders,
+        limit,
+        offset,
+    })
+}
+
+/// Looks up the latest stored computation of an order.
+#[utoipa::path(
+    get,
+    path = "/v1/orders/{order_id}",
+    params(("order_id" = i32, Path, description = "The `order_id` the order was computed with")),
+    responses(
+        (status = 200, description = "The stored order", body = StoredOrder),
+        (status = 404, description = "No order with that id was stored", body = ErrorResponse),
+        (status = 501, description = "Order persistence is not enabled", body = ErrorResponse),
+    )
+)]
+pub fn get(app: &App, order_id: &str) -> Result<String, ComputeError> {
+    let order_id = order_id
+        .parse::<i32>()
+        .map_err(|_| ComputeError::OrderNotFound)?;
+    match find(app, order_id)? {
+        Some(order) => to_json(&order),
+        None => Err(ComputeError::OrderNotFound),
+    }
+}
+
+/// The latest stored computation of `order_id`, if there is one.
+pub fn find(app: &App, order_id: i32) -> Result<Option<StoredOrder>, ComputeError> {
+    store(app)?.find(order_id).map_err(unexpected)
+}
+
+fn store(app: &App) -> Result<&dyn OrderStore, ComputeError> {
+    app.store
+        .as_deref()
+        .ok_or(ComputeError::PersistenceDisabled)
+}
+
+fn unexpected(err: anyhow::Error) -> ComputeError {
+    ComputeError::Unexpected(err.into())
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, ComputeError> {
+    crate::pretty::to_string(value).map_err(|err| ComputeError::Unexpected(Box::new(err)))
+}
@@ -0,0 +1,74 @@
+//! Whether the JSON bodies of a response are pretty-printed. They are
+//! compact by default, which is what machine-to-machine calls at volume
+//! want; a person reading them asks for indentation with `?pretty=true` or
+//! `Accept: application/json; pretty=1`.
+
+use hyper::header::ACCEPT;
+use hyper::Request;
+use serde::Serialize;
+use std::future::Future;
+
+tokio::task_local! {
+    static PRETTY: bool;
+}
+
+/// Whether `req` asks for pretty-printed JSON, in its query or as a
+/// parameter of the JSON media type it accepts.
+pub fn from_request<B>(req: &Request<B>) -> bool {
+    let in_query =
+        serde_urlencoded::from_str::<Vec<(String, String)>>(req.uri().query().unwrap_or(""))
+            .unwrap_or_default()
+            .iter()
+            .any(|(name, value)| name == "pretty" && is_true(value));
+    let in_accept = req
+        .headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter(|range| {
+            range.split(';').next().is_some_and(|media_type| {
+                media_type.trim().eq_ignore_ascii_case("application/json")
+            })
+        })
+        .flat_map(|range| range.split(';').skip(1))
+        .filter_map(|param| param.split_once('='))
+        .any(|(name, value)| name.trim().eq_ignore_ascii_case("pretty") && is_true(value));
+    in_query || in_accept
+}
+
+fn is_true(value: &str) -> bool {
+    matches!(
+        value.trim().trim_matches('"').to_ascii_lowercase().as_str(),
+        "1" | "true"
+    )
+}
+
+/// Runs `future` with `pretty` deciding how the JSON it writes is laid out.
+pub async fn scope<F: Future>(pretty: bool, future: F) -> F::Output {
+    PRETTY.scope(pretty, future).await
+}
+
+/// Whether the request being handled asked for pretty-printed JSON; outside
+/// of a request, it did not.
+pub fn current() -> bool {
+    PRETTY.try_with(|pretty| *pretty).unwrap_or(false)
+}
+
+/// `value` as a JSON response body, laid out as the request asked.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>> {
+    if current() {
+        serde_json::to_vec_pretty(value)
+    } else {
+        serde_json::to_vec(value)
+    }
+}
+
+/// `to_vec`, as a string.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    if current() {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
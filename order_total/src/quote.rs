@@ -0,0 +1,194 @@
+use crate::body::Body;
+use crate::codec::Format;
+use crate::config::env_or;
+use crate::error::ComputeError;
+use crate::{body, calculate, dry_run, json_result, notify, App};
+use chrono::{DateTime, Utc};
+use hyper::{Request, Response};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use order_total_core::Order;
+use rand::Rng;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Two-phase checkout: `POST /v1/quote` computes an order without storing or
+/// announcing it, and hands back the result signed into a token;
+/// `POST /v1/finalize` takes the token back before it expires and records the
+/// order at the quoted rate, however the rate changed in between.
+///
+/// * `QUOTE_SECRET` - the HS256 key quote tokens are signed with; unset, a
+///   random key per process, so only the replica that quoted can finalize
+/// * `QUOTE_TTL_SECS` - how long a quote can be finalized (default 900)
+///
+/// Which quotes were finalized is remembered by this replica until they
+/// expire, so each is finalized once.
+pub struct Quotes {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    ttl: Duration,
+    /// The finalized quotes, by id, with their expiry.
+    finalized: Mutex<HashMap<String, i64>>,
+}
+
+/// What the token of a quote carries.
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    jti: String,
+    exp: i64,
+    order: Order,
+    /// As a string, so the rate is not rounded through a float.
+    tax_rate: String,
+}
+
+/// A computed order whose price is guaranteed until `expires_at`.
+#[derive(Serialize, ToSchema)]
+pub struct Quote {
+    pub quote_id: String,
+    /// Send this to `POST /v1/finalize` to record the order at this price.
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+    pub order: Order,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct FinalizeRequest {
+    /// The `token` of the quote.
+    pub token: String,
+}
+
+impl Quotes {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let secret = match std::env::var("QUOTE_SECRET") {
+            Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+            _ => rand::thread_rng().gen::<[u8; 32]>().to_vec(),
+        };
+        Ok(Self {
+            encoding: EncodingKey::from_secret(&secret),
+            decoding: DecodingKey::from_secret(&secret),
+            ttl: Duration::from_secs(env_or("QUOTE_TTL_SECS", 900)?),
+            finalized: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn issue(&self, order: Order, tax_rate: Decimal) -> Result<Quote, ComputeError> {
+        let quote_id = uuid::Uuid::new_v4().to_string();
+        let expires_at = Utc::now() + self.ttl;
+        let claims = Claims {
+            jti: quote_id.clone(),
+            exp: expires_at.timestamp(),
+            order,
+            tax_rate: tax_rate.to_string(),
+        };
+        let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &self.encoding)
+            .map_err(|err| ComputeError::Unexpected(Box::new(err)))?;
+        Ok(Quote {
+            quote_id,
+            token,
+            expires_at,
+            order: claims.order,
+        })
+    }
+
+    /// Checks the token and marks its quote finalized, or for a dry run
+    /// only checks that it could be.
+    fn redeem(&self, token: &str, dry_run: bool) -> Result<(Order, Decimal), ComputeError> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        validation.validate_aud = false;
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.decoding, &validation)
+            .map_err(|err| match err.kind() {
+                ErrorKind::ExpiredSignature => ComputeError::QuoteExpired,
+                _ => ComputeError::QuoteInvalid,
+            })?
+            .claims;
+        let tax_rate = claims
+            .tax_rate
+            .parse()
+            .map_err(|_| ComputeError::QuoteInvalid)?;
+
+        let mut finalized = self.finalized.lock().unwrap();
+        let now = Utc::now().timestamp();
+        finalized.retain(|_, exp| *exp >= now);
+        if finalized.contains_key(&claims.jti) {
+            return Err(ComputeError::QuoteAlreadyFinalized);
+        }
+        if !dry_run {
+            finalized.insert(claims.jti, claims.exp);
+        }
+        let mut order = claims.order;
+        order.dry_run = dry_run;
+        Ok((order, tax_rate))
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/quote",
+    request_body(content = Order, description = "The order to quote, as JSON"),
+    responses(
+        (status = 200, description = "The computed order and its signed quote token", body = Quote),
+        (status = 400, description = "The body is not a valid order", body = ErrorResponse),
+        (status = 413, description = "The body exceeds `MAX_BODY_BYTES`", body = ErrorResponse),
+        (status = 422, description = "The order failed validation", body = ErrorResponse),
+        (status = 503, description = "No sales tax rate is available for the zip code", body = ErrorResponse),
+        (status = 504, description = "The sales tax rate service timed out", body = ErrorResponse),
+    )
+)]
+pub async fn quote(req: Request<Body>, app: &App) -> Response<Body> {
+    let result = async {
+        let schema = app.schema.of_request(req.headers())?;
+        let bytes = body::to_bytes_limited(req, app.max_body_bytes).await?;
+        let order = schema.decode(Format::Json, &bytes)?;
+        tracing::Span::current().record("zip", order.shipping_zip.as_str());
+        let (order, tax_rate) = calculate(order, app).await?;
+        let quote = app.quotes.issue(order, tax_rate)?;
+        tracing::info!(quote_id = %quote.quote_id, "order quoted");
+        Ok(crate::pretty::to_string(&quote).unwrap())
+    };
+    json_result(result.await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/finalize",
+    request_body(content = FinalizeRequest, description = "The token of a quote"),
+    params(
+        ("callback_url" = Option<String>, Query, description = "Also POST the finalized order to this URL, signed with `WEBHOOK_SECRET`"),
+        ("dry_run" = Option<bool>, Query, description = "Only check that the quote could be finalized: it stays open, and the order is not stored, audited or sent to `callback_url`"),
+    ),
+    responses(
+        (status = 200, description = "The order as quoted, now recorded", body = Order),
+        (status = 400, description = "The body is not a finalize request", body = ErrorResponse),
+        (status = 409, description = "The quote was already finalized", body = ErrorResponse),
+        (status = 410, description = "The quote has expired", body = ErrorResponse),
+        (status = 422, description = "The token is not a valid quote", body = ErrorResponse),
+    )
+)]
+pub async fn finalize(req: Request<Body>, app: &App) -> Response<Body> {
+    let result = async {
+        let callback = app.webhooks.callback_url(req.uri().query())?;
+        let dry_run = dry_run(req.uri().query())?;
+        let bytes = body::to_bytes_limited(req, app.max_body_bytes).await?;
+        let request: FinalizeRequest = Format::Json.decode(&bytes)?;
+        let (order, tax_rate) = app.quotes.redeem(&request.token, dry_run)?;
+        tracing::Span::current().record("zip", order.shipping_zip.as_str());
+        if let Some(store) = app.store.as_ref().filter(|_| !dry_run) {
+            store
+                .save(&order, tax_rate, Utc::now())
+                .map_err(|err| ComputeError::Unexpected(err.into()))?;
+        }
+        let result = Ok(order);
+        notify(app, callback, &result);
+        result
+    };
+    json_result(
+        result
+            .await
+            .map(|order| crate::pretty::to_string(&order).unwrap()),
+    )
+}
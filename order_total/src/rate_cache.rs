@@ -0,0 +1,380 @@
+use crate::config::env_or;
+use crate::error::ComputeError;
+use crate::redis::Redis;
+use crate::tax_rate::{self, TaxRate, TaxRateProvider};
+use anyhow::Context;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use models::RateRequest;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const REDIS_NEEDS_TTL: &str = "REDIS_URL needs RATE_CACHE_SECS to say how long rates are kept";
+
+/// The upper bounds, in seconds, of the age buckets `stats` reports.
+const AGE_BUCKETS: [u64; 4] = [60, 300, 900, 3600];
+
+/// Looked-up rates kept for a while: in memory first, then, with
+/// `REDIS_URL` set, in a Redis every replica shares, so a zip code one
+/// replica looked up is not asked of the upstream again by the others.
+/// Redis failures are logged and treated as a miss; they never fail an
+/// order.
+///
+/// * `RATE_CACHE_SECS` - how long a rate is kept, 0 to disable (default 0)
+/// * `RATE_CACHE_MAX` - most rates kept in memory at once (default 10000)
+/// * `REDIS_URL` - `redis://[[user]:password@]host[:port][/db]` of the shared cache (unset by default)
+/// * `REDIS_KEY_PREFIX` - prepended to the zip code (and `@date` of a dated lookup) to form a key (default `order_total:rate:`)
+/// * `REDIS_TIMEOUT_MS` - bound on one Redis command (default 250)
+///
+/// `RATE_CACHE_SECS` and `RATE_CACHE_MAX` can be changed by a reload, see
+/// `reconfigure`; the Redis settings only by a restart.
+pub struct CachedRates {
+    ttl_secs: AtomicU64,
+    max_entries: AtomicUsize,
+    rates: Mutex<HashMap<String, (Instant, TaxRate)>>,
+    redis: Option<Redis>,
+    prefix: String,
+    hits: AtomicU64,
+    shared_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// `RATE_CACHE_SECS` and `RATE_CACHE_MAX` as `CachedRates::limits` read
+/// them.
+pub struct CacheLimits {
+    ttl_secs: u64,
+    max_entries: usize,
+}
+
+/// What `GET /admin/cache/stats` reports.
+#[derive(Serialize)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub shared: bool,
+    pub ttl_secs: u64,
+    /// Rates currently held in memory.
+    pub entries: usize,
+    pub max_entries: usize,
+    /// Lookups answered from memory, from Redis, and by the upstream.
+    pub hits: u64,
+    pub shared_hits: u64,
+    pub misses: u64,
+    /// The share of lookups answered from either cache, `null` before the
+    /// first lookup.
+    pub hit_ratio: Option<f64>,
+    /// How many of the entries are younger than each bound.
+    pub age_distribution: Vec<AgeBucket>,
+}
+
+#[derive(Serialize)]
+pub struct AgeBucket {
+    /// `null` for the last bucket, the entries older than every bound.
+    pub max_age_secs: Option<u64>,
+    pub entries: usize,
+}
+
+impl CachedRates {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let ttl: u64 = env_or("RATE_CACHE_SECS", 0)?;
+        let redis = match std::env::var("REDIS_URL") {
+            Ok(url) if !url.trim().is_empty() => {
+                if ttl == 0 {
+                    anyhow::bail!("{REDIS_NEEDS_TTL}");
+                }
+                let timeout = Duration::from_millis(env_or("REDIS_TIMEOUT_MS", 250)?);
+                Some(Redis::new(url.trim(), timeout)?)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            ttl_secs: AtomicU64::new(ttl),
+            max_entries: AtomicUsize::new(env_or("RATE_CACHE_MAX", 10_000)?),
+            rates: Mutex::new(HashMap::new()),
+            redis,
+            prefix: env_or("REDIS_KEY_PREFIX", "order_total:rate:".to_owned())?,
+            hits: AtomicU64::new(0),
+            shared_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// The cache of a tenant's rates, see `tenant`: configured as the
+    /// service's, and keeping its rates in Redis under
+    /// `REDIS_KEY_PREFIX` followed by `tenant:`, which clearing the
+    /// service's cache clears too.
+    pub fn for_tenant(tenant: &str) -> anyhow::Result<Self> {
+        let mut cache = Self::from_env()?;
+        cache.prefix = format!("{}{tenant}:", cache.prefix);
+        Ok(cache)
+    }
+
+    /// Reads `RATE_CACHE_SECS` and `RATE_CACHE_MAX` anew, for
+    /// `reconfigure` to take once every other setting of a reload is valid.
+    pub fn limits(&self) -> anyhow::Result<CacheLimits> {
+        let ttl_secs: u64 = env_or("RATE_CACHE_SECS", 0)?;
+        if ttl_secs == 0 && self.redis.is_some() {
+            anyhow::bail!("{REDIS_NEEDS_TTL}");
+        }
+        Ok(CacheLimits {
+            ttl_secs,
+            max_entries: env_or("RATE_CACHE_MAX", 10_000)?,
+        })
+    }
+
+    /// Takes `limits`. Rates already cached are kept for the new time since
+    /// they were cached.
+    pub fn reconfigure(&self, limits: CacheLimits) {
+        self.ttl_secs.store(limits.ttl_secs, Ordering::Relaxed);
+        self.max_entries
+            .store(limits.max_entries, Ordering::Relaxed);
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs.load(Ordering::Relaxed))
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let shared_hits = self.shared_hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + shared_hits + misses;
+
+        let ttl = self.ttl();
+        let mut counts = [0; AGE_BUCKETS.len() + 1];
+        let mut entries = 0;
+        for (cached, _) in self.rates.lock().unwrap().values() {
+            let age = cached.elapsed();
+            if age >= ttl {
+                continue;
+            }
+            entries += 1;
+            let bucket = AGE_BUCKETS
+                .iter()
+                .position(|bound| age < Duration::from_secs(*bound))
+                .unwrap_or(AGE_BUCKETS.len());
+            counts[bucket] += 1;
+        }
+        let bounds = AGE_BUCKETS.iter().copied().map(Some).chain([None]);
+        CacheStats {
+            enabled: !ttl.is_zero(),
+            shared: self.redis.is_some(),
+            ttl_secs: ttl.as_secs(),
+            entries,
+            max_entries: self.max_entries.load(Ordering::Relaxed),
+            hits,
+            shared_hits,
+            misses,
+            hit_ratio: (lookups > 0).then(|| (hits + shared_hits) as f64 / lookups as f64),
+            age_distribution: bounds
+                .zip(counts)
+                .map(|(max_age_secs, entries)| AgeBucket {
+                    max_age_secs,
+                    entries,
+                })
+                .collect(),
+        }
+    }
+
+    /// Forgets the rate of `zip`, and the rates it had on past days, here
+    /// and in Redis; whether this replica had any in memory.
+    pub async fn invalidate(&self, zip: &str) -> anyhow::Result<bool> {
+        let dated = format!("{zip}@");
+        let removed = {
+            let mut rates = self.rates.lock().unwrap();
+            let before = rates.len();
+            rates.retain(|key, _| key != zip && !key.starts_with(&dated));
+            rates.len() < before
+        };
+        if let Some(redis) = &self.redis {
+            redis.del(&[self.key(zip)]).await?;
+            redis
+                .delete_matching(&format!("{}*", self.key(&dated)))
+                .await?;
+        }
+        Ok(removed)
+    }
+
+    /// Forgets every rate, here and in Redis; how many this replica had in
+    /// memory.
+    pub async fn clear(&self) -> anyhow::Result<usize> {
+        let removed = std::mem::take(&mut *self.rates.lock().unwrap()).len();
+        if let Some(redis) = &self.redis {
+            redis.delete_matching(&format!("{}*", self.prefix)).await?;
+        }
+        Ok(removed)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    fn cached(&self, key: &str) -> Option<TaxRate> {
+        self.rates
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|(cached, _)| cached.elapsed() < self.ttl())
+            .map(|(_, rate)| rate.clone())
+    }
+
+    fn remember(&self, key: &str, rate: TaxRate) {
+        let ttl = self.ttl();
+        let mut rates = self.rates.lock().unwrap();
+        rates.retain(|_, (cached, _)| cached.elapsed() < ttl);
+        if rates.len() < self.max_entries.load(Ordering::Relaxed) {
+            rates.insert(key.to_owned(), (Instant::now(), rate));
+        }
+    }
+
+    /// Rates are shared in the body form of a lookup: a plain decimal, or
+    /// JSON for a rate with components, which older replicas take as a miss.
+    async fn shared(&self, redis: &Redis, key: &str) -> Option<TaxRate> {
+        match redis.get(key).await {
+            Ok(value) => value.and_then(|value| TaxRate::from_body(&value).ok()),
+            Err(err) => {
+                tracing::warn!(error = %err, "could not read the shared rate cache");
+                None
+            }
+        }
+    }
+}
+
+fn shared_value(rate: &TaxRate) -> String {
+    if rate.components.is_empty() {
+        rate.to_body()
+    } else {
+        rate.to_json()
+    }
+}
+
+/// Zip codes looked up before the service starts taking traffic, so the
+/// first requests for the busiest ones find their rate cached.
+///
+/// * `CACHE_WARM_ZIPS` - comma separated zip codes (unset by default)
+/// * `CACHE_WARM_ZIPS_FILE` - a file of more of them, one per line; `#` starts a comment
+/// * `CACHE_WARM_CONCURRENCY` - lookups in flight at once (default 8)
+pub struct WarmUp {
+    zips: Vec<String>,
+    concurrency: usize,
+}
+
+impl WarmUp {
+    pub fn from_env(cache: &CachedRates) -> anyhow::Result<Self> {
+        let mut zips: Vec<String> = std::env::var("CACHE_WARM_ZIPS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|zip| zip.trim().to_owned())
+            .filter(|zip| !zip.is_empty())
+            .collect();
+        if let Ok(path) = std::env::var("CACHE_WARM_ZIPS_FILE") {
+            let file = std::fs::read_to_string(&path)
+                .with_context(|| format!("could not read CACHE_WARM_ZIPS_FILE {path:?}"))?;
+            zips.extend(
+                file.lines()
+                    .map(|line| line.split('#').next().unwrap_or_default().trim())
+                    .filter(|zip| !zip.is_empty())
+                    .map(str::to_owned),
+            );
+        }
+        zips.sort();
+        zips.dedup();
+        if !zips.is_empty() && cache.ttl().is_zero() {
+            anyhow::bail!("CACHE_WARM_ZIPS needs RATE_CACHE_SECS to keep the rates it looks up");
+        }
+        Ok(Self {
+            zips,
+            concurrency: env_or("CACHE_WARM_CONCURRENCY", 8usize)?.max(1),
+        })
+    }
+
+    /// Looks every zip code up through `rates`, which caches them. A zip
+    /// code that fails is logged and left for the first order to look up.
+    pub async fn run(&self, rates: &dyn TaxRateProvider) -> Warmed {
+        let mut warmed = Warmed {
+            zip_codes: self.zips.len(),
+            failed: 0,
+        };
+        if self.zips.is_empty() {
+            return warmed;
+        }
+        let started = Instant::now();
+        let failed = AtomicU64::new(0);
+        futures_util::stream::iter(&self.zips)
+            .for_each_concurrent(self.concurrency, |zip| {
+                let failed = &failed;
+                async move {
+                    if let Err(err) = rates.find_rate(&RateRequest::new(zip)).await {
+                        tracing::warn!(zip, code = ?err.code(), "could not warm the rate cache");
+                        failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .await;
+        warmed.failed = failed.into_inner();
+        tracing::info!(
+            zip_codes = warmed.zip_codes,
+            failed = warmed.failed,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "rate cache warmed up"
+        );
+        warmed
+    }
+}
+
+/// How a warm-up went.
+pub struct Warmed {
+    pub zip_codes: usize,
+    pub failed: u64,
+}
+
+/// Answers lookups from `cache` where it can, asking `inner` otherwise.
+pub struct RateCache<P> {
+    inner: P,
+    cache: Arc<CachedRates>,
+}
+
+impl<P> RateCache<P> {
+    pub fn new(inner: P, cache: Arc<CachedRates>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl<P: TaxRateProvider> TaxRateProvider for RateCache<P> {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        let cache = &self.cache;
+        if cache.ttl().is_zero() {
+            return self.inner.find_rate(request).await;
+        }
+        let key = tax_rate::key(request);
+        if let Some(rate) = cache.cached(&key) {
+            cache.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(rate);
+        }
+
+        let shared_key = cache.key(&key);
+        if let Some(redis) = &cache.redis {
+            if let Some(rate) = cache.shared(redis, &shared_key).await {
+                cache.shared_hits.fetch_add(1, Ordering::Relaxed);
+                cache.remember(&key, rate.clone());
+                return Ok(rate);
+            }
+        }
+
+        cache.misses.fetch_add(1, Ordering::Relaxed);
+        let rate = self.inner.find_rate(request).await?;
+        cache.remember(&key, rate.clone());
+        if let Some(redis) = &cache.redis {
+            if let Err(err) = redis
+                .set_ex(&shared_key, &shared_value(&rate), cache.ttl())
+                .await
+            {
+                tracing::warn!(error = %err, "could not write the shared rate cache");
+            }
+        }
+        Ok(rate)
+    }
+}
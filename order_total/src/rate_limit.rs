@@ -0,0 +1,86 @@
+use crate::api_keys::ceil_secs;
+use crate::config::env_or;
+use crate::error::ComputeError;
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+use order_total_core::Quota;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One token bucket for every API request, whoever sends it, so that a
+/// client stuck in a retry loop is answered `429` rather than taking the
+/// service down with it. API keys have buckets of their own besides, see
+/// `api_keys`.
+///
+/// * `RATE_LIMIT_PER_SEC` - requests per second, unset or 0 for no limit
+/// * `RATE_LIMIT_BURST` - most requests let through at once after a quiet
+///   spell (default the per-second rate, at least 1)
+///
+/// The standard `RateLimit-Limit`, `RateLimit-Remaining` and
+/// `RateLimit-Reset` headers report the bucket on every API response, and a
+/// refused request also gets a `Retry-After`.
+pub struct RateLimit {
+    bucket: Option<Mutex<Bucket>>,
+    per_sec: f64,
+    burst: u32,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let per_sec = env_or("RATE_LIMIT_PER_SEC", 0.0f64)?.max(0.0);
+        let burst = env_or("RATE_LIMIT_BURST", per_sec.ceil() as u32)?.max(1);
+        Ok(Self {
+            bucket: (per_sec > 0.0).then(|| {
+                Mutex::new(Bucket {
+                    tokens: f64::from(burst),
+                    updated: Instant::now(),
+                })
+            }),
+            per_sec,
+            burst,
+        })
+    }
+
+    /// Takes a token for one request: the quota left, `None` when there is
+    /// no limit, or `Throttled` when the bucket is empty.
+    pub fn take(&self) -> Result<Option<Quota>, ComputeError> {
+        let Some(bucket) = &self.bucket else {
+            return Ok(None);
+        };
+        let capacity = f64::from(self.burst);
+        let mut bucket = bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * self.per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.updated = now;
+
+        let admitted = bucket.tokens >= 1.0;
+        if admitted {
+            bucket.tokens -= 1.0;
+        }
+        let quota = Quota {
+            limit: self.burst,
+            remaining: bucket.tokens as u32,
+            reset: Duration::from_secs_f64((capacity - bucket.tokens) / self.per_sec),
+        };
+        if admitted {
+            Ok(Some(quota))
+        } else {
+            tracing::debug!("over RATE_LIMIT_PER_SEC, refusing request");
+            let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_sec);
+            Err(ComputeError::Throttled(quota, wait))
+        }
+    }
+}
+
+/// Reports `quota` in the `RateLimit-*` headers of a response.
+pub fn annotate(quota: &Quota, headers: &mut HeaderMap) {
+    headers.insert("RateLimit-Limit", quota.limit.into());
+    headers.insert("RateLimit-Remaining", quota.remaining.into());
+    headers.insert("RateLimit-Reset", HeaderValue::from(ceil_secs(quota.reset)));
+}
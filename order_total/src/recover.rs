@@ -0,0 +1,149 @@
+//! Turning a panic in a request handler into a `500`, so that one bad
+//! request is answered rather than dropping its connection, and the
+//! server goes on serving the others.
+
+use crate::body::Body;
+use crate::error::ErrorCode;
+use crate::error_report::{self, Event, Level};
+use crate::request_id;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Response, StatusCode};
+use serde::Serialize;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::pin::Pin;
+use std::sync::Once;
+use std::task::{Context, Poll};
+use utoipa::ToSchema;
+
+/// The media type of `Problem`.
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The body of the `500` a panic is answered with: an RFC 9457 problem
+/// detail, with the `code` and `request_id` of the other error bodies as
+/// extension members, so that clients branching on `code` need not tell
+/// the two apart.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Problem {
+    /// Always `about:blank`: the problem is no more than its status.
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: &'static str,
+    /// Always `INTERNAL_ERROR`.
+    pub code: ErrorCode,
+    /// The id the panic is logged under.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+thread_local! {
+    /// Set while a handler is being polled, whose panics are logged by
+    /// `catch` rather than printed by the default hook.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// Where the last caught panic happened, and its backtrace.
+    static PANICKED_AT: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) };
+}
+
+/// Runs `handler`, answering a panic in it with a `500` `Problem` that
+/// carries the request id, and logging and reporting the panic with its location and
+/// backtrace.
+///
+/// `handler` is boxed right away: handler futures are large, and the
+/// futures wrapping them would otherwise carry them on the stack.
+pub fn catch<F, E>(handler: F) -> impl Future<Output = Result<Response<Body>, E>>
+where
+    F: Future<Output = Result<Response<Body>, E>>,
+{
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() {
+                record(info);
+            } else {
+                previous(info);
+            }
+        }));
+    });
+
+    let caught = Catch {
+        future: Box::pin(handler),
+    };
+    async move {
+        match caught.await {
+            Ok(result) => result,
+            Err(payload) => {
+                let (location, backtrace) = PANICKED_AT
+                    .take()
+                    .map(|(location, backtrace)| (location, backtrace.to_string()))
+                    .unwrap_or_default();
+                let panic = message(&*payload);
+                tracing::error!(panic, location, backtrace, "request handler panicked");
+                error_report::capture(
+                    Event::new(Level::Error, "panic", panic)
+                        .with("location", location)
+                        .with("backtrace", backtrace),
+                );
+                Ok(problem())
+            }
+        }
+    }
+}
+
+fn problem() -> Response<Body> {
+    let status = StatusCode::INTERNAL_SERVER_ERROR;
+    let problem = Problem {
+        kind: "about:blank",
+        title: status.canonical_reason().unwrap_or_default(),
+        status: status.as_u16(),
+        detail: "The request could not be answered because of an internal error.",
+        code: ErrorCode::InternalError,
+        request_id: request_id::current(),
+    };
+    let body = crate::pretty::to_string(&problem).expect("a Problem always serializes");
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    response
+}
+
+fn record(info: &PanicHookInfo<'_>) {
+    let location = info.location().map(ToString::to_string).unwrap_or_default();
+    PANICKED_AT.set(Some((location, Backtrace::force_capture())));
+}
+
+/// The message a panic was raised with, when it is a string.
+fn message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+/// Polls `future`, resolving to the panic payload if a poll panics.
+struct Catch<F> {
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Catch<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let catching = CATCHING.replace(true);
+        let polled = panic::catch_unwind(AssertUnwindSafe(|| self.future.as_mut().poll(cx)));
+        CATCHING.set(catching);
+        match polled {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, bail, Context};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+/// Just enough of a Redis client for the shared rate cache: `GET`, `SET`
+/// with an expiry, `DEL` and `SCAN` over one connection, speaking RESP directly so it runs on
+/// tokio_wasi's sockets as well as tokio's. Commands take turns on the
+/// connection, which is opened again after any failure.
+pub struct Redis {
+    addr: String,
+    username: Option<String>,
+    password: Option<String>,
+    db: Option<u32>,
+    timeout: Duration,
+    conn: Mutex<Option<BufReader<TcpStream>>>,
+}
+
+/// A RESP reply; statuses and integers are not looked into.
+enum Reply {
+    Status,
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Redis {
+    /// `url` is `redis://[[user]:password@]host[:port][/db]`; nothing is
+    /// connected until the first command.
+    pub fn new(url: &str, timeout: Duration) -> anyhow::Result<Self> {
+        let parsed =
+            reqwest::Url::parse(url).with_context(|| format!("invalid REDIS_URL {url:?}"))?;
+        if parsed.scheme() != "redis" {
+            bail!("invalid REDIS_URL {url:?}: only redis:// is supported");
+        }
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| anyhow!("invalid REDIS_URL {url:?}: no host"))?;
+        let db = match parsed.path().trim_matches('/') {
+            "" => None,
+            db => Some(db.parse().map_err(|_| {
+                anyhow!("invalid REDIS_URL {url:?}: {db:?} is not a database number")
+            })?),
+        };
+        Ok(Self {
+            addr: format!("{host}:{}", parsed.port().unwrap_or(6379)),
+            username: Some(parsed.username())
+                .filter(|user| !user.is_empty())
+                .map(str::to_owned),
+            password: parsed.password().map(str::to_owned),
+            db,
+            timeout,
+            conn: Mutex::new(None),
+        })
+    }
+
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        match self.command(&[b"GET", key.as_bytes()]).await? {
+            Reply::Bulk(value) => {
+                Ok(value.map(|value| String::from_utf8_lossy(&value).into_owned()))
+            }
+            _ => bail!("unexpected reply to GET"),
+        }
+    }
+
+    pub async fn set_ex(&self, key: &str, value: &str, ttl: Duration) -> anyhow::Result<()> {
+        let secs = ttl.as_secs().max(1).to_string();
+        self.command(&[
+            b"SET",
+            key.as_bytes(),
+            value.as_bytes(),
+            b"EX",
+            secs.as_bytes(),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    pub async fn del(&self, keys: &[String]) -> anyhow::Result<()> {
+        let mut args: Vec<&[u8]> = vec![b"DEL"];
+        args.extend(keys.iter().map(|key| key.as_bytes()));
+        self.command(&args).await?;
+        Ok(())
+    }
+
+    /// Deletes every key matching the glob `pattern`, a batch at a time.
+    pub async fn delete_matching(&self, pattern: &str) -> anyhow::Result<()> {
+        let mut cursor = "0".to_owned();
+        loop {
+            let reply = self
+                .command(&[
+                    b"SCAN",
+                    cursor.as_bytes(),
+                    b"MATCH",
+                    pattern.as_bytes(),
+                    b"COUNT",
+                    b"500",
+                ])
+                .await?;
+            let Reply::Array(mut parts) = reply else {
+                bail!("unexpected reply to SCAN");
+            };
+            let (Some(Reply::Array(keys)), Some(Reply::Bulk(Some(next)))) =
+                (parts.pop(), parts.pop())
+            else {
+                bail!("unexpected reply to SCAN");
+            };
+            let keys: Vec<String> = keys
+                .into_iter()
+                .filter_map(|key| match key {
+                    Reply::Bulk(Some(key)) => String::from_utf8(key).ok(),
+                    _ => None,
+                })
+                .collect();
+            if !keys.is_empty() {
+                self.del(&keys).await?;
+            }
+            cursor = String::from_utf8_lossy(&next).into_owned();
+            if cursor == "0" {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Sends one command and reads its reply within the timeout, dropping
+    /// the connection if either fails.
+    async fn command(&self, args: &[&[u8]]) -> anyhow::Result<Reply> {
+        let mut conn = self.conn.lock().await;
+        let result = tokio::time::timeout(self.timeout, async {
+            if conn.is_none() {
+                *conn = Some(self.connect().await?);
+            }
+            round_trip(conn.as_mut().unwrap(), args).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("Redis did not answer in time")));
+        if result.is_err() {
+            *conn = None;
+        }
+        result
+    }
+
+    async fn connect(&self) -> anyhow::Result<BufReader<TcpStream>> {
+        let stream = TcpStream::connect(self.addr.as_str())
+            .await
+            .with_context(|| format!("could not connect to Redis at {}", self.addr))?;
+        let mut conn = BufReader::new(stream);
+        match (&self.username, &self.password) {
+            (Some(user), Some(password)) => {
+                round_trip(&mut conn, &[b"AUTH", user.as_bytes(), password.as_bytes()]).await?;
+            }
+            (None, Some(password)) => {
+                round_trip(&mut conn, &[b"AUTH", password.as_bytes()]).await?;
+            }
+            _ => {}
+        }
+        if let Some(db) = self.db {
+            round_trip(&mut conn, &[b"SELECT", db.to_string().as_bytes()]).await?;
+        }
+        Ok(conn)
+    }
+}
+
+async fn round_trip(conn: &mut BufReader<TcpStream>, args: &[&[u8]]) -> anyhow::Result<Reply> {
+    let mut frame = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        frame.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        frame.extend_from_slice(arg);
+        frame.extend_from_slice(b"\r\n");
+    }
+    conn.get_mut().write_all(&frame).await?;
+    read_reply(conn).await
+}
+
+fn read_reply(
+    conn: &mut BufReader<TcpStream>,
+) -> Pin<Box<dyn Future<Output = anyhow::Result<Reply>> + Send + '_>> {
+    Box::pin(async move {
+        let mut line = String::new();
+        if conn.read_line(&mut line).await? == 0 {
+            bail!("Redis closed the connection");
+        }
+        let line = line.trim_end();
+        let kind = line.get(..1).unwrap_or_default();
+        match (kind, line.get(1..).unwrap_or_default()) {
+            ("+" | ":", _) => Ok(Reply::Status),
+            ("-", message) => bail!("Redis answered {message}"),
+            ("$", "-1") => Ok(Reply::Bulk(None)),
+            ("$", len) => {
+                let len: usize = len.parse().context("malformed Redis reply")?;
+                let mut value = vec![0; len + 2];
+                conn.read_exact(&mut value).await?;
+                value.truncate(len);
+                Ok(Reply::Bulk(Some(value)))
+            }
+            ("*", "-1") => Ok(Reply::Array(Vec::new())),
+            ("*", len) => {
+                let len: usize = len.parse().context("malformed Redis reply")?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(read_reply(conn).await?);
+                }
+                Ok(Reply::Array(items))
+            }
+            _ => bail!("unexpected Redis reply {line:?}"),
+        }
+    })
+}
@@ -0,0 +1,66 @@
+use crate::body::Body;
+use crate::codec::Format;
+use crate::error::{ComputeError, FieldError};
+use crate::{body, json_result, orders, App};
+use hyper::{Request, Response};
+use order_total_core::{Order, ReturnedItem};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// A return against an order: either the `order_id` of a stored order, or
+/// the `order` as it was computed, with its `tax_rate`.
+#[derive(Deserialize, ToSchema)]
+pub struct RefundRequest {
+    /// Looked up in the order store; needs `DATABASE_URL`.
+    #[serde(default)]
+    pub order_id: Option<i32>,
+    /// The computed order, as `/v1/compute` answered it.
+    #[serde(default)]
+    pub order: Option<Order>,
+    pub returned_items: Vec<ReturnedItem>,
+}
+
+/// Computes the credit memo for items returned from an order, reversing the
+/// tax at the rate that was applied when it was bought.
+#[utoipa::path(
+    post,
+    path = "/v1/refund",
+    request_body(content = RefundRequest, description = "The order and the items returned from it"),
+    responses(
+        (status = 200, description = "The amounts to credit, tax included", body = CreditMemo),
+        (status = 400, description = "The body is not a refund request", body = ErrorResponse),
+        (status = 404, description = "No order with that id was stored", body = ErrorResponse),
+        (status = 422, description = "The returned items do not match the order", body = ErrorResponse),
+        (status = 501, description = "An `order_id` was given but order persistence is not enabled", body = ErrorResponse),
+    )
+)]
+pub async fn refund(req: Request<Body>, app: &App) -> Response<Body> {
+    let result = async {
+        let bytes = body::to_bytes_limited(req, app.max_body_bytes).await?;
+        let request: RefundRequest = Format::Json.decode(&bytes)?;
+        let order = match (request.order_id, request.order) {
+            (Some(order_id), None) => {
+                orders::find(app, order_id)?
+                    .ok_or(ComputeError::OrderNotFound)?
+                    .order
+            }
+            (None, Some(order)) => order,
+            _ => {
+                return Err(ComputeError::Validation(vec![FieldError::new(
+                    "order_id",
+                    "give either order_id or order",
+                )]))
+            }
+        };
+        tracing::Span::current().record("zip", order.shipping_zip.as_str());
+        let rounding = app
+            .calculator()
+            .rounding
+            .with_override(order.rounding)
+            .in_currency(&order.currency());
+        let memo = order.refund(&request.returned_items, rounding)?;
+        tracing::info!(order_id = memo.order_id, tax_amount = %memo.tax_amount, "refund computed");
+        Ok(crate::pretty::to_string(&memo).unwrap())
+    };
+    json_result(result.await)
+}
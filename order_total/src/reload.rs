@@ -0,0 +1,40 @@
+use crate::App;
+use std::sync::Arc;
+
+/// Reloads the configuration of `app` on every SIGHUP, as `POST
+/// /admin/reload` does. WASI has no signals, so under WasmEdge only the
+/// endpoint reloads.
+pub fn on_hangup(app: Arc<App>) {
+    tokio::spawn(async move {
+        let mut hangups = hangups();
+        while hangups.recv().await.is_some() {
+            tracing::info!("SIGHUP received, reloading the configuration");
+            if let Err(err) = app.reload() {
+                tracing::error!("could not reload the configuration: {err:#}");
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+fn hangups() -> tokio::signal::unix::Signal {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    signal(SignalKind::hangup()).expect("failed to install SIGHUP handler")
+}
+
+#[cfg(not(unix))]
+fn hangups() -> NoSignal {
+    NoSignal
+}
+
+/// Never delivers a signal.
+#[cfg(not(unix))]
+struct NoSignal;
+
+#[cfg(not(unix))]
+impl NoSignal {
+    async fn recv(&mut self) -> Option<()> {
+        std::future::pending().await
+    }
+}
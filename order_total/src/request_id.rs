@@ -0,0 +1,43 @@
+use hyper::Request;
+use std::future::Future;
+
+/// The header a request id arrives in, is echoed back in, and is forwarded
+/// to the sales tax rate service in.
+pub const HEADER: &str = models::logging::REQUEST_ID_HEADER;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Honors a well-formed incoming `X-Request-Id`, otherwise generates a new
+/// random id.
+pub fn from_request<B>(req: &Request<B>) -> String {
+    req.headers()
+        .get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse)
+        .map(str::to_owned)
+        .unwrap_or_else(generate)
+}
+
+/// `id` without surrounding whitespace, if it is well-formed enough to be
+/// honored: 1 to 128 visible ASCII characters.
+pub fn parse(id: &str) -> Option<&str> {
+    Some(id.trim())
+        .filter(|id| !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic()))
+}
+
+/// A new random id.
+pub fn generate() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Runs `future` with `id` as the current request id.
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(id, future).await
+}
+
+/// The id of the request being handled, if any.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
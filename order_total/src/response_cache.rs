@@ -0,0 +1,99 @@
+use crate::codec::Format;
+use crate::config::env_or;
+use hyper::body::Bytes;
+use order_total_core::Order;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Remembers the responses to `/compute` orders for a short while, so that a
+/// storefront asking for the same cart preview over and over is answered
+/// without computing it again or looking its rate up.
+///
+/// * `RESPONSE_CACHE_SECS` - how long a response is kept, 0 to disable
+///   (default 0)
+/// * `RESPONSE_CACHE_MAX` - most responses kept at once; the oldest is
+///   evicted first (default 1000)
+///
+/// A response is keyed on the order as it was decoded, so the same order is
+/// the same whatever its layout, key order or encoding, and on the format
+/// and layout it was answered in, for each tenant apart. Only successes are
+/// kept, and `reload` empties the cache.
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<Key, (Instant, Bytes)>>,
+}
+
+/// What a cached response is kept under: all of it, not a hash of it, so
+/// that two requests share a response only when they are the same.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Key {
+    /// The order as it was decoded, in JSON.
+    order: Vec<u8>,
+    /// The day an order without an `order_date` is computed for.
+    today: Option<NaiveDate>,
+    content_type: &'static str,
+    pretty: bool,
+    tenant: Option<String>,
+}
+
+impl ResponseCache {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            ttl: Duration::from_secs(env_or("RESPONSE_CACHE_SECS", 0)?),
+            max_entries: env_or("RESPONSE_CACHE_MAX", 1000)?.max(1),
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The key of the response to `order` in `format`, or `None` when the
+    /// cache is off. An order without an `order_date` is computed for
+    /// today, so today is part of its key.
+    pub fn key(&self, order: &Order, format: Format) -> Option<Key> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        Some(Key {
+            order: serde_json::to_vec(order).ok()?,
+            today: order
+                .order_date
+                .is_none()
+                .then(|| chrono::Utc::now().date_naive()),
+            content_type: format.content_type(),
+            pretty: crate::pretty::current(),
+            tenant: crate::tenant::current(),
+        })
+    }
+
+    /// The response kept under `key`, unless it has expired.
+    pub fn get(&self, key: &Key) -> Option<Bytes> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(created, _)| created.elapsed() < self.ttl)
+            .map(|(_, body)| body.clone())
+    }
+
+    pub fn insert(&self, key: Key, body: &Bytes) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (created, _)| now.duration_since(*created) < self.ttl);
+        if entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (created, _))| *created)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, (now, body.clone()));
+    }
+
+    /// Forgets every response, which new settings may no longer give.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,62 @@
+use crate::config::env_or;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// How many times, and how far apart, a failed call to the sales tax rate
+/// service is attempted before the error is surfaced to the client.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Reads the policy from `TAX_SERVICE_MAX_ATTEMPTS`,
+    /// `TAX_SERVICE_RETRY_BASE_MS` and `TAX_SERVICE_RETRY_MAX_MS`, falling
+    /// back to 3 attempts, 100ms and 2s respectively.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            max_attempts: env_or("TAX_SERVICE_MAX_ATTEMPTS", 3u32)?.max(1),
+            base_delay: Duration::from_millis(env_or("TAX_SERVICE_RETRY_BASE_MS", 100)?),
+            max_delay: Duration::from_millis(env_or("TAX_SERVICE_RETRY_MAX_MS", 2000)?),
+        })
+    }
+
+    /// Delay before retrying after the given (1-based) failed attempt, using
+    /// "full jitter": a random duration between zero and the exponential
+    /// backoff ceiling for that attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let ceiling = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        if millis == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+
+    /// Runs `op` until it succeeds, fails with an error `is_transient`
+    /// rejects, or the attempts are exhausted; the last error is returned.
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        mut op: F,
+        is_transient: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(err) if attempt < self.max_attempts && is_transient(&err) => {
+                    tokio::time::sleep(self.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
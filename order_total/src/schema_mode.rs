@@ -0,0 +1,263 @@
+//! How strictly an order body is held to the documented schema, so that a
+//! misspelt field name can be refused instead of silently defaulting, and
+//! how tolerant it is of numbers sent as strings.
+
+use crate::codec::Format;
+use crate::config::env_or;
+use crate::error::{ComputeError, FieldError};
+use hyper::HeaderMap;
+use order_total_core::{JsonError, Order};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::str::FromStr;
+
+/// Picks the mode of one request over `SCHEMA_MODE`.
+pub const HEADER: &str = "x-schema-mode";
+
+/// The fields a single-item order is computed from, which default to zero
+/// when left out.
+const SINGLE_ITEM_FIELDS: [&str; 3] = ["product_id", "quantity", "subtotal"];
+
+lazy_static::lazy_static! {
+    /// The component schemas of `/openapi.json`, which name every field an
+    /// order and what it nests may have.
+    static ref SCHEMAS: Map<String, Value> = {
+        let mut doc: Value = serde_json::from_str(&crate::openapi::json()).unwrap();
+        match doc["components"]["schemas"].take() {
+            Value::Object(schemas) => schemas,
+            _ => unreachable!("the OpenAPI document has component schemas"),
+        }
+    };
+}
+
+/// * `lenient` - unknown fields are ignored, as are the fields the
+///   computation fills in, such as `total`; left out, a field takes its
+///   default
+/// * `strict` - a field the schema does not have is refused, and so is a
+///   single-item order without `product_id`, `quantity` or `subtotal`
+///
+/// Either way the body must otherwise be a valid order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaMode {
+    Lenient,
+    Strict,
+}
+
+/// How order bodies are read: in which `SchemaMode`, and whether numbers
+/// may be sent as strings.
+///
+/// * `SCHEMA_MODE` - `lenient` or `strict` (default `lenient`); `X-Schema-Mode` picks the mode of one request
+/// * `NUMERIC_STRINGS` - accept the numeric fields of an order as strings, e.g. `"subtotal": "123.45"` (default `false`)
+#[derive(Debug, Clone, Copy)]
+pub struct OrderSchema {
+    mode: SchemaMode,
+    numeric_strings: bool,
+}
+
+impl SchemaMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "lenient" => Some(Self::Lenient),
+            "strict" => Some(Self::Strict),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lenient => "lenient",
+            Self::Strict => "strict",
+        }
+    }
+}
+
+impl OrderSchema {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match std::env::var("SCHEMA_MODE") {
+            Ok(value) => SchemaMode::parse(&value).ok_or_else(|| {
+                anyhow::anyhow!("invalid SCHEMA_MODE {value:?}: expected lenient or strict")
+            })?,
+            Err(_) => SchemaMode::Lenient,
+        };
+        Ok(Self {
+            mode,
+            numeric_strings: env_or("NUMERIC_STRINGS", false)?,
+        })
+    }
+
+    /// The schema with the mode the `X-Schema-Mode` header asks for, if
+    /// any.
+    pub fn of_request(self, headers: &HeaderMap) -> Result<Self, ComputeError> {
+        let Some(value) = headers.get(HEADER) else {
+            return Ok(self);
+        };
+        let mode = value
+            .to_str()
+            .ok()
+            .and_then(SchemaMode::parse)
+            .ok_or(ComputeError::InvalidRequest)?;
+        Ok(Self { mode, ..self })
+    }
+
+    /// Decodes an order sent in `format`.
+    pub fn decode(self, format: Format, body: &[u8]) -> Result<Order, ComputeError> {
+        if self.mode == SchemaMode::Lenient && !self.numeric_strings {
+            return format.decode(body);
+        }
+        self.order(format.decode(body)?)
+    }
+
+    /// The order an already parsed body holds.
+    pub fn order(self, mut value: Value) -> Result<Order, ComputeError> {
+        let mut check = Check {
+            unknown_fields: self.mode == SchemaMode::Strict,
+            numeric_strings: self.numeric_strings,
+            errors: Vec::new(),
+        };
+        check.walk(&mut value, &SCHEMAS["Order"], "");
+        if self.mode == SchemaMode::Strict {
+            missing_fields(&value, &mut check.errors);
+        }
+        if !check.errors.is_empty() {
+            return Err(ComputeError::Validation(check.errors));
+        }
+        // Read from text, not from the value, so that an error names its
+        // field.
+        let text = value.to_string();
+        serde_json::from_str(&text).map_err(|err| {
+            ComputeError::MalformedJson(JsonError::new(&err, text.as_bytes()).with_field_only())
+        })
+    }
+}
+
+/// What to check a body against its schema for, and the problems found.
+struct Check {
+    unknown_fields: bool,
+    numeric_strings: bool,
+    errors: Vec<FieldError>,
+}
+
+impl Check {
+    /// Goes through `value`, at `path`, and the objects and arrays nested in
+    /// it: reports the fields `schema` does not have, and checks the
+    /// strings where it has numbers, see `number`.
+    fn walk(&mut self, value: &mut Value, schema: &Value, path: &str) {
+        match value {
+            Value::Object(fields) => {
+                let properties = properties(schema);
+                if properties.is_empty() {
+                    return;
+                }
+                for (name, field) in fields {
+                    let field_path = match path {
+                        "" => name.clone(),
+                        path => format!("{path}.{name}"),
+                    };
+                    match properties.iter().find(|(property, _)| *property == name) {
+                        Some((_, schema)) => self.walk(field, schema, &field_path),
+                        None if self.unknown_fields => self
+                            .errors
+                            .push(FieldError::new(field_path, "is not a known field")),
+                        None => {}
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for schema in resolve(schema) {
+                    if let Some(item_schema) = schema.get("items") {
+                        for (index, item) in items.iter_mut().enumerate() {
+                            self.walk(item, item_schema, &format!("{path}[{index}]"));
+                        }
+                    }
+                }
+            }
+            Value::String(text) if self.numeric_strings => {
+                let Some(kind) = resolve(schema)
+                    .into_iter()
+                    .find_map(|schema| schema.get("type").and_then(Value::as_str))
+                    .filter(|kind| matches!(*kind, "number" | "integer"))
+                else {
+                    return;
+                };
+                match number(text, kind) {
+                    Some(number) => *value = number,
+                    None => self.errors.push(FieldError::new(
+                        path,
+                        if kind == "integer" {
+                            "must be a whole number"
+                        } else {
+                            "must be a number"
+                        },
+                    )),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The value to read the number `text` spells from, if it is one of `kind`:
+/// a JSON integer, or a decimal as the trimmed string, which amounts read
+/// exactly where a JSON number would go through a float.
+fn number(text: &str, kind: &str) -> Option<Value> {
+    let text = text.trim();
+    if kind == "number" {
+        return Decimal::from_str(text)
+            .is_ok()
+            .then(|| Value::String(text.to_owned()));
+    }
+    let number: Number = text.parse().ok()?;
+    (number.is_i64() || number.is_u64()).then_some(Value::Number(number))
+}
+
+/// The properties of `schema`, with those of the schemas it refers to or is
+/// composed of.
+fn properties(schema: &Value) -> Vec<(&String, &Value)> {
+    resolve(schema)
+        .into_iter()
+        .filter_map(|schema| schema.get("properties").and_then(Value::as_object))
+        .flatten()
+        .collect()
+}
+
+/// `schema` and the schemas its `$ref`, `allOf`, `oneOf` and `anyOf` stand
+/// for.
+fn resolve(schema: &Value) -> Vec<&Value> {
+    let mut resolved = vec![schema];
+    if let Some(name) = schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|reference| reference.strip_prefix("#/components/schemas/"))
+    {
+        if let Some(referred) = SCHEMAS.get(name) {
+            resolved.extend(resolve(referred));
+        }
+    }
+    for composition in ["allOf", "oneOf", "anyOf"] {
+        if let Some(parts) = schema.get(composition).and_then(Value::as_array) {
+            resolved.extend(parts.iter().flat_map(resolve));
+        }
+    }
+    resolved
+}
+
+/// Reports the fields of a single-item order that are left out.
+fn missing_fields(value: &Value, errors: &mut Vec<FieldError>) {
+    let has_line_items = value
+        .get("line_items")
+        .and_then(Value::as_array)
+        .is_some_and(|items| !items.is_empty());
+    if has_line_items || !value.is_object() {
+        return;
+    }
+    for field in SINGLE_ITEM_FIELDS {
+        if value.get(field).is_none_or(Value::is_null) {
+            errors.push(FieldError::new(
+                field,
+                "is required in strict mode, unless the order has line_items",
+            ));
+        }
+    }
+}
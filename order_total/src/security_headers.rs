@@ -0,0 +1,52 @@
+use crate::api::Route;
+use crate::body::Body;
+use hyper::header::{self, HeaderName, HeaderValue};
+use hyper::Response;
+
+/// Security headers added to every response of the HTTP API, error and
+/// `404` responses included; a header a handler set itself is kept.
+///
+/// * `X-Content-Type-Options: nosniff`, `Referrer-Policy: no-referrer` and `X-Frame-Options: DENY` on every response
+/// * `Cache-Control: no-store` and a `Content-Security-Policy` allowing nothing on the API and admin responses, which carry prices and are never rendered; the documentation pages are left cacheable and free to load their scripts
+/// * `Strict-Transport-Security` when the service serves TLS itself (`TLS_CERT_PATH`), for `HSTS_MAX_AGE_SECS` (default one year, `0` leaves it out)
+pub struct SecurityHeaders {
+    hsts: Option<HeaderValue>,
+}
+
+const NO_STORE: HeaderValue = HeaderValue::from_static("no-store");
+const NOTHING_ALLOWED: HeaderValue =
+    HeaderValue::from_static("default-src 'none'; frame-ancestors 'none'");
+
+impl SecurityHeaders {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let tls = std::env::var_os("TLS_CERT_PATH").is_some_and(|path| !path.is_empty());
+        let max_age = crate::config::env_or("HSTS_MAX_AGE_SECS", 365 * 24 * 60 * 60u64)?;
+        Ok(Self {
+            hsts: (tls && max_age > 0)
+                .then(|| HeaderValue::from_str(&format!("max-age={max_age}")).unwrap()),
+        })
+    }
+
+    pub fn apply(&self, route: &Route<'_>, response: &mut Response<Body>) {
+        let headers = response.headers_mut();
+        let mut set = |name: HeaderName, value: HeaderValue| {
+            headers.entry(name).or_insert(value);
+        };
+        set(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        );
+        set(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        );
+        set(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        if !route.is_documentation() {
+            set(header::CACHE_CONTROL, NO_STORE);
+            set(header::CONTENT_SECURITY_POLICY, NOTHING_ALLOWED);
+        }
+        if let Some(hsts) = &self.hsts {
+            set(header::STRICT_TRANSPORT_SECURITY, hsts.clone());
+        }
+    }
+}
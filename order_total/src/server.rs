@@ -0,0 +1,162 @@
+//! The socket servers of `order_total serve`: plain HTTP and HTTPS over
+//! TCP, HTTP on a Unix domain socket, the HTTPS redirect and the gRPC
+//! listener. Built with the `server` feature; without it the service still
+//! answers through `wagi`, `pipe` and `OrderTotalService`.
+
+use crate::connection::{self, Connections, Protocols};
+use crate::https::{self, Tls};
+use crate::shutdown::Shutdown;
+use crate::unix_socket::UnixSocket;
+use crate::{config, grpc, middleware, or_exit, App, RuntimeFlavor};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower_service::Service;
+
+/// Serves `app` over plain HTTP on `addr` until `shutdown` resolves, and
+/// returns the address actually bound (port 0 picks a free one) along with
+/// the server to await.
+pub fn bind(
+    app: Arc<App>,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<(SocketAddr, impl Future<Output = anyhow::Result<()>>)> {
+    let (local_addr, connections) = connection::listen(&addr)?;
+    Ok((local_addr, serve(connections, app, shutdown)))
+}
+
+/// Serves `app` over plain HTTP on the Unix domain socket at `path` until
+/// `shutdown` resolves; a stale socket file there is replaced.
+#[cfg(unix)]
+pub fn bind_unix(
+    app: Arc<App>,
+    path: &std::path::Path,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
+    let socket = UnixSocket::at(path.to_owned());
+    Ok(serve(socket.incoming()?, app, shutdown))
+}
+
+/// Serves the app on `connections`, plain or TLS, until `shutdown`
+/// resolves.
+async fn serve<C>(
+    connections: Connections<C>,
+    app: Arc<App>,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = middleware::stack(&app);
+    let handler = move |conn: &connection::Tracked<C>| {
+        let service = service.clone();
+        let requests = conn.requests();
+        move |req| {
+            let mut service = service.clone();
+            let in_progress = requests.start();
+            async move {
+                let _in_progress = in_progress;
+                service.call(req).await
+            }
+        }
+    };
+    let connections = app.connections.track(connections);
+    connection::serve(
+        connections,
+        Protocols::Http(&app.connections),
+        handler,
+        shutdown,
+    )
+    .await
+}
+
+/// Reads the settings of the listeners, without binding them.
+pub fn check_config() -> anyhow::Result<()> {
+    let addr = config::listen_addr()?;
+    config::grpc_addr(addr)?;
+    let tls = Tls::from_env()?;
+    config::listen_tcp(UnixSocket::from_env()?.is_some(), tls.is_some())?;
+    Ok(())
+}
+
+/// Serves `app` on the listeners the environment configures until the
+/// process is asked to shut down.
+pub async fn run(app: Arc<App>, flavor: RuntimeFlavor) {
+    let addr = or_exit(config::listen_addr());
+    let tls = or_exit(Tls::from_env());
+
+    let shutdown = or_exit(Shutdown::listen());
+    if let Some(grpc_addr) = or_exit(config::grpc_addr(addr)) {
+        let (grpc_addr, grpc) = or_exit(grpc::bind(
+            app.clone(),
+            grpc_addr,
+            shutdown.clone().requested(),
+        ));
+        tracing::info!(addr = %grpc_addr, "gRPC server started");
+        tokio::spawn(async move {
+            if let Err(e) = grpc.await {
+                tracing::error!(error = %e, "gRPC server error");
+            }
+        });
+    }
+    #[cfg(feature = "nats")]
+    if let Some(url) = or_exit(config::nats_url()) {
+        let responder =
+            or_exit(crate::nats::subscribe(app.clone(), &url, shutdown.clone().requested()).await);
+        tokio::spawn(responder);
+    }
+    #[cfg(not(feature = "nats"))]
+    or_exit(config::nats_url());
+    let socket = or_exit(UnixSocket::from_env());
+    let tcp = or_exit(config::listen_tcp(socket.is_some(), tls.is_some()));
+    let mut servers: Vec<Pin<Box<dyn Future<Output = anyhow::Result<()>>>>> = Vec::new();
+    if tcp {
+        servers.push(match &tls {
+            None => Box::pin(or_exit(bind(app.clone(), addr, shutdown.clone().requested())).1),
+            Some(tls) => Box::pin(serve(
+                or_exit(tls.incoming(&addr)),
+                app.clone(),
+                shutdown.clone().requested(),
+            )),
+        });
+        tracing::info!(%addr, tls = tls.is_some(), runtime = %flavor, "server started");
+    }
+    #[cfg(unix)]
+    if let Some(socket) = &socket {
+        servers.push(Box::pin(serve(
+            or_exit(socket.incoming()),
+            app.clone(),
+            shutdown.clone().requested(),
+        )));
+        tracing::info!(path = %socket.path.display(), runtime = %flavor, "server started on a Unix socket");
+    }
+    if let Some(port) = tls.as_ref().and_then(|tls| tls.redirect_port) {
+        let redirect = or_exit(https::redirect(
+            SocketAddr::new(addr.ip(), port),
+            addr.port(),
+            app.connections.clone(),
+            shutdown.clone().requested(),
+        ));
+        tokio::spawn(async move {
+            if let Err(e) = redirect.await {
+                tracing::error!(error = %e, "redirect server error");
+            }
+        });
+    }
+    let server = futures_util::future::try_join_all(servers);
+    tokio::select! {
+        result = server => {
+            if let Err(e) = result {
+                tracing::error!(error = %e, "server error");
+            }
+        }
+        _ = shutdown.deadline() => {
+            tracing::warn!("shutdown deadline exceeded, dropping in-flight requests");
+        }
+    }
+    if let Some(socket) = &socket {
+        socket.remove();
+    }
+}
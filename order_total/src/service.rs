@@ -0,0 +1,71 @@
+//! The service as a tower `Service`, for another program to mount inside
+//! its own router or server rather than having `bind` listen for it.
+
+use crate::body::Body;
+use crate::error::{self, ComputeError};
+use crate::{middleware, App};
+use hyper::{Request, Response};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+type Call = dyn Fn(Request<Body>) -> middleware::BoxFuture + Send + Sync;
+
+/// Answers requests as the server does, through the same layers and routes,
+/// see `middleware::stack`; cloning it is cheap, and the clones share one
+/// `App`.
+///
+/// ```ignore
+/// let orders = OrderTotalService::new(Arc::new(App::from_env()?));
+/// let orders = tower::ServiceBuilder::new()
+///     .map_request(|req: Request<axum::body::Body>| req.map(Body::new))
+///     .service(orders);
+/// let app = axum::Router::new().nest_service("/orders", orders);
+/// ```
+///
+/// The paths it serves are its own, `/v1/compute` and the others: a router
+/// that mounts it under a prefix hands it the request without the prefix.
+/// It is never not ready, and answers whatever goes wrong with an error
+/// body rather than failing, so that it can be mounted wherever an
+/// infallible service is expected.
+#[derive(Clone)]
+pub struct OrderTotalService {
+    call: Arc<Call>,
+}
+
+impl OrderTotalService {
+    pub fn new(app: Arc<App>) -> Self {
+        let service = middleware::stack(&app);
+        Self {
+            call: Arc::new(move |req| service.clone().call(req)),
+        }
+    }
+
+    /// The service of the app the environment variables listed in the
+    /// README configure, see `App::from_env`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self::new(Arc::new(App::from_env()?)))
+    }
+}
+
+impl Service<Request<Body>> for OrderTotalService {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let answer = (self.call)(req);
+        Box::pin(async move {
+            Ok(answer
+                .await
+                .unwrap_or_else(|err| error::response(ComputeError::Unexpected(err.into()))))
+        })
+    }
+}
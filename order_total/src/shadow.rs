@@ -0,0 +1,133 @@
+use crate::error::ComputeError;
+use crate::request_id;
+use crate::tax_rate::{HttpTaxRateProvider, TaxRate, TaxRateProvider};
+use async_trait::async_trait;
+use models::RateRequest;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{Instrument, Span};
+
+/// Sends every lookup also to a second sales tax rate service, named by
+/// `SHADOW_TAX_RATE_SERVICE` (one URL or a comma separated list of
+/// replicas), to gain confidence in it before moving to it. The shadow is
+/// asked in parallel and compared in the background: it never delays,
+/// changes or fails an order. Rates that differ are logged and counted in
+/// `ShadowStats`.
+///
+/// The shadow is called with the `TAX_SERVICE_*` settings of the primary
+/// service, and has a retry policy and circuit breaker of its own.
+pub struct Shadowed {
+    primary: Arc<dyn TaxRateProvider>,
+    shadow: Arc<HttpTaxRateProvider>,
+    stats: Arc<ShadowStats>,
+}
+
+/// Wraps `primary` in `Shadowed` when `SHADOW_TAX_RATE_SERVICE` is set,
+/// counting the comparisons in `stats`.
+pub fn from_env(
+    primary: Arc<dyn TaxRateProvider>,
+    stats: &Arc<ShadowStats>,
+) -> anyhow::Result<Arc<dyn TaxRateProvider>> {
+    match std::env::var("SHADOW_TAX_RATE_SERVICE") {
+        Ok(urls) if !urls.trim().is_empty() => Ok(Arc::new(Shadowed {
+            primary,
+            shadow: Arc::new(HttpTaxRateProvider::from_env(&urls)?),
+            stats: stats.clone(),
+        })),
+        _ => Ok(primary),
+    }
+}
+
+#[async_trait]
+impl TaxRateProvider for Shadowed {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        let shadow = self.shadow.clone();
+        let shadowed = request.clone();
+        let id = request_id::current().unwrap_or_default();
+        let lookup = async move { shadow.find_rate(&shadowed).await };
+        let shadow = tokio::spawn(request_id::scope(id, lookup).instrument(Span::current()));
+
+        let result = self.primary.find_rate(request).await;
+        if let Some(primary) = answer(&result) {
+            let stats = self.stats.clone();
+            let zip = request.zip.clone();
+            let comparison = async move {
+                let shadow = shadow.await.ok().and_then(|result| answer(&result));
+                stats.compare(&zip, primary, shadow);
+            };
+            tokio::spawn(comparison.instrument(Span::current()));
+        }
+        result
+    }
+}
+
+/// The rate a lookup answered, `Some(None)` for a zip code without one, or
+/// `None` when the lookup failed.
+fn answer(result: &Result<TaxRate, ComputeError>) -> Option<Option<Decimal>> {
+    match result {
+        Ok(rate) => Some(Some(rate.rate)),
+        Err(ComputeError::TaxRateNotAvailable) => Some(None),
+        Err(_) => None,
+    }
+}
+
+/// How the shadow service's answers compared with the primary's since the
+/// service started; reloads keep counting. Only lookups the primary
+/// answered are compared, and only their rates, not the components.
+#[derive(Default)]
+pub struct ShadowStats {
+    matched: AtomicU64,
+    mismatched: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// What `GET /admin/shadow/stats` reports.
+#[derive(Serialize)]
+pub struct ShadowReport {
+    /// Lookups both services answered, with a rate or with none.
+    pub compared: u64,
+    pub matched: u64,
+    pub mismatched: u64,
+    /// Lookups the shadow service failed, which are not compared.
+    pub shadow_failed: u64,
+    /// The share of comparisons that matched, `null` before the first.
+    pub match_ratio: Option<f64>,
+}
+
+impl ShadowStats {
+    fn compare(&self, zip: &str, primary: Option<Decimal>, shadow: Option<Option<Decimal>>) {
+        match shadow {
+            None => {
+                tracing::debug!(zip, "shadow tax rate service failed");
+                self.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(shadow) if shadow == primary => {
+                self.matched.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(shadow) => {
+                tracing::warn!(
+                    zip,
+                    primary = ?primary,
+                    shadow = ?shadow,
+                    "shadow tax rate service disagrees"
+                );
+                self.mismatched.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn report(&self) -> ShadowReport {
+        let matched = self.matched.load(Ordering::Relaxed);
+        let mismatched = self.mismatched.load(Ordering::Relaxed);
+        let compared = matched + mismatched;
+        ShadowReport {
+            compared,
+            matched,
+            mismatched,
+            shadow_failed: self.failed.load(Ordering::Relaxed),
+            match_ratio: (compared > 0).then(|| matched as f64 / compared as f64),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::config::env_or;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Broadcasts the moment the process is asked to stop, so that both the
+/// server (to stop accepting connections) and the drain deadline can wait
+/// on it.
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+    grace: Duration,
+}
+
+impl Shutdown {
+    /// Starts listening for SIGTERM and SIGINT. WASI has no signals, so
+    /// under WasmEdge this never fires and the runtime simply stops the
+    /// module.
+    pub fn listen() -> anyhow::Result<Self> {
+        let grace = grace()?;
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            signal().await;
+            tracing::info!("shutdown requested, draining in-flight requests");
+            let _ = tx.send(true);
+        });
+        Ok(Self { rx, grace })
+    }
+
+    /// Resolves once shutdown has been requested.
+    pub async fn requested(mut self) {
+        while !*self.rx.borrow() {
+            if self.rx.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Resolves when the drain deadline (`SHUTDOWN_GRACE_SECS`, default 30)
+    /// has passed since shutdown was requested.
+    pub async fn deadline(self) {
+        let grace = self.grace;
+        self.requested().await;
+        tokio::time::sleep(grace).await;
+    }
+}
+
+/// The drain deadline, `SHUTDOWN_GRACE_SECS` (default 30).
+pub fn grace() -> anyhow::Result<Duration> {
+    Ok(Duration::from_secs(env_or("SHUTDOWN_GRACE_SECS", 30)?))
+}
+
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut term = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    let mut int = signal(SignalKind::interrupt()).expect("failed to install SIGINT handler");
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = int.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() {
+    std::future::pending::<()>().await
+}
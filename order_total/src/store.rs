@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+use order_total_core::Order;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Somewhere to keep every successfully computed order, together with the
+/// rate that was applied and when, for later retrieval and auditing.
+pub trait OrderStore: Send + Sync {
+    fn save(
+        &self,
+        order: &Order,
+        tax_rate: Decimal,
+        computed_at: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+
+    /// Stored orders, most recently computed first.
+    fn list(&self, limit: u32, offset: u32) -> anyhow::Result<Vec<StoredOrder>>;
+
+    /// The latest computation stored for `order_id`.
+    fn find(&self, order_id: i32) -> anyhow::Result<Option<StoredOrder>>;
+}
+
+/// A computed order as it was stored. The applied rate is the order's own
+/// `tax_rate`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StoredOrder {
+    #[serde(flatten)]
+    pub order: Order,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// Opens the store named by `DATABASE_URL`, if any. Persistence is off when
+/// the variable is unset.
+///
+/// Supported URLs are `sqlite://path/to/orders.db` (or a bare path) and
+/// `sqlite::memory:`, and require the `sqlite` cargo feature.
+pub fn from_env() -> anyhow::Result<Option<Arc<dyn OrderStore>>> {
+    match std::env::var("DATABASE_URL") {
+        Ok(url) => open(&url).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open(url: &str) -> anyhow::Result<Arc<dyn OrderStore>> {
+    let store = sqlite::SqliteStore::open(url)?;
+    tracing::info!(url, "persisting computed orders");
+    Ok(Arc::new(store))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open(_url: &str) -> anyhow::Result<Arc<dyn OrderStore>> {
+    anyhow::bail!("DATABASE_URL is set but order_total was built without the `sqlite` feature")
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{OrderStore, StoredOrder};
+    use anyhow::Context;
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use order_total_core::Order;
+    use rusqlite::{params, Connection, OptionalExtension, Row};
+    use rust_decimal::Decimal;
+    use std::sync::Mutex;
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS orders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            order_id INTEGER NOT NULL,
+            shipping_zip TEXT NOT NULL,
+            subtotal TEXT NOT NULL,
+            tax_rate TEXT NOT NULL,
+            total TEXT NOT NULL,
+            computed_at TEXT NOT NULL,
+            body TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS orders_order_id ON orders (order_id);
+    ";
+
+    /// Amounts are stored as text so they round-trip exactly; `body` keeps
+    /// the full computed order as JSON.
+    pub struct SqliteStore {
+        conn: Mutex<Connection>,
+    }
+
+    impl SqliteStore {
+        pub fn open(url: &str) -> anyhow::Result<Self> {
+            let conn = match url.strip_prefix("sqlite:").unwrap_or(url) {
+                ":memory:" => Connection::open_in_memory(),
+                path => Connection::open(path.trim_start_matches("//")),
+            }
+            .with_context(|| format!("cannot open database {url:?}"))?;
+            conn.execute_batch(SCHEMA)
+                .context("cannot create the orders table")?;
+            Ok(Self {
+                conn: Mutex::new(conn),
+            })
+        }
+    }
+
+    impl OrderStore for SqliteStore {
+        fn save(
+            &self,
+            order: &Order,
+            tax_rate: Decimal,
+            computed_at: DateTime<Utc>,
+        ) -> anyhow::Result<()> {
+            let body = serde_json::to_string(order)?;
+            self.conn.lock().unwrap().execute(
+                "INSERT INTO orders (order_id, shipping_zip, subtotal, tax_rate, total, computed_at, body)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    order.order_id,
+                    order.shipping_zip,
+                    order.subtotal.to_string(),
+                    tax_rate.to_string(),
+                    order.total.to_string(),
+                    computed_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                    body,
+                ],
+            )?;
+            Ok(())
+        }
+
+        fn list(&self, limit: u32, offset: u32) -> anyhow::Result<Vec<StoredOrder>> {
+            let conn = self.conn.lock().unwrap();
+            let mut statement = conn.prepare(
+                "SELECT body, tax_rate, computed_at FROM orders ORDER BY id DESC LIMIT ?1 OFFSET ?2",
+            )?;
+            let rows = statement.query_map(params![limit, offset], read_row)?;
+            rows.map(|row| row?).collect()
+        }
+
+        fn find(&self, order_id: i32) -> anyhow::Result<Option<StoredOrder>> {
+            self.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT body, tax_rate, computed_at FROM orders
+                     WHERE order_id = ?1 ORDER BY id DESC LIMIT 1",
+                    params![order_id],
+                    read_row,
+                )
+                .optional()?
+                .transpose()
+        }
+    }
+
+    /// Decoding errors are kept apart from SQLite ones so that a corrupt row
+    /// is reported as such.
+    fn read_row(row: &Row<'_>) -> rusqlite::Result<anyhow::Result<StoredOrder>> {
+        Ok(decode(row.get(0)?, row.get(1)?, row.get(2)?))
+    }
+
+    /// Orders stored before the response carried `tax_rate` get it back from
+    /// the column.
+    fn decode(body: String, tax_rate: String, computed_at: String) -> anyhow::Result<StoredOrder> {
+        let mut order: Order = serde_json::from_str(&body)?;
+        if order.tax_rate.is_none() {
+            order.tax_rate = Some(tax_rate.parse()?);
+        }
+        Ok(StoredOrder {
+            order,
+            computed_at: DateTime::parse_from_rfc3339(&computed_at)?.with_timezone(&Utc),
+        })
+    }
+}
@@ -0,0 +1,141 @@
+use crate::circuit_breaker::CircuitBreaker;
+use crate::coalesce::Coalescing;
+use crate::config::{self, TaxRateSource};
+use crate::error::ComputeError;
+use crate::error_report::{self, Event, Level};
+use crate::hedge::Hedging;
+use crate::negative_cache::NegativeCache;
+use crate::rate_cache::{CachedRates, RateCache};
+use crate::retry::RetryPolicy;
+use crate::upstream::{self, is_transient, FetchError, UpstreamClient};
+use async_trait::async_trait;
+use models::{RateRequest, RateResponse};
+use order_total_core::{FallbackProvider, FixedRateProvider, RateTable};
+use std::sync::Arc;
+
+pub use order_total_core::{TaxRate, TaxRateProvider};
+
+/// What lookups of `request` are cached and coalesced under: the zip code,
+/// and `@date` when the request names the day.
+pub fn key(request: &RateRequest) -> String {
+    match request.date {
+        Some(date) => format!("{}@{date}", request.zip),
+        None => request.zip.clone(),
+    }
+}
+
+/// Builds the provider selected by `TAX_RATE_SOURCE`. Concurrent service
+/// lookups for the same zip code are coalesced into one, zip codes the
+/// service has no rate for are remembered for a while, and with
+/// `RATE_CACHE_SECS` set the rates it answers are kept in `cache`.
+pub fn from_env(
+    service_url: &str,
+    cache: &Arc<CachedRates>,
+) -> anyhow::Result<Arc<dyn TaxRateProvider>> {
+    Ok(match config::tax_rate_source()? {
+        TaxRateSource::Service => Arc::new(service(service_url, cache)?),
+        TaxRateSource::Embedded => Arc::new(load_table()?),
+        TaxRateSource::Fallback => Arc::new(FallbackProvider {
+            primary: service(service_url, cache)?,
+            fallback: load_table()?,
+        }),
+        TaxRateSource::Fixed(rate) => Arc::new(FixedRateProvider(rate)),
+    })
+}
+
+type ServiceProvider = RateCache<NegativeCache<Coalescing<HttpTaxRateProvider>>>;
+
+fn service(service_url: &str, cache: &Arc<CachedRates>) -> anyhow::Result<ServiceProvider> {
+    let provider = HttpTaxRateProvider::from_env(service_url)?;
+    let provider = NegativeCache::from_env(Coalescing::new(provider))?;
+    Ok(RateCache::new(provider, cache.clone()))
+}
+
+fn load_table() -> anyhow::Result<RateTable> {
+    let table = RateTable::load()?;
+    tracing::info!(zip_codes = table.len(), "loaded tax rate table");
+    Ok(table)
+}
+
+/// Asks the sales tax rate service, retrying transient failures and failing
+/// fast while its circuit breaker is open. With several replicas configured,
+/// each attempt tries them in order until one answers; with hedging on, a
+/// slow attempt is raced by one starting at the next replica.
+pub struct HttpTaxRateProvider {
+    urls: Vec<String>,
+    client: UpstreamClient,
+    retry: RetryPolicy,
+    breaker: CircuitBreaker,
+    hedging: Option<Hedging>,
+}
+
+impl HttpTaxRateProvider {
+    /// `urls` is one service URL or a comma separated list of replicas.
+    pub fn from_env(urls: &str) -> anyhow::Result<Self> {
+        let urls: Vec<String> = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_owned)
+            .collect();
+        if urls.is_empty() {
+            anyhow::bail!("SALES_TAX_RATE_SERVICE does not name any URL");
+        }
+        Ok(Self {
+            urls,
+            client: upstream::client_from_env()?,
+            retry: RetryPolicy::from_env()?,
+            breaker: CircuitBreaker::from_env()?,
+            hedging: Hedging::from_env()?,
+        })
+    }
+
+    /// One attempt at a lookup, hedged when configured: the hedge tries the
+    /// replicas in order from the second one, or the same service again
+    /// when there is just one.
+    async fn attempt(&self, request: &RateRequest) -> Result<String, FetchError> {
+        let Some(hedging) = &self.hedging else {
+            return upstream::fetch_rate_failover(&self.client, &self.urls, request).await;
+        };
+        let request = |start: usize| async move {
+            let mut urls = self.urls.clone();
+            urls.rotate_left(start % self.urls.len());
+            upstream::fetch_rate_failover(&self.client, &urls, request).await
+        };
+        let is_answer = |result: &Result<String, FetchError>| match result {
+            Ok(_) => true,
+            Err(err) => !is_transient(err),
+        };
+        hedging.run(request, is_answer).await
+    }
+}
+
+#[async_trait]
+impl TaxRateProvider for HttpTaxRateProvider {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        self.breaker
+            .try_acquire()
+            .map_err(ComputeError::CircuitOpen)?;
+
+        let result = self.retry.run(|| self.attempt(request), is_transient).await;
+        match &result {
+            // Says nothing about the service's health.
+            Err(FetchError::DeadlineExceeded) => {}
+            Err(err) if is_transient(err) => {
+                tracing::warn!(error = %err, "sales tax rate service unavailable");
+                error_report::capture(Event::new(
+                    Level::Warning,
+                    "UpstreamUnavailable",
+                    format!("sales tax rate service unavailable: {err}"),
+                ));
+                self.breaker.record_failure()
+            }
+            Err(err) => {
+                tracing::info!(error = %err, "no sales tax rate for zip code");
+                self.breaker.record_success()
+            }
+            Ok(_) => self.breaker.record_success(),
+        }
+        Ok(RateResponse::from_body(&result?)?)
+    }
+}
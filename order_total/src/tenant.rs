@@ -0,0 +1,216 @@
+//! Serving several brands from one deployment: a request names its brand
+//! in `X-Tenant-Id`, and is computed with that tenant's default rate,
+//! rounding, rate service and currency, with rate and response caches of its
+//! own. Its log lines and its line of the access log name the tenant.
+
+use crate::rate_cache::CachedRates;
+use anyhow::{anyhow, Context};
+use hyper::Request;
+use order_total_core::{currency, Calculator, RoundingMode, RoundingScope};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+pub const HEADER: &str = "x-tenant-id";
+
+tokio::task_local! {
+    static TENANT: Option<String>;
+}
+
+/// The tenants of `TENANTS_FILE`, a CSV with a `tenant` column and any of
+/// these, each overriding a service-wide setting for the tenant's requests
+/// where it is not empty:
+///
+/// * `default_tax_rate` - `DEFAULT_TAX_RATE`
+/// * `rate_service_url` - `SALES_TAX_RATE_SERVICE`
+/// * `rounding_mode` and `rounding_scope` - `ROUNDING_MODE` and
+///   `ROUNDING_SCOPE`
+/// * `currency` - the currency of the orders that name none, instead of
+///   `USD`
+///
+/// The file is read at startup; a reload builds the tenants' calculators
+/// anew from it and the other settings.
+pub struct Tenants {
+    tenants: HashMap<String, Tenant>,
+}
+
+/// A row of `TENANTS_FILE`.
+#[derive(Deserialize)]
+struct Row {
+    tenant: String,
+    #[serde(default)]
+    default_tax_rate: Option<String>,
+    #[serde(default)]
+    rate_service_url: Option<String>,
+    #[serde(default)]
+    rounding_mode: Option<String>,
+    #[serde(default)]
+    rounding_scope: Option<String>,
+    #[serde(default)]
+    currency: Option<String>,
+}
+
+/// What a tenant overrides, see `Tenants`.
+pub struct Settings {
+    pub default_tax_rate: Option<Decimal>,
+    pub rate_service_url: Option<String>,
+    pub rounding_mode: Option<RoundingMode>,
+    pub rounding_scope: Option<RoundingScope>,
+    pub currency: Option<String>,
+}
+
+pub struct Tenant {
+    pub settings: Settings,
+    /// Rates the tenant's lookups answered, kept apart from the other
+    /// tenants' under a Redis key prefix of its own.
+    pub rate_cache: Arc<CachedRates>,
+    calculator: RwLock<Arc<Calculator>>,
+}
+
+impl Tenants {
+    /// `None` when `TENANTS_FILE` is unset, i.e. there is one tenant and
+    /// `X-Tenant-Id` is ignored. `build` makes the calculator of a tenant,
+    /// see `reload`.
+    pub fn from_env<F>(build: F) -> anyhow::Result<Option<Self>>
+    where
+        F: Fn(&Settings, &Arc<CachedRates>) -> anyhow::Result<Calculator>,
+    {
+        let Ok(path) = std::env::var("TENANTS_FILE") else {
+            return Ok(None);
+        };
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("cannot open TENANTS_FILE {path:?}"))?;
+        let tenants = Self::from_csv(file, build)
+            .with_context(|| format!("invalid TENANTS_FILE {path:?}"))?;
+        tracing::info!(tenants = tenants.tenants.len(), "tenants loaded");
+        Ok(Some(tenants))
+    }
+
+    fn from_csv<F>(reader: impl std::io::Read, build: F) -> anyhow::Result<Self>
+    where
+        F: Fn(&Settings, &Arc<CachedRates>) -> anyhow::Result<Calculator>,
+    {
+        let mut tenants = HashMap::new();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        for (index, row) in reader.deserialize::<Row>().enumerate() {
+            let line = index + 2;
+            let row = row.with_context(|| format!("line {line}"))?;
+            let id = row.tenant.clone();
+            if id.is_empty() {
+                anyhow::bail!("line {line}: missing tenant");
+            }
+            let settings = Settings::parse(row).with_context(|| format!("line {line}"))?;
+            let rate_cache = Arc::new(CachedRates::for_tenant(&id)?);
+            let calculator = build(&settings, &rate_cache)
+                .with_context(|| format!("line {line}: tenant {id:?}"))?;
+            let tenant = Tenant {
+                settings,
+                rate_cache,
+                calculator: RwLock::new(Arc::new(calculator)),
+            };
+            if tenants.insert(id, tenant).is_some() {
+                anyhow::bail!("line {line}: duplicate tenant");
+            }
+        }
+        Ok(Self { tenants })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Tenant> {
+        self.tenants.get(id)
+    }
+
+    /// Builds every tenant's calculator anew with `build`, and takes its
+    /// cache settings anew; the tenants keep the old ones when any fails.
+    /// Nothing is swapped before every tenant's new settings are valid.
+    pub fn reload<F>(&self, build: F) -> anyhow::Result<()>
+    where
+        F: Fn(&Settings, &Arc<CachedRates>) -> anyhow::Result<Calculator>,
+    {
+        let mut reloaded = Vec::with_capacity(self.tenants.len());
+        for (id, tenant) in &self.tenants {
+            let calculator = build(&tenant.settings, &tenant.rate_cache)
+                .and_then(|calculator| Ok((calculator, tenant.rate_cache.limits()?)))
+                .with_context(|| format!("tenant {id:?}"))?;
+            reloaded.push((tenant, calculator));
+        }
+        for (tenant, (calculator, limits)) in reloaded {
+            tenant.rate_cache.reconfigure(limits);
+            *tenant.calculator.write().unwrap() = Arc::new(calculator);
+        }
+        Ok(())
+    }
+}
+
+impl Settings {
+    fn parse(row: Row) -> anyhow::Result<Self> {
+        let present = |value: Option<String>| value.filter(|value| !value.is_empty());
+        let default_tax_rate = match present(row.default_tax_rate) {
+            Some(rate) => Some(
+                rate.parse::<Decimal>()
+                    .ok()
+                    .filter(|rate| !rate.is_sign_negative())
+                    .ok_or_else(|| anyhow!("invalid default_tax_rate {rate:?}"))?,
+            ),
+            None => None,
+        };
+        let currency = present(row.currency).map(|code| currency::normalize(&code));
+        if let Some(code) = &currency {
+            currency::minor_units(code).ok_or_else(|| anyhow!("unknown currency {code:?}"))?;
+        }
+        Ok(Self {
+            default_tax_rate,
+            rate_service_url: present(row.rate_service_url),
+            rounding_mode: present(row.rounding_mode)
+                .map(|mode| mode.parse())
+                .transpose()?,
+            rounding_scope: present(row.rounding_scope)
+                .map(|scope| scope.parse())
+                .transpose()?,
+            currency,
+        })
+    }
+
+    /// Overrides the service-wide settings of `calculator` with the
+    /// tenant's.
+    pub fn apply(&self, calculator: &mut Calculator) {
+        if let Some(rate) = self.default_tax_rate {
+            calculator.default_tax_rate = Some(rate);
+        }
+        if let Some(mode) = self.rounding_mode {
+            calculator.rounding.mode = mode;
+        }
+        if let Some(scope) = self.rounding_scope {
+            calculator.rounding.scope = scope;
+        }
+    }
+}
+
+impl Tenant {
+    pub fn calculator(&self) -> Arc<Calculator> {
+        self.calculator.read().unwrap().clone()
+    }
+}
+
+/// The tenant `req` names in `X-Tenant-Id`, if any.
+pub fn from_request<B>(req: &Request<B>) -> Option<String> {
+    req.headers()
+        .get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_owned)
+}
+
+/// Runs `future` with `tenant` as the current tenant.
+pub async fn scope<F: Future>(tenant: Option<String>, future: F) -> F::Output {
+    TENANT.scope(tenant, future).await
+}
+
+/// The tenant of the request being handled, if it names one.
+pub fn current() -> Option<String> {
+    TENANT.try_with(Clone::clone).ok().flatten()
+}
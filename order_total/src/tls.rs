@@ -0,0 +1,220 @@
+//! TLS towards the sales tax rate service, and the pieces the HTTPS
+//! listener of `https` shares with it.
+
+use anyhow::{anyhow, Context};
+#[cfg(feature = "wasmedge")]
+use hyper::client::connect::{Connected, Connection, HttpConnector};
+use hyper::Uri;
+#[cfg(feature = "native")]
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+#[cfg(feature = "native")]
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context as TaskContext, Poll};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::{client, TlsConnector};
+use tower_service::Service;
+
+/// The TLS settings for calling the sales tax rate service, from PEM files:
+///
+/// * `TAX_SERVICE_CA_BUNDLE` - the CAs the service's certificate must chain
+///   to; there are no built-in roots, so it is required
+/// * `TAX_SERVICE_CLIENT_CERT` / `TAX_SERVICE_CLIENT_KEY` - the certificate
+///   chain and private key presented to the service (mutual TLS)
+///
+/// `None` when none is set, i.e. the default client is used.
+pub fn upstream_from_env() -> anyhow::Result<Option<TlsConnector>> {
+    let ca_bundle = std::env::var("TAX_SERVICE_CA_BUNDLE").ok();
+    let cert = std::env::var("TAX_SERVICE_CLIENT_CERT").ok();
+    let key = std::env::var("TAX_SERVICE_CLIENT_KEY").ok();
+    if ca_bundle.is_none() && cert.is_none() && key.is_none() {
+        return Ok(None);
+    }
+    let ca_bundle = ca_bundle
+        .ok_or_else(|| anyhow!("TAX_SERVICE_CA_BUNDLE is required with TAX_SERVICE_CLIENT_CERT"))?;
+
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs("TAX_SERVICE_CA_BUNDLE", &ca_bundle)? {
+        roots
+            .add(cert)
+            .with_context(|| format!("invalid CA certificate in {ca_bundle:?}"))?;
+    }
+    let provider = Arc::new(rustls_rustcrypto::provider());
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots);
+    let config = match (cert, key) {
+        (None, None) => builder.with_no_client_auth(),
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(
+                load_certs("TAX_SERVICE_CLIENT_CERT", &cert)?,
+                load_key("TAX_SERVICE_CLIENT_KEY", &key)?,
+            )
+            .context(
+                "TAX_SERVICE_CLIENT_KEY does not match the certificate in TAX_SERVICE_CLIENT_CERT",
+            )?,
+        _ => {
+            anyhow::bail!("TAX_SERVICE_CLIENT_CERT and TAX_SERVICE_CLIENT_KEY must be set together")
+        }
+    };
+    Ok(Some(TlsConnector::from(Arc::new(config))))
+}
+
+/// Connects hyper's client over TCP and then TLS with the upstream settings.
+#[derive(Clone)]
+pub struct UpstreamConnector {
+    pub http: HttpConnector,
+    pub tls: TlsConnector,
+}
+
+pub type UpstreamConn = Compat<client::TlsStream<Compat<TcpStream>>>;
+
+/// A connection as hyper's client takes it: hyper 1.x has I/O traits of its
+/// own, which `TokioIo` adapts tokio's to.
+#[cfg(feature = "wasmedge")]
+type Io<T> = T;
+#[cfg(feature = "native")]
+type Io<T> = TokioIo<T>;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+impl Service<Uri> for UpstreamConnector {
+    type Response = Io<UpstreamConn>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Io<UpstreamConn>, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        if uri.scheme_str() != Some("https") {
+            return Box::pin(async move {
+                Err(format!("{uri} is not an https URL, but TAX_SERVICE_CA_BUNDLE is set").into())
+            });
+        }
+        let tls = self.tls.clone();
+        let host = uri
+            .host()
+            .unwrap_or_default()
+            .trim_matches(['[', ']'])
+            .to_owned();
+        let connecting = self.http.call(uri);
+        Box::pin(async move {
+            let name = ServerName::try_from(host)?;
+            let tcp = connecting.await?;
+            #[cfg(feature = "native")]
+            let tcp = tcp.into_inner();
+            let conn = Compat(tls.connect(name, Compat(tcp)).await?);
+            #[cfg(feature = "native")]
+            let conn = TokioIo::new(conn);
+            Ok(conn)
+        })
+    }
+}
+
+impl Connection for UpstreamConn {
+    fn connected(&self) -> Connected {
+        (self.0).get_ref().0 .0.connected()
+    }
+}
+
+/// Reads a PEM certificate chain; `name` is the variable it was configured
+/// in, for the error messages.
+pub fn load_certs(name: &str, path: &str) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read(path).with_context(|| format!("cannot read {name} {path:?}"))?;
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid {name} {path:?}"))?;
+    if certs.is_empty() {
+        anyhow::bail!("{name} {path:?} contains no certificate");
+    }
+    Ok(certs)
+}
+
+pub fn load_key(name: &str, path: &str) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let pem = std::fs::read(path).with_context(|| format!("cannot read {name} {path:?}"))?;
+    rustls_pemfile::private_key(&mut &pem[..])
+        .with_context(|| format!("invalid {name} {path:?}"))?
+        .ok_or_else(|| anyhow!("{name} {path:?} contains no private key"))
+}
+
+/// Bridges the I/O traits of `tokio_wasi`, which hyper uses, and those of
+/// tokio proper, which `tokio_rustls` uses. Both have the same shape, so
+/// this only converts the read buffers. In a native build both are tokio
+/// proper and this is a plain wrapper.
+pub struct Compat<T>(pub T);
+
+#[cfg(not(feature = "wasmedge"))]
+use tokio as tokio_proper;
+
+impl<T: tokio::io::AsyncRead + Unpin> tokio_proper::io::AsyncRead for Compat<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut tokio_proper::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut inner = tokio::io::ReadBuf::new(buf.initialize_unfilled());
+        ready!(Pin::new(&mut self.0).poll_read(cx, &mut inner))?;
+        let read = inner.filled().len();
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: tokio::io::AsyncWrite + Unpin> tokio_proper::io::AsyncWrite for Compat<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "wasmedge")]
+impl<T: tokio_proper::io::AsyncRead + Unpin> tokio::io::AsyncRead for Compat<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut inner = tokio_proper::io::ReadBuf::new(buf.initialize_unfilled());
+        ready!(Pin::new(&mut self.0).poll_read(cx, &mut inner))?;
+        let read = inner.filled().len();
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "wasmedge")]
+impl<T: tokio_proper::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for Compat<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,97 @@
+//! Serving HTTP on a Unix domain socket, for a proxy on the same host:
+//! local traffic skips the TCP stack and uses no ephemeral ports. Native
+//! Unix builds only; WASI has no Unix sockets.
+
+use anyhow::Context;
+use std::path::PathBuf;
+
+/// The socket to listen on besides (or instead of) TCP:
+///
+/// * `LISTEN_SOCKET` - its path, e.g. `/run/order_total.sock` (unset by default)
+/// * `LISTEN_SOCKET_MODE` - octal permissions of the socket file, e.g. `660` (default: as the umask leaves them)
+///
+/// A socket file left behind by an earlier run is replaced; any other file
+/// at the path is an error. The file is removed again on shutdown.
+pub struct UnixSocket {
+    pub path: PathBuf,
+    // `from_env` refuses a socket where there are no Unix sockets to bind.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    mode: Option<u32>,
+}
+
+impl UnixSocket {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(path) = std::env::var_os("LISTEN_SOCKET").filter(|path| !path.is_empty()) else {
+            return Ok(None);
+        };
+        if cfg!(not(unix)) {
+            anyhow::bail!("LISTEN_SOCKET is set, but this platform has no Unix domain sockets");
+        }
+        let mode = match std::env::var("LISTEN_SOCKET_MODE") {
+            Ok(mode) => Some(
+                u32::from_str_radix(mode.trim(), 8)
+                    .ok()
+                    .filter(|&mode| mode <= 0o777)
+                    .with_context(|| {
+                        format!("invalid LISTEN_SOCKET_MODE {mode:?}: expected octal permissions such as 660")
+                    })?,
+            ),
+            Err(_) => None,
+        };
+        Ok(Some(Self {
+            path: path.into(),
+            mode,
+        }))
+    }
+
+    /// Removes the socket file, once the server is done with it.
+    pub fn remove(&self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            tracing::warn!(path = %self.path.display(), error = %err, "could not remove the socket file");
+        }
+    }
+}
+
+#[cfg(unix)]
+mod listener {
+    use super::*;
+    use crate::connection::Connections;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::path::Path;
+    use tokio::net::{UnixListener, UnixStream};
+
+    impl UnixSocket {
+        /// The socket at `path`, with the permissions the umask leaves.
+        pub fn at(path: PathBuf) -> Self {
+            Self { path, mode: None }
+        }
+
+        /// Binds the socket, replacing a stale socket file.
+        pub fn incoming(&self) -> anyhow::Result<Connections<UnixStream>> {
+            replace_stale(&self.path)?;
+            let listener = UnixListener::bind(&self.path)
+                .with_context(|| format!("cannot listen on {}", self.path.display()))?;
+            if let Some(mode) = self.mode {
+                std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(mode))
+                    .with_context(|| format!("cannot set the mode of {}", self.path.display()))?;
+            }
+            Ok(Box::pin(futures_util::stream::poll_fn(move |cx| {
+                listener
+                    .poll_accept(cx)
+                    .map(|accepted| Some(accepted.map(|(stream, _)| stream)))
+            })))
+        }
+    }
+
+    fn replace_stale(path: &Path) -> anyhow::Result<()> {
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)
+                .with_context(|| format!("cannot replace the socket file {}", path.display())),
+            Ok(_) => anyhow::bail!(
+                "LISTEN_SOCKET {} exists and is not a socket",
+                path.display()
+            ),
+            Err(_) => Ok(()),
+        }
+    }
+}
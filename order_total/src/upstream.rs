@@ -0,0 +1,241 @@
+use crate::access_log;
+use crate::body::{Body, BoxError};
+use crate::config::env_or;
+use crate::deadline;
+use crate::request_id;
+use crate::telemetry;
+use crate::tls::{self, UpstreamConnector};
+#[cfg(feature = "wasmedge")]
+use hyper::client::{Client, HttpConnector};
+use hyper::header::{HeaderMap, HeaderValue, ACCEPT};
+use hyper::StatusCode;
+#[cfg(feature = "native")]
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use models::{RateRequest, RATE_JSON};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The HTTP client shared by all requests to the sales tax rate service, so
+/// connections are pooled and kept alive between orders. reqwest_wasi cannot
+/// present a client certificate, so TLS towards the service (see
+/// `tls::upstream_from_env`) goes through hyper's client with a rustls
+/// connector instead.
+pub enum UpstreamClient {
+    Plain(reqwest::Client),
+    Tls {
+        client: Box<Client<UpstreamConnector, Body>>,
+        timeout: Duration,
+    },
+}
+
+/// Builds the client from:
+///
+/// * `TAX_SERVICE_POOL_MAX_IDLE` - idle connections kept per host (default 32)
+/// * `TAX_SERVICE_POOL_IDLE_SECS` - how long an idle connection is kept (default 90)
+/// * `TAX_SERVICE_TCP_KEEPALIVE_SECS` - TCP keep-alive interval, 0 to disable (default 60)
+/// * `TAX_SERVICE_CONNECT_TIMEOUT_MS` - bound on establishing a connection (default 2000)
+/// * `TAX_SERVICE_TIMEOUT_MS` - bound on a whole lookup, connect included (default 5000)
+pub fn client_from_env() -> anyhow::Result<UpstreamClient> {
+    let keepalive = env_or("TAX_SERVICE_TCP_KEEPALIVE_SECS", 60)?;
+    let keepalive = (keepalive > 0).then(|| Duration::from_secs(keepalive));
+    let connect_timeout = Duration::from_millis(env_or("TAX_SERVICE_CONNECT_TIMEOUT_MS", 2000)?);
+    let timeout = Duration::from_millis(env_or("TAX_SERVICE_TIMEOUT_MS", 5000)?);
+    let max_idle = env_or("TAX_SERVICE_POOL_MAX_IDLE", 32)?;
+    let idle_timeout = Duration::from_secs(env_or("TAX_SERVICE_POOL_IDLE_SECS", 90)?);
+
+    if let Some(tls) = tls::upstream_from_env()? {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(Some(connect_timeout));
+        http.set_keepalive(keepalive);
+        #[cfg(feature = "wasmedge")]
+        let mut builder = Client::builder();
+        #[cfg(feature = "native")]
+        let mut builder = Client::builder(hyper_util::rt::TokioExecutor::new());
+        #[cfg(feature = "native")]
+        builder.pool_timer(hyper_util::rt::TokioTimer::new());
+        let client = builder
+            .pool_max_idle_per_host(max_idle)
+            .pool_idle_timeout(idle_timeout)
+            .build(UpstreamConnector { http, tls });
+        return Ok(UpstreamClient::Tls {
+            client: Box::new(client),
+            timeout,
+        });
+    }
+
+    let client = reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .timeout(timeout)
+        .pool_max_idle_per_host(max_idle)
+        .pool_idle_timeout(idle_timeout)
+        .tcp_keepalive(keepalive)
+        .build()?;
+    Ok(UpstreamClient::Plain(client))
+}
+
+/// Why a lookup failed, whichever client made it.
+#[derive(Debug)]
+pub enum FetchError {
+    Reqwest(reqwest::Error),
+    Status(StatusCode),
+    Timeout,
+    /// The request's deadline passed before the service answered.
+    DeadlineExceeded,
+    Transport(BoxError),
+    InvalidUrl(String),
+}
+
+impl FetchError {
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Reqwest(err) => err.status(),
+            Self::Status(status) => Some(*status),
+            Self::Timeout | Self::DeadlineExceeded | Self::Transport(_) | Self::InvalidUrl(_) => {
+                None
+            }
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::Reqwest(err) => err.is_timeout(),
+            Self::Timeout => true,
+            Self::Status(_) | Self::DeadlineExceeded | Self::Transport(_) | Self::InvalidUrl(_) => {
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reqwest(err) => err.fmt(f),
+            Self::Status(status) => write!(f, "HTTP status {status}"),
+            Self::Timeout => f.write_str("request timed out"),
+            Self::DeadlineExceeded => f.write_str("the request deadline passed"),
+            Self::Transport(err) => err.fmt(f),
+            Self::InvalidUrl(url) => write!(f, "invalid URL {url:?}"),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// Asks the sales tax rate service for the rate of `zip`, with its
+/// components where the service knows them, returning the raw response
+/// body. Each attempt is its own client span, and its `traceparent`
+/// is sent along so the service's spans join the same trace.
+///
+/// Within a request with a deadline, the attempt gets no longer than the
+/// time left, see `deadline`. Its time counts towards the request's
+/// upstream latency in the access log.
+#[tracing::instrument(name = "tax_rate_lookup", skip_all, fields(otel.kind = "client", %url, zip))]
+pub async fn fetch_rate(
+    client: &UpstreamClient,
+    url: &str,
+    request: &RateRequest,
+) -> Result<String, FetchError> {
+    let started = Instant::now();
+    let fetched = match deadline::remaining() {
+        Some(remaining) => tokio::time::timeout(remaining, fetch(client, url, request))
+            .await
+            .map_err(|_| FetchError::DeadlineExceeded)
+            .and_then(|fetched| fetched),
+        None => fetch(client, url, request).await,
+    };
+    access_log::add_upstream(started.elapsed());
+    fetched
+}
+
+async fn fetch(
+    client: &UpstreamClient,
+    url: &str,
+    request: &RateRequest,
+) -> Result<String, FetchError> {
+    let url = match request.query() {
+        Some(query) => format!("{url}?{query}"),
+        None => url.to_owned(),
+    };
+    let mut headers = telemetry::propagation_headers();
+    headers.insert(ACCEPT, HeaderValue::from_static(RATE_JSON));
+    if let Some(id) = request_id::current().and_then(|id| id.parse().ok()) {
+        headers.insert(request_id::HEADER, id);
+    }
+    match client {
+        UpstreamClient::Plain(client) => {
+            let send = async {
+                let response = client
+                    .post(&url)
+                    .headers(headers)
+                    .body(request.to_body())
+                    .send()
+                    .await?;
+                response.error_for_status()?.text().await
+            };
+            send.await.map_err(FetchError::Reqwest)
+        }
+        UpstreamClient::Tls { client, timeout } => {
+            tokio::time::timeout(*timeout, fetch_with_hyper(client, &url, headers, request))
+                .await
+                .map_err(|_| FetchError::Timeout)?
+        }
+    }
+}
+
+async fn fetch_with_hyper(
+    client: &Client<UpstreamConnector, Body>,
+    url: &str,
+    headers: HeaderMap,
+    rate_request: &RateRequest,
+) -> Result<String, FetchError> {
+    let mut request = hyper::Request::post(url)
+        .body(Body::from(rate_request.to_body()))
+        .map_err(|_| FetchError::InvalidUrl(url.to_owned()))?;
+    request.headers_mut().extend(headers);
+    let response = client
+        .request(request)
+        .await
+        .map_err(|err| FetchError::Transport(err.into()))?;
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        return Err(FetchError::Status(status));
+    }
+    let body = crate::body::to_bytes(response.into_body())
+        .await
+        .map_err(|err| FetchError::Transport(err.into()))?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Asks each replica in `urls` in turn, moving on to the next one after a
+/// connection problem or a 5xx. Other answers, such as the 404 for an
+/// unknown zip code, are final.
+pub async fn fetch_rate_failover(
+    client: &UpstreamClient,
+    urls: &[String],
+    request: &RateRequest,
+) -> Result<String, FetchError> {
+    let (last, others) = urls.split_last().expect("at least one service URL");
+    for url in others {
+        match fetch_rate(client, url, request).await {
+            Err(err) if is_transient(&err) => {
+                tracing::warn!(%url, error = %err, "sales tax rate replica failed, trying the next one");
+            }
+            result => return result,
+        }
+    }
+    fetch_rate(client, last, request).await
+}
+
+/// Connection problems and 5xx responses are worth retrying; a 4xx (such as
+/// the 404 returned for an unknown zip code) will not change on a retry, and
+/// neither will a passed deadline.
+pub fn is_transient(err: &FetchError) -> bool {
+    let invalid = match err {
+        FetchError::Reqwest(err) => err.is_builder(),
+        FetchError::InvalidUrl(_) | FetchError::DeadlineExceeded => true,
+        _ => false,
+    };
+    !invalid && err.status().is_none_or(|status| status.is_server_error())
+}
@@ -0,0 +1,298 @@
+use crate::config::env_or;
+use crate::tenant;
+use crate::webhook::Webhooks;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+tokio::task_local! {
+    static BATCH: Arc<Batch>;
+}
+
+/// Counts, by the hour, the requests each tenant and client made and the
+/// orders they computed, so that the teams sharing the service can be billed
+/// for what they use. The client is the API key's, or the bearer token's
+/// subject.
+///
+/// * `USAGE_RETENTION_HOURS` - how long the hourly counts are kept for
+///   `GET /admin/usage` (default 744, 31 days)
+/// * `USAGE_SINK_URL` - where to POST the usage of every period as a
+///   `usage.reported` event, signed and retried like a webhook; unset,
+///   nothing is sent
+/// * `USAGE_REPORT_SECS` - how long a period lasts (default 3600)
+///
+/// A request counts when it computes at least one order, and its batch is
+/// the number of orders it computed; the orders of a job count as one batch
+/// of their own once it has run. Counts live in the memory of the replica,
+/// so they are lost on restart; a sink keeps them.
+pub struct Usage {
+    retention: TimeDelta,
+    hours: Mutex<BTreeMap<Key, Counts>>,
+    sink: Option<Sink>,
+}
+
+struct Sink {
+    url: String,
+    period: Duration,
+    webhooks: Webhooks,
+    /// The counts since the last report, and when it was sent.
+    unreported: Mutex<(DateTime<Utc>, HashMap<Client, Counts>)>,
+}
+
+/// The tenant and client usage is counted for.
+type Client = (Option<String>, Option<String>);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    hour: DateTime<Utc>,
+    client: Client,
+}
+
+#[derive(Clone, Copy, Default, Serialize)]
+struct Counts {
+    requests: u64,
+    computations: u64,
+    largest_batch: u64,
+}
+
+/// What a request computed, see `measure`.
+pub struct Batch {
+    client: Mutex<Option<String>>,
+    computations: AtomicU64,
+}
+
+/// The query of `GET /admin/usage`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UsageRange {
+    /// Only the hours starting at or after this time (RFC 3339).
+    from: Option<DateTime<Utc>>,
+    /// Only the hours starting before this time (RFC 3339).
+    to: Option<DateTime<Utc>>,
+}
+
+/// The usage of a range of time, per tenant and client and in total.
+#[derive(Serialize)]
+pub struct UsageReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<DateTime<Utc>>,
+    usage: Vec<UsageLine>,
+    total: Summary,
+}
+
+#[derive(Serialize)]
+struct UsageLine {
+    tenant: Option<String>,
+    client: Option<String>,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    #[serde(flatten)]
+    counts: Counts,
+    /// Orders per request, on average.
+    mean_batch: f64,
+}
+
+impl Usage {
+    /// `webhooks` delivers the reports to `USAGE_SINK_URL`, which therefore
+    /// needs `WEBHOOK_SECRET` to sign them.
+    pub fn from_env(webhooks: &Webhooks) -> anyhow::Result<Self> {
+        let sink = match std::env::var("USAGE_SINK_URL") {
+            Ok(url) if !url.is_empty() => {
+                reqwest::Url::parse(&url)
+                    .map_err(|err| anyhow::anyhow!("invalid USAGE_SINK_URL {url:?}: {err}"))?;
+                if !webhooks.signs() {
+                    anyhow::bail!("USAGE_SINK_URL needs WEBHOOK_SECRET to sign the reports");
+                }
+                Some(Sink {
+                    url,
+                    period: Duration::from_secs(env_or("USAGE_REPORT_SECS", 3600)?.max(1)),
+                    webhooks: webhooks.clone(),
+                    unreported: Mutex::new((Utc::now(), HashMap::new())),
+                })
+            }
+            _ => None,
+        };
+        Ok(Self {
+            retention: TimeDelta::hours(env_or("USAGE_RETENTION_HOURS", 744)?),
+            hours: Mutex::new(BTreeMap::new()),
+            sink,
+        })
+    }
+
+    /// Counts `batch` for the current tenant, unless it computed nothing.
+    pub fn record(&self, batch: &Batch) {
+        let computations = batch.computations.load(Ordering::Relaxed);
+        if computations == 0 {
+            return;
+        }
+        let now = Utc::now();
+        let client = (tenant::current(), batch.client.lock().unwrap().clone());
+        if let Some(sink) = &self.sink {
+            let mut unreported = sink.unreported.lock().unwrap();
+            unreported
+                .1
+                .entry(client.clone())
+                .or_default()
+                .add(computations);
+        }
+        let hour = now.duration_trunc(TimeDelta::hours(1)).unwrap();
+        let mut hours = self.hours.lock().unwrap();
+        while hours
+            .first_key_value()
+            .is_some_and(|(key, _)| key.hour + self.retention <= hour)
+        {
+            hours.pop_first();
+        }
+        hours
+            .entry(Key { hour, client })
+            .or_default()
+            .add(computations);
+    }
+
+    /// The usage of the hours `range` covers, every hour kept when it is
+    /// left open.
+    pub fn report(&self, range: &UsageRange) -> UsageReport {
+        let hours = self.hours.lock().unwrap();
+        let mut clients: BTreeMap<&Client, Counts> = BTreeMap::new();
+        for (key, counts) in hours.iter().filter(|(key, _)| {
+            range.from.is_none_or(|from| key.hour >= from)
+                && range.to.is_none_or(|to| key.hour < to)
+        }) {
+            clients.entry(&key.client).or_default().merge(counts);
+        }
+        let usage = clients
+            .into_iter()
+            .map(|(client, counts)| UsageLine::new(client, counts))
+            .collect();
+        UsageReport::new(range.from, range.to, usage)
+    }
+
+    /// Sends the usage since the last report to `USAGE_SINK_URL`, if there
+    /// is a sink and anything to report; a period with nothing to report is
+    /// part of the next one.
+    pub fn send_report(&self) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        let now = Utc::now();
+        let (from, clients) = {
+            let mut unreported = sink.unreported.lock().unwrap();
+            if unreported.1.is_empty() {
+                return;
+            }
+            std::mem::replace(&mut *unreported, (now, HashMap::new()))
+        };
+        let mut usage: Vec<_> = clients
+            .iter()
+            .map(|(client, counts)| UsageLine::new(client, *counts))
+            .collect();
+        usage.sort_by(|a, b| (&a.tenant, &a.client).cmp(&(&b.tenant, &b.client)));
+        let report = UsageReport::new(Some(from), Some(now), usage);
+        let body = serde_json::to_vec(&report).unwrap();
+        sink.webhooks
+            .spawn(sink.url.clone(), "usage.reported", body);
+    }
+}
+
+/// Sends the usage of `app` to its sink every `USAGE_REPORT_SECS`, see
+/// `App::report_usage`.
+pub fn report_periodically(app: Arc<crate::App>) {
+    let Some(period) = app.usage.sink.as_ref().map(|sink| sink.period) else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(period);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            app.report_usage();
+        }
+    });
+}
+
+impl Counts {
+    fn add(&mut self, computations: u64) {
+        self.requests += 1;
+        self.computations += computations;
+        self.largest_batch = self.largest_batch.max(computations);
+    }
+
+    fn merge(&mut self, other: &Counts) {
+        self.requests += other.requests;
+        self.computations += other.computations;
+        self.largest_batch = self.largest_batch.max(other.largest_batch);
+    }
+}
+
+impl Summary {
+    fn new(counts: Counts) -> Self {
+        Self {
+            counts,
+            mean_batch: counts.computations as f64 / counts.requests.max(1) as f64,
+        }
+    }
+}
+
+impl UsageLine {
+    fn new((tenant, client): &Client, counts: Counts) -> Self {
+        Self {
+            tenant: tenant.clone(),
+            client: client.clone(),
+            summary: Summary::new(counts),
+        }
+    }
+}
+
+impl UsageReport {
+    fn new(from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>, usage: Vec<UsageLine>) -> Self {
+        let mut total = Counts::default();
+        for line in &usage {
+            total.merge(&line.summary.counts);
+        }
+        Self {
+            from,
+            to,
+            usage,
+            total: Summary::new(total),
+        }
+    }
+}
+
+/// Runs `future` as one batch, on behalf of `client` unless `identify`
+/// names another, and returns what it computed for `Usage::record`.
+pub async fn measure<F: Future>(client: Option<String>, future: F) -> (F::Output, Arc<Batch>) {
+    let batch = Arc::new(Batch {
+        client: Mutex::new(client),
+        computations: AtomicU64::new(0),
+    });
+    let output = BATCH.scope(batch.clone(), future).await;
+    (output, batch)
+}
+
+/// Names the client of the current batch, once its credentials are checked.
+pub fn identify(client: &str) {
+    let _ = BATCH.try_with(|batch| *batch.client.lock().unwrap() = Some(client.to_owned()));
+}
+
+/// The client of the current batch, if it is known.
+pub fn client() -> Option<String> {
+    BATCH
+        .try_with(|batch| batch.client.lock().unwrap().clone())
+        .ok()
+        .flatten()
+}
+
+/// Counts one order computed in the current batch, if any.
+pub fn count() {
+    let _ = BATCH.try_with(|batch| batch.computations.fetch_add(1, Ordering::Relaxed));
+}
@@ -0,0 +1,48 @@
+//! `GET /version`: which build is running, for operators comparing
+//! environments. `build.rs` captures the commit, time and target.
+
+use serde::Serialize;
+
+/// The build's version, commit, time (RFC 3339), cargo features, target
+/// triple and profile.
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    version: &'static str,
+    git_sha: &'static str,
+    built_at: String,
+    features: Vec<&'static str>,
+    target: &'static str,
+    profile: &'static str,
+}
+
+/// The cargo features, by whether the build enabled them.
+const FEATURES: [(&str, bool); 5] = [
+    ("wasmedge", cfg!(feature = "wasmedge")),
+    ("native", cfg!(feature = "native")),
+    ("sqlite", cfg!(feature = "sqlite")),
+    ("kafka", cfg!(feature = "kafka")),
+    ("nats", cfg!(feature = "nats")),
+];
+
+fn info() -> BuildInfo {
+    let built_at = env!("ORDER_TOTAL_BUILT_AT")
+        .parse()
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .unwrap_or_default();
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("ORDER_TOTAL_GIT_SHA"),
+        built_at: built_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        features: FEATURES
+            .into_iter()
+            .filter_map(|(name, enabled)| enabled.then_some(name))
+            .collect(),
+        target: env!("ORDER_TOTAL_TARGET"),
+        profile: env!("ORDER_TOTAL_PROFILE"),
+    }
+}
+
+pub fn json() -> String {
+    crate::pretty::to_string(&info()).unwrap()
+}
@@ -0,0 +1,129 @@
+//! Answering one request handed over as CGI, the way Spin's `wagi`
+//! executor and other WAGI hosts run a WASI module: the method, path and
+//! headers come in environment variables and the body on standard input,
+//! and the response goes to standard output, without listening on a
+//! socket.
+//!
+//! The request goes through the same `middleware` stack as one served over
+//! a socket.
+
+use crate::body::Body;
+use crate::{middleware, App};
+use anyhow::Context;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::http::response::Parts;
+use hyper::Request;
+use std::io::{Read, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// The CGI variables that carry headers without the `HTTP_` prefix.
+const UNPREFIXED_HEADERS: [(&str, &str); 2] = [
+    ("CONTENT_TYPE", "content-type"),
+    ("CONTENT_LENGTH", "content-length"),
+];
+
+/// Reads the request from the environment and standard input, answers it
+/// and writes the response; exits 1 when the response is a server error.
+pub async fn run() -> anyhow::Result<ExitCode> {
+    let app = Arc::new(App::from_env()?);
+    let req = request(std::env::vars(), std::io::stdin().lock())?;
+    let response = middleware::stack(&app).call(req).await?;
+    let failed = response.status().is_server_error();
+    let (parts, body) = response.into_parts();
+    let body = crate::body::to_bytes(body)
+        .await
+        .context("could not read the response body")?;
+    write(&parts, &body, &mut std::io::stdout().lock())?;
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// The request the CGI `vars` describe, with the body read from `body`:
+/// `CONTENT_LENGTH` bytes, or everything when it is unset.
+fn request(
+    vars: impl Iterator<Item = (String, String)>,
+    mut body: impl Read,
+) -> anyhow::Result<Request<Body>> {
+    let mut builder = Request::builder();
+    let (mut method, mut script, mut path_info, mut query) = (None, None, None, None);
+    let mut length = None;
+    for (name, value) in vars {
+        match name.as_str() {
+            "REQUEST_METHOD" => method = Some(value),
+            "SCRIPT_NAME" => script = Some(value),
+            "PATH_INFO" => path_info = Some(value),
+            "QUERY_STRING" => query = Some(value).filter(|query| !query.is_empty()),
+            _ => {
+                if name == "CONTENT_LENGTH" {
+                    length = value.trim().parse::<u64>().ok();
+                }
+                if let Some(header) = header_name(&name) {
+                    if let Ok(value) = HeaderValue::from_str(&value) {
+                        builder = builder.header(header, value);
+                    }
+                }
+            }
+        }
+    }
+    let path = format!(
+        "{}{}",
+        script.unwrap_or_default(),
+        path_info.unwrap_or_default()
+    );
+    let path = if path.is_empty() {
+        "/".to_owned()
+    } else {
+        path
+    };
+    let uri = match query {
+        Some(query) => format!("{path}?{query}"),
+        None => path,
+    };
+    let mut bytes = Vec::new();
+    match length {
+        Some(length) => body.take(length).read_to_end(&mut bytes),
+        None => body.read_to_end(&mut bytes),
+    }
+    .context("could not read the request body from standard input")?;
+    builder
+        .method(method.as_deref().unwrap_or("GET"))
+        .uri(uri)
+        .body(Body::from(bytes))
+        .context("the CGI variables do not make a valid request")
+}
+
+/// The header a CGI variable carries, if any: `HTTP_X_REQUEST_ID` is
+/// `x-request-id`.
+fn header_name(var: &str) -> Option<HeaderName> {
+    let name = match UNPREFIXED_HEADERS.iter().find(|(cgi, _)| *cgi == var) {
+        Some((_, header)) => (*header).to_owned(),
+        None => var.strip_prefix("HTTP_")?.replace('_', "-"),
+    };
+    HeaderName::from_bytes(name.to_ascii_lowercase().as_bytes()).ok()
+}
+
+/// Writes a response as CGI does: a `Status` header, the response headers,
+/// a blank line and the body.
+fn write(parts: &Parts, body: &[u8], out: &mut impl Write) -> anyhow::Result<()> {
+    writeln!(
+        out,
+        "Status: {} {}",
+        parts.status.as_u16(),
+        parts.status.canonical_reason().unwrap_or_default()
+    )?;
+    for (name, value) in &parts.headers {
+        out.write_all(name.as_str().as_bytes())?;
+        out.write_all(b": ")?;
+        out.write_all(value.as_bytes())?;
+        out.write_all(b"\n")?;
+    }
+    out.write_all(b"\n")?;
+    out.write_all(body)?;
+    out.flush()?;
+    Ok(())
+}
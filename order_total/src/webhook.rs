@@ -0,0 +1,166 @@
+use crate::config::env_or;
+use crate::error::{ComputeError, FieldError};
+use crate::request_id;
+use crate::retry::RetryPolicy;
+use hmac::{Hmac, Mac};
+use hyper::StatusCode;
+use serde::Deserialize;
+use sha2::Sha256;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{Instrument, Span};
+
+/// The headers a delivery is signed with: the signature covers
+/// `{timestamp}.{body}`, so a captured delivery cannot be replayed later
+/// with a fresh timestamp.
+const SIGNATURE_HEADER: &str = "x-webhook-signature";
+const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+const EVENT_HEADER: &str = "x-webhook-event";
+/// The same for every attempt of one delivery, so receivers can drop
+/// duplicates.
+const DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+/// POSTs computed results to the `callback_url` a request named, signed
+/// with HMAC-SHA256 and retried with backoff until the receiver answers
+/// `2xx`. Callbacks are refused unless a secret is configured.
+///
+/// * `WEBHOOK_SECRET` - the signing key; unset, `callback_url` is rejected
+/// * `WEBHOOK_MAX_ATTEMPTS` - deliveries tried before giving up (default 5)
+/// * `WEBHOOK_RETRY_BASE_MS` / `WEBHOOK_RETRY_MAX_MS` - backoff between attempts (default 500ms, 30s)
+/// * `WEBHOOK_TIMEOUT_MS` - bound on one attempt (default 5000)
+#[derive(Clone)]
+pub struct Webhooks {
+    secret: Option<Arc<[u8]>>,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    callback_url: Option<String>,
+}
+
+/// An attempt that did not end in a `2xx`.
+#[derive(Debug)]
+enum DeliveryError {
+    Reqwest(reqwest::Error),
+    Status(StatusCode),
+}
+
+impl Webhooks {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(env_or("WEBHOOK_TIMEOUT_MS", 5000)?))
+            .build()?;
+        Ok(Self {
+            secret: std::env::var("WEBHOOK_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty())
+                .map(|secret| secret.into_bytes().into()),
+            client,
+            retry: RetryPolicy {
+                max_attempts: env_or("WEBHOOK_MAX_ATTEMPTS", 5u32)?.max(1),
+                base_delay: Duration::from_millis(env_or("WEBHOOK_RETRY_BASE_MS", 500)?),
+                max_delay: Duration::from_millis(env_or("WEBHOOK_RETRY_MAX_MS", 30_000)?),
+            },
+        })
+    }
+
+    /// The `callback_url` query parameter of a request, if it has one. It
+    /// must be an `http` or `https` URL, and callbacks must be enabled.
+    pub fn callback_url(&self, query: Option<&str>) -> Result<Option<String>, ComputeError> {
+        let params: CallbackParams = serde_urlencoded::from_str(query.unwrap_or(""))
+            .map_err(|_| ComputeError::InvalidRequest)?;
+        let Some(url) = params.callback_url else {
+            return Ok(None);
+        };
+        let invalid = |message: &str| {
+            ComputeError::Validation(vec![FieldError::new("callback_url", message)])
+        };
+        if self.secret.is_none() {
+            return Err(invalid("callbacks are not enabled; set WEBHOOK_SECRET"));
+        }
+        match reqwest::Url::parse(&url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(Some(url)),
+            _ => Err(invalid("must be an http or https URL")),
+        }
+    }
+
+    /// Whether deliveries can be signed, i.e. `WEBHOOK_SECRET` is set.
+    pub fn signs(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Delivers `body` to `url` in the background, as an `event` such as
+    /// `order.computed`.
+    pub fn spawn(&self, url: String, event: &'static str, body: Vec<u8>) {
+        let webhooks = self.clone();
+        let id = request_id::current().unwrap_or_default();
+        let delivery = async move { webhooks.deliver(&url, event, body).await };
+        tokio::spawn(request_id::scope(id, delivery).instrument(Span::current()));
+    }
+
+    #[tracing::instrument(name = "webhook_delivery", skip_all, fields(otel.kind = "client", %url, event))]
+    async fn deliver(&self, url: &str, event: &str, body: Vec<u8>) {
+        let secret = self.secret.as_deref().expect("callbacks need a secret");
+        let delivery = uuid::Uuid::new_v4().to_string();
+        let attempt = || async {
+            let timestamp = chrono::Utc::now().timestamp().to_string();
+            let mut request = self
+                .client
+                .post(url)
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, signature(secret, &timestamp, &body))
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(EVENT_HEADER, event)
+                .header(DELIVERY_HEADER, delivery.as_str());
+            if let Some(id) = request_id::current() {
+                request = request.header(request_id::HEADER, id);
+            }
+            let response = request
+                .body(body.clone())
+                .send()
+                .await
+                .map_err(DeliveryError::Reqwest)?;
+            match response.status() {
+                status if status.is_success() => Ok(()),
+                status => Err(DeliveryError::Status(status)),
+            }
+        };
+        match self.retry.run(attempt, DeliveryError::is_transient).await {
+            Ok(()) => tracing::info!(%delivery, "webhook delivered"),
+            Err(err) => {
+                tracing::warn!(%delivery, error = ?err, "webhook delivery failed, giving up")
+            }
+        }
+    }
+}
+
+impl DeliveryError {
+    /// A `4xx` other than `408` and `429` means the receiver rejected the
+    /// delivery itself; anything else may go through on a retry.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Reqwest(err) => !err.is_builder(),
+            Self::Status(status) => {
+                !status.is_client_error()
+                    || *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}` under `secret`.
+fn signature(secret: &[u8], timestamp: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let mut hex = String::from("sha256=");
+    for byte in mac.finalize().into_bytes() {
+        write!(hex, "{byte:02x}").unwrap();
+    }
+    hex
+}
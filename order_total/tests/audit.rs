@@ -0,0 +1,167 @@
+//! The audit log of every computation but dry runs, its rotation, and its
+//! review and verification under `/admin/audit`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+use std::path::{Path, PathBuf};
+
+const ZIP: &str = "78701";
+const ADMIN: &[(&str, &str)] = &[("Authorization", "Bearer admin-token")];
+
+fn log_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "order_total_audit_{name}_{}.jsonl",
+        std::process::id()
+    ))
+}
+
+async fn audited(mock: &MockTaxService, log: &Path, extra: &[(&str, &str)]) -> TestService {
+    let mut env = vec![
+        ("AUDIT_LOG", log.to_str().unwrap()),
+        ("ADMIN_TOKEN", "admin-token"),
+    ];
+    env.extend_from_slice(extra);
+    TestService::start(mock, &env).await
+}
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+#[tokio::test]
+async fn every_computation_is_recorded() {
+    let mock = with_rate().await;
+    let log = log_path("recorded");
+    let service = audited(&mock, &log, &[]).await;
+    service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Request-Id", "audit-request-1")],
+            &order(ZIP),
+        )
+        .await;
+    service.post("/v1/compute", &order("not-a-zip")).await;
+
+    let all = service.send(Method::GET, "/admin/audit", ADMIN, "").await;
+    let failed = service
+        .send(Method::GET, "/admin/audit?failed=true", ADMIN, "")
+        .await;
+    let by_request = service
+        .send(
+            Method::GET,
+            "/admin/audit?request_id=audit-request-1",
+            ADMIN,
+            "",
+        )
+        .await;
+
+    assert_eq!(all.status, StatusCode::OK);
+    let entries = all.json["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["seq"], 2);
+    assert_eq!(entries[0]["error"], "VALIDATION_FAILED");
+    assert_eq!(entries[1]["seq"], 1);
+    assert_eq!(entries[1]["request_id"], "audit-request-1");
+    assert_eq!(entries[1]["order_id"], 123);
+    assert_eq!(entries[1]["input"]["subtotal"], 20.0);
+    assert_eq!(entries[1]["tax_rate"], "0.0825");
+    assert_eq!(entries[1]["rate_source"], "lookup");
+    assert_eq!(entries[1]["result"]["total"], 21.65);
+    assert_eq!(entries[0]["prev_hash"], entries[1]["hash"]);
+    assert_eq!(failed.json["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(by_request.json["entries"][0]["seq"], 1);
+    assert_eq!(by_request.json["entries"].as_array().unwrap().len(), 1);
+    std::fs::remove_file(log).unwrap();
+}
+
+#[tokio::test]
+async fn an_edited_entry_breaks_the_chain() {
+    let mock = with_rate().await;
+    let log = log_path("edited");
+    let service = audited(&mock, &log, &[]).await;
+    for _ in 0..3 {
+        service.post("/v1/compute", &order(ZIP)).await;
+    }
+
+    let intact = service
+        .send(Method::GET, "/admin/audit/verify", ADMIN, "")
+        .await;
+    let written = std::fs::read_to_string(&log).unwrap();
+    let edited = written.replacen("21.65", "20.65", 2);
+    std::fs::write(&log, &edited).unwrap();
+    let broken = service
+        .send(Method::GET, "/admin/audit/verify", ADMIN, "")
+        .await;
+
+    assert_ne!(written, edited);
+    assert_eq!(intact.json["entries"], 3);
+    assert_eq!(intact.json["intact"], true);
+    assert_eq!(broken.json["intact"], false);
+    assert_eq!(broken.json["broken_at"], 1);
+    std::fs::remove_file(log).unwrap();
+}
+
+#[tokio::test]
+async fn rotated_files_stay_part_of_the_log() {
+    let mock = with_rate().await;
+    let log = log_path("rotated");
+    let rotate_every_entry = [("AUDIT_LOG_MAX_BYTES", "1")];
+    let service = audited(&mock, &log, &rotate_every_entry).await;
+    for _ in 0..2 {
+        service.post("/v1/compute", &order(ZIP)).await;
+    }
+    let restarted = audited(&mock, &log, &rotate_every_entry).await;
+    restarted.post("/v1/compute", &order(ZIP)).await;
+
+    let entries = restarted
+        .send(Method::GET, "/admin/audit?limit=2", ADMIN, "")
+        .await;
+    let verified = restarted
+        .send(Method::GET, "/admin/audit/verify", ADMIN, "")
+        .await;
+
+    let rotated: Vec<PathBuf> = (1..=2)
+        .map(|n| PathBuf::from(format!("{}.{n}", log.display())))
+        .collect();
+    assert!(rotated.iter().all(|path| path.exists()));
+    assert_eq!(entries.json["limit"], 2);
+    assert_eq!(entries.json["entries"][0]["seq"], 3);
+    assert_eq!(entries.json["entries"][1]["seq"], 2);
+    assert_eq!(verified.json["entries"], 3);
+    assert_eq!(verified.json["intact"], true);
+    for path in rotated.iter().chain([&log]) {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn dry_runs_are_not_recorded() {
+    let mock = with_rate().await;
+    let log = log_path("dry_run");
+    let service = audited(&mock, &log, &[]).await;
+
+    let simulated = service.post("/v1/compute?dry_run=true", &order(ZIP)).await;
+    let entries = service.send(Method::GET, "/admin/audit", ADMIN, "").await;
+
+    assert_eq!(simulated.status, StatusCode::OK);
+    assert_eq!(simulated.json["dry_run"], true);
+    assert_eq!(simulated.json["total"], 21.65);
+    assert_eq!(entries.json["entries"], serde_json::json!([]));
+    std::fs::remove_file(log).unwrap();
+}
+
+#[tokio::test]
+async fn the_audit_routes_need_an_audit_log() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[("ADMIN_TOKEN", "admin-token")]).await;
+
+    let response = service.send(Method::GET, "/admin/audit", ADMIN, "").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
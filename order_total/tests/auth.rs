@@ -0,0 +1,116 @@
+//! Bearer token authentication against a JWKS.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, Response, StatusCode};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use order_total::Body;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Serves an empty JWKS, slowly, counting how often it is fetched.
+async fn jwks() -> (SocketAddr, Arc<AtomicUsize>) {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counted = fetches.clone();
+    let addr = common::serve(move |_| {
+        let fetches = counted.clone();
+        async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok::<_, Infallible>(Response::new(Body::from(r#"{"keys":[]}"#)))
+        }
+    })
+    .await;
+    (addr, fetches)
+}
+
+fn token(kid: &str) -> String {
+    let header = Header {
+        kid: Some(kid.to_owned()),
+        ..Header::default()
+    };
+    let claims = serde_json::json!({"sub": "someone", "exp": u32::MAX});
+    encode(
+        &header,
+        &claims,
+        &EncodingKey::from_secret(b"not the issuer's"),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn an_unknown_kid_fetches_the_jwks_once() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    let (addr, fetches) = jwks().await;
+    let url = format!("http://{addr}/jwks.json");
+    let service = TestService::start(&mock, &[("JWT_JWKS_URL", &url)]).await;
+    let bearer = format!("Bearer {}", token("rotated"));
+    let headers = [("Authorization", bearer.as_str())];
+    let body = order("78701");
+
+    let requests = (0..5).map(|_| service.send(Method::POST, "/v1/compute", &headers, &body));
+    let responses = futures_util::future::join_all(requests).await;
+
+    for response in responses {
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn a_token_signed_with_the_secret_is_accepted() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[("JWT_SECRET", "not the issuer's")]).await;
+    let bearer = format!("Bearer {}", token("any"));
+
+    let accepted = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("Authorization", &bearer)],
+            &order("78701"),
+        )
+        .await;
+    let anonymous = service.post("/v1/compute", &order("78701")).await;
+
+    assert_eq!(accepted.status, StatusCode::OK);
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn a_jwks_that_never_answers_is_given_up_on() {
+    let mock = MockTaxService::start().await;
+    let addr = common::serve(|_| async {
+        std::future::pending::<()>().await;
+        Ok::<_, Infallible>(Response::new(Body::empty()))
+    })
+    .await;
+    let url = format!("http://{addr}/jwks.json");
+    let service = TestService::start(
+        &mock,
+        &[("JWT_JWKS_URL", &url), ("JWT_JWKS_TIMEOUT_MS", "100")],
+    )
+    .await;
+    let bearer = format!("Bearer {}", token("rotated"));
+
+    let response = tokio::time::timeout(
+        Duration::from_secs(5),
+        service.send(
+            Method::POST,
+            "/v1/compute",
+            &[("Authorization", &bearer)],
+            &order("78701"),
+        ),
+    )
+    .await
+    .expect("the JWKS fetch was not given up on");
+
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,99 @@
+//! The cap on request bodies, `MAX_BODY_BYTES`, enforced while the body
+//! streams in.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::body::Bytes;
+use hyper::{Method, StatusCode};
+use order_total::Body;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const ZIP: &str = "78701";
+
+async fn limited_to(limit: usize) -> (MockTaxService, TestService) {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let limit = limit.to_string();
+    let service = TestService::start(&mock, &[("MAX_BODY_BYTES", &limit)]).await;
+    (mock, service)
+}
+
+/// `order(ZIP)` padded with leading whitespace to `len` bytes.
+fn padded(len: usize) -> String {
+    let order = order(ZIP);
+    " ".repeat(len - order.len()) + &order
+}
+
+#[tokio::test]
+async fn a_body_of_exactly_the_limit_is_accepted() {
+    let (_mock, service) = limited_to(1024).await;
+
+    let at_limit = service.post("/v1/compute", &padded(1024)).await;
+    let over_limit = service.post("/v1/compute", &padded(1025)).await;
+
+    assert_eq!(at_limit.status, StatusCode::OK);
+    assert_eq!(over_limit.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(over_limit.json["code"], "PAYLOAD_TOO_LARGE");
+    assert_eq!(
+        over_limit.message(),
+        "The request body exceeds the limit of 1024 bytes."
+    );
+}
+
+#[tokio::test]
+async fn a_body_announced_past_the_limit_is_refused_before_the_lookup() {
+    let (mock, service) = limited_to(1024).await;
+
+    let response = service.post("/v1/compute", &padded(4096)).await;
+
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn a_streamed_body_is_refused_once_it_passes_the_limit() {
+    let (mock, service) = limited_to(1024).await;
+    let mut stream = TcpStream::connect(service.addr()).await.unwrap();
+    let head = "POST /v1/compute HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n";
+    stream.write_all(head.as_bytes()).await.unwrap();
+    // 2 KiB in chunks, and never the last chunk: the body does not end.
+    for _ in 0..8 {
+        let chunk = format!("100\r\n{}\r\n", " ".repeat(256));
+        stream.write_all(chunk.as_bytes()).await.unwrap();
+    }
+
+    let mut buf = vec![0; 64 * 1024];
+    let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf))
+        .await
+        .expect("the service waited for the end of the body")
+        .unwrap();
+    let response = String::from_utf8_lossy(&buf[..read]);
+
+    assert!(response.starts_with("HTTP/1.1 413"), "{response}");
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn a_streamed_body_within_the_limit_is_read_whole() {
+    let (_mock, service) = limited_to(1024).await;
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        for chunk in padded(1024).as_bytes().chunks(100) {
+            sender
+                .send_data(Bytes::copy_from_slice(chunk))
+                .await
+                .unwrap();
+        }
+    });
+
+    let response = service
+        .send_body(Method::POST, "/v1/compute", &[], body)
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+}
@@ -0,0 +1,98 @@
+//! Orders shipped to Canada, taxed with the GST, HST and provincial sales
+//! taxes of the province instead of a looked-up rate.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockTaxService, TestService};
+use hyper::StatusCode;
+use serde_json::{json, Value};
+
+fn canadian_order(postal_code: &str) -> String {
+    let mut order: Value = serde_json::from_str(&order(postal_code)).unwrap();
+    order["shipping_country"] = json!("CA");
+    order.to_string()
+}
+
+#[tokio::test]
+async fn harmonized_provinces_charge_the_hst() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post("/v1/compute", &canadian_order("K1A 0B1"))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["jurisdiction"], "ON");
+    assert_eq!(response.json["tax_rate"], 0.13);
+    assert_eq!(response.json["tax_amount"], 2.6);
+    assert_eq!(response.json["total"], 22.6);
+    assert_eq!(
+        response.json["tax_components"],
+        json!([{"level": "federal", "name": "HST", "rate": 0.13, "amount": 2.6}])
+    );
+    assert_eq!(mock.hits("K1A 0B1"), 0);
+}
+
+#[tokio::test]
+async fn other_provinces_stack_their_own_tax_on_the_gst() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", &canadian_order("h2x1y4")).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["jurisdiction"], "QC");
+    assert_eq!(response.json["tax_rate"], 0.14975);
+    assert_eq!(response.json["tax_amount"], 3.0);
+    assert_eq!(
+        response.json["tax_components"],
+        json!([
+            {"level": "federal", "name": "GST", "rate": 0.05, "amount": 1.0},
+            {"level": "province", "name": "QST", "rate": 0.09975, "amount": 2.0}
+        ])
+    );
+}
+
+#[tokio::test]
+async fn compound_taxes_apply_to_the_taxes_before_them() {
+    let mock = MockTaxService::start().await;
+    let table = std::env::temp_dir().join(format!("order_total_canada_{}.csv", std::process::id()));
+    std::fs::write(
+        &table,
+        "province,tax,level,rate,compound\nQC,GST,federal,0.05,false\nQC,QST,province,0.09975,true\n",
+    )
+    .unwrap();
+    let service = TestService::start(&mock, &[("CANADA_TAX_TABLE", table.to_str().unwrap())]).await;
+
+    let response = service
+        .post("/v1/compute", &canadian_order("H2X 1Y4"))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["tax_rate"], 0.1547375);
+    assert_eq!(response.json["tax_amount"], 3.09);
+    assert_eq!(response.json["total"], 23.09);
+    let components = &response.json["tax_components"];
+    assert_eq!(components[0]["amount"], 1.0);
+    assert_eq!(components[1]["rate"], 0.09975);
+    assert_eq!(components[1]["amount"], 2.09);
+    std::fs::remove_file(table).unwrap();
+}
+
+#[tokio::test]
+async fn the_postal_code_must_match_the_country() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut unsupported: Value = serde_json::from_str(&order("78701")).unwrap();
+    unsupported["shipping_country"] = json!("MX");
+
+    let canadian = service.post("/v1/compute", &canadian_order("78701")).await;
+    let elsewhere = service.post("/v1/compute", &unsupported.to_string()).await;
+
+    assert_eq!(canadian.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(canadian.json["errors"][0]["field"], "shipping_zip");
+    assert_eq!(elsewhere.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(elsewhere.json["errors"][0]["field"], "shipping_country");
+}
@@ -0,0 +1,150 @@
+//! Orders priced by a product catalog, a local table or a mock catalog
+//! service, rather than by the prices they were sent with.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Request, Response, StatusCode};
+use order_total::Body;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+
+const ZIP: &str = "78701";
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+fn multi_item_order() -> String {
+    let mut order: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    order["line_items"] = json!([
+        {"product_id": 321, "quantity": 2, "unit_price": 1.0},
+        {"product_id": 322, "quantity": 1, "unit_price": 4.99}
+    ]);
+    order.to_string()
+}
+
+/// A catalog service selling product 321 at 10.00 and 322 at 4.99, and
+/// misquoting 323 at a negative price and 324 at over a trillion.
+async fn start_catalog() -> String {
+    let addr = common::serve(|req: Request<Body>| async move {
+        let price = match req.uri().path() {
+            "/products/321" => Some("10.00"),
+            "/products/322" => Some("4.99"),
+            "/products/323" => Some("-4.99"),
+            "/products/324" => Some("10000000000000"),
+            _ => None,
+        };
+        let body = price.map(|price| format!(r#"{{"unit_price":{price}}}"#));
+        let mut response = Response::new(Body::from(body.clone().unwrap_or_default()));
+        if body.is_none() {
+            *response.status_mut() = StatusCode::NOT_FOUND;
+        }
+        Ok::<_, Infallible>(response)
+    })
+    .await;
+    format!("http://{addr}/products")
+}
+
+#[tokio::test]
+async fn line_items_are_priced_by_the_catalog_service() {
+    let mock = with_rate().await;
+    let catalog = start_catalog().await;
+    let service = TestService::start(&mock, &[("PRODUCT_CATALOG_URL", &catalog)]).await;
+
+    let response = service.post("/v1/compute", &multi_item_order()).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["line_items"][0]["unit_price"], 10.0);
+    assert_eq!(response.json["subtotal"], 24.99);
+    assert_eq!(response.json["total"], 27.05);
+    assert_eq!(
+        response.json["price_mismatches"],
+        json!([{
+            "field": "line_items[0].unit_price",
+            "product_id": 321,
+            "submitted": 1.0,
+            "catalog": 10.0
+        }])
+    );
+}
+
+#[tokio::test]
+async fn subtotal_is_priced_by_the_catalog_table() {
+    let mock = with_rate().await;
+    let table =
+        std::env::temp_dir().join(format!("order_total_catalog_{}.csv", std::process::id()));
+    std::fs::write(&table, "product_id,unit_price\n321,10.00\n").unwrap();
+    let service =
+        TestService::start(&mock, &[("PRODUCT_CATALOG_TABLE", table.to_str().unwrap())]).await;
+    let mut tampered: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    tampered["subtotal"] = json!(2.0);
+
+    let honest = service.post("/v1/compute", &order(ZIP)).await;
+    let tampered = service.post("/v1/compute", &tampered.to_string()).await;
+
+    assert_eq!(honest.status, StatusCode::OK);
+    assert_eq!(honest.json.get("price_mismatches"), None);
+    assert_eq!(tampered.json["subtotal"], 20.0);
+    assert_eq!(tampered.json["total"], 21.65);
+    assert_eq!(tampered.json["price_mismatches"][0]["field"], "subtotal");
+    assert_eq!(tampered.json["price_mismatches"][0]["submitted"], 2.0);
+    std::fs::remove_file(table).unwrap();
+}
+
+#[tokio::test]
+async fn products_not_in_the_catalog_fail_validation() {
+    let mock = with_rate().await;
+    let catalog = start_catalog().await;
+    let service = TestService::start(&mock, &[("PRODUCT_CATALOG_URL", &catalog)]).await;
+    let mut body: Value = serde_json::from_str(&multi_item_order()).unwrap();
+    body["line_items"][1]["product_id"] = json!(999);
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json["errors"],
+        json!([{"field": "line_items[1].product_id", "message": "is not in the catalog"}])
+    );
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn prices_out_of_range_are_the_catalog_failing() {
+    let mock = with_rate().await;
+    let catalog = start_catalog().await;
+    let service = TestService::start(&mock, &[("PRODUCT_CATALOG_URL", &catalog)]).await;
+
+    for product_id in [323, 324] {
+        let mut body: Value = serde_json::from_str(&multi_item_order()).unwrap();
+        body["line_items"][1]["product_id"] = json!(product_id);
+
+        let response = service.post("/v1/compute", &body.to_string()).await;
+
+        assert_eq!(
+            response.status,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "{product_id}"
+        );
+    }
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn a_catalog_table_with_a_price_out_of_range_is_refused() {
+    let mock = with_rate().await;
+    let table = std::env::temp_dir().join(format!(
+        "order_total_catalog_too_large_{}.csv",
+        std::process::id()
+    ));
+    std::fs::write(&table, "product_id,unit_price\n321,10000000000000\n").unwrap();
+
+    let refused = common::try_app(&mock, &[("PRODUCT_CATALOG_TABLE", table.to_str().unwrap())]);
+
+    assert!(refused.is_err());
+    std::fs::remove_file(table).unwrap();
+}
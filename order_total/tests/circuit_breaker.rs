@@ -0,0 +1,109 @@
+//! The circuit breaker around the sales tax rate service: it opens after
+//! consecutive failures, fails fast while open, and lets one probe through
+//! once the open period is over.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+const OPEN: Duration = Duration::from_millis(1100);
+
+/// The service with a breaker that opens after 2 failures, for a second.
+async fn guarded(mock: &MockTaxService) -> TestService {
+    let env = [
+        ("TAX_SERVICE_MAX_ATTEMPTS", "1"),
+        ("CIRCUIT_BREAKER_THRESHOLD", "2"),
+        ("CIRCUIT_BREAKER_OPEN_SECS", "1"),
+    ];
+    TestService::start(mock, &env).await
+}
+
+/// Fails the two lookups that open the circuit.
+async fn open(mock: &MockTaxService, service: &TestService) {
+    let failing = MockResponse::status(StatusCode::BAD_GATEWAY);
+    mock.respond_first(ZIP, &[failing.clone(), failing]);
+    for _ in 0..2 {
+        let response = service.post("/v1/compute", &order(ZIP)).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
+
+#[tokio::test]
+async fn failures_below_the_threshold_keep_the_circuit_closed() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = guarded(&mock).await;
+
+    for _ in 0..3 {
+        mock.respond_first(ZIP, &[MockResponse::status(StatusCode::BAD_GATEWAY)]);
+        let failed = service.post("/v1/compute", &order(ZIP)).await;
+        let succeeded = service.post("/v1/compute", &order(ZIP)).await;
+        assert_eq!(failed.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(succeeded.status, StatusCode::OK);
+    }
+    assert_eq!(mock.hits(ZIP), 6);
+}
+
+#[tokio::test]
+async fn a_successful_probe_closes_the_circuit() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = guarded(&mock).await;
+    open(&mock, &service).await;
+
+    let open_response = service.post("/v1/compute", &order(ZIP)).await;
+    assert_eq!(open_response.json["code"], "CIRCUIT_OPEN");
+    assert_eq!(open_response.header("retry-after"), Some("1"));
+    assert_eq!(mock.hits(ZIP), 2);
+
+    tokio::time::sleep(OPEN).await;
+    let probe = service.post("/v1/compute", &order(ZIP)).await;
+    let closed = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(probe.status, StatusCode::OK);
+    assert_eq!(closed.status, StatusCode::OK);
+    assert_eq!(mock.hits(ZIP), 4);
+}
+
+#[tokio::test]
+async fn a_failed_probe_opens_the_circuit_again() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::status(StatusCode::BAD_GATEWAY));
+    let service = guarded(&mock).await;
+    open(&mock, &service).await;
+
+    tokio::time::sleep(OPEN).await;
+    let probe = service.post("/v1/compute", &order(ZIP)).await;
+    let reopened = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(probe.json["code"], "UPSTREAM_UNAVAILABLE");
+    assert_eq!(reopened.json["code"], "CIRCUIT_OPEN");
+    assert_eq!(mock.hits(ZIP), 3);
+}
+
+#[tokio::test]
+async fn only_one_probe_is_let_through_while_half_open() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_millis(300)),
+    );
+    let service = guarded(&mock).await;
+    open(&mock, &service).await;
+
+    tokio::time::sleep(OPEN).await;
+    let body = order(ZIP);
+    let (probe, during_probe) = tokio::join!(service.post("/v1/compute", &body), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        service.post("/v1/compute", &order("10001")).await
+    });
+
+    assert_eq!(probe.status, StatusCode::OK);
+    assert_eq!(during_probe.json["code"], "CIRCUIT_OPEN");
+    assert_eq!(mock.hits(ZIP), 3);
+    assert_eq!(mock.hits("10001"), 0);
+}
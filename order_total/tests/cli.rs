@@ -0,0 +1,176 @@
+//! The subcommands of the `order_total` binary besides `serve`.
+#![cfg(feature = "native")]
+
+mod common;
+
+use common::order;
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+const FIXED_RATE: &[(&str, &str)] = &[("TAX_RATE_SOURCE", "fixed"), ("FIXED_TAX_RATE", "0.0825")];
+
+fn orders_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "order_total_cli_{name}_{}.json",
+        std::process::id()
+    ));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn run(args: &[&str], env: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_order_total"))
+        .args(args)
+        .env_remove("RUNTIME_FLAVOR")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+fn pipe(input: &str, env: &[(&str, &str)]) -> Output {
+    with_input("pipe", input, env)
+}
+
+fn with_input(command: &str, input: &str, env: &[(&str, &str)]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_order_total"))
+        .arg(command)
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout_json(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn compute_prints_the_computed_order() {
+    let path = orders_file("single", &order("78701"));
+
+    let output = run(&["compute", "--file", path.to_str().unwrap()], FIXED_RATE);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout_json(&output)["total"], 21.65);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn compute_answers_each_order_of_an_array() {
+    let orders = format!("[{}, {}]", order("78701"), order("not-a-zip"));
+    let path = orders_file("batch", &orders);
+
+    let output = run(&["compute", "--file", path.to_str().unwrap()], FIXED_RATE);
+
+    let results = stdout_json(&output);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(results[0]["total"], 21.65);
+    assert_eq!(results[1]["code"], "VALIDATION_FAILED");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn pipe_answers_each_line_with_the_embedded_rates() {
+    let input = format!("{}\n\nnot json\n{}", order("78701"), order("00000"));
+
+    let output = pipe(&input, &[("SALES_TAX_RATE_SERVICE", "http://127.0.0.1:9")]);
+
+    let lines: Vec<Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["total"], 21.65);
+    assert_eq!(lines[1]["code"], "INVALID_REQUEST");
+    assert_eq!(lines[2]["code"], "TAX_RATE_UNAVAILABLE");
+}
+
+#[test]
+fn pipe_refuses_settings_that_need_the_network() {
+    let output = pipe("", &[("PRODUCT_CATALOG_URL", "http://127.0.0.1:9")]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("PRODUCT_CATALOG_URL"));
+}
+
+#[test]
+fn check_config_accepts_a_valid_configuration() {
+    let output = run(&["check-config"], FIXED_RATE);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "configuration is valid\n"
+    );
+}
+
+#[test]
+fn check_config_names_what_is_wrong() {
+    let output = run(&["check-config"], &[("ROUNDING_MODE", "sideways")]);
+    let bad_runtime = run(&["check-config", "--runtime", "sideways"], &[]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ROUNDING_MODE"));
+    assert_eq!(bad_runtime.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&bad_runtime.stderr).contains("invalid runtime flavor"));
+}
+
+#[test]
+fn warm_cache_needs_a_shared_cache() {
+    let output = run(
+        &["warm-cache"],
+        &[("CACHE_WARM_ZIPS", "78701"), ("RATE_CACHE_SECS", "60")],
+    );
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("REDIS_URL"));
+}
+
+#[test]
+fn wagi_answers_the_request_of_the_cgi_variables() {
+    let body = order("78701");
+    let length = body.len().to_string();
+    let mut env = FIXED_RATE.to_vec();
+    env.extend([
+        ("REQUEST_METHOD", "POST"),
+        ("SCRIPT_NAME", ""),
+        ("PATH_INFO", "/v1/compute"),
+        ("QUERY_STRING", "pretty=true"),
+        ("CONTENT_TYPE", "application/json"),
+        ("CONTENT_LENGTH", length.as_str()),
+        ("HTTP_X_REQUEST_ID", "cgi-request"),
+    ]);
+
+    let output = with_input("wagi", &body, &env);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (head, body) = stdout.split_once("\n\n").unwrap();
+    assert_eq!(output.status.code(), Some(0), "{stdout}");
+    assert!(head.starts_with("Status: 200 OK\n"), "{head}");
+    assert!(head.contains("\ncontent-type: application/json"), "{head}");
+    assert!(head.contains("\nx-request-id: cgi-request"), "{head}");
+    assert!(body.contains("\n  \"total\": 21.65"), "{body}");
+}
+
+#[test]
+fn wagi_answers_an_unknown_path_404() {
+    let mut env = FIXED_RATE.to_vec();
+    env.extend([("REQUEST_METHOD", "GET"), ("PATH_INFO", "/nowhere")]);
+
+    let output = with_input("wagi", "", &env);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Status: 404 Not Found\n"));
+}
@@ -0,0 +1,82 @@
+//! Concurrent lookups for the same zip code sharing one upstream call.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestResponse, TestService};
+use futures_util::future::join_all;
+use hyper::StatusCode;
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+const OTHER_ZIP: &str = "10001";
+const LOOKUP: Duration = Duration::from_millis(200);
+
+/// Posts the orders for `zips` all at once.
+async fn concurrently(service: &TestService, zips: &[&str]) -> Vec<TestResponse> {
+    let bodies: Vec<String> = zips.iter().map(|zip| order(zip)).collect();
+    join_all(bodies.iter().map(|body| service.post("/v1/compute", body))).await
+}
+
+#[tokio::test]
+async fn concurrent_lookups_for_a_zip_share_one_upstream_call() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825").delayed(LOOKUP));
+    let service = TestService::start(&mock, &[]).await;
+
+    let responses = concurrently(&service, &[ZIP; 10]).await;
+
+    for response in responses {
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json["total"], 21.65);
+    }
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn lookups_for_other_zips_are_not_shared() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825").delayed(LOOKUP))
+        .respond(OTHER_ZIP, MockResponse::rate("0.08875").delayed(LOOKUP));
+    let service = TestService::start(&mock, &[]).await;
+
+    let responses = concurrently(&service, &[ZIP, OTHER_ZIP, ZIP, OTHER_ZIP]).await;
+
+    assert_eq!(responses[0].json["tax_rate"], 0.0825);
+    assert_eq!(responses[1].json["tax_rate"], 0.08875);
+    assert_eq!(responses[2].json["tax_rate"], 0.0825);
+    assert_eq!(responses[3].json["tax_rate"], 0.08875);
+    assert_eq!(mock.hits(ZIP), 1);
+    assert_eq!(mock.hits(OTHER_ZIP), 1);
+}
+
+#[tokio::test]
+async fn a_failed_lookup_is_shared_too() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::status(StatusCode::SERVICE_UNAVAILABLE).delayed(LOOKUP),
+    );
+    let service = TestService::start(&mock, &[("TAX_SERVICE_MAX_ATTEMPTS", "1")]).await;
+
+    let responses = concurrently(&service, &[ZIP; 5]).await;
+
+    for response in responses {
+        assert_eq!(response.json["code"], "UPSTREAM_UNAVAILABLE");
+    }
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn a_finished_lookup_is_not_reused() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let first = service.post("/v1/compute", &order(ZIP)).await;
+    let second = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(second.status, StatusCode::OK);
+    assert_eq!(mock.hits(ZIP), 2);
+}
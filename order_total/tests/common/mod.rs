@@ -0,0 +1,401 @@
+//! The harness of the integration tests: the service on an ephemeral port,
+//! talking to an in-process mock of the sales tax rate service. Each test
+//! file uses only some of it.
+#![allow(dead_code)]
+
+use http_body_util::BodyExt;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::HeaderMap;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use order_total::{App, Body};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How the mock answers a lookup for one zip code.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub body: String,
+    pub delay: Duration,
+}
+
+impl MockResponse {
+    pub fn rate(rate: &str) -> Self {
+        Self::status(StatusCode::OK).with_body(rate)
+    }
+
+    pub fn status(status: StatusCode) -> Self {
+        Self {
+            status,
+            body: String::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn with_body(mut self, body: &str) -> Self {
+        self.body = body.to_owned();
+        self
+    }
+
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    responses: HashMap<String, MockResponse>,
+    queued: HashMap<String, VecDeque<MockResponse>>,
+    hits: HashMap<String, usize>,
+}
+
+/// Stands in for `sales_tax_rate`: `POST /find_rate` answers each zip code as
+/// configured, and `404` for the others, like the real service.
+pub struct MockTaxService {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTaxService {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let shared = state.clone();
+        let addr = serve(move |req| mock_lookup(req, shared.clone())).await;
+        Self { addr, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}{}", self.addr, models::FIND_RATE_PATH)
+    }
+
+    pub fn respond(&self, zip: &str, response: MockResponse) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state.responses.insert(zip.to_owned(), response);
+        self
+    }
+
+    /// Answers the next lookups for `zip` with `responses`, one each and in
+    /// order, before going back to what `respond` set.
+    pub fn respond_first(&self, zip: &str, responses: &[MockResponse]) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        let queued = state.queued.entry(zip.to_owned()).or_default();
+        queued.extend(responses.iter().cloned());
+        self
+    }
+
+    /// Answers lookups of the rate `zip` had on `date` with `response`;
+    /// other days get what `respond` set.
+    pub fn respond_on(&self, zip: &str, date: &str, response: MockResponse) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state.responses.insert(format!("{zip}@{date}"), response);
+        self
+    }
+
+    /// How many lookups reached the mock for `zip`.
+    pub fn hits(&self, zip: &str) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .hits
+            .get(zip)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+async fn mock_lookup(
+    req: Request<Body>,
+    state: Arc<Mutex<MockState>>,
+) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::POST || req.uri().path() != models::FIND_RATE_PATH {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+    let query = req.uri().query().map(str::to_owned);
+    let body = to_bytes(req.into_body()).await;
+    let request = models::RateRequest::from_body(&body)
+        .and_then(|request| request.with_query(query.as_deref()))
+        .unwrap();
+    let zip = request.zip;
+    let response = {
+        let mut state = state.lock().unwrap();
+        *state.hits.entry(zip.clone()).or_default() += 1;
+        let queued = state.queued.get_mut(&zip).and_then(VecDeque::pop_front);
+        queued.or_else(|| {
+            request
+                .date
+                .and_then(|date| state.responses.get(&format!("{zip}@{date}")))
+                .or_else(|| state.responses.get(&zip))
+                .cloned()
+        })
+    };
+    let response = response.unwrap_or_else(|| MockResponse::status(StatusCode::NOT_FOUND));
+    tokio::time::sleep(response.delay).await;
+    let mut reply = Response::new(Body::from(response.body));
+    *reply.status_mut() = response.status;
+    Ok(reply)
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::default();
+    *response.status_mut() = status;
+    response
+}
+
+/// Serves `handler` on an ephemeral port of localhost, e.g. as a service the
+/// app calls out to, and returns where.
+pub async fn serve<H, F>(handler: H) -> SocketAddr
+where
+    H: Fn(Request<Body>) -> F + Clone + Send + 'static,
+    F: Future<Output = Result<Response<Body>, Infallible>> + Send + 'static,
+{
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let builder = auto::Builder::new(TokioExecutor::new());
+        while let Ok((stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            let service = service_fn(move |req: Request<Incoming>| handler(req.map(Body::from)));
+            tokio::spawn(
+                builder
+                    .serve_connection(TokioIo::new(stream), service)
+                    .into_owned(),
+            );
+        }
+    });
+    addr
+}
+
+/// A client of plain HTTP, speaking HTTP/1.1.
+pub fn client() -> Client<HttpConnector, Body> {
+    Client::builder(TokioExecutor::new()).build_http()
+}
+
+/// A client of plain HTTP, speaking HTTP/2 with prior knowledge.
+pub fn http2_client() -> Client<HttpConnector, Body> {
+    Client::builder(TokioExecutor::new())
+        .http2_only(true)
+        .build_http()
+}
+
+/// Reads the whole of `body`.
+pub async fn to_bytes<B>(body: B) -> Bytes
+where
+    B: hyper::body::Body,
+    B::Error: std::fmt::Debug,
+{
+    body.collect().await.unwrap().to_bytes()
+}
+
+/// `App::from_env` reads the process environment, which the tests share, so
+/// apps are built one at a time.
+static ENV: Mutex<()> = Mutex::new(());
+
+/// The app configured by `env` on top of `BASE_ENV`, looking rates up from
+/// `mock`.
+pub fn app(mock: &MockTaxService, env: &[(&str, &str)]) -> Arc<App> {
+    try_app(mock, env).expect("invalid test configuration")
+}
+
+/// Like `app`, for tests of configurations that are refused.
+pub fn try_app(mock: &MockTaxService, env: &[(&str, &str)]) -> anyhow::Result<Arc<App>> {
+    let url = mock.url();
+    let service = [("SALES_TAX_RATE_SERVICE", url.as_str())];
+    let vars: Vec<(&str, &str)> = BASE_ENV
+        .iter()
+        .chain(&service)
+        .chain(env)
+        .copied()
+        .collect();
+    let _guard = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (name, value) in &vars {
+        std::env::set_var(name, value);
+    }
+    let app = App::from_env();
+    for (name, _) in &vars {
+        std::env::remove_var(name);
+    }
+    app.map(Arc::new)
+}
+
+/// Runs `task`, e.g. a request to `POST /admin/reload`, with the
+/// environment set up as `app` sets it up for `mock` and `env`, and no app
+/// being built meanwhile.
+pub async fn with_env<T>(
+    mock: &MockTaxService,
+    env: &[(&str, &str)],
+    task: impl std::future::Future<Output = T>,
+) -> T {
+    let vars: Vec<(String, String)> = BASE_ENV
+        .iter()
+        .copied()
+        .chain([("SALES_TAX_RATE_SERVICE", mock.url().as_str())])
+        .chain(env.iter().copied())
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    let (set, is_set) = tokio::sync::oneshot::channel();
+    let (done, is_done) = std::sync::mpsc::channel::<()>();
+    // The lock is held by a thread of its own, not across the awaits below.
+    let holder = tokio::task::spawn_blocking(move || {
+        let _guard = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (name, value) in &vars {
+            std::env::set_var(name, value);
+        }
+        let _ = set.send(());
+        let _ = is_done.recv();
+        for (name, _) in &vars {
+            std::env::remove_var(name);
+        }
+    });
+    is_set.await.unwrap();
+    let output = task.await;
+    done.send(()).unwrap();
+    holder.await.unwrap();
+    output
+}
+
+/// Settings every test starts from: quick retries, and short timeouts so the
+/// timeout tests stay fast.
+const BASE_ENV: &[(&str, &str)] = &[
+    ("TAX_SERVICE_RETRY_BASE_MS", "1"),
+    ("TAX_SERVICE_RETRY_MAX_MS", "5"),
+    ("TAX_SERVICE_TIMEOUT_MS", "1000"),
+    ("UNKNOWN_ZIP_CACHE_SECS", "0"),
+];
+
+/// The service under test, configured by `env` on top of `BASE_ENV` and
+/// looking rates up from `mock`.
+pub struct TestService {
+    addr: SocketAddr,
+    client: Client<HttpConnector, Body>,
+}
+
+/// A response with its body read, and parsed when it is JSON.
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub json: serde_json::Value,
+    pub body: Bytes,
+}
+
+#[cfg(feature = "server")]
+impl TestService {
+    pub async fn start(mock: &MockTaxService, env: &[(&str, &str)]) -> Self {
+        Self::serve(app(mock, env))
+    }
+
+    /// Serves an app the test built (and perhaps prepared) itself.
+    pub fn serve(app: Arc<App>) -> Self {
+        let (addr, server) =
+            order_total::bind(app, ([127, 0, 0, 1], 0).into(), std::future::pending()).unwrap();
+        tokio::spawn(server);
+        Self {
+            addr,
+            client: client(),
+        }
+    }
+
+    /// Where the service listens, for tests that speak HTTP themselves.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub async fn post(&self, path: &str, body: &str) -> TestResponse {
+        self.send(Method::POST, path, &[], body).await
+    }
+
+    pub async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> TestResponse {
+        self.send_bytes(method, path, headers, body.as_bytes().to_vec())
+            .await
+    }
+
+    pub async fn send_bytes(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> TestResponse {
+        self.send_body(method, path, headers, Body::from(body))
+            .await
+    }
+
+    /// Sends `body` as it is, e.g. streamed without a `Content-Length`.
+    pub async fn send_body(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Body,
+    ) -> TestResponse {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{path}", self.addr));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(body).unwrap();
+        let response = self.client.request(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = to_bytes(response.into_body()).await;
+        TestResponse {
+            status,
+            headers,
+            json: serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null),
+            body: bytes,
+        }
+    }
+}
+
+impl TestResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    pub fn message(&self) -> &str {
+        self.json["message"].as_str().unwrap_or_default()
+    }
+}
+
+/// The order in the repository's `order.json`, shipped to `zip`.
+pub fn order(zip: &str) -> String {
+    serde_json::json!({
+        "order_id": 123,
+        "product_id": 321,
+        "quantity": 2,
+        "subtotal": 20.0,
+        "shipping_address": "123 Main St, Anytown USA",
+        "shipping_zip": zip,
+        "total": 0.0
+    })
+    .to_string()
+}
+
+/// A request body that panics as the handler reads it, standing in for a
+/// bug in a handler. A client sending it would panic itself, so it is
+/// handed to an `OrderTotalService` directly.
+pub fn panicking_body() -> Body {
+    Body::wrap_stream(futures_util::stream::poll_fn(
+        |_| -> std::task::Poll<Option<Result<Bytes, Infallible>>> {
+            panic!("the request body broke")
+        },
+    ))
+}
@@ -0,0 +1,174 @@
+//! Compressed request bodies and `Accept-Encoding` negotiation.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use flate2::write::GzEncoder;
+use hyper::{Method, StatusCode};
+use std::io::{Read, Write};
+
+const ZIP: &str = "78701";
+
+async fn service() -> (MockTaxService, TestService) {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[("COMPRESS_MIN_BYTES", "0")]).await;
+    (mock, service)
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn gunzip(data: &[u8]) -> serde_json::Value {
+    let mut json = Vec::new();
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut json)
+        .unwrap();
+    serde_json::from_slice(&json).unwrap()
+}
+
+#[tokio::test]
+async fn decompresses_a_gzip_request_body() {
+    let (_mock, service) = service().await;
+
+    let response = service
+        .send_bytes(
+            Method::POST,
+            "/v1/compute",
+            &[("Content-Encoding", "gzip")],
+            gzip(order(ZIP).as_bytes()),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn rejects_corrupt_and_unknown_encodings() {
+    let (_mock, service) = service().await;
+
+    let corrupt = service
+        .send_bytes(
+            Method::POST,
+            "/v1/compute",
+            &[("Content-Encoding", "gzip")],
+            order(ZIP).into_bytes(),
+        )
+        .await;
+    let unknown = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("Content-Encoding", "zstd")],
+            &order(ZIP),
+        )
+        .await;
+
+    assert_eq!(corrupt.status, StatusCode::BAD_REQUEST);
+    assert_eq!(corrupt.json["code"], "INVALID_REQUEST");
+    assert_eq!(unknown.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(unknown.json["code"], "UNSUPPORTED_ENCODING");
+}
+
+#[tokio::test]
+async fn compresses_responses_as_accept_encoding_allows() {
+    let (_mock, service) = service().await;
+
+    let gzipped = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("Accept-Encoding", "gzip, br;q=0.5")],
+            &order(ZIP),
+        )
+        .await;
+    let brotli = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("Accept-Encoding", "gzip, br")],
+            &order(ZIP),
+        )
+        .await;
+    let plain = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(gzipped.header("content-encoding"), Some("gzip"));
+    assert_eq!(gzipped.header("vary"), Some("accept-encoding"));
+    assert_eq!(gunzip(&gzipped.body)["total"], 21.65);
+
+    assert_eq!(brotli.header("content-encoding"), Some("br"));
+    let mut json = Vec::new();
+    brotli::Decompressor::new(&brotli.body[..], 4096)
+        .read_to_end(&mut json)
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&json).unwrap()["total"],
+        21.65
+    );
+
+    assert_eq!(plain.header("content-encoding"), None);
+    assert_eq!(plain.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn compresses_a_stream_of_results() {
+    let (_mock, service) = service().await;
+    let lines = format!("{}\n{}\n", order(ZIP), order("00000"));
+
+    let response = service
+        .send(
+            Method::POST,
+            "/v1/compute_stream",
+            &[("Accept-Encoding", "gzip")],
+            &lines,
+        )
+        .await;
+
+    assert_eq!(response.header("content-encoding"), Some("gzip"));
+    let mut text = String::new();
+    flate2::read::GzDecoder::new(&response.body[..])
+        .read_to_string(&mut text)
+        .unwrap();
+    let results: Vec<serde_json::Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["total"], 21.65);
+    assert_eq!(results[1]["code"], "TAX_RATE_UNAVAILABLE");
+}
+
+#[tokio::test]
+async fn refuses_a_body_that_decompresses_past_the_limit() {
+    let mock = MockTaxService::start().await;
+    let env = [("MAX_BODY_BYTES", "1024"), ("MAX_CSV_BYTES", "1024")];
+    let service = TestService::start(&mock, &env).await;
+    let zeros = vec![b'0'; 8 * 1024 * 1024];
+    let mut brotli = Vec::new();
+    brotli::BrotliCompress(&mut &zeros[..], &mut brotli, &Default::default()).unwrap();
+
+    let gzipped = service
+        .send_bytes(
+            Method::POST,
+            "/v1/compute",
+            &[("Content-Encoding", "gzip")],
+            gzip(&zeros),
+        )
+        .await;
+    let brotli = service
+        .send_bytes(
+            Method::POST,
+            "/v1/compute",
+            &[("Content-Encoding", "br")],
+            brotli,
+        )
+        .await;
+
+    assert_eq!(gzipped.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(brotli.status, StatusCode::PAYLOAD_TOO_LARGE);
+}
@@ -0,0 +1,1164 @@
+//! End-to-end behavior of `/v1/compute` against a mock sales tax rate
+//! service, and the response each `ComputeError` maps to.
+//!
+//! The tests bind loopback sockets for the service and the mock, so they are
+//! built for native runs only:
+//! `cargo test -p order_total --no-default-features --features native --target <host>`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+use order_total_core::{ComputeError, FieldError, Quota};
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+#[tokio::test]
+async fn computes_the_total_with_the_looked_up_rate() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Request-Id", "test-request")],
+            &order(ZIP),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+    assert_eq!(response.json["tax_amount"], 1.65);
+    assert_eq!(response.json["tax_rate"], 0.0825);
+    assert_eq!(response.json["rate_source"], "lookup");
+    assert_eq!(response.json["jurisdiction"], "TX");
+    assert_eq!(response.json.get("tax_components"), None);
+    assert_eq!(response.header("x-request-id"), Some("test-request"));
+    assert_eq!(response.header("deprecation"), None);
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn unversioned_compute_is_served_as_deprecated() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+    assert_eq!(response.header("deprecation"), Some("true"));
+}
+
+/// The test order with `discounts` and, when given, `line_items`.
+fn discounted(discounts: serde_json::Value, line_items: Option<serde_json::Value>) -> String {
+    let mut order: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    order["discounts"] = discounts;
+    if let Some(line_items) = line_items {
+        order["line_items"] = line_items;
+    }
+    order.to_string()
+}
+
+#[tokio::test]
+async fn discounts_apply_percentages_first_then_tax_then_post_tax() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let body = discounted(
+        serde_json::json!([
+            {"code": "FIVE", "kind": "fixed", "value": 5},
+            {"code": "LOYAL", "kind": "fixed", "value": 1, "applies": "post_tax"},
+            {"code": "SAVE10", "kind": "percentage", "value": 10}
+        ]),
+        None,
+    );
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let amounts: Vec<f64> = response.json["discounts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|discount| discount["amount"].as_f64().unwrap())
+        .collect();
+    assert_eq!(amounts, [5.0, 1.0, 2.0]);
+    assert_eq!(response.json["subtotal"], 20.0);
+    assert_eq!(response.json["tax_amount"], 1.07);
+    assert_eq!(response.json["discount_amount"], 8.0);
+    assert_eq!(response.json["total"], 13.07);
+}
+
+#[tokio::test]
+async fn pre_tax_discounts_are_shared_between_line_items() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let body = discounted(
+        serde_json::json!([{"kind": "fixed", "value": 10}]),
+        Some(serde_json::json!([
+            {"product_id": 1, "quantity": 3, "unit_price": 9.99},
+            {"product_id": 2, "quantity": 1, "unit_price": 25.0}
+        ])),
+    );
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let lines = &response.json["line_items"];
+    assert_eq!(lines[0]["discount"], 5.45);
+    assert_eq!(lines[0]["tax"], 2.02);
+    assert_eq!(lines[1]["discount"], 4.55);
+    assert_eq!(lines[1]["tax"], 1.69);
+    assert_eq!(response.json["subtotal"], 54.97);
+    assert_eq!(response.json["tax_amount"], 3.71);
+    assert_eq!(response.json["total"], 48.68);
+}
+
+#[tokio::test]
+async fn discount_beyond_the_whole_is_invalid() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let body = discounted(
+        serde_json::json!([{"kind": "percentage", "value": 150}]),
+        None,
+    );
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json["errors"][0]["field"], "discounts[0].value");
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn line_items_are_taxed_by_category() {
+    let mock = with_rate().await;
+    let table =
+        std::env::temp_dir().join(format!("order_total_categories_{}.csv", std::process::id()));
+    std::fs::write(
+        &table,
+        "category,jurisdiction,factor\ngrocery,TX,0\ngrocery,*,0.5\nclothing,*,0.5\n",
+    )
+    .unwrap();
+    let service =
+        TestService::start(&mock, &[("TAX_CATEGORY_TABLE", table.to_str().unwrap())]).await;
+    let mut body: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["line_items"] = serde_json::json!([
+        {"product_id": 1, "quantity": 1, "unit_price": 10.0, "tax_category": "grocery"},
+        {"product_id": 2, "quantity": 1, "unit_price": 20.0, "tax_category": "clothing"},
+        {"product_id": 3, "quantity": 1, "unit_price": 5.0},
+        {"product_id": 4, "quantity": 1, "unit_price": 7.0, "tax_category": "exempt"}
+    ]);
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let lines = &response.json["line_items"];
+    assert_eq!(lines[0]["tax_rate"], 0.0);
+    assert_eq!(lines[0]["tax"], 0.0);
+    assert_eq!(lines[1]["tax_rate"], 0.04125);
+    assert_eq!(lines[1]["tax"], 0.83);
+    assert_eq!(lines[2]["tax_rate"], serde_json::Value::Null);
+    assert_eq!(lines[2]["tax"], 0.41);
+    assert_eq!(lines[3]["tax"], 0.0);
+    assert_eq!(response.json["tax_rate"], 0.0825);
+    assert_eq!(response.json["tax_amount"], 1.24);
+    assert_eq!(response.json["total"], 43.24);
+    std::fs::remove_file(table).unwrap();
+}
+
+#[tokio::test]
+async fn line_items_are_exempt_during_a_tax_holiday() {
+    let mock = with_rate().await;
+    let calendar =
+        std::env::temp_dir().join(format!("order_total_holidays_{}.csv", std::process::id()));
+    std::fs::write(
+        &calendar,
+        "name,start,end,jurisdiction,category,cap\nBack to school,2025-08-08,2025-08-10,TX,clothing,100\n",
+    )
+    .unwrap();
+    let service = TestService::start(
+        &mock,
+        &[("TAX_HOLIDAY_CALENDAR", calendar.to_str().unwrap())],
+    )
+    .await;
+    let mut body: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["line_items"] = serde_json::json!([
+        {"product_id": 1, "quantity": 1, "unit_price": 50.0, "tax_category": "clothing"},
+        {"product_id": 2, "quantity": 1, "unit_price": 150.0, "tax_category": "clothing"},
+        {"product_id": 3, "quantity": 1, "unit_price": 10.0}
+    ]);
+    body["order_date"] = serde_json::json!("2025-08-09");
+    let during = service.post("/v1/compute", &body.to_string()).await;
+    body["order_date"] = serde_json::json!("2025-08-11");
+    let after = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(during.status, StatusCode::OK);
+    let lines = &during.json["line_items"];
+    assert_eq!(lines[0]["tax_holiday"], "Back to school");
+    assert_eq!(lines[0]["tax"], 0.0);
+    assert_eq!(lines[1].get("tax_holiday"), None);
+    assert_eq!(lines[1]["tax"], 12.38);
+    assert_eq!(during.json["tax_amount"], 13.21);
+    assert_eq!(after.json["line_items"][0].get("tax_holiday"), None);
+    assert_eq!(after.json["tax_amount"], 17.34);
+    std::fs::remove_file(calendar).unwrap();
+}
+
+#[tokio::test]
+async fn an_order_without_line_items_is_exempt_during_a_tax_holiday() {
+    let mock = with_rate().await;
+    let calendar = std::env::temp_dir().join(format!(
+        "order_total_holidays_single_{}.csv",
+        std::process::id()
+    ));
+    std::fs::write(
+        &calendar,
+        "name,start,end,jurisdiction,category,cap\nFree week,2025-08-08,2025-08-10,*,*,\nBack to school,2025-08-08,2025-08-10,TX,clothing,100\n",
+    )
+    .unwrap();
+    let service = TestService::start(
+        &mock,
+        &[("TAX_HOLIDAY_CALENDAR", calendar.to_str().unwrap())],
+    )
+    .await;
+    let mut body: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["order_date"] = serde_json::json!("2025-08-09");
+    let during = service.post("/v1/compute", &body.to_string()).await;
+    body["order_date"] = serde_json::json!("2025-08-11");
+    let after = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(during.status, StatusCode::OK);
+    assert_eq!(during.json["tax_holiday"], "Free week");
+    assert_eq!(during.json["tax_amount"], 0.0);
+    assert_eq!(during.json["total"], 20.0);
+    assert_eq!(after.json.get("tax_holiday"), None);
+    assert_eq!(after.json["total"], 21.65);
+    std::fs::remove_file(calendar).unwrap();
+}
+
+#[tokio::test]
+async fn order_date_looks_up_the_rate_in_effect_that_day() {
+    let mock = with_rate().await;
+    mock.respond_on(ZIP, "2019-06-01", MockResponse::rate("0.0625"));
+    let service = TestService::start(&mock, &[]).await;
+    let mut body: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["order_date"] = serde_json::json!("2019-06-01");
+
+    let past = service.post("/v1/compute", &body.to_string()).await;
+    let today = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(past.status, StatusCode::OK);
+    assert_eq!(past.json["tax_rate"], 0.0625);
+    assert_eq!(past.json["tax_amount"], 1.25);
+    assert_eq!(today.json["tax_rate"], 0.0825);
+}
+
+#[tokio::test]
+async fn embedded_table_keeps_the_rates_of_past_days() {
+    let mock = MockTaxService::start().await;
+    let table = std::env::temp_dir().join(format!("order_total_rates_{}.csv", std::process::id()));
+    std::fs::write(
+        &table,
+        "zip,rate,state,county,city,special,effective_from\n78701,0.0625,0.0625,,,,\n78701,0.0825,0.0625,,0.01,0.01,2020-01-01\n",
+    )
+    .unwrap();
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_RATE_SOURCE", "embedded"),
+            ("TAX_RATE_TABLE", table.to_str().unwrap()),
+        ],
+    )
+    .await;
+    let mut body: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["order_date"] = serde_json::json!("2019-12-31");
+    let before = service.post("/v1/compute", &body.to_string()).await;
+    body["order_date"] = serde_json::json!("2020-01-01");
+    let after = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(before.json["tax_rate"], 0.0625);
+    assert_eq!(after.json["tax_rate"], 0.0825);
+    assert_eq!(mock.hits(ZIP), 0);
+    std::fs::remove_file(table).unwrap();
+}
+
+#[tokio::test]
+async fn composite_rate_is_broken_down_by_jurisdiction_level() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate(
+            r#"{"rate":0.0825,"components":[{"level":"state","rate":0.0625},{"level":"city","rate":0.01},{"level":"special","rate":0.01}]}"#,
+        ),
+    );
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["tax_rate"], 0.0825);
+    assert_eq!(response.json["tax_amount"], 1.65);
+    assert_eq!(
+        response.json["tax_components"],
+        serde_json::json!([
+            {"level": "state", "rate": 0.0625, "amount": 1.25},
+            {"level": "city", "rate": 0.01, "amount": 0.2},
+            {"level": "special", "rate": 0.01, "amount": 0.2}
+        ])
+    );
+}
+
+#[tokio::test]
+async fn malformed_body_is_a_bad_request() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", "not an order").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json["status"], "error");
+    assert_eq!(
+        response.message(),
+        "invalid request: expected ident at line 1 column 2"
+    );
+    assert_eq!(response.json["code"], "INVALID_REQUEST");
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn a_value_of_the_wrong_type_is_reported_with_its_field_but_not_its_value() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut order: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    order["line_items"] = serde_json::json!([
+        {"product_id": 1, "quantity": 1, "unit_price": 10.0},
+        {"product_id": 2, "quantity": "two", "unit_price": 10.0}
+    ]);
+
+    let response = service.post("/v1/compute", &order.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json["code"], "INVALID_REQUEST");
+    assert_eq!(
+        response.json["errors"],
+        serde_json::json!([
+            {"field": "line_items[1].quantity", "message": "invalid type, expected i32"}
+        ])
+    );
+    assert!(response.message().contains(" at line 1 column "));
+    let body = String::from_utf8_lossy(&response.body);
+    assert!(!body.contains("two"), "{body}");
+}
+
+#[tokio::test]
+async fn a_missing_field_is_reported_with_its_path_and_line() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let body = r#"{
+        "order_id": 1,
+        "shipping_address": "123 Main St, Anytown USA",
+        "shipping_zip": "78701",
+        "line_items": [
+            {"product_id": 1, "quantity": 2}
+        ]
+    }"#;
+
+    let response = service.post("/v1/compute", body).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.message(),
+        "invalid request: missing field `unit_price` in line_items[0].unit_price at line 6 column 44"
+    );
+    assert_eq!(
+        response.json["errors"][0]["field"],
+        "line_items[0].unit_price"
+    );
+}
+
+#[tokio::test]
+async fn zip_plus_four_is_normalized_and_looked_up_by_its_zip() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", &order(" 787011234 ")).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["shipping_zip"], "78701-1234");
+    assert_eq!(response.json["tax_rate"], 0.0825);
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn malformed_zip_is_rejected_before_the_lookup() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", &order("7870")).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json["errors"],
+        serde_json::json!([{
+            "field": "shipping_zip",
+            "message": "must be a postal code of US, e.g. 78701 or 78701-1234"
+        }])
+    );
+    assert_eq!(mock.hits("7870"), 0);
+}
+
+#[tokio::test]
+async fn shipping_address_is_normalized_and_parsed() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut body: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["shipping_address"] = serde_json::json!("  123 n. Main St  ste 200 ,Austin,  tx 78701 ");
+    let abbreviated = service.post("/v1/compute", &body.to_string()).await;
+    body["shipping_address"] =
+        serde_json::json!("123 North Main Street, Suite 200, AUSTIN, TX 78701");
+    let spelled_out = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(abbreviated.status, StatusCode::OK);
+    assert_eq!(
+        abbreviated.json["shipping_address"],
+        "123 NORTH MAIN STREET SUITE 200, AUSTIN, TX 78701"
+    );
+    assert_eq!(
+        abbreviated.json["shipping_address_parts"],
+        serde_json::json!({
+            "street": "123 NORTH MAIN STREET",
+            "unit": "SUITE 200",
+            "city": "AUSTIN",
+            "region": "TX",
+            "postal_code": "78701"
+        })
+    );
+    assert_eq!(
+        spelled_out.json["shipping_address_parts"],
+        abbreviated.json["shipping_address_parts"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_order_lists_every_field_error() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let body = order("").replace("\"quantity\":2", "\"quantity\":0");
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<&str> = response.json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["shipping_zip", "quantity"]);
+}
+
+#[tokio::test]
+async fn amounts_too_large_to_compute_are_invalid() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut body: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["line_items"] = serde_json::json!([
+        {"product_id": 1, "quantity": 2_000_000_000, "unit_price": 1e27},
+        {"product_id": 2, "quantity": 1, "unit_price": 1e12},
+    ]);
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json["errors"],
+        serde_json::json!([
+            {"field": "line_items[0].quantity", "message": "must be at most 1000000"},
+            {"field": "line_items[0].unit_price", "message": "must be at most 1000000000000"},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn oversized_body_is_refused() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let body = " ".repeat(300 * 1024) + &order(ZIP);
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        response.message(),
+        "The request body exceeds the limit of 262144 bytes."
+    );
+}
+
+#[tokio::test]
+async fn unknown_zip_code_has_no_rate() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", &order("99999")).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        response.message(),
+        "The zip code in the order does not have a corresponding sales tax rate."
+    );
+    assert_eq!(response.json["code"], "TAX_RATE_UNAVAILABLE");
+    // A 404 is final; it is not retried.
+    assert_eq!(mock.hits("99999"), 1);
+}
+
+#[tokio::test]
+async fn unknown_zip_code_uses_the_default_rate_when_configured() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[("DEFAULT_TAX_RATE", "0.05")]).await;
+
+    let response = service.post("/v1/compute", &order("99999")).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.0);
+    assert_eq!(response.json["rate_source"], "default");
+}
+
+#[tokio::test]
+async fn failing_upstream_is_retried_then_reported_unavailable() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::status(StatusCode::INTERNAL_SERVER_ERROR));
+    let service = TestService::start(&mock, &[("TAX_SERVICE_MAX_ATTEMPTS", "3")]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        response.message(),
+        "The sales tax rate service could not be reached."
+    );
+    assert_eq!(mock.hits(ZIP), 3);
+}
+
+#[tokio::test]
+async fn garbled_rate_is_not_available() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("eight percent"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+/// A rate outside `0..1` is the rate service's fault, and is never used:
+/// one would make the tax negative, or larger than the order.
+#[tokio::test]
+async fn rate_out_of_range_is_an_upstream_error() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("70000000000000000000000000000"))
+        .respond("10001", MockResponse::rate("-0.05"));
+    let service = TestService::start(&mock, &[]).await;
+
+    for zip in [ZIP, "10001"] {
+        let response = service.post("/v1/compute", &order(zip)).await;
+
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE, "{zip}");
+        assert_eq!(response.json["code"], "UPSTREAM_UNAVAILABLE", "{zip}");
+    }
+}
+
+#[tokio::test]
+async fn slow_upstream_times_out() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_secs(2)),
+    );
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_SERVICE_TIMEOUT_MS", "100"),
+            ("TAX_SERVICE_MAX_ATTEMPTS", "1"),
+        ],
+    )
+    .await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(
+        response.message(),
+        "The sales tax rate service did not respond in time."
+    );
+    assert_eq!(response.json["code"], "UPSTREAM_TIMEOUT");
+}
+
+#[tokio::test]
+async fn request_deadline_bounds_the_lookup() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_secs(2)),
+    );
+    let service = TestService::start(&mock, &[]).await;
+
+    let started = std::time::Instant::now();
+    let response = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Request-Deadline-Ms", "100")],
+            &order(ZIP),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(response.json["code"], "DEADLINE_EXCEEDED");
+    assert_eq!(
+        response.message(),
+        "The request deadline passed before the order could be computed."
+    );
+    assert!(started.elapsed() < Duration::from_millis(900));
+    // A passed deadline is not retried.
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn server_wide_deadline_caps_the_requested_one() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_millis(300)),
+    );
+    let service = TestService::start(&mock, &[("REQUEST_DEADLINE_MS", "100")]).await;
+
+    let capped = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Request-Deadline-Ms", "5000")],
+            &order(ZIP),
+        )
+        .await;
+    let without_header = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(capped.json["code"], "DEADLINE_EXCEEDED");
+    assert_eq!(without_header.json["code"], "DEADLINE_EXCEEDED");
+
+    let generous = TestService::start(&mock, &[]).await;
+    let response = generous
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Request-Deadline-Ms", "5000")],
+            &order(ZIP),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn request_timeout_bounds_a_request_without_a_deadline() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_secs(2)),
+    );
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_SERVICE_TIMEOUT_MS", "5000"),
+            ("REQUEST_TIMEOUT_MS", "100"),
+        ],
+    )
+    .await;
+
+    let started = std::time::Instant::now();
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(response.json["code"], "DEADLINE_EXCEEDED");
+    assert!(response.json["request_id"].is_string());
+    assert!(started.elapsed() < Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn slow_lookup_is_hedged_to_the_next_replica() {
+    let slow = MockTaxService::start().await;
+    slow.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_secs(2)),
+    );
+    let fast = with_rate().await;
+    let urls = format!("{},{}", slow.url(), fast.url());
+    let service = TestService::start(
+        &slow,
+        &[
+            ("SALES_TAX_RATE_SERVICE", urls.as_str()),
+            ("TAX_SERVICE_HEDGE_PERCENTILE", "95"),
+            ("TAX_SERVICE_HEDGE_MIN_MS", "50"),
+        ],
+    )
+    .await;
+
+    let started = std::time::Instant::now();
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+    assert!(started.elapsed() < Duration::from_millis(900));
+    assert_eq!(slow.hits(ZIP), 1);
+    assert_eq!(fast.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn open_circuit_fails_fast() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::status(StatusCode::BAD_GATEWAY));
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_SERVICE_MAX_ATTEMPTS", "1"),
+            ("CIRCUIT_BREAKER_THRESHOLD", "2"),
+            ("CIRCUIT_BREAKER_OPEN_SECS", "30"),
+        ],
+    )
+    .await;
+
+    for _ in 0..2 {
+        let response = service.post("/v1/compute", &order(ZIP)).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        response.message(),
+        "The sales tax rate service is currently unavailable."
+    );
+    assert_eq!(response.header("retry-after"), Some("30"));
+    assert_eq!(mock.hits(ZIP), 2);
+}
+
+#[tokio::test]
+async fn orders_need_persistence() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    for path in ["/v1/orders", "/v1/orders/123"] {
+        let response = service.send(Method::GET, path, &[], "").await;
+        assert_eq!(response.status, StatusCode::NOT_IMPLEMENTED, "{path}");
+        assert_eq!(
+            response.message(),
+            "Order persistence is not enabled; set DATABASE_URL."
+        );
+    }
+}
+
+#[tokio::test]
+async fn idempotent_retry_is_replayed() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let headers = [("Idempotency-Key", "order-123")];
+
+    let first = service
+        .send(Method::POST, "/v1/compute", &headers, &order(ZIP))
+        .await;
+    let retry = service
+        .send(Method::POST, "/v1/compute", &headers, &order(ZIP))
+        .await;
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(retry.status, StatusCode::OK);
+    assert_eq!(retry.json, first.json);
+    assert_eq!(retry.header("idempotent-replayed"), Some("true"));
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn idempotency_key_reused_with_another_body() {
+    let mock = with_rate().await;
+    mock.respond("78702", MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let headers = [("Idempotency-Key", "order-123")];
+
+    service
+        .send(Method::POST, "/v1/compute", &headers, &order(ZIP))
+        .await;
+    let response = service
+        .send(Method::POST, "/v1/compute", &headers, &order("78702"))
+        .await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.message(),
+        "This Idempotency-Key was already used with a different request body."
+    );
+}
+
+#[tokio::test]
+async fn idempotency_key_in_flight() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_millis(300)),
+    );
+    let service = TestService::start(&mock, &[]).await;
+    let headers = [("Idempotency-Key", "order-123")];
+    let body = order(ZIP);
+
+    let (first, second) = tokio::join!(
+        service.send(Method::POST, "/v1/compute", &headers, &body),
+        async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            service
+                .send(Method::POST, "/v1/compute", &headers, &body)
+                .await
+        },
+    );
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(second.status, StatusCode::CONFLICT);
+    assert_eq!(
+        second.message(),
+        "A request with this Idempotency-Key is still being processed."
+    );
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_kept_apart_per_client() {
+    let mock = with_rate().await;
+    mock.respond("78702", MockResponse::rate("0.0825"));
+    let keys = std::env::temp_dir().join(format!(
+        "order_total_idempotency_keys_{}.csv",
+        std::process::id()
+    ));
+    std::fs::write(
+        &keys,
+        "key,client,requests_per_minute\nacme-key,acme,60\nglobex-key,globex,60\n",
+    )
+    .unwrap();
+    let service = TestService::start(&mock, &[("API_KEYS_FILE", keys.to_str().unwrap())]).await;
+    std::fs::remove_file(&keys).unwrap();
+
+    let acme = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Api-Key", "acme-key"), ("Idempotency-Key", "order-123")],
+            &order(ZIP),
+        )
+        .await;
+    let globex = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Api-Key", "globex-key"), ("Idempotency-Key", "order-123")],
+            &order("78702"),
+        )
+        .await;
+
+    assert_eq!(acme.status, StatusCode::OK);
+    assert_eq!(globex.status, StatusCode::OK);
+    assert_eq!(globex.header("idempotent-replayed"), None);
+    assert_eq!(globex.json["shipping_zip"], "78702");
+}
+
+#[tokio::test]
+async fn requests_beyond_max_in_flight_are_shed() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_millis(300)),
+    );
+    let service = TestService::start(
+        &mock,
+        &[("MAX_IN_FLIGHT", "1"), ("LOAD_SHED_RETRY_AFTER_SECS", "2")],
+    )
+    .await;
+    let body = order(ZIP);
+
+    let (first, second) = tokio::join!(service.post("/v1/compute", &body), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        service.post("/v1/compute", &body).await
+    });
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(second.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        second.message(),
+        "The service is at capacity; retry shortly."
+    );
+    assert_eq!(second.header("retry-after"), Some("2"));
+}
+
+#[tokio::test]
+async fn bearer_token_is_required_when_jwt_auth_is_on() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[("JWT_SECRET", "test-secret")]).await;
+    let claims = serde_json::json!({ "sub": "tester", "exp": 4_000_000_000u64 });
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+    let bearer = format!("Bearer {token}");
+
+    let anonymous = service.post("/v1/compute", &order(ZIP)).await;
+    let forged = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("Authorization", "Bearer not.a.token")],
+            &order(ZIP),
+        )
+        .await;
+    let authorized = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("Authorization", &bearer)],
+            &order(ZIP),
+        )
+        .await;
+
+    for response in [&anonymous, &forged] {
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(response.message(), "A valid bearer token is required.");
+        assert_eq!(response.header("www-authenticate"), Some("Bearer"));
+    }
+    assert_eq!(authorized.status, StatusCode::OK);
+    // The docs stay public.
+    let docs = service.send(Method::GET, "/openapi.json", &[], "").await;
+    assert_eq!(docs.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn api_keys_are_checked_and_rate_limited() {
+    let mock = with_rate().await;
+    let keys =
+        std::env::temp_dir().join(format!("order_total_api_keys_{}.csv", std::process::id()));
+    std::fs::write(
+        &keys,
+        "key,client,requests_per_minute\npartner-key,acme,2\n",
+    )
+    .unwrap();
+    let service = TestService::start(&mock, &[("API_KEYS_FILE", keys.to_str().unwrap())]).await;
+    std::fs::remove_file(&keys).unwrap();
+    let post = |key: &'static str| {
+        let service = &service;
+        async move {
+            service
+                .send(
+                    Method::POST,
+                    "/v1/compute",
+                    &[("X-Api-Key", key)],
+                    &order(ZIP),
+                )
+                .await
+        }
+    };
+
+    let unknown = post("guessed-key").await;
+    assert_eq!(unknown.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(unknown.message(), "A valid X-Api-Key is required.");
+
+    let first = post("partner-key").await;
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(first.header("x-ratelimit-limit"), Some("2"));
+    assert_eq!(first.header("x-ratelimit-remaining"), Some("1"));
+    assert_eq!(post("partner-key").await.status, StatusCode::OK);
+
+    let limited = post("partner-key").await;
+    assert_eq!(limited.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        limited.message(),
+        "The rate limit of this API key has been exceeded."
+    );
+    assert_eq!(limited.header("x-ratelimit-remaining"), Some("0"));
+    assert!(limited.header("retry-after").is_some());
+}
+
+#[tokio::test]
+async fn unknown_route_is_not_found() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.send(Method::GET, "/v1/nothing", &[], "").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+/// Every variant, including those a black-box test cannot provoke (a stored
+/// order lookup needs the `sqlite` feature, an unexpected failure a broken
+/// store), maps to its documented status and code.
+#[test]
+fn every_compute_error_maps_to_its_status() {
+    let quota = Quota {
+        limit: 1,
+        remaining: 0,
+        reset: Duration::from_secs(60),
+    };
+    let wait = Duration::from_secs(1);
+    let cases = [
+        (
+            ComputeError::InvalidRequest,
+            StatusCode::BAD_REQUEST,
+            "INVALID_REQUEST",
+        ),
+        (
+            ComputeError::Validation(vec![FieldError::new(
+                "quantity",
+                "must be greater than zero",
+            )]),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "VALIDATION_FAILED",
+        ),
+        (
+            ComputeError::PayloadTooLarge(1),
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "PAYLOAD_TOO_LARGE",
+        ),
+        (
+            ComputeError::UnsupportedEncoding,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "UNSUPPORTED_ENCODING",
+        ),
+        (
+            ComputeError::TaxRateNotAvailable,
+            StatusCode::SERVICE_UNAVAILABLE,
+            "TAX_RATE_UNAVAILABLE",
+        ),
+        (
+            ComputeError::FxRateNotAvailable,
+            StatusCode::SERVICE_UNAVAILABLE,
+            "FX_RATE_UNAVAILABLE",
+        ),
+        (
+            ComputeError::UpstreamUnavailable,
+            StatusCode::SERVICE_UNAVAILABLE,
+            "UPSTREAM_UNAVAILABLE",
+        ),
+        (
+            ComputeError::UpstreamTimeout,
+            StatusCode::GATEWAY_TIMEOUT,
+            "UPSTREAM_TIMEOUT",
+        ),
+        (
+            ComputeError::DeadlineExceeded,
+            StatusCode::GATEWAY_TIMEOUT,
+            "DEADLINE_EXCEEDED",
+        ),
+        (
+            ComputeError::CircuitOpen(wait),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "CIRCUIT_OPEN",
+        ),
+        (
+            ComputeError::OrderNotFound,
+            StatusCode::NOT_FOUND,
+            "ORDER_NOT_FOUND",
+        ),
+        (
+            ComputeError::PersistenceDisabled,
+            StatusCode::NOT_IMPLEMENTED,
+            "PERSISTENCE_DISABLED",
+        ),
+        (
+            ComputeError::JobNotFound,
+            StatusCode::NOT_FOUND,
+            "JOB_NOT_FOUND",
+        ),
+        (
+            ComputeError::JobNotFinished,
+            StatusCode::CONFLICT,
+            "JOB_NOT_FINISHED",
+        ),
+        (
+            ComputeError::QuoteInvalid,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "QUOTE_INVALID",
+        ),
+        (
+            ComputeError::QuoteExpired,
+            StatusCode::GONE,
+            "QUOTE_EXPIRED",
+        ),
+        (
+            ComputeError::QuoteAlreadyFinalized,
+            StatusCode::CONFLICT,
+            "QUOTE_ALREADY_FINALIZED",
+        ),
+        (
+            ComputeError::ExemptionRejected,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "EXEMPTION_REJECTED",
+        ),
+        (
+            ComputeError::IdempotencyKeyInFlight,
+            StatusCode::CONFLICT,
+            "IDEMPOTENCY_KEY_IN_FLIGHT",
+        ),
+        (
+            ComputeError::IdempotencyKeyReused,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "IDEMPOTENCY_KEY_REUSED",
+        ),
+        (
+            ComputeError::Unauthorized,
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+        ),
+        (
+            ComputeError::InvalidApiKey,
+            StatusCode::UNAUTHORIZED,
+            "INVALID_API_KEY",
+        ),
+        (
+            ComputeError::UnknownTenant,
+            StatusCode::BAD_REQUEST,
+            "UNKNOWN_TENANT",
+        ),
+        (
+            ComputeError::RateLimited(quota, wait),
+            StatusCode::TOO_MANY_REQUESTS,
+            "RATE_LIMITED",
+        ),
+        (
+            ComputeError::Throttled(quota, wait),
+            StatusCode::TOO_MANY_REQUESTS,
+            "RATE_LIMITED",
+        ),
+        (
+            ComputeError::Overloaded(wait),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "OVERLOADED",
+        ),
+        (
+            ComputeError::Unexpected("disk full".into()),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL_ERROR",
+        ),
+    ];
+    for (error, status, code) in cases {
+        let name = format!("{error:?}");
+        assert_eq!(serde_json::to_value(error.code()).unwrap(), code, "{name}");
+        let (actual, body) = error.into_parts();
+        assert_eq!(actual.as_u16(), status.as_u16(), "{name}");
+        let body = serde_json::to_value(body).unwrap();
+        assert_eq!(body["status"], "error", "{name}");
+        assert_eq!(body["code"], code, "{name}");
+        assert!(!body["message"].as_str().unwrap().is_empty(), "{name}");
+    }
+}
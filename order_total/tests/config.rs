@@ -0,0 +1,191 @@
+//! The `--config` file, its validation, and the environment variables that
+//! override it.
+#![cfg(all(feature = "native", feature = "server"))]
+
+use order_total::Config;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn config_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "order_total_config_{name}_{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn load_error(name: &str, contents: &str) -> String {
+    let path = config_file(name, contents);
+    let err = Config::load(&path).unwrap_err().to_string();
+    std::fs::remove_file(&path).unwrap();
+    assert!(err.contains(path.to_str().unwrap()), "{err}");
+    err
+}
+
+/// Runs the server binary, which exits before listening when its settings
+/// are invalid.
+fn run(args: &[&str], env: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_order_total"))
+        .args(args)
+        .env_remove("RUNTIME_FLAVOR")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn settings_stand_for_environment_variables() {
+    let path = config_file(
+        "vars",
+        r#"
+[server]
+port = 9000
+runtime_flavor = "current_thread"
+
+[upstream]
+urls = ["http://rates-a:8001/find_rate", "http://rates-b:8001/find_rate"]
+shadow_urls = ["http://vendor:8001/find_rate"]
+tax_rate_source = "fallback"
+default_tax_rate = 0.0825
+timeout_ms = 1500
+
+[cache]
+secs = 300
+warm_zips = ["78701", "10001"]
+
+[auth]
+admin_token = "admin-token"
+
+[logging]
+level = "order_total=debug"
+format = "json"
+"#,
+    );
+
+    let vars = Config::load(&path).unwrap().vars();
+
+    assert_eq!(
+        vars,
+        [
+            ("PORT", "9000"),
+            ("RUNTIME_FLAVOR", "current_thread"),
+            (
+                "SALES_TAX_RATE_SERVICE",
+                "http://rates-a:8001/find_rate,http://rates-b:8001/find_rate"
+            ),
+            ("SHADOW_TAX_RATE_SERVICE", "http://vendor:8001/find_rate"),
+            ("TAX_RATE_SOURCE", "fallback"),
+            ("DEFAULT_TAX_RATE", "0.0825"),
+            ("TAX_SERVICE_TIMEOUT_MS", "1500"),
+            ("RATE_CACHE_SECS", "300"),
+            ("CACHE_WARM_ZIPS", "78701,10001"),
+            ("ADMIN_TOKEN", "admin-token"),
+            ("RUST_LOG", "order_total=debug"),
+            ("LOG_FORMAT", "json"),
+        ]
+        .map(|(name, value)| (name, value.to_owned()))
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn features_stand_for_the_feature_flags() {
+    let path = config_file(
+        "features",
+        r#"
+[features]
+flags = { batch = false, order_rounding = true }
+
+[features.tenants.acme]
+batch = true
+"#,
+    );
+
+    let vars = Config::load(&path).unwrap().vars();
+
+    assert_eq!(
+        vars,
+        [(
+            "FEATURE_FLAGS",
+            "batch=off,order_rounding=on,acme:batch=on".to_owned()
+        )]
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn invalid_settings_are_named() {
+    let misspelt = load_error("misspelt", "[upstream]\ntimout_ms = 1500\n");
+    let mistyped = load_error("mistyped", "[server]\nport = \"eighty\"\n");
+    let unknown_source = load_error("source", "[upstream]\ntax_rate_source = \"guess\"\n");
+    let no_fixed_rate = load_error("fixed", "[upstream]\ntax_rate_source = \"fixed\"\n");
+    let bad_url = load_error("url", "[upstream]\nurls = [\"not a url\"]\n");
+    let unknown_flag = load_error("flag", "[features.tenants.acme]\nbatches = true\n");
+
+    assert!(misspelt.contains("timout_ms"), "{misspelt}");
+    assert!(mistyped.contains("port"), "{mistyped}");
+    assert!(mistyped.contains("line 2"), "{mistyped}");
+    assert!(unknown_source.contains("`embedded`"), "{unknown_source}");
+    assert!(
+        no_fixed_rate.contains("upstream.fixed_tax_rate"),
+        "{no_fixed_rate}"
+    );
+    assert!(bad_url.contains("upstream.urls[0]"), "{bad_url}");
+    assert!(
+        unknown_flag.contains("features.tenants.acme") && unknown_flag.contains("\"batches\""),
+        "{unknown_flag}"
+    );
+}
+
+#[test]
+fn an_invalid_file_stops_the_server_from_starting() {
+    let path = config_file("startup", "[cache]\nsecs = -1\n");
+
+    let output = run(&["--config", path.to_str().unwrap()], &[]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr.contains(path.to_str().unwrap()), "{stderr}");
+    assert!(stderr.contains("secs"), "{stderr}");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn the_environment_overrides_the_file() {
+    let path = config_file(
+        "override",
+        "[server]\nruntime_flavor = \"current_thread\"\n",
+    );
+
+    let output = run(
+        &["--config", path.to_str().unwrap()],
+        &[("RUNTIME_FLAVOR", "sideways")],
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr.contains("invalid runtime flavor \"sideways\""),
+        "{stderr}"
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn an_unparseable_environment_value_stops_the_server_from_starting() {
+    let path = config_file("unparseable", "[upstream]\ntimeout_ms = 1500\n");
+
+    let output = run(
+        &["--config", path.to_str().unwrap()],
+        &[("TAX_SERVICE_TIMEOUT_MS", "soon")],
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stdout.contains("invalid TAX_SERVICE_TIMEOUT_MS \"soon\""),
+        "{stdout}"
+    );
+    std::fs::remove_file(path).unwrap();
+}
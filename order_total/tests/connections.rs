@@ -0,0 +1,156 @@
+//! HTTP/2 and the settings of the connections the server keeps.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{app, order, MockResponse, MockTaxService};
+use hyper::{Method, Request, StatusCode, Version};
+use order_total::Body;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const ZIP: &str = "78701";
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+async fn serve(mock: &MockTaxService, env: &[(&str, &str)]) -> SocketAddr {
+    let (addr, server) = order_total::bind(
+        app(mock, env),
+        ([127, 0, 0, 1], 0).into(),
+        std::future::pending(),
+    )
+    .unwrap();
+    tokio::spawn(server);
+    addr
+}
+
+/// Sends a keep-alive request on `stream` and reads the whole response.
+async fn get_root(stream: &mut TcpStream) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![0; 64 * 1024];
+    let read = stream.read(&mut buf).await.unwrap();
+    String::from_utf8_lossy(&buf[..read]).into_owned()
+}
+
+#[tokio::test]
+async fn http2_is_served_with_prior_knowledge() {
+    let mock = with_rate().await;
+    let addr = serve(&mock, &[]).await;
+    let client = common::http2_client();
+
+    let requests = (0..3).map(|_| {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://{addr}/v1/compute"))
+            .body(Body::from(order(ZIP)))
+            .unwrap();
+        client.request(request)
+    });
+    let responses = futures_util::future::try_join_all(requests).await.unwrap();
+
+    for response in responses {
+        assert_eq!(response.version(), Version::HTTP_2);
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = common::to_bytes(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["total"], 21.65);
+    }
+}
+
+#[tokio::test]
+async fn idle_connections_are_closed() {
+    let mock = with_rate().await;
+    let addr = serve(&mock, &[("HTTP_IDLE_TIMEOUT_SECS", "1")]).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let first = get_root(&mut stream).await;
+    let mut rest = Vec::new();
+    let closed = tokio::time::timeout(Duration::from_secs(3), stream.read_to_end(&mut rest)).await;
+
+    assert!(first.starts_with("HTTP/1.1 200"), "{first}");
+    assert!(closed.is_ok(), "the idle connection was kept open");
+}
+
+#[tokio::test]
+async fn a_slow_request_does_not_leave_its_connection_idle() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_millis(1500)),
+    );
+    let env = [
+        ("HTTP_IDLE_TIMEOUT_SECS", "1"),
+        ("TAX_SERVICE_TIMEOUT_MS", "5000"),
+    ];
+    let addr = serve(&mock, &env).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let body = order(ZIP);
+
+    let request = format!(
+        "POST /v1/compute HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut buf = vec![0; 64 * 1024];
+    let read = stream.read(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf[..read]);
+
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.contains("21.65"), "{response}");
+}
+
+#[tokio::test]
+async fn a_stalled_request_body_times_out() {
+    let mock = with_rate().await;
+    let addr = serve(&mock, &[("REQUEST_TIMEOUT_MS", "200")]).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // Announces more body than it sends, and then sends nothing.
+    let request = "POST /v1/compute HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\n{";
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut buf = vec![0; 64 * 1024];
+    let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf))
+        .await
+        .expect("the stalled request was not answered")
+        .unwrap();
+    let response = String::from_utf8_lossy(&buf[..read]);
+
+    assert!(response.starts_with("HTTP/1.1 504"), "{response}");
+    assert!(response.contains("DEADLINE_EXCEEDED"), "{response}");
+}
+
+#[tokio::test]
+async fn connections_are_kept_alive_by_default() {
+    let mock = with_rate().await;
+    let addr = serve(&mock, &[]).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let first = get_root(&mut stream).await;
+    let second = get_root(&mut stream).await;
+
+    assert!(first.starts_with("HTTP/1.1 200"), "{first}");
+    assert!(second.starts_with("HTTP/1.1 200"), "{second}");
+}
+
+#[tokio::test]
+async fn keep_alive_can_be_turned_off() {
+    let mock = with_rate().await;
+    let addr = serve(&mock, &[("HTTP1_KEEPALIVE", "false")]).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let first = get_root(&mut stream).await;
+    let mut rest = Vec::new();
+    let closed = tokio::time::timeout(Duration::from_secs(1), stream.read_to_end(&mut rest)).await;
+
+    assert!(first.starts_with("HTTP/1.1 200"), "{first}");
+    assert!(closed.is_ok(), "the connection was kept open");
+}
@@ -0,0 +1,123 @@
+//! Orders priced in another currency than the dollar, rounded to its minor
+//! unit, and totals converted at the configured exchange rates.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use serde_json::{json, Value};
+
+const ZIP: &str = "78701";
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+fn priced_in(currency: &str, subtotal: f64) -> Value {
+    let mut order: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    order["currency"] = json!(currency);
+    order["subtotal"] = json!(subtotal);
+    order
+}
+
+#[tokio::test]
+async fn yen_orders_are_rounded_to_whole_yen() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post("/v1/compute", &priced_in("JPY", 1999.0).to_string())
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["currency"], "JPY");
+    assert_eq!(response.json["tax_amount"], 165.0);
+    assert_eq!(response.json["total"], 2164.0);
+}
+
+#[tokio::test]
+async fn unknown_currencies_fail_validation() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut body = priced_in("XYZ", 20.0);
+    body["convert_to"] = json!("dollars");
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<&str> = response.json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["currency", "convert_to"]);
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn totals_are_converted_at_the_configured_rates() {
+    let mock = with_rate().await;
+    let table = std::env::temp_dir().join(format!("order_total_fx_{}.csv", std::process::id()));
+    std::fs::write(&table, "from,to,rate\nUSD,EUR,0.92\nUSD,JPY,150\n").unwrap();
+    let service = TestService::start(&mock, &[("FX_RATE_TABLE", table.to_str().unwrap())]).await;
+    let mut body: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["convert_to"] = json!("eur");
+    let euros = service.post("/v1/compute", &body.to_string()).await;
+    body["convert_to"] = json!("JPY");
+    let yen = service.post("/v1/compute", &body.to_string()).await;
+    let mut body = priced_in("EUR", 18.4);
+    body["convert_to"] = json!("USD");
+    let dollars = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(euros.status, StatusCode::OK);
+    assert_eq!(euros.json["total"], 21.65);
+    assert_eq!(
+        euros.json["converted"],
+        json!({
+            "currency": "EUR",
+            "fx_rate": 0.92,
+            "subtotal": 18.4,
+            "tax_amount": 1.52,
+            "total": 19.92
+        })
+    );
+    assert_eq!(yen.json["converted"]["total"], 3248.0);
+    assert_eq!(dollars.json["converted"]["subtotal"], 20.0);
+    std::fs::remove_file(table).unwrap();
+}
+
+#[tokio::test]
+async fn missing_exchange_rate_is_unavailable() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut body: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["convert_to"] = json!("GBP");
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.json["code"], "FX_RATE_UNAVAILABLE");
+}
+
+#[tokio::test]
+async fn a_conversion_sent_with_the_order_is_not_echoed() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut body = priced_in("USD", 20.0);
+    body["converted"] = json!({
+        "currency": "EUR",
+        "fx_rate": 1000.0,
+        "subtotal": 20000.0,
+        "tax_amount": 0.0,
+        "total": 20000.0
+    });
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json.get("converted"), None);
+}
@@ -0,0 +1,139 @@
+//! Errors reported to an in-process stand-in for Sentry.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::header::HeaderMap;
+use hyper::{Request, Response, StatusCode};
+use order_total::{Body, OrderTotalService};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower_service::Service;
+
+const ZIP: &str = "78701";
+
+/// Records the events of every envelope it gets, with the headers and path
+/// they came with.
+struct Sentry {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<(String, HeaderMap, Value)>>>,
+}
+
+impl Sentry {
+    async fn start() -> Self {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let shared = received.clone();
+        let addr = common::serve(move |req| record(req, shared.clone())).await;
+        Self { addr, received }
+    }
+
+    fn dsn(&self) -> String {
+        format!("http://public-key@{}/42", self.addr)
+    }
+
+    /// Waits for `count` events to have arrived.
+    async fn events(&self, count: usize) -> Vec<(String, HeaderMap, Value)> {
+        for _ in 0..200 {
+            let events = self.received.lock().unwrap().clone();
+            if events.len() >= count {
+                return events;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{count} events did not arrive");
+    }
+}
+
+/// Keeps the event of an envelope: its third line, after the envelope's
+/// header and the item's.
+async fn record(
+    req: Request<Body>,
+    received: Arc<Mutex<Vec<(String, HeaderMap, Value)>>>,
+) -> Result<Response<Body>, Infallible> {
+    let path = req.uri().path().to_owned();
+    let headers = req.headers().clone();
+    let body = common::to_bytes(req.into_body()).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let event = serde_json::from_str(body.lines().nth(2).unwrap()).unwrap();
+    received.lock().unwrap().push((path, headers, event));
+    Ok(Response::default())
+}
+
+#[tokio::test]
+async fn a_panic_is_reported_with_its_request() {
+    let mock = MockTaxService::start().await;
+    let sentry = Sentry::start().await;
+    let dsn = sentry.dsn();
+    let mut service = OrderTotalService::new(common::app(
+        &mock,
+        &[("SENTRY_DSN", &dsn), ("SENTRY_ENVIRONMENT", "staging")],
+    ));
+
+    let response = service
+        .call(
+            Request::post("/v1/compute")
+                .header("X-Request-Id", "panicking-order")
+                .body(common::panicking_body())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let events = sentry.events(1).await;
+    let (path, headers, event) = &events[0];
+    assert_eq!(path, "/api/42/envelope/");
+    let auth = headers.get("x-sentry-auth").unwrap().to_str().unwrap();
+    assert!(auth.contains("sentry_key=public-key"), "{auth}");
+    assert_eq!(event["level"], "error");
+    assert_eq!(event["environment"], "staging");
+    assert_eq!(event["exception"]["values"][0]["type"], "panic");
+    assert_eq!(
+        event["exception"]["values"][0]["value"],
+        "the request body broke"
+    );
+    assert!(event["extra"]["location"].as_str().unwrap().contains(".rs"));
+    assert_eq!(event["request"]["method"], "POST");
+    assert_eq!(event["request"]["url"], "/v1/compute");
+    assert_eq!(event["tags"]["request_id"], "panicking-order");
+    assert_eq!(event["tags"]["route"], "/v1/compute");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(sentry.received.lock().unwrap().len(), 1, "reported once");
+}
+
+#[tokio::test]
+async fn an_unavailable_rate_service_is_reported_as_a_warning() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::status(StatusCode::SERVICE_UNAVAILABLE));
+    let sentry = Sentry::start().await;
+    let service = TestService::start(&mock, &[("SENTRY_DSN", &sentry.dsn())]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    let events = sentry.events(1).await;
+    let (_, _, event) = &events[0];
+    assert_eq!(event["level"], "warning");
+    assert_eq!(
+        event["exception"]["values"][0]["type"],
+        "UpstreamUnavailable"
+    );
+    assert_eq!(event["request"]["url"], "/v1/compute");
+}
+
+#[tokio::test]
+async fn a_client_error_is_not_reported() {
+    let mock = MockTaxService::start().await;
+    let sentry = Sentry::start().await;
+    let service = TestService::start(&mock, &[("SENTRY_DSN", &sentry.dsn())]).await;
+
+    let response = service.post("/v1/compute", "not json").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(sentry.received.lock().unwrap().is_empty());
+}
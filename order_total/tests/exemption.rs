@@ -0,0 +1,109 @@
+//! Tax-exempt purchases with an `exemption_certificate`, against a mock
+//! sales tax rate service and a mock certificate verifier.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Request, Response, StatusCode};
+use order_total::Body;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+
+const ZIP: &str = "78701";
+
+fn exempt_order(certificate: Value) -> String {
+    let mut order: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    order["exemption_certificate"] = certificate;
+    order.to_string()
+}
+
+/// A verifier that accepts the certificates whose id starts with `OK`.
+async fn start_verifier() -> String {
+    let addr = common::serve(|req: Request<Body>| async move {
+        let body = common::to_bytes(req.into_body()).await;
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        let id = request["certificate"]["id"].as_str().unwrap_or_default();
+        let mut response = Response::new(Body::empty());
+        if !id.starts_with("OK") {
+            *response.status_mut() = StatusCode::FORBIDDEN;
+        }
+        Ok::<_, Infallible>(response)
+    })
+    .await;
+    format!("http://{addr}/verify")
+}
+
+#[tokio::test]
+async fn exempt_order_is_computed_without_tax() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let certificate = json!({"id": "TX-1234567", "jurisdiction": "TX", "holder": "Acme Co"});
+
+    let response = service
+        .post("/v1/compute", &exempt_order(certificate.clone()))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 20.0);
+    assert_eq!(response.json["tax_amount"], 0.0);
+    assert_eq!(response.json["tax_rate"], 0.0);
+    assert_eq!(response.json["rate_source"], "exempt");
+    assert_eq!(response.json["exemption_certificate"], certificate);
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn malformed_certificate_is_invalid() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post(
+            "/v1/compute",
+            &exempt_order(json!({"id": "no!", "jurisdiction": "CA"})),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<&str> = response.json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            "exemption_certificate.id",
+            "exemption_certificate.jurisdiction"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn verifier_decides_whether_a_certificate_exempts() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let verifier = start_verifier().await;
+    let service = TestService::start(&mock, &[("EXEMPTION_VERIFIER_URL", &verifier)]).await;
+
+    let accepted = service
+        .post(
+            "/v1/compute",
+            &exempt_order(json!({"id": "OK-1234", "jurisdiction": "TX"})),
+        )
+        .await;
+    let rejected = service
+        .post(
+            "/v1/compute",
+            &exempt_order(json!({"id": "REVOKED-1", "jurisdiction": "TX"})),
+        )
+        .await;
+
+    assert_eq!(accepted.status, StatusCode::OK);
+    assert_eq!(accepted.json["tax_amount"], 0.0);
+    assert_eq!(rejected.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(rejected.json["code"], "EXEMPTION_REJECTED");
+}
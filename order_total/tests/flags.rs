@@ -0,0 +1,120 @@
+//! Feature flags of `FEATURE_FLAGS`, for the service and per tenant.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, with_env, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+use serde_json::{json, Value};
+
+const ZIP: &str = "78701";
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+fn rounded() -> String {
+    let mut body: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    body["rounding"] = json!({"mode": "up"});
+    body.to_string()
+}
+
+fn fields(errors: &Value) -> Vec<&str> {
+    errors
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn the_batch_endpoints_are_not_served_with_batch_off() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[("FEATURE_FLAGS", "batch=off")]).await;
+
+    let job = service.post("/v1/jobs", &format!("[{}]", order(ZIP))).await;
+    let stream = service.post("/v1/compute_stream", &order(ZIP)).await;
+    let single = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(job.status, StatusCode::NOT_FOUND);
+    assert_eq!(stream.status, StatusCode::NOT_FOUND);
+    assert_eq!(single.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn orders_may_not_ask_for_what_is_off() {
+    let mock = with_rate().await;
+    let env = [(
+        "FEATURE_FLAGS",
+        "order_rounding=off, currency_conversion=false",
+    )];
+    let service = TestService::start(&mock, &env).await;
+    let mut body: Value = serde_json::from_str(&rounded()).unwrap();
+    body["convert_to"] = json!("EUR");
+
+    let refused = service.post("/v1/compute", &body.to_string()).await;
+    let plain = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(refused.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(refused.json["code"], "VALIDATION_FAILED");
+    assert_eq!(fields(&refused.json["errors"]), ["rounding", "convert_to"]);
+    assert_eq!(plain.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn a_tenant_setting_wins_over_the_service() {
+    let mock = with_rate().await;
+    let tenants = std::env::temp_dir().join(format!(
+        "order_total_flags_tenants_{}.csv",
+        std::process::id()
+    ));
+    std::fs::write(&tenants, "tenant\nacme\nglobex\n").unwrap();
+    let env = [
+        ("TENANTS_FILE", tenants.to_str().unwrap()),
+        ("FEATURE_FLAGS", "order_rounding=off,acme:order_rounding=on"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+    let as_tenant = |tenant| [("X-Tenant-Id", tenant)];
+
+    let acme = service
+        .send(Method::POST, "/v1/compute", &as_tenant("acme"), &rounded())
+        .await;
+    let globex = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &as_tenant("globex"),
+            &rounded(),
+        )
+        .await;
+    let untenanted = service.post("/v1/compute", &rounded()).await;
+
+    assert_eq!(acme.status, StatusCode::OK);
+    assert_eq!(globex.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(untenanted.status, StatusCode::UNPROCESSABLE_ENTITY);
+    std::fs::remove_file(&tenants).ok();
+}
+
+#[tokio::test]
+async fn a_reload_reads_the_flags_anew() {
+    let mock = with_rate().await;
+    let admin = ("ADMIN_TOKEN", "admin-token");
+    let service = TestService::start(&mock, &[admin, ("FEATURE_FLAGS", "batch=off")]).await;
+    let reload = service.send(
+        Method::POST,
+        "/admin/reload",
+        &[("Authorization", "Bearer admin-token")],
+        "",
+    );
+
+    let before = service.post("/v1/compute_stream", &order(ZIP)).await;
+    let reloaded = with_env(&mock, &[admin, ("FEATURE_FLAGS", "batch=on")], reload).await;
+    let after = service.post("/v1/compute_stream", &order(ZIP)).await;
+
+    assert_eq!(before.status, StatusCode::NOT_FOUND);
+    assert_eq!(reloaded.status, StatusCode::OK);
+    assert_eq!(after.status, StatusCode::OK);
+}
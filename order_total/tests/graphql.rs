@@ -0,0 +1,115 @@
+//! The GraphQL API at `/graphql`, against a mock sales tax rate service.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+
+const ZIP: &str = "78701";
+
+const COMPUTE_ORDER: &str = "mutation Compute($order: OrderInput!) {
+    computeOrder(order: $order) { orderId total taxAmount taxRate rateSource jurisdiction computedAt }
+}";
+
+fn variables(zip: &str, quantity: i32) -> serde_json::Value {
+    serde_json::json!({
+        "order": {
+            "orderId": 123,
+            "productId": 321,
+            "quantity": quantity,
+            "subtotal": "20.00",
+            "shippingAddress": "123 Main St, Anytown USA",
+            "shippingZip": zip,
+        }
+    })
+}
+
+fn query(query: &str, variables: serde_json::Value) -> String {
+    serde_json::json!({ "query": query, "variables": variables }).to_string()
+}
+
+#[tokio::test]
+async fn computes_an_order() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post("/graphql", &query(COMPUTE_ORDER, variables(ZIP, 2)))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["errors"], serde_json::Value::Null);
+    let order = &response.json["data"]["computeOrder"];
+    assert_eq!(order["orderId"], 123);
+    assert_eq!(order["total"], "21.65");
+    assert_eq!(order["taxAmount"], "1.65");
+    assert_eq!(order["taxRate"], "0.0825");
+    assert_eq!(order["rateSource"], "LOOKUP");
+    assert_eq!(order["jurisdiction"], "TX");
+    assert_eq!(order["computedAt"], serde_json::Value::Null);
+    assert_eq!(response.header("deprecation"), None);
+}
+
+#[tokio::test]
+async fn reports_compute_errors_with_their_code() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post("/graphql", &query(COMPUTE_ORDER, variables(ZIP, 0)))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["data"], serde_json::Value::Null);
+    let error = &response.json["errors"][0];
+    assert_eq!(error["path"][0], "computeOrder");
+    assert_eq!(error["extensions"]["code"], "VALIDATION_FAILED");
+    assert_eq!(error["extensions"]["errors"][0]["field"], "quantity");
+    assert!(error["extensions"]["requestId"].is_string());
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn order_queries_need_persistence() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post(
+            "/graphql",
+            &query("{ orders(limit: 5) { orderId } }", serde_json::json!({})),
+        )
+        .await;
+
+    assert_eq!(
+        response.json["errors"][0]["extensions"]["code"],
+        "PERSISTENCE_DISABLED"
+    );
+}
+
+#[tokio::test]
+async fn rejects_a_body_that_is_not_a_graphql_request() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/graphql", "not json").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json["code"], "INVALID_REQUEST");
+}
+
+#[tokio::test]
+async fn the_schema_is_public_but_queries_need_credentials() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[("JWT_SECRET", "test-secret")]).await;
+
+    let schema = service.send(Method::GET, "/schema.graphql", &[], "").await;
+    let graphql = service
+        .post("/graphql", &query("{ __typename }", serde_json::json!({})))
+        .await;
+
+    assert_eq!(schema.status, StatusCode::OK);
+    assert_eq!(graphql.status, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,147 @@
+//! The gRPC interface on `GRPC_PORT`, driven with hand-framed HTTP/2
+//! requests, as the generated code has no client without tonic's transport.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{MockResponse, MockTaxService};
+use http_body_util::BodyExt;
+use hyper::Request;
+use order_total::grpc::proto::{self, ComputeOrderRequest, ComputeOrderResponse, ErrorDetail};
+use order_total::Body;
+use prost::Message;
+use std::net::SocketAddr;
+use tonic::{Code, Status};
+
+const ZIP: &str = "78701";
+const COMPUTE_ORDER: &str = "/order_total.v1.OrderTotal/ComputeOrder";
+
+async fn start(mock: &MockTaxService, env: &[(&str, &str)]) -> SocketAddr {
+    let (addr, server) = order_total::grpc::bind(
+        common::app(mock, env),
+        ([127, 0, 0, 1], 0).into(),
+        std::future::pending(),
+    )
+    .unwrap();
+    tokio::spawn(server);
+    addr
+}
+
+/// Sends one `ComputeOrder` call and returns its response message, or the
+/// status it failed with.
+async fn compute_order(
+    addr: SocketAddr,
+    order: proto::Order,
+    headers: &[(&str, &str)],
+) -> Result<ComputeOrderResponse, Status> {
+    let message = ComputeOrderRequest { order: Some(order) }.encode_to_vec();
+    let mut frame = vec![0];
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+
+    let mut request = Request::post(format!("http://{addr}{COMPUTE_ORDER}"))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = common::http2_client()
+        .request(request.body(Body::from(frame)).unwrap())
+        .await
+        .unwrap();
+    let headers = response.headers().clone();
+    let body = response.into_body().collect().await.unwrap();
+    let trailers = body.trailers().cloned().unwrap_or_default();
+    let data = body.to_bytes();
+
+    // An error without a message is answered with the status in the headers.
+    let status = Status::from_header_map(&trailers)
+        .or_else(|| Status::from_header_map(&headers))
+        .expect("a grpc-status");
+    if status.code() != Code::Ok {
+        return Err(status);
+    }
+    Ok(ComputeOrderResponse::decode(data.slice(5..)).unwrap())
+}
+
+fn order(zip: &str) -> proto::Order {
+    proto::Order {
+        order_id: 123,
+        product_id: 321,
+        quantity: 2,
+        subtotal: "20.00".into(),
+        shipping_address: "123 Main St, Anytown USA".into(),
+        shipping_zip: zip.into(),
+        ..Default::default()
+    }
+}
+
+fn detail(status: &Status) -> ErrorDetail {
+    ErrorDetail::decode(status.details()).unwrap()
+}
+
+#[tokio::test]
+async fn computes_an_order() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let addr = start(&mock, &[]).await;
+
+    let response = compute_order(addr, order(ZIP), &[]).await.unwrap();
+
+    let order = response.order.unwrap();
+    assert_eq!(order.total, "21.65");
+    assert_eq!(order.tax_amount, "1.65");
+    assert_eq!(order.tax_rate.as_deref(), Some("0.0825"));
+    assert_eq!(order.rate_source(), proto::RateSource::Lookup);
+    assert_eq!(order.jurisdiction.as_deref(), Some("TX"));
+}
+
+#[tokio::test]
+async fn reports_invalid_decimals_as_field_errors() {
+    let mock = MockTaxService::start().await;
+    let addr = start(&mock, &[]).await;
+    let mut order = order(ZIP);
+    order.subtotal = "twenty".into();
+
+    let status = compute_order(addr, order, &[]).await.unwrap_err();
+
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let detail = detail(&status);
+    assert_eq!(detail.code, "VALIDATION_FAILED");
+    assert_eq!(detail.errors.len(), 1);
+    assert_eq!(detail.errors[0].field, "subtotal");
+    assert!(!detail.request_id.is_empty());
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn maps_an_unknown_zip_code_to_failed_precondition() {
+    let mock = MockTaxService::start().await;
+    let addr = start(&mock, &[]).await;
+
+    let status = compute_order(addr, order("00000"), &[]).await.unwrap_err();
+
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(detail(&status).code, "TAX_RATE_UNAVAILABLE");
+}
+
+#[tokio::test]
+async fn requires_a_bearer_token_when_authentication_is_on() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let addr = start(&mock, &[("JWT_SECRET", "test-secret")]).await;
+
+    let status = compute_order(addr, order(ZIP), &[]).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(detail(&status).code, "UNAUTHORIZED");
+
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &serde_json::json!({ "sub": "grpc-caller", "exp": u32::MAX }),
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+    let authorization = format!("Bearer {token}");
+    let response = compute_order(addr, order(ZIP), &[("authorization", &authorization)]).await;
+    assert_eq!(response.unwrap().order.unwrap().total, "21.65");
+}
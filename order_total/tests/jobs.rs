@@ -0,0 +1,101 @@
+//! Background batch jobs at `/v1/jobs`, against a mock sales tax rate service.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestResponse, TestService};
+use hyper::{Method, StatusCode};
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+
+fn batch(orders: &[String]) -> String {
+    format!("[{}]", orders.join(","))
+}
+
+async fn get(service: &TestService, path: &str) -> TestResponse {
+    service.send(Method::GET, path, &[], "").await
+}
+
+/// Polls the job at `location` until it has finished.
+async fn finished(service: &TestService, location: &str) -> TestResponse {
+    for _ in 0..200 {
+        let status = get(service, location).await;
+        if status.json["status"] == "finished" {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the job at {location} did not finish");
+}
+
+#[tokio::test]
+async fn computes_a_batch_in_the_background() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let orders = [order(ZIP), order("00000"), "\"not an order\"".to_owned()];
+
+    let queued = service.post("/v1/jobs", &batch(&orders)).await;
+
+    assert_eq!(queued.status, StatusCode::ACCEPTED);
+    assert_eq!(queued.json["total"], 3);
+    let location = queued.header("location").unwrap().to_owned();
+    assert_eq!(
+        location,
+        format!("/v1/jobs/{}", queued.json["job_id"].as_str().unwrap())
+    );
+
+    let status = finished(&service, &location).await;
+    assert_eq!(status.json["completed"], 3);
+    assert_eq!(status.json["failed"], 2);
+    assert!(status.json["finished_at"].is_string());
+
+    let result = get(&service, &format!("{location}/result")).await;
+    assert_eq!(result.status, StatusCode::OK);
+    let results = result.json["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0]["total"], 21.65);
+    assert_eq!(results[1]["code"], "TAX_RATE_UNAVAILABLE");
+    assert_eq!(results[2]["code"], "INVALID_REQUEST");
+}
+
+#[tokio::test]
+async fn queues_jobs_beyond_the_worker_count() {
+    let mock = MockTaxService::start().await;
+    mock.respond(
+        ZIP,
+        MockResponse::rate("0.0825").delayed(Duration::from_millis(300)),
+    );
+    let service = TestService::start(&mock, &[("JOB_WORKERS", "1")]).await;
+
+    let first = service.post("/v1/jobs", &batch(&[order(ZIP)])).await;
+    let second = service.post("/v1/jobs", &batch(&[order(ZIP)])).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let location = second.header("location").unwrap();
+    assert_eq!(get(&service, location).await.json["status"], "queued");
+    let early = get(&service, &format!("{location}/result")).await;
+    assert_eq!(early.status, StatusCode::CONFLICT);
+    assert_eq!(early.json["code"], "JOB_NOT_FINISHED");
+
+    assert_eq!(
+        get(&service, first.header("location").unwrap()).await.json["status"],
+        "running"
+    );
+    finished(&service, location).await;
+}
+
+#[tokio::test]
+async fn rejects_unknown_jobs_and_bodies_that_are_not_a_batch() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let unknown = get(&service, "/v1/jobs/no-such-job").await;
+    let not_a_batch = service.post("/v1/jobs", &order(ZIP)).await;
+
+    assert_eq!(unknown.status, StatusCode::NOT_FOUND);
+    assert_eq!(unknown.json["code"], "JOB_NOT_FOUND");
+    assert_eq!(not_a_batch.status, StatusCode::BAD_REQUEST);
+    assert_eq!(not_a_batch.json["code"], "INVALID_REQUEST");
+}
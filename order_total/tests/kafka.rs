@@ -0,0 +1,147 @@
+//! The Kafka run mode's handling of one record, without a broker: decoding,
+//! routing the result, and committing the offset.
+#![cfg(feature = "kafka")]
+
+mod common;
+
+use async_trait::async_trait;
+use common::{order, MockResponse, MockTaxService};
+use order_total::kafka::{Destination, Message, PartitionConsumer, Publish};
+use rskafka::client::consumer::StartOffset;
+use rskafka::record::Record;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const ZIP: &str = "78701";
+
+/// Keeps what it is given to publish, or fails while `failing` is set.
+#[derive(Clone, Default)]
+struct Published {
+    records: Arc<Mutex<Vec<(Destination, Record)>>>,
+    failing: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl Publish for Published {
+    async fn publish(&self, destination: Destination, record: Record) -> anyhow::Result<()> {
+        if self.failing.load(Ordering::SeqCst) {
+            anyhow::bail!("the broker is away");
+        }
+        self.records.lock().unwrap().push((destination, record));
+        Ok(())
+    }
+}
+
+fn record(value: Option<&str>, request_id: Option<&str>) -> Record {
+    Record {
+        key: Some(b"order-123".to_vec()),
+        value: value.map(|value| value.as_bytes().to_vec()),
+        headers: request_id
+            .map(|id| BTreeMap::from([("x-request-id".to_owned(), id.as_bytes().to_vec())]))
+            .unwrap_or_default(),
+        timestamp: chrono::Utc::now(),
+    }
+}
+
+async fn consumer() -> (MockTaxService, PartitionConsumer<Published>, Published) {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let published = Published::default();
+    let consumer = PartitionConsumer::new(
+        common::app(&mock, &[]),
+        published.clone(),
+        StartOffset::Earliest,
+    );
+    (mock, consumer, published)
+}
+
+fn value(record: &Record) -> serde_json::Value {
+    serde_json::from_slice(record.value.as_deref().unwrap()).unwrap()
+}
+
+#[test]
+fn decodes_the_order_and_its_request_id() {
+    let message = Message::decode(record(Some("{}"), Some(" abc-123 ")));
+    let malformed = Message::decode(record(Some("{}"), Some("two words")));
+    let empty = Message::decode(record(None, None));
+
+    assert_eq!(message.key.as_deref(), Some(&b"order-123"[..]));
+    assert_eq!(message.order, b"{}");
+    assert_eq!(message.request_id, "abc-123");
+    assert_ne!(malformed.request_id, "two words");
+    assert!(!malformed.request_id.is_empty());
+    assert!(empty.order.is_empty());
+}
+
+#[tokio::test]
+async fn a_computed_order_goes_to_the_output_topic() {
+    let (_mock, mut consumer, published) = consumer().await;
+
+    consumer
+        .handle(7, record(Some(&order(ZIP)), Some("abc-123")))
+        .await
+        .unwrap();
+
+    let records = published.records.lock().unwrap();
+    let (destination, result) = &records[0];
+    assert_eq!(*destination, Destination::Output);
+    assert_eq!(result.key.as_deref(), Some(&b"order-123"[..]));
+    assert_eq!(result.headers["result"], b"order");
+    assert_eq!(result.headers["x-request-id"], b"abc-123");
+    assert_eq!(value(result)["total"], 21.65);
+    assert_eq!(consumer.committed(), Some(8));
+}
+
+#[tokio::test]
+async fn an_order_that_fails_goes_to_the_error_topic_and_is_committed() {
+    let (_mock, mut consumer, published) = consumer().await;
+
+    consumer.handle(7, record(None, None)).await.unwrap();
+    consumer
+        .handle(8, record(Some(&order("00000")), None))
+        .await
+        .unwrap();
+
+    let records = published.records.lock().unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records
+        .iter()
+        .all(|(destination, _)| *destination == Destination::Errors));
+    assert_eq!(records[0].1.headers["result"], b"error");
+    assert_eq!(value(&records[0].1)["code"], "INVALID_REQUEST");
+    assert_eq!(value(&records[1].1)["code"], "TAX_RATE_UNAVAILABLE");
+    assert_eq!(consumer.committed(), Some(9));
+}
+
+#[tokio::test]
+async fn a_result_that_cannot_be_published_is_not_committed() {
+    let (_mock, mut consumer, published) = consumer().await;
+    consumer
+        .handle(7, record(Some(&order(ZIP)), None))
+        .await
+        .unwrap();
+
+    published.failing.store(true, Ordering::SeqCst);
+    let failed = consumer.handle(8, record(Some(&order(ZIP)), None)).await;
+
+    assert!(failed.is_err());
+    assert_eq!(consumer.committed(), Some(8));
+    assert!(matches!(consumer.resume_from(), StartOffset::At(8)));
+
+    published.failing.store(false, Ordering::SeqCst);
+    consumer
+        .handle(8, record(Some(&order(ZIP)), None))
+        .await
+        .unwrap();
+    assert_eq!(published.records.lock().unwrap().len(), 2);
+    assert_eq!(consumer.committed(), Some(9));
+}
+
+#[tokio::test]
+async fn consumption_starts_at_the_start_offset_before_a_commit() {
+    let (_mock, consumer, _published) = consumer().await;
+
+    assert_eq!(consumer.committed(), None);
+    assert!(matches!(consumer.resume_from(), StartOffset::Earliest));
+}
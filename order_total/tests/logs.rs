@@ -0,0 +1,218 @@
+//! The logs of the `order_total` binary: its access log, and changing its
+//! filter at runtime.
+#![cfg(all(feature = "native", feature = "server", unix))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService};
+use hyper::{Method, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use order_total::Body;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use tokio::net::UnixStream;
+
+/// The binary serving on a socket of its own, logging JSON lines.
+fn serve(name: &str, mock: &MockTaxService, env: &[(&str, &str)]) -> (Child, PathBuf) {
+    let path = std::env::temp_dir().join(format!(
+        "order_total_logs_{name}_{}.sock",
+        std::process::id()
+    ));
+    let child = Command::new(env!("CARGO_BIN_EXE_order_total"))
+        .env("LISTEN_SOCKET", &path)
+        .env("LISTEN_TCP", "false")
+        .env("LOG_FORMAT", "json")
+        .env("RUST_LOG", "access_log=info,warn")
+        .env("SALES_TAX_RATE_SERVICE", mock.url())
+        .env("TAX_SERVICE_RETRY_BASE_MS", "1")
+        .env("TAX_SERVICE_RETRY_MAX_MS", "5")
+        .envs(env.iter().copied())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    (child, path)
+}
+
+async fn send(path: &Path, method: Method, uri: &str, body: String) -> StatusCode {
+    request(path, method, uri, &[], body).await.0
+}
+
+async fn request(
+    path: &Path,
+    method: Method,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: String,
+) -> (StatusCode, Value) {
+    let mut stream = None;
+    for _ in 0..100 {
+        match UnixStream::connect(path).await {
+            Ok(connected) => {
+                stream = Some(connected);
+                break;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    }
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream.expect("not serving")))
+            .await
+            .unwrap();
+    tokio::spawn(connection);
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Host", "localhost")
+        .header("X-Request-Id", "logged-request");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = sender
+        .send_request(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = common::to_bytes(response.into_body()).await;
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+/// Stops the binary and returns the fields of its access log lines.
+fn access_lines(mut child: Child, path: &Path) -> Vec<Value> {
+    child.kill().unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = std::fs::remove_file(path);
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|line| line["target"] == "access_log")
+        .map(|line| line["fields"].clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn each_request_is_logged_with_its_route_and_latencies() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    let (child, path) = serve("logged", &mock, &[]);
+
+    let computed = send(&path, Method::POST, "/v1/compute", order("78701")).await;
+    let missing = send(&path, Method::GET, "/v1/orders/42", String::new()).await;
+
+    let lines = access_lines(child, &path);
+    assert_eq!(computed, StatusCode::OK);
+    assert_eq!(lines.len(), 2, "{lines:?}");
+    let line = &lines[0];
+    assert_eq!(line["method"], "POST");
+    assert_eq!(line["path"], "/v1/compute");
+    assert_eq!(line["route"], "/v1/compute");
+    assert_eq!(line["status"], 200);
+    assert!(line["bytes"].as_u64().unwrap() > 0);
+    assert!(line["latency_ms"].as_u64().unwrap() >= line["upstream_ms"].as_u64().unwrap());
+    assert!(line["latency_bucket"].as_str().unwrap().starts_with("le_"));
+    assert_eq!(line["request_id"], "logged-request");
+    assert_eq!(lines[1]["route"], "/v1/orders/{order_id}");
+    assert_eq!(lines[1]["status"], missing.as_u16());
+}
+
+#[tokio::test]
+async fn sampling_keeps_the_server_errors() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    mock.respond(
+        "10001",
+        MockResponse::status(StatusCode::INTERNAL_SERVER_ERROR),
+    );
+    let (child, path) = serve("sampled", &mock, &[("ACCESS_LOG_SAMPLE_RATE", "0")]);
+
+    let computed = send(&path, Method::POST, "/v1/compute", order("78701")).await;
+    let failed = send(&path, Method::POST, "/v1/compute", order("10001")).await;
+
+    let lines = access_lines(child, &path);
+    assert_eq!(computed, StatusCode::OK);
+    assert_eq!(failed, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(lines.len(), 1, "{lines:?}");
+    assert_eq!(lines[0]["status"], 503);
+}
+
+const ADMIN: &[(&str, &str)] = &[("Authorization", "Bearer admin-token")];
+
+#[tokio::test]
+async fn the_log_filter_can_be_changed_at_runtime() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    let (child, path) = serve("filtered", &mock, &[("ADMIN_TOKEN", "admin-token")]);
+
+    let before = request(&path, Method::GET, "/admin/log_level", ADMIN, String::new()).await;
+    let changed = request(
+        &path,
+        Method::PUT,
+        "/admin/log_level",
+        ADMIN,
+        r#"{"filter": "warn"}"#.to_owned(),
+    )
+    .await;
+    send(&path, Method::POST, "/v1/compute", order("78701")).await;
+    let after = request(&path, Method::GET, "/admin/log_level", ADMIN, String::new()).await;
+
+    let lines = access_lines(child, &path);
+    assert_eq!(before.1["filter"], "access_log=info,warn");
+    assert_eq!(changed.0, StatusCode::OK);
+    assert_eq!(changed.1["previous"], "access_log=info,warn");
+    assert_eq!(after.1["filter"], "warn");
+    // Only the request answered before the change was logged.
+    assert_eq!(lines.len(), 1, "{lines:?}");
+    assert_eq!(lines[0]["method"], "GET");
+}
+
+#[tokio::test]
+async fn a_temporary_filter_reverts() {
+    let mock = MockTaxService::start().await;
+    let (child, path) = serve("temporary", &mock, &[("ADMIN_TOKEN", "admin-token")]);
+
+    let changed = request(
+        &path,
+        Method::PUT,
+        "/admin/log_level",
+        ADMIN,
+        r#"{"filter": "order_total=debug,info", "revert_after_secs": 1}"#.to_owned(),
+    )
+    .await;
+    let during = request(&path, Method::GET, "/admin/log_level", ADMIN, String::new()).await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let reverted = request(&path, Method::GET, "/admin/log_level", ADMIN, String::new()).await;
+
+    access_lines(child, &path);
+    assert_eq!(changed.0, StatusCode::OK);
+    assert_eq!(during.1["filter"], "order_total=debug,info");
+    assert_eq!(reverted.1["filter"], "access_log=info,warn");
+}
+
+#[tokio::test]
+async fn setting_the_log_filter_needs_the_admin_token_and_a_valid_filter() {
+    let mock = MockTaxService::start().await;
+    let (child, path) = serve("refused", &mock, &[("ADMIN_TOKEN", "admin-token")]);
+    let body = r#"{"filter": "debug"}"#.to_owned();
+
+    let anonymous = request(&path, Method::PUT, "/admin/log_level", &[], body).await;
+    let invalid = request(
+        &path,
+        Method::PUT,
+        "/admin/log_level",
+        ADMIN,
+        r#"{"filter": "order_total=loud"}"#.to_owned(),
+    )
+    .await;
+    let unchanged = request(&path, Method::GET, "/admin/log_level", ADMIN, String::new()).await;
+
+    access_lines(child, &path);
+    assert_eq!(anonymous.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(invalid.0, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(invalid.1["errors"][0]["field"], "filter");
+    assert_eq!(unchanged.1["filter"], "access_log=info,warn");
+}
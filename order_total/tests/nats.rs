@@ -0,0 +1,242 @@
+//! Compute requests over NATS request-reply, through a minimal in-process
+//! NATS server: enough of the protocol for one subscriber and one requester.
+#![cfg(feature = "nats")]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+const ZIP: &str = "78701";
+const SUBJECT: &str = "order_total.compute";
+
+struct Subscription {
+    connection: usize,
+    sid: String,
+    pattern: String,
+    queue: Option<String>,
+}
+
+#[derive(Default)]
+struct Broker {
+    connections: Vec<mpsc::UnboundedSender<Vec<u8>>>,
+    subscriptions: Vec<Subscription>,
+}
+
+/// Whether `subject` matches `pattern`, with NATS' `*` and `>` wildcards.
+fn matches(pattern: &str, subject: &str) -> bool {
+    let mut subject = subject.split('.');
+    for token in pattern.split('.') {
+        match (token, subject.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (token, Some(part)) if token == part => {}
+            _ => return false,
+        }
+    }
+    subject.next().is_none()
+}
+
+impl Broker {
+    /// Hands a message to every matching subscription, only one per queue
+    /// group.
+    fn route(&self, subject: &str, reply: Option<&str>, headers: &[u8], payload: &[u8]) {
+        let mut groups = Vec::new();
+        for sub in &self.subscriptions {
+            if !matches(&sub.pattern, subject) {
+                continue;
+            }
+            if let Some(queue) = &sub.queue {
+                if groups.contains(&queue) {
+                    continue;
+                }
+                groups.push(queue);
+            }
+            let reply = reply.map(|reply| format!(" {reply}")).unwrap_or_default();
+            let mut frame = if headers.is_empty() {
+                format!("MSG {subject} {}{reply} {}\r\n", sub.sid, payload.len()).into_bytes()
+            } else {
+                let total = headers.len() + payload.len();
+                format!(
+                    "HMSG {subject} {}{reply} {} {total}\r\n",
+                    sub.sid,
+                    headers.len()
+                )
+                .into_bytes()
+            };
+            frame.extend_from_slice(headers);
+            frame.extend_from_slice(payload);
+            frame.extend_from_slice(b"\r\n");
+            let _ = self.connections[sub.connection].send(frame);
+        }
+    }
+}
+
+/// Starts the server and returns its URL.
+async fn start_broker() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let broker = Arc::new(Mutex::new(Broker::default()));
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let connection = {
+                let mut broker = broker.lock().unwrap();
+                broker.connections.push(tx.clone());
+                broker.connections.len() - 1
+            };
+            let (read, mut write) = socket.into_split();
+            tokio::spawn(async move {
+                while let Some(frame) = rx.recv().await {
+                    if write.write_all(&frame).await.is_err() {
+                        return;
+                    }
+                }
+            });
+            let info = format!(
+                "INFO {{\"server_id\":\"test\",\"server_name\":\"test\",\"version\":\"2.10.0\",\"go\":\"go1.21\",\"host\":\"127.0.0.1\",\"port\":{},\"headers\":true,\"max_payload\":1048576,\"proto\":1}}\r\n",
+                addr.port()
+            );
+            tx.send(info.into_bytes()).unwrap();
+            tokio::spawn(serve(BufReader::new(read), tx, connection, broker.clone()));
+        }
+    });
+    format!("nats://{addr}")
+}
+
+async fn serve(
+    mut read: BufReader<tokio::net::tcp::OwnedReadHalf>,
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    connection: usize,
+    broker: Arc<Mutex<Broker>>,
+) {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if read.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.first().map(|op| op.to_ascii_uppercase()).as_deref() {
+            Some("PING") => {
+                let _ = tx.send(b"PONG\r\n".to_vec());
+            }
+            Some("SUB") => {
+                let (queue, sid) = match args.len() {
+                    4 => (Some(args[2].to_owned()), args[3]),
+                    _ => (None, args[2]),
+                };
+                broker.lock().unwrap().subscriptions.push(Subscription {
+                    connection,
+                    sid: sid.to_owned(),
+                    pattern: args[1].to_owned(),
+                    queue,
+                });
+            }
+            Some("UNSUB") => broker
+                .lock()
+                .unwrap()
+                .subscriptions
+                .retain(|sub| sub.connection != connection || sub.sid != args[1]),
+            Some(op @ ("PUB" | "HPUB")) => {
+                let sizes: Vec<usize> = args[args.len() - if op == "PUB" { 1 } else { 2 }..]
+                    .iter()
+                    .map(|size| size.parse().unwrap())
+                    .collect();
+                let (header_len, total) = match sizes[..] {
+                    [total] => (0, total),
+                    [headers, total] => (headers, total),
+                    _ => unreachable!(),
+                };
+                let reply = (args.len() == 2 + sizes.len() + 1).then(|| args[2]);
+                let mut frame = vec![0; total + 2];
+                read.read_exact(&mut frame).await.unwrap();
+                broker.lock().unwrap().route(
+                    args[1],
+                    reply,
+                    &frame[..header_len],
+                    &frame[header_len..total],
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn start(mock: &MockTaxService, env: &[(&str, &str)]) -> async_nats::Client {
+    let url = start_broker().await;
+    let responder =
+        order_total::nats::subscribe(common::app(mock, env), &url, std::future::pending())
+            .await
+            .unwrap();
+    tokio::spawn(responder);
+    async_nats::connect(&url).await.unwrap()
+}
+
+async fn request(
+    client: &async_nats::Client,
+    headers: async_nats::HeaderMap,
+    payload: String,
+) -> (String, serde_json::Value) {
+    let reply = client
+        .request_with_headers(SUBJECT, headers, payload.into())
+        .await
+        .unwrap();
+    let result = reply.headers.as_ref().unwrap().get("result").unwrap();
+    (
+        result.to_string(),
+        serde_json::from_slice(&reply.payload).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn answers_a_compute_request() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let client = start(&mock, &[]).await;
+
+    let (result, order) = request(&client, async_nats::HeaderMap::new(), order(ZIP)).await;
+
+    assert_eq!(result, "order");
+    assert_eq!(order["total"], 21.65);
+    assert_eq!(order["jurisdiction"], "TX");
+}
+
+#[tokio::test]
+async fn answers_errors_with_the_error_body() {
+    let mock = MockTaxService::start().await;
+    let client = start(&mock, &[]).await;
+
+    let (result, body) = request(&client, async_nats::HeaderMap::new(), "{".to_owned()).await;
+
+    assert_eq!(result, "error");
+    assert_eq!(body["code"], "INVALID_REQUEST");
+    assert!(body["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn authenticates_by_the_request_headers() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let client = start(&mock, &[("JWT_SECRET", "test-secret")]).await;
+
+    let (result, body) = request(&client, async_nats::HeaderMap::new(), order(ZIP)).await;
+    assert_eq!(result, "error");
+    assert_eq!(body["code"], "UNAUTHORIZED");
+
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &serde_json::json!({ "sub": "edge-service", "exp": u32::MAX }),
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+    let mut headers = async_nats::HeaderMap::new();
+    headers.insert("Authorization", format!("Bearer {token}").as_str());
+    let (result, order) = request(&client, headers, order(ZIP)).await;
+    assert_eq!(result, "order");
+    assert_eq!(order["total"], 21.65);
+}
@@ -0,0 +1,91 @@
+//! The memory of zip codes the sales tax rate service has no rate for,
+//! `UNKNOWN_ZIP_CACHE_SECS`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use std::time::Duration;
+
+const UNKNOWN: &str = "99999";
+const OTHER_UNKNOWN: &str = "99998";
+
+/// Posts the order for `zip` `times` times, expecting no rate each time.
+async fn unknown(service: &TestService, zip: &str, times: usize) {
+    for _ in 0..times {
+        let response = service.post("/v1/compute", &order(zip)).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.json["code"], "TAX_RATE_UNAVAILABLE");
+    }
+}
+
+#[tokio::test]
+async fn an_unknown_zip_is_looked_up_once_while_remembered() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[("UNKNOWN_ZIP_CACHE_SECS", "60")]).await;
+
+    unknown(&service, UNKNOWN, 5).await;
+
+    assert_eq!(mock.hits(UNKNOWN), 1);
+}
+
+#[tokio::test]
+async fn an_unknown_zip_is_looked_up_again_once_forgotten() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[("UNKNOWN_ZIP_CACHE_SECS", "1")]).await;
+    unknown(&service, UNKNOWN, 2).await;
+    assert_eq!(mock.hits(UNKNOWN), 1);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    mock.respond(UNKNOWN, MockResponse::rate("0.05"));
+    let response = service.post("/v1/compute", &order(UNKNOWN)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.0);
+    assert_eq!(mock.hits(UNKNOWN), 2);
+}
+
+#[tokio::test]
+async fn failures_are_not_remembered() {
+    let mock = MockTaxService::start().await;
+    mock.respond(UNKNOWN, MockResponse::status(StatusCode::BAD_GATEWAY));
+    let env = [
+        ("UNKNOWN_ZIP_CACHE_SECS", "60"),
+        ("TAX_SERVICE_MAX_ATTEMPTS", "1"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    for _ in 0..2 {
+        let response = service.post("/v1/compute", &order(UNKNOWN)).await;
+        assert_eq!(response.json["code"], "UPSTREAM_UNAVAILABLE");
+    }
+
+    assert_eq!(mock.hits(UNKNOWN), 2);
+}
+
+#[tokio::test]
+async fn no_more_zips_than_the_maximum_are_remembered() {
+    let mock = MockTaxService::start().await;
+    let env = [
+        ("UNKNOWN_ZIP_CACHE_SECS", "60"),
+        ("UNKNOWN_ZIP_CACHE_MAX", "1"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    unknown(&service, UNKNOWN, 2).await;
+    unknown(&service, OTHER_UNKNOWN, 2).await;
+
+    assert_eq!(mock.hits(UNKNOWN), 1);
+    assert_eq!(mock.hits(OTHER_UNKNOWN), 2);
+}
+
+#[tokio::test]
+async fn a_ttl_of_zero_remembers_nothing() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[("UNKNOWN_ZIP_CACHE_SECS", "0")]).await;
+
+    unknown(&service, UNKNOWN, 3).await;
+
+    assert_eq!(mock.hits(UNKNOWN), 3);
+}
@@ -0,0 +1,77 @@
+//! Compact JSON by default, pretty-printed when asked for.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+
+const ZIP: &str = "78701";
+
+fn text(response: &common::TestResponse) -> String {
+    String::from_utf8_lossy(&response.body).into_owned()
+}
+
+#[tokio::test]
+async fn bodies_are_compact_by_default() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let computed = service.post("/v1/compute", &order(ZIP)).await;
+    let refused = service.post("/v1/compute", "not json").await;
+
+    assert_eq!(computed.status, StatusCode::OK);
+    assert_eq!(computed.json["total"], 21.65);
+    assert!(!text(&computed).contains('\n'), "{}", text(&computed));
+    assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+    assert!(!text(&refused).contains('\n'), "{}", text(&refused));
+}
+
+#[tokio::test]
+async fn the_query_asks_for_pretty_bodies() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let computed = service.post("/v1/compute?pretty=true", &order(ZIP)).await;
+    let refused = service.post("/v1/compute?pretty=1", "not json").await;
+    let compact = service.post("/v1/compute?pretty=false", &order(ZIP)).await;
+
+    assert_eq!(computed.status, StatusCode::OK);
+    assert_eq!(computed.json["total"], 21.65);
+    assert!(text(&computed).contains("\n  \"total\": 21.65"));
+    assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+    assert!(text(&refused).contains("\n  \"code\": \"INVALID_REQUEST\""));
+    assert!(!text(&compact).contains('\n'));
+}
+
+#[tokio::test]
+async fn the_accept_header_asks_for_pretty_bodies() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let computed = service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("Accept", "application/json; pretty=1")],
+            &order(ZIP),
+        )
+        .await;
+    let version = service
+        .send(
+            Method::GET,
+            "/version",
+            &[("Accept", "application/json;q=0.9;pretty=true")],
+            "",
+        )
+        .await;
+
+    assert_eq!(computed.status, StatusCode::OK);
+    assert_eq!(computed.header("content-type"), Some("application/json"));
+    assert!(text(&computed).contains("\n  \"total\": 21.65"));
+    assert_eq!(version.status, StatusCode::OK);
+    assert!(text(&version).contains("\n  \"version\": "));
+}
@@ -0,0 +1,100 @@
+//! Two-phase checkout at `/v1/quote` and `/v1/finalize`, against a mock sales
+//! tax rate service.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestResponse, TestService};
+use hyper::StatusCode;
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+
+async fn finalize(service: &TestService, token: &str) -> TestResponse {
+    let body = serde_json::json!({ "token": token }).to_string();
+    service.post("/v1/finalize", &body).await
+}
+
+#[tokio::test]
+async fn finalizes_at_the_quoted_rate() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let quote = service.post("/v1/quote", &order(ZIP)).await;
+
+    assert_eq!(quote.status, StatusCode::OK);
+    assert_eq!(quote.json["order"]["total"], 21.65);
+    assert!(quote.json["quote_id"].is_string());
+    assert!(quote.json["expires_at"].is_string());
+
+    mock.respond(ZIP, MockResponse::rate("0.10"));
+    let finalized = finalize(&service, quote.json["token"].as_str().unwrap()).await;
+
+    assert_eq!(finalized.status, StatusCode::OK);
+    assert_eq!(finalized.json["total"], 21.65);
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn finalizes_a_quote_once() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let quote = service.post("/v1/quote", &order(ZIP)).await;
+    let token = quote.json["token"].as_str().unwrap();
+
+    assert_eq!(finalize(&service, token).await.status, StatusCode::OK);
+    let again = finalize(&service, token).await;
+
+    assert_eq!(again.status, StatusCode::CONFLICT);
+    assert_eq!(again.json["code"], "QUOTE_ALREADY_FINALIZED");
+}
+
+#[tokio::test]
+async fn a_dry_run_leaves_the_quote_open() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let quote = service.post("/v1/quote", &order(ZIP)).await;
+    let body = serde_json::json!({ "token": quote.json["token"] }).to_string();
+
+    let dry_run = service.post("/v1/finalize?dry_run=true", &body).await;
+    let finalized = service.post("/v1/finalize", &body).await;
+
+    assert_eq!(dry_run.status, StatusCode::OK);
+    assert_eq!(dry_run.json["dry_run"], true);
+    assert_eq!(dry_run.json["total"], 21.65);
+    assert_eq!(finalized.status, StatusCode::OK);
+    assert_eq!(finalized.json.get("dry_run"), None);
+}
+
+#[tokio::test]
+async fn rejects_a_tampered_token() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[("QUOTE_SECRET", "quote secret")]).await;
+    let other = TestService::start(&mock, &[("QUOTE_SECRET", "another secret")]).await;
+    let quote = other.post("/v1/quote", &order(ZIP)).await;
+
+    let forged = finalize(&service, quote.json["token"].as_str().unwrap()).await;
+    let garbled = finalize(&service, "not.a.token").await;
+
+    assert_eq!(forged.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(forged.json["code"], "QUOTE_INVALID");
+    assert_eq!(garbled.json["code"], "QUOTE_INVALID");
+}
+
+#[tokio::test]
+async fn rejects_an_expired_quote() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[("QUOTE_TTL_SECS", "0")]).await;
+    let quote = service.post("/v1/quote", &order(ZIP)).await;
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let expired = finalize(&service, quote.json["token"].as_str().unwrap()).await;
+
+    assert_eq!(expired.status, StatusCode::GONE);
+    assert_eq!(expired.json["code"], "QUOTE_EXPIRED");
+}
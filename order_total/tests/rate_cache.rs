@@ -0,0 +1,274 @@
+//! The rate cache, in memory and shared through a minimal in-process Redis
+//! (enough of RESP for `AUTH`, `SELECT`, `GET`, `SET ... EX`, `DEL` and
+//! `SCAN`), and its administration under `/admin/cache`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const ZIP: &str = "78701";
+
+/// The keys set, with their expiry in seconds.
+type Store = Arc<Mutex<HashMap<String, (String, Option<String>)>>>;
+
+struct FakeRedis {
+    url: String,
+    store: Store,
+}
+
+impl FakeRedis {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let store = Store::default();
+        let shared = store.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve(BufReader::new(socket), shared.clone()));
+            }
+        });
+        Self { url, store }
+    }
+
+    fn get(&self, key: &str) -> Option<(String, Option<String>)> {
+        self.store.lock().unwrap().get(key).cloned()
+    }
+}
+
+async fn serve(mut conn: BufReader<TcpStream>, store: Store) {
+    while let Some(args) = read_command(&mut conn).await {
+        let reply = match args[0].to_ascii_uppercase().as_str() {
+            "GET" => match store.lock().unwrap().get(&args[1]) {
+                Some((value, _)) => format!("${}\r\n{value}\r\n", value.len()),
+                None => "$-1\r\n".to_owned(),
+            },
+            "SET" => {
+                let expiry = args.get(4).cloned();
+                store
+                    .lock()
+                    .unwrap()
+                    .insert(args[1].clone(), (args[2].clone(), expiry));
+                "+OK\r\n".to_owned()
+            }
+            "DEL" => {
+                let mut store = store.lock().unwrap();
+                let removed = args[1..]
+                    .iter()
+                    .filter(|key| store.remove(*key).is_some())
+                    .count();
+                format!(":{removed}\r\n")
+            }
+            // One batch with every key; the pattern is a prefix and `*`.
+            "SCAN" => {
+                let prefix = args[3].trim_end_matches('*');
+                let keys: Vec<String> = store
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .filter(|key| key.starts_with(prefix))
+                    .map(|key| format!("${}\r\n{key}\r\n", key.len()))
+                    .collect();
+                format!("*2\r\n$1\r\n0\r\n*{}\r\n{}", keys.len(), keys.concat())
+            }
+            "AUTH" | "SELECT" => "+OK\r\n".to_owned(),
+            _ => "-ERR unknown command\r\n".to_owned(),
+        };
+        if conn.get_mut().write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn read_command(conn: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    conn.read_line(&mut line)
+        .await
+        .ok()
+        .filter(|read| *read > 0)?;
+    let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        conn.read_line(&mut line).await.ok()?;
+        let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+        let mut arg = vec![0; len + 2];
+        conn.read_exact(&mut arg).await.ok()?;
+        arg.truncate(len);
+        args.push(String::from_utf8(arg).ok()?);
+    }
+    Some(args)
+}
+
+#[tokio::test]
+async fn caches_rates_in_memory() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[("RATE_CACHE_SECS", "60")]).await;
+
+    for _ in 0..3 {
+        let response = service.post("/v1/compute", &order(ZIP)).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json["total"], 21.65);
+    }
+
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn replicas_share_rates_through_redis() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let redis = FakeRedis::start().await;
+    let env = [("RATE_CACHE_SECS", "60"), ("REDIS_URL", redis.url.as_str())];
+    let first = TestService::start(&mock, &env).await;
+    let second = TestService::start(&mock, &env).await;
+
+    let looked_up = first.post("/v1/compute", &order(ZIP)).await;
+    let shared = second.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(looked_up.json["total"], 21.65);
+    assert_eq!(shared.json["total"], 21.65);
+    assert_eq!(mock.hits(ZIP), 1);
+    assert_eq!(
+        redis.get(&format!("order_total:rate:{ZIP}")),
+        Some(("0.0825".to_owned(), Some("60".to_owned())))
+    );
+}
+
+#[tokio::test]
+async fn unreachable_redis_falls_back_to_the_lookup() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("redis://{}", closed.local_addr().unwrap());
+    drop(closed);
+    let service = TestService::start(
+        &mock,
+        &[("RATE_CACHE_SECS", "60"), ("REDIS_URL", url.as_str())],
+    )
+    .await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+const ADMIN: &[(&str, &str)] = &[("Authorization", "Bearer admin-token")];
+
+#[tokio::test]
+async fn reports_cache_statistics() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(
+        &mock,
+        &[("RATE_CACHE_SECS", "60"), ("ADMIN_TOKEN", "admin-token")],
+    )
+    .await;
+    for _ in 0..4 {
+        service.post("/v1/compute", &order(ZIP)).await;
+    }
+
+    let stats = service
+        .send(Method::GET, "/admin/cache/stats", ADMIN, "")
+        .await;
+
+    assert_eq!(stats.status, StatusCode::OK);
+    assert_eq!(stats.json["entries"], 1);
+    assert_eq!(stats.json["hits"], 3);
+    assert_eq!(stats.json["misses"], 1);
+    assert_eq!(stats.json["hit_ratio"], 0.75);
+    assert_eq!(stats.json["age_distribution"][0]["max_age_secs"], 60);
+    assert_eq!(stats.json["age_distribution"][0]["entries"], 1);
+}
+
+#[tokio::test]
+async fn invalidation_forces_a_fresh_lookup() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let redis = FakeRedis::start().await;
+    let service = TestService::start(
+        &mock,
+        &[
+            ("RATE_CACHE_SECS", "60"),
+            ("REDIS_URL", redis.url.as_str()),
+            ("ADMIN_TOKEN", "admin-token"),
+        ],
+    )
+    .await;
+    service.post("/v1/compute", &order(ZIP)).await;
+    mock.respond(ZIP, MockResponse::rate("0.09"));
+
+    let path = format!("/admin/cache/{ZIP}");
+    let invalidated = service.send(Method::DELETE, &path, ADMIN, "").await;
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(invalidated.status, StatusCode::OK);
+    assert_eq!(invalidated.json["removed"], 1);
+    assert_eq!(response.json["tax_rate"], 0.09);
+    assert_eq!(mock.hits(ZIP), 2);
+
+    let cleared = service
+        .send(Method::DELETE, "/admin/cache", ADMIN, "")
+        .await;
+    assert_eq!(cleared.json["removed"], 1);
+    assert_eq!(redis.get(&format!("order_total:rate:{ZIP}")), None);
+}
+
+#[tokio::test]
+async fn admin_routes_need_the_admin_token() {
+    let mock = MockTaxService::start().await;
+    let disabled = TestService::start(&mock, &[]).await;
+    let enabled = TestService::start(&mock, &[("ADMIN_TOKEN", "admin-token")]).await;
+
+    let without_token = disabled
+        .send(Method::GET, "/admin/cache/stats", ADMIN, "")
+        .await;
+    let wrong_token = enabled
+        .send(
+            Method::DELETE,
+            "/admin/cache",
+            &[("Authorization", "Bearer guess")],
+            "",
+        )
+        .await;
+
+    assert_eq!(without_token.status, StatusCode::NOT_FOUND);
+    assert_eq!(wrong_token.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(wrong_token.json["code"], "UNAUTHORIZED");
+}
+
+#[tokio::test]
+async fn warm_up_looks_the_listed_zip_codes_up_before_serving() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock.respond("78702", MockResponse::rate("0.0825"));
+    let file = std::env::temp_dir().join(format!("warm-zips-{}", std::process::id()));
+    std::fs::write(&file, "# busiest first\n78702\n\n99999 # no rate\n").unwrap();
+    let app = common::app(
+        &mock,
+        &[
+            ("RATE_CACHE_SECS", "60"),
+            ("CACHE_WARM_ZIPS", "78701, 78702"),
+            ("CACHE_WARM_ZIPS_FILE", file.to_str().unwrap()),
+        ],
+    );
+    std::fs::remove_file(&file).unwrap();
+
+    app.warm_up().await;
+    let service = TestService::serve(app);
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.json["total"], 21.65);
+    assert_eq!(mock.hits(ZIP), 1);
+    assert_eq!(mock.hits("78702"), 1);
+    assert_eq!(mock.hits("99999"), 1);
+}
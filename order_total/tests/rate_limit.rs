@@ -0,0 +1,83 @@
+//! The service-wide rate limit of `RATE_LIMIT_PER_SEC`, and the standard
+//! `RateLimit-*` headers that report it.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+
+const ZIP: &str = "78701";
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+#[tokio::test]
+async fn requests_beyond_the_burst_are_answered_429() {
+    let mock = with_rate().await;
+    let limited = [("RATE_LIMIT_PER_SEC", "0.5"), ("RATE_LIMIT_BURST", "2")];
+    let service = TestService::start(&mock, &limited).await;
+
+    let first = service.post("/v1/compute", &order(ZIP)).await;
+    let second = service.post("/v1/compute", &order(ZIP)).await;
+    let refused = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(first.header("ratelimit-limit"), Some("2"));
+    assert_eq!(first.header("ratelimit-remaining"), Some("1"));
+    assert_eq!(second.status, StatusCode::OK);
+    assert_eq!(second.header("ratelimit-remaining"), Some("0"));
+    assert_eq!(refused.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(refused.json["code"], "RATE_LIMITED");
+    assert_eq!(refused.header("ratelimit-limit"), Some("2"));
+    assert_eq!(refused.header("ratelimit-remaining"), Some("0"));
+    assert_eq!(refused.header("retry-after"), Some("2"));
+    assert_eq!(mock.hits(ZIP), 2);
+}
+
+#[tokio::test]
+async fn unauthenticated_requests_count_too() {
+    let mock = with_rate().await;
+    let env = [
+        ("RATE_LIMIT_PER_SEC", "0.5"),
+        ("RATE_LIMIT_BURST", "1"),
+        ("JWT_SECRET", "secret"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    let unauthorized = service.post("/v1/compute", &order(ZIP)).await;
+    let refused = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(unauthorized.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(refused.status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn only_api_requests_are_limited() {
+    let mock = with_rate().await;
+    let limited = [("RATE_LIMIT_PER_SEC", "0.5"), ("RATE_LIMIT_BURST", "1")];
+    let service = TestService::start(&mock, &limited).await;
+
+    service.post("/v1/compute", &order(ZIP)).await;
+    let version = service.send(Method::GET, "/version", &[], "").await;
+    let docs = service.send(Method::GET, "/openapi.json", &[], "").await;
+
+    assert_eq!(version.status, StatusCode::OK);
+    assert_eq!(version.header("ratelimit-limit"), None);
+    assert_eq!(docs.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn there_is_no_limit_by_default() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    for _ in 0..5 {
+        let computed = service.post("/v1/compute", &order(ZIP)).await;
+        assert_eq!(computed.status, StatusCode::OK);
+        assert_eq!(computed.header("ratelimit-limit"), None);
+    }
+}
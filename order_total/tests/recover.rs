@@ -0,0 +1,59 @@
+//! Panics while answering a request.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService};
+use hyper::{Request, StatusCode};
+use order_total::{Body, OrderTotalService};
+use serde_json::{json, Value};
+use tower_service::Service;
+
+const ZIP: &str = "78701";
+
+#[tokio::test]
+async fn a_panic_is_answered_500_and_the_service_keeps_serving() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let mut service = OrderTotalService::new(common::app(&mock, &[]));
+
+    let panicked = service
+        .call(
+            Request::post("/v1/compute")
+                .header("X-Request-Id", "panicking-order")
+                .body(common::panicking_body())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let next = service
+        .call(
+            Request::post("/v1/compute")
+                .body(Body::from(order(ZIP)))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(panicked.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let headers = panicked.headers().clone();
+    assert_eq!(headers["x-request-id"], "panicking-order");
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert_eq!(headers["content-type"], "application/problem+json");
+    let body: Value =
+        serde_json::from_slice(&common::to_bytes(panicked.into_body()).await).unwrap();
+    assert_eq!(
+        body,
+        json!({
+            "type": "about:blank",
+            "title": "Internal Server Error",
+            "status": 500,
+            "detail": "The request could not be answered because of an internal error.",
+            "code": "INTERNAL_ERROR",
+            "request_id": "panicking-order"
+        })
+    );
+    assert_eq!(next.status(), StatusCode::OK);
+    let next: Value = serde_json::from_slice(&common::to_bytes(next.into_body()).await).unwrap();
+    assert_eq!(next["total"], 21.65);
+}
@@ -0,0 +1,123 @@
+//! Credit memos for returns at `/v1/refund`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestResponse, TestService};
+use hyper::StatusCode;
+use serde_json::{json, Value};
+
+const ZIP: &str = "78701";
+
+/// The order with two products, as `/v1/compute` answered it at 8.25%.
+async fn computed(service: &TestService) -> Value {
+    let order = json!({
+        "order_id": 7,
+        "shipping_address": "123 Main St, Anytown USA",
+        "shipping_zip": ZIP,
+        "line_items": [
+            {"product_id": 1, "quantity": 3, "unit_price": 9.99},
+            {"product_id": 2, "quantity": 1, "unit_price": 25.0}
+        ]
+    });
+    service.post("/v1/compute", &order.to_string()).await.json
+}
+
+async fn refund(service: &TestService, request: Value) -> TestResponse {
+    service.post("/v1/refund", &request.to_string()).await
+}
+
+#[tokio::test]
+async fn credits_part_of_a_line_at_the_original_rate() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let order = computed(&service).await;
+    mock.respond(ZIP, MockResponse::rate("0.10"));
+
+    let memo = refund(
+        &service,
+        json!({"order": order, "returned_items": [{"product_id": 1, "quantity": 2}]}),
+    )
+    .await;
+
+    assert_eq!(memo.status, StatusCode::OK);
+    assert_eq!(memo.json["order_id"], 7);
+    assert_eq!(memo.json["tax_rate"], 0.0825);
+    assert_eq!(memo.json["jurisdiction"], "TX");
+    assert_eq!(memo.json["lines"][0]["subtotal"], 19.98);
+    assert_eq!(memo.json["lines"][0]["tax"], 1.65);
+    assert_eq!(memo.json["tax_amount"], 1.65);
+    assert_eq!(memo.json["total"], 21.63);
+}
+
+#[tokio::test]
+async fn reverses_the_whole_tax_of_a_full_return() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let single = service.post("/v1/compute", &order(ZIP)).await.json;
+
+    let memo = refund(
+        &service,
+        json!({"order": single, "returned_items": [{"product_id": 321, "quantity": 2}]}),
+    )
+    .await;
+
+    assert_eq!(memo.status, StatusCode::OK);
+    assert_eq!(memo.json["subtotal"], 20.0);
+    assert_eq!(memo.json["tax_amount"], 1.65);
+    assert_eq!(memo.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn rejects_returns_the_order_does_not_cover() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let order = computed(&service).await;
+
+    let rejected = refund(
+        &service,
+        json!({"order": order, "returned_items": [
+            {"product_id": 2, "quantity": 2},
+            {"product_id": 9, "quantity": 1}
+        ]}),
+    )
+    .await;
+
+    assert_eq!(rejected.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<&str> = rejected.json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        fields,
+        ["returned_items[0].quantity", "returned_items[1].product_id"]
+    );
+}
+
+#[tokio::test]
+async fn needs_the_rate_the_order_was_computed_with() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+    let uncomputed: Value = serde_json::from_str(&order(ZIP)).unwrap();
+
+    let rejected = refund(
+        &service,
+        json!({"order": uncomputed, "returned_items": [{"product_id": 321, "quantity": 1}]}),
+    )
+    .await;
+    let stored = refund(
+        &service,
+        json!({"order_id": 123, "returned_items": [{"product_id": 321, "quantity": 1}]}),
+    )
+    .await;
+
+    assert_eq!(rejected.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(rejected.json["errors"][0]["field"], "tax_rate");
+    assert_eq!(stored.status, StatusCode::NOT_IMPLEMENTED);
+    assert_eq!(stored.json["code"], "PERSISTENCE_DISABLED");
+}
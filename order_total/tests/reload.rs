@@ -0,0 +1,97 @@
+//! Reloading the configuration of a running service with `POST
+//! /admin/reload`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, with_env, MockResponse, MockTaxService, TestResponse, TestService};
+use hyper::{Method, StatusCode};
+
+const ZIP: &str = "78701";
+const ADMIN_TOKEN: (&str, &str) = ("ADMIN_TOKEN", "admin-token");
+const ADMIN: &[(&str, &str)] = &[("Authorization", "Bearer admin-token")];
+
+async fn reload(service: &TestService) -> TestResponse {
+    service.send(Method::POST, "/admin/reload", ADMIN, "").await
+}
+
+#[tokio::test]
+async fn a_reload_picks_up_a_changed_rate_table() {
+    let mock = MockTaxService::start().await;
+    let table = std::env::temp_dir().join(format!("order_total_reload_{}.csv", std::process::id()));
+    std::fs::write(&table, "zip,rate\n78701,0.0825\n").unwrap();
+    let env = [
+        ADMIN_TOKEN,
+        ("TAX_RATE_SOURCE", "embedded"),
+        ("TAX_RATE_TABLE", table.to_str().unwrap()),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    let before = service.post("/v1/compute", &order(ZIP)).await;
+    std::fs::write(&table, "zip,rate\n78701,0.09\n").unwrap();
+    let reloaded = with_env(&mock, &env, reload(&service)).await;
+    let after = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(before.json["tax_rate"], 0.0825);
+    assert_eq!(reloaded.status, StatusCode::OK);
+    assert_eq!(reloaded.json["reloaded"], true);
+    assert_eq!(after.json["tax_rate"], 0.09);
+    assert_eq!(after.json["total"], 21.8);
+    std::fs::remove_file(table).unwrap();
+}
+
+#[tokio::test]
+async fn a_reload_applies_new_settings() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[ADMIN_TOKEN]).await;
+
+    let before = service.post("/v1/compute", &order(ZIP)).await;
+    let env = [
+        ADMIN_TOKEN,
+        ("TAX_RATE_SOURCE", "fixed"),
+        ("FIXED_TAX_RATE", "0.05"),
+        ("RATE_CACHE_SECS", "60"),
+    ];
+    with_env(&mock, &env, reload(&service)).await;
+    let after = service.post("/v1/compute", &order(ZIP)).await;
+    let stats = service
+        .send(Method::GET, "/admin/cache/stats", ADMIN, "")
+        .await;
+
+    assert_eq!(before.json["tax_rate"], 0.0825);
+    assert_eq!(after.json["tax_rate"], 0.05);
+    assert_eq!(mock.hits(ZIP), 1);
+    assert_eq!(stats.json["enabled"], true);
+    assert_eq!(stats.json["ttl_secs"], 60);
+}
+
+#[tokio::test]
+async fn an_invalid_configuration_leaves_the_old_one_in_force() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[ADMIN_TOKEN]).await;
+
+    let env = [
+        ADMIN_TOKEN,
+        ("TAX_RATE_SOURCE", "fixed"),
+        ("FIXED_TAX_RATE", "0.05"),
+        ("ROUNDING_MODE", "sideways"),
+    ];
+    let reloaded = with_env(&mock, &env, reload(&service)).await;
+    let after = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(reloaded.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(reloaded.message().contains("ROUNDING_MODE"));
+    assert_eq!(after.json["tax_rate"], 0.0825);
+}
+
+#[tokio::test]
+async fn reloading_needs_the_admin_token() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[ADMIN_TOKEN]).await;
+
+    let response = service.send(Method::POST, "/admin/reload", &[], "").await;
+
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
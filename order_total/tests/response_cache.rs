@@ -0,0 +1,99 @@
+//! Repeated orders answered from the response cache of
+//! `RESPONSE_CACHE_SECS`, without computing them again.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+
+const ZIP: &str = "78701";
+const CACHED: &[(&str, &str)] = &[("RESPONSE_CACHE_SECS", "60")];
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+#[tokio::test]
+async fn a_repeated_order_is_answered_from_the_cache() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, CACHED).await;
+    let reordered = serde_json::json!({
+        "total": 0,
+        "shipping_zip": ZIP,
+        "shipping_address": "123 Main St, Anytown USA",
+        "subtotal": 20,
+        "quantity": 2,
+        "product_id": 321,
+        "order_id": 123,
+    })
+    .to_string();
+
+    let first = service.post("/v1/compute", &order(ZIP)).await;
+    let second = service.post("/v1/compute", &order(ZIP)).await;
+    let laid_out_otherwise = service.post("/v1/compute", &reordered).await;
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(first.header("x-cache"), Some("miss"));
+    assert_eq!(second.header("x-cache"), Some("hit"));
+    assert_eq!(laid_out_otherwise.header("x-cache"), Some("hit"));
+    assert_eq!(second.body, first.body);
+    assert_eq!(second.json["total"], 21.65);
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn other_orders_and_failures_are_computed() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::status(StatusCode::SERVICE_UNAVAILABLE));
+    let service = TestService::start(&mock, CACHED).await;
+    let mut more = serde_json::from_str::<serde_json::Value>(&order(ZIP)).unwrap();
+    more["quantity"] = 3.into();
+
+    let failed = service.post("/v1/compute", &order(ZIP)).await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let computed = service.post("/v1/compute", &order(ZIP)).await;
+    let other = service.post("/v1/compute", &more.to_string()).await;
+
+    assert_eq!(failed.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(failed.header("x-cache"), None);
+    assert_eq!(computed.status, StatusCode::OK);
+    assert_eq!(computed.header("x-cache"), Some("miss"));
+    assert_eq!(other.header("x-cache"), Some("miss"));
+}
+
+#[tokio::test]
+async fn audited_orders_are_cached_only_as_dry_runs() {
+    let mock = with_rate().await;
+    let log = std::env::temp_dir().join(format!(
+        "order_total_response_cache_{}.jsonl",
+        std::process::id()
+    ));
+    let env = [CACHED[0], ("AUDIT_LOG", log.to_str().unwrap())];
+    let service = TestService::start(&mock, &env).await;
+
+    service.post("/v1/compute", &order(ZIP)).await;
+    let recorded = service.post("/v1/compute", &order(ZIP)).await;
+    service.post("/v1/compute?dry_run=true", &order(ZIP)).await;
+    let previewed = service.post("/v1/compute?dry_run=true", &order(ZIP)).await;
+
+    assert_eq!(recorded.status, StatusCode::OK);
+    assert_eq!(recorded.header("x-cache"), None);
+    assert_eq!(previewed.header("x-cache"), Some("hit"));
+    assert_eq!(mock.hits(ZIP), 3);
+    std::fs::remove_file(&log).ok();
+}
+
+#[tokio::test]
+async fn the_cache_is_off_by_default() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    service.post("/v1/compute", &order(ZIP)).await;
+    let second = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(second.header("x-cache"), None);
+    assert_eq!(mock.hits(ZIP), 2);
+}
@@ -0,0 +1,85 @@
+//! Retries of failed lookups, with jittered exponential backoff.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use std::time::{Duration, Instant};
+
+const ZIP: &str = "78701";
+
+async fn recovering(failures: &[MockResponse]) -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"))
+        .respond_first(ZIP, failures);
+    mock
+}
+
+#[tokio::test]
+async fn a_transient_failure_is_retried_until_the_lookup_succeeds() {
+    let mock = recovering(&[
+        MockResponse::status(StatusCode::SERVICE_UNAVAILABLE),
+        MockResponse::status(StatusCode::BAD_GATEWAY),
+    ])
+    .await;
+    let service = TestService::start(&mock, &[("TAX_SERVICE_MAX_ATTEMPTS", "3")]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+    assert_eq!(mock.hits(ZIP), 3);
+}
+
+#[tokio::test]
+async fn a_timed_out_attempt_is_retried() {
+    let slow = MockResponse::rate("0.0825").delayed(Duration::from_secs(2));
+    let mock = recovering(&[slow]).await;
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_SERVICE_TIMEOUT_MS", "100"),
+            ("TAX_SERVICE_MAX_ATTEMPTS", "2"),
+        ],
+    )
+    .await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(mock.hits(ZIP), 2);
+}
+
+#[tokio::test]
+async fn a_client_error_is_not_retried() {
+    let mock = recovering(&[MockResponse::status(StatusCode::BAD_REQUEST)]).await;
+    let service = TestService::start(&mock, &[("TAX_SERVICE_MAX_ATTEMPTS", "3")]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_ne!(response.status, StatusCode::OK);
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn the_backoff_is_capped_by_the_retry_maximum() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::status(StatusCode::SERVICE_UNAVAILABLE));
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_SERVICE_MAX_ATTEMPTS", "4"),
+            ("TAX_SERVICE_RETRY_BASE_MS", "60000"),
+            ("TAX_SERVICE_RETRY_MAX_MS", "20"),
+        ],
+    )
+    .await;
+
+    let started = Instant::now();
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(mock.hits(ZIP), 4);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
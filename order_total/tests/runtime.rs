@@ -0,0 +1,111 @@
+//! The Tokio runtime the server runs on, chosen by `RUNTIME_FLAVOR` and
+//! `WORKER_THREADS`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use futures_util::future::join_all;
+use hyper::StatusCode;
+use order_total::{runtime_flavor, App, RuntimeFlavor};
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+
+/// `runtime_flavor()` with only the given variables set.
+fn flavor(flavor: Option<&str>, workers: Option<&str>) -> anyhow::Result<RuntimeFlavor> {
+    for (name, value) in [("RUNTIME_FLAVOR", flavor), ("WORKER_THREADS", workers)] {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+    let resolved = runtime_flavor();
+    std::env::remove_var("RUNTIME_FLAVOR");
+    std::env::remove_var("WORKER_THREADS");
+    resolved
+}
+
+// The only test of this file that changes the environment, so the cases run
+// one after another.
+#[test]
+fn the_flavor_and_worker_count_are_resolved_from_the_environment() {
+    let error = |resolved: anyhow::Result<RuntimeFlavor>| resolved.unwrap_err().to_string();
+
+    assert_eq!(
+        flavor(None, None).unwrap(),
+        RuntimeFlavor::MultiThread { workers: None }
+    );
+    assert_eq!(
+        flavor(None, Some("4")).unwrap(),
+        RuntimeFlavor::MultiThread { workers: Some(4) }
+    );
+    assert_eq!(
+        flavor(Some(" multi_thread "), Some("2")).unwrap(),
+        RuntimeFlavor::MultiThread { workers: Some(2) }
+    );
+    assert_eq!(
+        flavor(Some("current_thread"), None).unwrap(),
+        RuntimeFlavor::CurrentThread
+    );
+    assert_eq!(
+        error(flavor(Some("current_thread"), Some("2"))),
+        "a worker thread count needs the multi_thread runtime"
+    );
+    assert!(error(flavor(None, Some("0"))).contains("invalid worker thread count \"0\""));
+    assert!(error(flavor(None, Some("many"))).contains("invalid worker thread count"));
+    assert!(error(flavor(Some("sideways"), None)).contains("invalid runtime flavor \"sideways\""));
+}
+
+#[test]
+fn the_runtime_is_built_with_the_configured_workers() {
+    let multi = RuntimeFlavor::MultiThread { workers: Some(3) }
+        .build()
+        .unwrap();
+    let current = RuntimeFlavor::CurrentThread.build().unwrap();
+
+    assert_eq!(multi.metrics().num_workers(), 3);
+    assert_eq!(current.metrics().num_workers(), 1);
+    assert_eq!(
+        RuntimeFlavor::MultiThread { workers: Some(3) }.to_string(),
+        "multi_thread (3 workers)"
+    );
+}
+
+#[test]
+fn the_app_can_be_shared_between_worker_threads() {
+    fn shared<T: Send + Sync>() {}
+    shared::<App>();
+}
+
+/// Serves concurrent orders on `runtime`, with lookups slow enough that
+/// they overlap.
+fn serve_concurrently_on(runtime: tokio::runtime::Runtime) {
+    runtime.block_on(async {
+        let mock = MockTaxService::start().await;
+        mock.respond(
+            ZIP,
+            MockResponse::rate("0.0825").delayed(Duration::from_millis(50)),
+        );
+        let service = TestService::start(&mock, &[]).await;
+        let body = order(ZIP);
+
+        let responses = join_all((0..20).map(|_| service.post("/v1/compute", &body))).await;
+
+        for response in responses {
+            assert_eq!(response.status, StatusCode::OK);
+            assert_eq!(response.json["total"], 21.65);
+        }
+    });
+}
+
+#[test]
+fn orders_are_served_on_the_multi_threaded_runtime() {
+    let flavor = RuntimeFlavor::MultiThread { workers: Some(4) };
+    serve_concurrently_on(flavor.build().unwrap());
+}
+
+#[test]
+fn orders_are_served_on_the_current_thread_runtime() {
+    serve_concurrently_on(RuntimeFlavor::CurrentThread.build().unwrap());
+}
@@ -0,0 +1,225 @@
+//! `SCHEMA_MODE` and the `X-Schema-Mode` header: how strictly order bodies
+//! are held to the documented schema.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+use serde_json::{json, Value};
+
+const ZIP: &str = "78701";
+const STRICT: &[(&str, &str)] = &[("SCHEMA_MODE", "strict")];
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+/// The default order, with `changes` made to it.
+fn order_with(changes: Value) -> String {
+    let mut order: Value = serde_json::from_str(&order(ZIP)).unwrap();
+    for (name, value) in changes.as_object().unwrap() {
+        match value {
+            Value::Null => order.as_object_mut().unwrap().remove(name),
+            value => order
+                .as_object_mut()
+                .unwrap()
+                .insert(name.clone(), value.clone()),
+        };
+    }
+    order.to_string()
+}
+
+fn fields(response: &common::TestResponse) -> Vec<&str> {
+    response.json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn lenient_ignores_unknown_fields() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post(
+            "/v1/compute",
+            &order_with(json!({"shipping_zipcode": "10001"})),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn strict_refuses_unknown_fields_at_any_depth() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, STRICT).await;
+
+    let body = order_with(json!({
+        "subtotl": 20.0,
+        "line_items": [{"product_id": 1, "quantity": 2, "unit_prise": 10.0, "unit_price": 10.0}],
+        "rounding": {"mode": "half_up", "scop": "per_order"},
+    }));
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json["code"], "VALIDATION_FAILED");
+    let mut fields = fields(&response);
+    fields.sort();
+    assert_eq!(
+        fields,
+        ["line_items[0].unit_prise", "rounding.scop", "subtotl"]
+    );
+}
+
+#[tokio::test]
+async fn strict_requires_the_fields_of_a_single_item_order() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, STRICT).await;
+
+    let response = service
+        .post("/v1/compute", &order_with(json!({"quantity": null})))
+        .await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(fields(&response), ["quantity"]);
+}
+
+#[tokio::test]
+async fn strict_accepts_a_computed_order_sent_back() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, STRICT).await;
+
+    let computed = service.post("/v1/compute", &order(ZIP)).await;
+    let again = service
+        .post("/v1/compute", &computed.json.to_string())
+        .await;
+
+    assert_eq!(computed.status, StatusCode::OK);
+    assert_eq!(again.status, StatusCode::OK, "{}", again.message());
+    assert_eq!(again.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn the_header_picks_the_mode_of_one_request() {
+    let mock = with_rate().await;
+    let lenient = TestService::start(&mock, &[]).await;
+    let strict = TestService::start(&mock, STRICT).await;
+    let misspelt = order_with(json!({"subtotl": 20.0}));
+
+    let made_strict = lenient
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Schema-Mode", "strict")],
+            &misspelt,
+        )
+        .await;
+    let made_lenient = strict
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Schema-Mode", "lenient")],
+            &misspelt,
+        )
+        .await;
+    let unknown = lenient
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Schema-Mode", "loose")],
+            &misspelt,
+        )
+        .await;
+
+    assert_eq!(made_strict.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(fields(&made_strict), ["subtotl"]);
+    assert_eq!(made_lenient.status, StatusCode::OK);
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn whole_numbers_sent_as_strings_are_refused_by_default() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post("/v1/compute", &order_with(json!({"quantity": "2"})))
+        .await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json["code"], "INVALID_REQUEST");
+}
+
+#[tokio::test]
+async fn numeric_strings_are_read_as_numbers() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[("NUMERIC_STRINGS", "true")]).await;
+
+    let single = service
+        .post(
+            "/v1/compute",
+            &order_with(json!({"subtotal": " 20.00", "quantity": "2", "order_id": "123"})),
+        )
+        .await;
+    let items = service
+        .post(
+            "/v1/compute",
+            &order_with(json!({
+                "line_items": [{"product_id": "321", "quantity": "2", "unit_price": "10.00"}],
+            })),
+        )
+        .await;
+
+    assert_eq!(single.status, StatusCode::OK, "{}", single.message());
+    assert_eq!(single.json["total"], 21.65);
+    assert_eq!(single.json["quantity"], 2);
+    assert_eq!(items.status, StatusCode::OK, "{}", items.message());
+    assert_eq!(items.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn numeric_strings_that_are_not_numbers_name_the_field() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[("NUMERIC_STRINGS", "true")]).await;
+
+    let response = service
+        .post(
+            "/v1/compute",
+            &order_with(json!({"subtotal": "twenty", "quantity": "2.5"})),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let errors = &response.json["errors"];
+    assert_eq!(errors.as_array().unwrap().len(), 2, "{errors}");
+    for error in errors.as_array().unwrap() {
+        let expected = match error["field"].as_str().unwrap() {
+            "subtotal" => "must be a number",
+            "quantity" => "must be a whole number",
+            other => panic!("unexpected field {other}"),
+        };
+        assert_eq!(error["message"], expected);
+    }
+}
+
+#[tokio::test]
+async fn strict_names_the_field_of_a_value_of_the_wrong_type() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, STRICT).await;
+
+    let response = service
+        .post("/v1/compute", &order_with(json!({"order_id": true})))
+        .await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(fields(&response), ["order_id"]);
+    assert!(!response.message().contains(" at line "));
+}
@@ -0,0 +1,88 @@
+//! The security headers on every response.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestResponse, TestService};
+use hyper::{Method, StatusCode};
+
+const ZIP: &str = "78701";
+
+fn assert_hardened(response: &TestResponse) {
+    assert_eq!(response.header("x-content-type-options"), Some("nosniff"));
+    assert_eq!(response.header("referrer-policy"), Some("no-referrer"));
+    assert_eq!(response.header("x-frame-options"), Some("DENY"));
+}
+
+#[tokio::test]
+async fn pricing_responses_are_not_stored() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_hardened(&response);
+    assert_eq!(response.header("cache-control"), Some("no-store"));
+    assert_eq!(
+        response.header("content-security-policy"),
+        Some("default-src 'none'; frame-ancestors 'none'")
+    );
+    assert_eq!(response.header("strict-transport-security"), None);
+}
+
+#[tokio::test]
+async fn errors_and_unknown_paths_carry_them_too() {
+    let mock = MockTaxService::start().await;
+    let authenticated = TestService::start(&mock, &[("JWT_SECRET", "test-secret")]).await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let unauthorized = authenticated.post("/v1/compute", &order(ZIP)).await;
+    let not_found = service.send(Method::GET, "/v1/nowhere", &[], "").await;
+
+    assert_eq!(unauthorized.status, StatusCode::UNAUTHORIZED);
+    assert_hardened(&unauthorized);
+    assert_eq!(unauthorized.header("cache-control"), Some("no-store"));
+    assert_eq!(not_found.status, StatusCode::NOT_FOUND);
+    assert_hardened(&not_found);
+    assert_eq!(not_found.header("cache-control"), Some("no-store"));
+}
+
+#[tokio::test]
+async fn documentation_keeps_its_scripts_and_caching() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let docs = service.send(Method::GET, "/docs", &[], "").await;
+
+    assert_eq!(docs.status, StatusCode::OK);
+    assert_hardened(&docs);
+    assert_eq!(docs.header("cache-control"), None);
+    assert_eq!(docs.header("content-security-policy"), None);
+}
+
+#[tokio::test]
+async fn hsts_is_sent_when_serving_tls() {
+    let mock = MockTaxService::start().await;
+    // The app only reads whether TLS is configured; the listener is the
+    // test's plain one.
+    let tls = TestService::start(&mock, &[("TLS_CERT_PATH", "/etc/order_total/cert.pem")]).await;
+    let disabled = TestService::start(
+        &mock,
+        &[
+            ("TLS_CERT_PATH", "/etc/order_total/cert.pem"),
+            ("HSTS_MAX_AGE_SECS", "0"),
+        ],
+    )
+    .await;
+
+    let response = tls.send(Method::GET, "/", &[], "").await;
+    let without = disabled.send(Method::GET, "/", &[], "").await;
+
+    assert_eq!(
+        response.header("strict-transport-security"),
+        Some("max-age=31536000")
+    );
+    assert_eq!(without.header("strict-transport-security"), None);
+}
@@ -0,0 +1,89 @@
+//! `OrderTotalService` called directly and mounted in another server.
+#![cfg(feature = "native")]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService};
+use hyper::{Method, Request, Response, StatusCode};
+use order_total::{Body, OrderTotalService};
+use serde_json::Value;
+use std::fmt::Debug;
+use tower_service::Service;
+
+const ZIP: &str = "78701";
+
+async fn json<B>(response: Response<B>) -> Value
+where
+    B: hyper::body::Body,
+    B::Error: Debug,
+{
+    let body = common::to_bytes(response.into_body()).await;
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn the_service_answers_as_the_server_does() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let mut service = OrderTotalService::new(common::app(&mock, &[]));
+
+    let computed = service
+        .call(
+            Request::post("/v1/compute")
+                .body(Body::from(order(ZIP)))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let missing = service
+        .call(Request::get("/nowhere").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(computed.status(), StatusCode::OK);
+    assert!(computed.headers().contains_key("x-request-id"));
+    assert_eq!(json(computed).await["total"], 21.65);
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+/// A server of another application, with the service under `/tax` and a
+/// route of its own beside it.
+#[tokio::test]
+async fn the_service_mounts_under_a_prefix() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let orders = OrderTotalService::new(common::app(&mock, &[]));
+    let addr = common::serve(move |mut req: Request<Body>| {
+        let mut orders = orders.clone();
+        async move {
+            match req.uri().path().strip_prefix("/tax") {
+                Some(path) => {
+                    *req.uri_mut() = path.parse().unwrap();
+                    orders.call(req).await
+                }
+                None => Ok(Response::new(Body::from("{\"app\":\"other\"}"))),
+            }
+        }
+    })
+    .await;
+    let client = common::client();
+
+    let computed = client
+        .request(
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!("http://{addr}/tax/v1/compute"))
+                .body(Body::from(order(ZIP)))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let other = client
+        .get(format!("http://{addr}/home").parse().unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(computed.status(), StatusCode::OK);
+    assert_eq!(json(computed).await["total"], 21.65);
+    assert_eq!(json(other).await["app"], "other");
+}
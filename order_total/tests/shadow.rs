@@ -0,0 +1,109 @@
+//! Shadow lookups at `SHADOW_TAX_RATE_SERVICE`, compared with the primary's
+//! and reported at `/admin/shadow/stats`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+const ADMIN_TOKEN: &str = "admin-token";
+
+/// The service looking rates up from `primary`, shadowed by `shadow`.
+async fn shadowed(primary: &MockTaxService, shadow: &MockTaxService) -> TestService {
+    let url = shadow.url();
+    let env = [
+        ("ADMIN_TOKEN", ADMIN_TOKEN),
+        ("SHADOW_TAX_RATE_SERVICE", url.as_str()),
+    ];
+    TestService::start(primary, &env).await
+}
+
+/// Polls the shadow stats until `done` says the comparisons are in.
+async fn stats(service: &TestService, done: impl Fn(&Value) -> bool) -> Value {
+    let auth = format!("Bearer {ADMIN_TOKEN}");
+    for _ in 0..200 {
+        let stats = service
+            .send(
+                Method::GET,
+                "/admin/shadow/stats",
+                &[("Authorization", &auth)],
+                "",
+            )
+            .await;
+        assert_eq!(stats.status, StatusCode::OK);
+        if done(&stats.json) {
+            return stats.json;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the shadow lookups were not compared");
+}
+
+#[tokio::test]
+async fn answers_with_the_primary_and_counts_disagreements() {
+    let primary = MockTaxService::start().await;
+    primary
+        .respond("78701", MockResponse::rate("0.0825"))
+        .respond("10001", MockResponse::rate("0.08875"))
+        .respond("00501", MockResponse::rate("0.08625"));
+    let shadow = MockTaxService::start().await;
+    shadow
+        .respond("78701", MockResponse::rate("0.08250"))
+        .respond("10001", MockResponse::rate("0.09"));
+    let service = shadowed(&primary, &shadow).await;
+
+    let agreed = service.post("/v1/compute", &order("78701")).await;
+    let disagreed = service.post("/v1/compute", &order("10001")).await;
+    let unknown = service.post("/v1/compute", &order("00501")).await;
+
+    assert_eq!(agreed.json["tax_rate"], 0.0825);
+    assert_eq!(disagreed.json["tax_rate"], 0.08875);
+    assert_eq!(unknown.status, StatusCode::OK);
+    let stats = stats(&service, |stats| stats["compared"] == 3).await;
+    assert_eq!(stats["matched"], 1);
+    assert_eq!(stats["mismatched"], 2);
+    assert_eq!(stats["shadow_failed"], 0);
+    assert_eq!(shadow.hits("10001"), 1);
+}
+
+#[tokio::test]
+async fn a_failing_shadow_fails_nothing() {
+    let primary = MockTaxService::start().await;
+    primary.respond("78701", MockResponse::rate("0.0825"));
+    let shadow = MockTaxService::start().await;
+    shadow.respond(
+        "78701",
+        MockResponse::status(StatusCode::SERVICE_UNAVAILABLE),
+    );
+    let service = shadowed(&primary, &shadow).await;
+
+    let response = service.post("/v1/compute", &order("78701")).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let stats = stats(&service, |stats| stats["shadow_failed"] == 1).await;
+    assert_eq!(stats["compared"], 0);
+    assert_eq!(stats["match_ratio"], Value::Null);
+}
+
+#[tokio::test]
+async fn a_slow_shadow_does_not_delay_the_answer() {
+    let primary = MockTaxService::start().await;
+    primary.respond("78701", MockResponse::rate("0.0825"));
+    let shadow = MockTaxService::start().await;
+    shadow.respond(
+        "78701",
+        MockResponse::rate("0.0825").delayed(Duration::from_millis(500)),
+    );
+    let service = shadowed(&primary, &shadow).await;
+
+    let started = Instant::now();
+    let response = service.post("/v1/compute", &order("78701")).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(started.elapsed() < Duration::from_millis(400));
+    let stats = stats(&service, |stats| stats["compared"] == 1).await;
+    assert_eq!(stats["match_ratio"], 1.0);
+}
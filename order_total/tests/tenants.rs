@@ -0,0 +1,176 @@
+//! The tenants of `TENANTS_FILE`, selected by `X-Tenant-Id`: their own
+//! default rate, rounding, rate service and currency, and caches apart.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, with_env, MockResponse, MockTaxService, TestResponse, TestService};
+use hyper::{Method, StatusCode};
+use std::path::PathBuf;
+
+const ZIP: &str = "78701";
+
+fn tenants_file(name: &str, csv: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "order_total_tenants_{name}_{}.csv",
+        std::process::id()
+    ));
+    std::fs::write(&path, csv).unwrap();
+    path
+}
+
+async fn with_rate(rate: &str) -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate(rate));
+    mock
+}
+
+async fn as_tenant(service: &TestService, tenant: &str, body: &str) -> TestResponse {
+    service
+        .send(
+            Method::POST,
+            "/v1/compute",
+            &[("X-Tenant-Id", tenant)],
+            body,
+        )
+        .await
+}
+
+#[tokio::test]
+async fn a_tenant_looks_rates_up_from_its_own_service() {
+    let shared = with_rate("0.0825").await;
+    let own = with_rate("0.05").await;
+    let file = tenants_file(
+        "service",
+        &format!("tenant,rate_service_url\nglobex,{}\nacme,\n", own.url()),
+    );
+    let env = [("TENANTS_FILE", file.to_str().unwrap())];
+    let service = TestService::start(&shared, &env).await;
+
+    let globex = as_tenant(&service, "globex", &order(ZIP)).await;
+    let acme = as_tenant(&service, "acme", &order(ZIP)).await;
+    let untenanted = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(globex.status, StatusCode::OK);
+    assert_eq!(globex.json["total"], 21.0);
+    assert_eq!(acme.json["total"], 21.65);
+    assert_eq!(untenanted.json["total"], 21.65);
+    assert_eq!(own.hits(ZIP), 1);
+    std::fs::remove_file(&file).ok();
+}
+
+#[tokio::test]
+async fn a_tenant_has_its_own_default_rate_rounding_and_currency() {
+    let mock = MockTaxService::start().await;
+    let file = tenants_file(
+        "settings",
+        "tenant,default_tax_rate,rounding_mode,currency\ninitech,0.05,up,eur\n",
+    );
+    let env = [("TENANTS_FILE", file.to_str().unwrap())];
+    let service = TestService::start(&mock, &env).await;
+    let mut priced = serde_json::from_str::<serde_json::Value>(&order("99999")).unwrap();
+    priced["subtotal"] = 20.01.into();
+
+    let initech = as_tenant(&service, "initech", &priced.to_string()).await;
+    let untenanted = service.post("/v1/compute", &order("99999")).await;
+
+    assert_eq!(initech.status, StatusCode::OK);
+    assert_eq!(initech.json["rate_source"], "default");
+    assert_eq!(initech.json["tax_amount"], 1.01);
+    assert_eq!(initech.json["currency"], "EUR");
+    assert_eq!(untenanted.status, StatusCode::SERVICE_UNAVAILABLE);
+    std::fs::remove_file(&file).ok();
+}
+
+#[tokio::test]
+async fn tenants_do_not_share_caches() {
+    let mock = with_rate("0.0825").await;
+    let file = tenants_file("caches", "tenant\nacme\nglobex\n");
+    let env = [
+        ("TENANTS_FILE", file.to_str().unwrap()),
+        ("RATE_CACHE_SECS", "60"),
+        ("RESPONSE_CACHE_SECS", "60"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    let acme = as_tenant(&service, "acme", &order(ZIP)).await;
+    let again = as_tenant(&service, "acme", &order(ZIP)).await;
+    let globex = as_tenant(&service, "globex", &order(ZIP)).await;
+
+    assert_eq!(acme.header("x-cache"), Some("miss"));
+    assert_eq!(again.header("x-cache"), Some("hit"));
+    assert_eq!(globex.header("x-cache"), Some("miss"));
+    assert_eq!(mock.hits(ZIP), 2);
+    std::fs::remove_file(&file).ok();
+}
+
+#[tokio::test]
+async fn an_unknown_tenant_is_refused() {
+    let mock = with_rate("0.0825").await;
+    let file = tenants_file("unknown", "tenant\nacme\n");
+    let env = [("TENANTS_FILE", file.to_str().unwrap())];
+    let service = TestService::start(&mock, &env).await;
+
+    let refused = as_tenant(&service, "umbrella", &order(ZIP)).await;
+
+    assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+    assert_eq!(refused.json["code"], "UNKNOWN_TENANT");
+    assert_eq!(mock.hits(ZIP), 0);
+    std::fs::remove_file(&file).ok();
+}
+
+#[tokio::test]
+async fn the_header_is_ignored_without_tenants() {
+    let mock = with_rate("0.0825").await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let computed = as_tenant(&service, "acme", &order(ZIP)).await;
+
+    assert_eq!(computed.status, StatusCode::OK);
+    assert_eq!(computed.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn a_reload_a_tenant_fails_changes_nothing() {
+    let mock = with_rate("0.0825").await;
+    let file = tenants_file("reload", "tenant,rate_service_url\nacme,\nbroken,\",\"\n");
+    let tenants = ("TENANTS_FILE", file.to_str().unwrap());
+    let admin = ("ADMIN_TOKEN", "admin-token");
+    let env = [
+        tenants,
+        admin,
+        ("TAX_RATE_SOURCE", "fixed"),
+        ("FIXED_TAX_RATE", "0.05"),
+        ("FEATURE_FLAGS", "batch=off"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+    let reload = service.send(
+        Method::POST,
+        "/admin/reload",
+        &[("Authorization", "Bearer admin-token")],
+        "",
+    );
+
+    // The broken tenant names no rate service, which only matters once
+    // rates come from one.
+    let reloaded = with_env(
+        &mock,
+        &[tenants, admin, ("FEATURE_FLAGS", "batch=on")],
+        reload,
+    )
+    .await;
+    let untenanted = service.post("/v1/compute", &order(ZIP)).await;
+    let acme = as_tenant(&service, "acme", &order(ZIP)).await;
+    let batch = service.post("/v1/compute_stream", &order(ZIP)).await;
+
+    assert_eq!(reloaded.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(
+        reloaded.message().contains("broken"),
+        "{}",
+        reloaded.message()
+    );
+    assert_eq!(untenanted.json["total"], 21.0);
+    assert_eq!(acme.json["total"], 21.0);
+    assert_eq!(batch.status, StatusCode::NOT_FOUND);
+    std::fs::remove_file(&file).ok();
+}
@@ -0,0 +1,72 @@
+//! Timeouts on lookups at a sales tax rate service that hangs, answered
+//! `504` rather than the `503` of an unreachable service.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use std::time::{Duration, Instant};
+
+const ZIP: &str = "78701";
+
+async fn answering_after(delay: Duration) -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825").delayed(delay));
+    mock
+}
+
+#[tokio::test]
+async fn a_lookup_answered_within_the_timeout_succeeds() {
+    let mock = answering_after(Duration::from_millis(100)).await;
+    let service = TestService::start(&mock, &[("TAX_SERVICE_TIMEOUT_MS", "1000")]).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+}
+
+#[tokio::test]
+async fn every_attempt_is_bounded_by_the_timeout() {
+    let mock = answering_after(Duration::from_secs(5)).await;
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_SERVICE_TIMEOUT_MS", "100"),
+            ("TAX_SERVICE_MAX_ATTEMPTS", "3"),
+        ],
+    )
+    .await;
+
+    let started = Instant::now();
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(response.json["code"], "UPSTREAM_TIMEOUT");
+    assert_eq!(mock.hits(ZIP), 3);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn timeouts_count_as_failures_of_the_service() {
+    let mock = answering_after(Duration::from_secs(5)).await;
+    let service = TestService::start(
+        &mock,
+        &[
+            ("TAX_SERVICE_TIMEOUT_MS", "100"),
+            ("TAX_SERVICE_MAX_ATTEMPTS", "1"),
+            ("CIRCUIT_BREAKER_THRESHOLD", "1"),
+        ],
+    )
+    .await;
+
+    let timed_out = service.post("/v1/compute", &order(ZIP)).await;
+    let open = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(timed_out.status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(open.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(open.json["code"], "CIRCUIT_OPEN");
+    assert_eq!(mock.hits(ZIP), 1);
+}
@@ -0,0 +1,80 @@
+//! Serving the API on a Unix domain socket.
+#![cfg(all(feature = "native", feature = "server", unix))]
+
+mod common;
+
+use common::{app, order, MockResponse, MockTaxService};
+use hyper::{Method, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use order_total::Body;
+use std::path::{Path, PathBuf};
+use tokio::net::UnixStream;
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("order_total_{name}_{}.sock", std::process::id()))
+}
+
+async fn post(path: &Path, body: String) -> (StatusCode, serde_json::Value) {
+    let stream = UnixStream::connect(path).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/v1/compute")
+        .header("Host", "localhost")
+        .body(Body::from(body))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    let status = response.status();
+    let bytes = common::to_bytes(response.into_body()).await;
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn orders_are_computed_over_the_socket() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    let path = socket_path("served");
+    let server = order_total::bind_unix(app(&mock, &[]), &path, std::future::pending()).unwrap();
+    tokio::spawn(server);
+
+    let (status, json) = post(&path, order("78701")).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 21.65);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn a_stale_socket_file_is_replaced() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    let path = socket_path("stale");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    let server = order_total::bind_unix(app(&mock, &[]), &path, std::future::pending()).unwrap();
+    tokio::spawn(server);
+    let (status, _) = post(&path, order("78701")).await;
+
+    assert_eq!(status, StatusCode::OK);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn another_file_at_the_path_is_left_alone() {
+    let mock = MockTaxService::start().await;
+    let path = socket_path("regular");
+    std::fs::write(&path, "not a socket").unwrap();
+
+    let result = order_total::bind_unix(app(&mock, &[]), &path, std::future::pending());
+
+    assert!(result
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("is not a socket"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,254 @@
+//! Mutual TLS towards the sales tax rate service: `TAX_SERVICE_CA_BUNDLE`,
+//! `TAX_SERVICE_CLIENT_CERT` and `TAX_SERVICE_CLIENT_KEY`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, try_app, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+const ZIP: &str = "78701";
+
+/// A CA, and the certificates it issued to the service and to the client,
+/// written to PEM files.
+struct Pki {
+    ca: Certificate,
+    ca_key: KeyPair,
+    server: Certificate,
+    server_key: KeyPair,
+    files: Vec<PathBuf>,
+}
+
+impl Pki {
+    fn issue(name: &str) -> Self {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.self_signed(&ca_key).unwrap();
+        let server_key = KeyPair::generate().unwrap();
+        let server = CertificateParams::new(vec!["127.0.0.1".to_owned()])
+            .unwrap()
+            .signed_by(&server_key, &ca, &ca_key)
+            .unwrap();
+        let mut pki = Self {
+            ca,
+            ca_key,
+            server,
+            server_key,
+            files: Vec::new(),
+        };
+        let ca_pem = pki.ca.pem();
+        pki.write(name, "ca.pem", &ca_pem);
+        pki
+    }
+
+    /// A client certificate and its key, as files.
+    fn client(&mut self, name: &str) -> (PathBuf, PathBuf) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["order-total".to_owned()])
+            .unwrap()
+            .signed_by(&key, &self.ca, &self.ca_key)
+            .unwrap();
+        (
+            self.write(name, "client.pem", &cert.pem()),
+            self.write(name, "client-key.pem", &key.serialize_pem()),
+        )
+    }
+
+    fn write(&mut self, name: &str, file: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "order_total_tls_{name}_{}_{file}",
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        self.files.push(path.clone());
+        path
+    }
+
+    fn ca_bundle(&self) -> &str {
+        self.files[0].to_str().unwrap()
+    }
+
+    /// Terminates TLS in front of `mock`, asking for a client certificate
+    /// issued by the CA. Returns the URL to look rates up at.
+    async fn front(&self, mock: &MockTaxService) -> String {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca.der().clone()).unwrap();
+        let provider = Arc::new(rustls_rustcrypto::provider());
+        let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider.clone())
+            .build()
+            .unwrap();
+        let key = PrivatePkcs8KeyDer::from(self.server_key.serialize_der());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(
+                vec![CertificateDer::from(self.server.der().to_vec())],
+                PrivateKeyDer::Pkcs8(key),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let backend: SocketAddr = mock
+            .url()
+            .trim_start_matches("http://")
+            .trim_end_matches(models::FIND_RATE_PATH)
+            .parse()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    // A client without a certificate fails the handshake.
+                    let Ok(mut tls) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut plain = TcpStream::connect(backend).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut tls, &mut plain).await;
+                });
+            }
+        });
+        format!("https://{addr}{}", models::FIND_RATE_PATH)
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        for file in &self.files {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+}
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+#[tokio::test]
+async fn the_client_certificate_is_presented_to_the_service() {
+    let mock = with_rate().await;
+    let mut pki = Pki::issue("presented");
+    let (cert, key) = pki.client("presented");
+    let url = pki.front(&mock).await;
+    let env = [
+        ("SALES_TAX_RATE_SERVICE", url.as_str()),
+        ("TAX_SERVICE_CA_BUNDLE", pki.ca_bundle()),
+        ("TAX_SERVICE_CLIENT_CERT", cert.to_str().unwrap()),
+        ("TAX_SERVICE_CLIENT_KEY", key.to_str().unwrap()),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+    assert_eq!(mock.hits(ZIP), 1);
+}
+
+#[tokio::test]
+async fn without_a_client_certificate_the_service_is_unavailable() {
+    let mock = with_rate().await;
+    let pki = Pki::issue("anonymous");
+    let url = pki.front(&mock).await;
+    let env = [
+        ("SALES_TAX_RATE_SERVICE", url.as_str()),
+        ("TAX_SERVICE_CA_BUNDLE", pki.ca_bundle()),
+        ("TAX_SERVICE_MAX_ATTEMPTS", "1"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.json["code"], "UPSTREAM_UNAVAILABLE");
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn a_service_certificate_from_another_ca_is_refused() {
+    let mock = with_rate().await;
+    let mut pki = Pki::issue("trusted");
+    let (cert, key) = pki.client("trusted");
+    let other = Pki::issue("other");
+    let url = other.front(&mock).await;
+    let env = [
+        ("SALES_TAX_RATE_SERVICE", url.as_str()),
+        ("TAX_SERVICE_CA_BUNDLE", pki.ca_bundle()),
+        ("TAX_SERVICE_CLIENT_CERT", cert.to_str().unwrap()),
+        ("TAX_SERVICE_CLIENT_KEY", key.to_str().unwrap()),
+        ("TAX_SERVICE_MAX_ATTEMPTS", "1"),
+    ];
+    let service = TestService::start(&mock, &env).await;
+
+    let response = service.post("/v1/compute", &order(ZIP)).await;
+
+    assert_eq!(response.json["code"], "UPSTREAM_UNAVAILABLE");
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn missing_or_invalid_files_are_startup_errors() {
+    let mock = with_rate().await;
+    let mut pki = Pki::issue("startup");
+    let (cert, key) = pki.client("startup");
+    let ca_bundle = pki.ca_bundle().to_owned();
+    let cert = cert.to_str().unwrap();
+    let error = |env: &[(&str, &str)]| match try_app(&mock, env) {
+        Ok(_) => panic!("{env:?} was accepted"),
+        Err(err) => format!("{err:#}"),
+    };
+
+    let missing = error(&[
+        ("TAX_SERVICE_CA_BUNDLE", &ca_bundle),
+        ("TAX_SERVICE_CLIENT_CERT", "/nonexistent/client.pem"),
+        ("TAX_SERVICE_CLIENT_KEY", key.to_str().unwrap()),
+    ]);
+    let not_pem = error(&[("TAX_SERVICE_CA_BUNDLE", key.to_str().unwrap())]);
+    let no_key = error(&[
+        ("TAX_SERVICE_CA_BUNDLE", &ca_bundle),
+        ("TAX_SERVICE_CLIENT_CERT", cert),
+        ("TAX_SERVICE_CLIENT_KEY", cert),
+    ]);
+    let without_key = error(&[
+        ("TAX_SERVICE_CA_BUNDLE", &ca_bundle),
+        ("TAX_SERVICE_CLIENT_CERT", cert),
+    ]);
+    let without_ca = error(&[
+        ("TAX_SERVICE_CLIENT_CERT", cert),
+        ("TAX_SERVICE_CLIENT_KEY", key.to_str().unwrap()),
+    ]);
+
+    assert!(
+        missing.contains("cannot read TAX_SERVICE_CLIENT_CERT \"/nonexistent/client.pem\""),
+        "{missing}"
+    );
+    assert!(
+        not_pem.contains("TAX_SERVICE_CA_BUNDLE") && not_pem.contains("no certificate"),
+        "{not_pem}"
+    );
+    assert!(
+        no_key.contains("TAX_SERVICE_CLIENT_KEY") && no_key.contains("no private key"),
+        "{no_key}"
+    );
+    assert!(
+        without_key.contains("must be set together"),
+        "{without_key}"
+    );
+    assert!(
+        without_ca.contains("TAX_SERVICE_CA_BUNDLE is required"),
+        "{without_ca}"
+    );
+}
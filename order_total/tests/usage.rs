@@ -0,0 +1,187 @@
+//! Usage metering: what each tenant and client computed, reported at
+//! `GET /admin/usage` and sent to `USAGE_SINK_URL`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestResponse, TestService};
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use order_total::Body;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+const ADMIN_TOKEN: &str = "admin-secret";
+const AUTHORIZATION: (&str, &str) = ("Authorization", "Bearer admin-secret");
+
+fn csv_file(name: &str, csv: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "order_total_usage_{name}_{}.csv",
+        std::process::id()
+    ));
+    std::fs::write(&path, csv).unwrap();
+    path
+}
+
+async fn with_rate() -> MockTaxService {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    mock
+}
+
+async fn usage(service: &TestService, query: &str) -> TestResponse {
+    let path = format!("/admin/usage{query}");
+    service.send(Method::GET, &path, &[AUTHORIZATION], "").await
+}
+
+/// Polls the job at `location`, with `headers`, until it has finished.
+async fn finished(service: &TestService, location: &str, headers: &[(&str, &str)]) {
+    for _ in 0..200 {
+        let status = service.send(Method::GET, location, headers, "").await;
+        if status.json["status"] == "finished" {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the job at {location} did not finish");
+}
+
+#[tokio::test]
+async fn counts_requests_and_orders_per_tenant_and_client() {
+    let mock = with_rate().await;
+    let keys = csv_file("keys", "key,client\nk-acme,acme\nk-globex,globex\n");
+    let tenants = csv_file("tenants", "tenant\nbrand\n");
+    let env = [
+        ("ADMIN_TOKEN", ADMIN_TOKEN),
+        ("API_KEYS_FILE", keys.to_str().unwrap()),
+        ("TENANTS_FILE", tenants.to_str().unwrap()),
+    ];
+    let service = TestService::start(&mock, &env).await;
+    let acme = [("X-Api-Key", "k-acme")];
+    let globex = [("X-Api-Key", "k-globex"), ("X-Tenant-Id", "brand")];
+
+    for _ in 0..2 {
+        let computed = service
+            .send(Method::POST, "/v1/compute", &acme, &order(ZIP))
+            .await;
+        assert_eq!(computed.status, StatusCode::OK);
+    }
+    let failed = service
+        .send(Method::POST, "/v1/compute", &acme, &order("00000"))
+        .await;
+    assert_eq!(failed.status, StatusCode::SERVICE_UNAVAILABLE);
+    let batch = format!("[{},{},{}]", order(ZIP), order(ZIP), order("00000"));
+    let queued = service
+        .send(Method::POST, "/v1/jobs", &globex, &batch)
+        .await;
+    finished(&service, queued.header("location").unwrap(), &globex).await;
+
+    let report = usage(&service, "").await;
+
+    assert_eq!(report.status, StatusCode::OK);
+    let lines = report.json["usage"].as_array().unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["tenant"], serde_json::Value::Null);
+    assert_eq!(lines[0]["client"], "acme");
+    assert_eq!(lines[0]["requests"], 2);
+    assert_eq!(lines[0]["computations"], 2);
+    assert_eq!(lines[0]["largest_batch"], 1);
+    assert_eq!(lines[1]["tenant"], "brand");
+    assert_eq!(lines[1]["client"], "globex");
+    assert_eq!(lines[1]["requests"], 1);
+    assert_eq!(lines[1]["computations"], 2);
+    assert_eq!(lines[1]["mean_batch"], 2.0);
+    assert_eq!(report.json["total"]["requests"], 3);
+    assert_eq!(report.json["total"]["computations"], 4);
+    assert_eq!(report.json["total"]["largest_batch"], 2);
+    std::fs::remove_file(&keys).ok();
+    std::fs::remove_file(&tenants).ok();
+}
+
+#[tokio::test]
+async fn a_range_covers_the_hours_within_it() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[("ADMIN_TOKEN", ADMIN_TOKEN)]).await;
+    service.post("/v1/compute", &order(ZIP)).await;
+    let next_hour = (chrono::Utc::now() + chrono::TimeDelta::hours(1)).to_rfc3339();
+    let next_hour = next_hour.replace('+', "%2B");
+
+    let current = usage(&service, &format!("?to={next_hour}")).await;
+    let later = usage(&service, &format!("?from={next_hour}")).await;
+    let invalid = usage(&service, "?from=yesterday").await;
+
+    assert_eq!(current.json["total"]["computations"], 1);
+    assert_eq!(current.json["usage"][0]["client"], serde_json::Value::Null);
+    assert_eq!(later.status, StatusCode::OK);
+    assert_eq!(later.json["usage"], serde_json::json!([]));
+    assert_eq!(later.json["total"]["requests"], 0);
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn usage_needs_the_admin_token() {
+    let mock = with_rate().await;
+    let service = TestService::start(&mock, &[("ADMIN_TOKEN", ADMIN_TOKEN)]).await;
+
+    let refused = service.send(Method::GET, "/admin/usage", &[], "").await;
+
+    assert_eq!(refused.status, StatusCode::UNAUTHORIZED);
+}
+
+/// Records the bodies POSTed to it.
+async fn sink() -> (String, Arc<Mutex<Vec<Bytes>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let shared = received.clone();
+    let addr = common::serve(move |req: Request<Body>| {
+        let received = shared.clone();
+        async move {
+            let body = common::to_bytes(req.into_body()).await;
+            received.lock().unwrap().push(body);
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }
+    })
+    .await;
+    (format!("http://{addr}/usage"), received)
+}
+
+#[tokio::test]
+async fn the_sink_gets_the_usage_of_each_period() {
+    let mock = with_rate().await;
+    let (url, received) = sink().await;
+    let env = [
+        ("USAGE_SINK_URL", url.as_str()),
+        ("WEBHOOK_SECRET", "secret"),
+    ];
+    let app = common::app(&mock, &env);
+    let service = TestService::serve(app.clone());
+
+    service.post("/v1/compute", &order(ZIP)).await;
+    app.report_usage();
+    app.report_usage();
+    service.post("/v1/compute", &order(ZIP)).await;
+    service.post("/v1/compute", &order(ZIP)).await;
+    app.report_usage();
+
+    let mut reports = Vec::new();
+    for _ in 0..200 {
+        reports = received.lock().unwrap().clone();
+        if reports.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(received.lock().unwrap().len(), 2);
+    let mut reports: Vec<serde_json::Value> = reports
+        .iter()
+        .map(|body| serde_json::from_slice(body).unwrap())
+        .collect();
+    reports.sort_by_key(|report| report["from"].as_str().unwrap().to_owned());
+    let (first, second) = (&reports[0], &reports[1]);
+    assert_eq!(first["total"]["computations"], 1);
+    assert_eq!(second["total"]["requests"], 2);
+    assert_eq!(first["to"], second["from"]);
+}
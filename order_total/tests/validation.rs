@@ -0,0 +1,99 @@
+//! Validation of orders before anything is looked up: `422` with a
+//! `{field, message}` entry for every problem.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use serde_json::{json, Value};
+
+const ZIP: &str = "78701";
+
+async fn service() -> (MockTaxService, TestService) {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    (mock, service)
+}
+
+/// `order(zip)` with `fields` replaced.
+fn order_with(zip: &str, fields: Value) -> String {
+    let mut body: Value = serde_json::from_str(&order(zip)).unwrap();
+    for (name, value) in fields.as_object().unwrap() {
+        body[name] = value.clone();
+    }
+    body.to_string()
+}
+
+#[tokio::test]
+async fn negative_amounts_and_an_empty_zip_are_each_reported() {
+    let (mock, service) = service().await;
+    let body = order_with("", json!({"quantity": -2, "subtotal": -20.0}));
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json["code"], "VALIDATION_FAILED");
+    assert_eq!(response.message(), "The order failed validation.");
+    assert_eq!(
+        response.json["errors"],
+        json!([
+            {"field": "shipping_zip", "message": "must not be empty"},
+            {"field": "quantity", "message": "must be greater than zero"},
+            {"field": "subtotal", "message": "must not be negative"},
+        ])
+    );
+    assert_eq!(mock.hits(ZIP), 0);
+}
+
+#[tokio::test]
+async fn a_blank_zip_or_address_is_empty() {
+    let (_mock, service) = service().await;
+    let body = order_with("   ", json!({"shipping_address": " "}));
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json["errors"],
+        json!([
+            {"field": "shipping_zip", "message": "must not be empty"},
+            {"field": "shipping_address", "message": "must not be empty"},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn line_items_are_reported_by_their_index() {
+    let (_mock, service) = service().await;
+    let body = order_with(
+        ZIP,
+        json!({"line_items": [
+            {"product_id": 1, "quantity": 1, "unit_price": 5.0},
+            {"product_id": 2, "quantity": 0, "unit_price": -5.0},
+        ]}),
+    );
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json["errors"],
+        json!([
+            {"field": "line_items[1].quantity", "message": "must be greater than zero"},
+            {"field": "line_items[1].unit_price", "message": "must not be negative"},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn a_free_order_is_valid() {
+    let (_mock, service) = service().await;
+    let body = order_with(ZIP, json!({"subtotal": 0.0}));
+
+    let response = service.post("/v1/compute", &body).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 0.0);
+}
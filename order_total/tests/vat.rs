@@ -0,0 +1,151 @@
+//! Orders shipped to the EU, charged the member state's VAT instead of a
+//! looked-up sales tax rate, and reverse-charged B2B sales.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hyper::StatusCode;
+use serde_json::{json, Value};
+
+fn eu_order(country: &str, vat_id: Option<&str>) -> Value {
+    let mut order: Value = serde_json::from_str(&order("10115")).unwrap();
+    order["shipping_country"] = json!(country);
+    if let Some(vat_id) = vat_id {
+        order["vat_id"] = json!(vat_id);
+    }
+    order
+}
+
+#[tokio::test]
+async fn charges_the_vat_of_the_member_state() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+
+    let response = service
+        .post("/v1/compute", &eu_order("de", None).to_string())
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["jurisdiction"], "DE");
+    assert_eq!(response.json["tax_rate"], 0.19);
+    assert_eq!(response.json["total"], 23.8);
+    assert_eq!(
+        response.json["vat"],
+        json!({
+            "country": "DE",
+            "reverse_charge": false,
+            "net_amount": 20.0,
+            "vat_amount": 3.8,
+            "gross_amount": 23.8
+        })
+    );
+    assert_eq!(mock.hits("10115"), 0);
+}
+
+#[tokio::test]
+async fn groceries_are_charged_the_reduced_rate() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut body = eu_order("FR", None);
+    body["line_items"] = json!([
+        {"product_id": 1, "quantity": 1, "unit_price": 10.0, "tax_category": "grocery"},
+        {"product_id": 2, "quantity": 1, "unit_price": 10.0}
+    ]);
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let lines = &response.json["line_items"];
+    assert_eq!(lines[0]["tax_rate"], 0.055);
+    assert_eq!(lines[0]["tax"], 0.55);
+    assert_eq!(lines[1]["tax"], 2.0);
+    assert_eq!(response.json["vat"]["vat_amount"], 2.55);
+    assert_eq!(response.json["vat"]["gross_amount"], 22.55);
+}
+
+#[tokio::test]
+async fn b2b_sales_to_other_member_states_are_reverse_charged() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[("VAT_SELLER_COUNTRY", "FR")]).await;
+
+    let foreign = eu_order("DE", Some("de 123.456.789"));
+    let domestic = eu_order("FR", Some("FR12345678901"));
+    let foreign = service.post("/v1/compute", &foreign.to_string()).await;
+    let domestic = service.post("/v1/compute", &domestic.to_string()).await;
+
+    assert_eq!(foreign.status, StatusCode::OK);
+    assert_eq!(foreign.json["rate_source"], "reverse_charge");
+    assert_eq!(foreign.json["tax_amount"], 0.0);
+    assert_eq!(foreign.json["total"], 20.0);
+    assert_eq!(foreign.json["vat"]["vat_id"], "DE123456789");
+    assert_eq!(foreign.json["vat"]["reverse_charge"], true);
+    assert_eq!(domestic.status, StatusCode::OK);
+    assert_eq!(domestic.json["rate_source"], "lookup");
+    assert_eq!(domestic.json["vat"]["reverse_charge"], false);
+    assert_eq!(domestic.json["vat"]["vat_amount"], 4.0);
+}
+
+#[tokio::test]
+async fn vat_ids_must_be_formatted_for_the_member_state() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut american: Value = serde_json::from_str(&order("78701")).unwrap();
+    american["vat_id"] = json!("DE123456789");
+
+    let malformed = eu_order("DE", Some("FR12345678901"));
+    let malformed = service.post("/v1/compute", &malformed.to_string()).await;
+    let american = service.post("/v1/compute", &american.to_string()).await;
+
+    assert_eq!(malformed.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(malformed.json["errors"][0]["field"], "vat_id");
+    assert_eq!(
+        malformed.json["errors"][0]["message"],
+        "must be a VAT number of DE"
+    );
+    assert_eq!(american.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(american.json["errors"][0]["field"], "vat_id");
+}
+
+#[tokio::test]
+async fn postal_codes_are_checked_against_the_member_state() {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut dutch = eu_order("NL", None);
+    dutch["shipping_zip"] = json!("1012ab");
+    let mut german = eu_order("DE", None);
+    german["shipping_zip"] = json!("1011");
+
+    let dutch = service.post("/v1/compute", &dutch.to_string()).await;
+    let german = service.post("/v1/compute", &german.to_string()).await;
+
+    assert_eq!(dutch.status, StatusCode::OK);
+    assert_eq!(dutch.json["shipping_zip"], "1012 AB");
+    assert_eq!(german.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(german.json["errors"][0]["field"], "shipping_zip");
+    assert_eq!(
+        german.json["errors"][0]["message"],
+        "must be a postal code of DE, e.g. 10115"
+    );
+}
+
+#[tokio::test]
+async fn vat_sent_with_an_order_outside_the_eu_is_not_echoed() {
+    let mock = MockTaxService::start().await;
+    mock.respond("78701", MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, &[]).await;
+    let mut body: Value = serde_json::from_str(&order("78701")).unwrap();
+    body["vat"] = json!({
+        "country": "DE",
+        "reverse_charge": true,
+        "net_amount": 20.0,
+        "vat_amount": 0.0,
+        "gross_amount": 20.0
+    });
+
+    let response = service.post("/v1/compute", &body.to_string()).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["total"], 21.65);
+    assert_eq!(response.json.get("vat"), None);
+}
@@ -0,0 +1,28 @@
+//! `GET /version`.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{MockTaxService, TestService};
+use hyper::{Method, StatusCode};
+
+#[tokio::test]
+async fn version_reports_the_build() {
+    let mock = MockTaxService::start().await;
+    // Operators check it without API credentials.
+    let service = TestService::start(&mock, &[("JWT_SECRET", "test-secret")]).await;
+
+    let response = service.send(Method::GET, "/version", &[], "").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    let build = &response.json;
+    assert_eq!(build["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!build["git_sha"].as_str().unwrap().is_empty());
+    assert!(chrono::DateTime::parse_from_rfc3339(build["built_at"].as_str().unwrap()).is_ok());
+    assert!(build["features"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("native")));
+    assert!(!build["target"].as_str().unwrap().is_empty());
+}
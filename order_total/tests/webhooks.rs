@@ -0,0 +1,205 @@
+//! Webhook callbacks of `/v1/compute` and `/v1/jobs`, delivered to an
+//! in-process receiver.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{order, MockResponse, MockTaxService, TestService};
+use hmac::{Hmac, Mac};
+use hyper::body::Bytes;
+use hyper::header::HeaderMap;
+use hyper::{Request, Response, StatusCode};
+use order_total::Body;
+use sha2::Sha256;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const ZIP: &str = "78701";
+const SECRET: &str = "webhook-secret";
+const ENV: &[(&str, &str)] = &[
+    ("WEBHOOK_SECRET", SECRET),
+    ("WEBHOOK_RETRY_BASE_MS", "1"),
+    ("WEBHOOK_RETRY_MAX_MS", "5"),
+];
+
+#[derive(Default)]
+struct Received {
+    /// Answered, in turn, to the first deliveries; `200` after that.
+    failures: Vec<StatusCode>,
+    deliveries: Vec<(HeaderMap, Bytes)>,
+}
+
+/// Records every delivery it gets.
+struct Receiver {
+    addr: SocketAddr,
+    received: Arc<Mutex<Received>>,
+}
+
+impl Receiver {
+    async fn start(failures: &[StatusCode]) -> Self {
+        let received = Arc::new(Mutex::new(Received {
+            failures: failures.iter().rev().copied().collect(),
+            deliveries: Vec::new(),
+        }));
+        let shared = received.clone();
+        let addr = common::serve(move |req| record(req, shared.clone())).await;
+        Self { addr, received }
+    }
+
+    fn url(&self) -> String {
+        format!("http://{}/hooks", self.addr)
+    }
+
+    /// Waits for `count` deliveries to have arrived.
+    async fn deliveries(&self, count: usize) -> Vec<(HeaderMap, Bytes)> {
+        for _ in 0..200 {
+            let deliveries = self.received.lock().unwrap().deliveries.clone();
+            if deliveries.len() >= count {
+                return deliveries;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{count} deliveries did not arrive");
+    }
+}
+
+async fn record(
+    req: Request<Body>,
+    received: Arc<Mutex<Received>>,
+) -> Result<Response<Body>, Infallible> {
+    let headers = req.headers().clone();
+    let body = common::to_bytes(req.into_body()).await;
+    let mut received = received.lock().unwrap();
+    received.deliveries.push((headers, body));
+    let mut response = Response::default();
+    *response.status_mut() = received.failures.pop().unwrap_or(StatusCode::OK);
+    Ok(response)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers.get(name).unwrap().to_str().unwrap()
+}
+
+fn assert_signed(headers: &HeaderMap, body: &[u8]) {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(header(headers, "x-webhook-timestamp").as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let expected: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    assert_eq!(
+        header(headers, "x-webhook-signature"),
+        format!("sha256={expected}")
+    );
+}
+
+#[tokio::test]
+async fn posts_the_computed_order_signed() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, ENV).await;
+    let receiver = Receiver::start(&[]).await;
+
+    let path = format!("/v1/compute?callback_url={}", receiver.url());
+    let response = service.post(&path, &order(ZIP)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let deliveries = receiver.deliveries(1).await;
+    let (headers, body) = &deliveries[0];
+    assert_eq!(header(headers, "x-webhook-event"), "order.computed");
+    assert_signed(headers, body);
+    let order: serde_json::Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(order["total"], 21.65);
+}
+
+#[tokio::test]
+async fn a_dry_run_is_not_announced() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, ENV).await;
+    let receiver = Receiver::start(&[]).await;
+    let mut dry_run: serde_json::Value = serde_json::from_str(&order(ZIP)).unwrap();
+    dry_run["order_id"] = 1.into();
+    dry_run["dry_run"] = true.into();
+
+    let path = format!("/v1/compute?callback_url={}", receiver.url());
+    let simulated = service.post(&path, &dry_run.to_string()).await;
+    let computed = service.post(&path, &order(ZIP)).await;
+
+    assert_eq!(simulated.status, StatusCode::OK);
+    assert_eq!(simulated.json["dry_run"], true);
+    assert_eq!(computed.status, StatusCode::OK);
+    let deliveries = receiver.deliveries(1).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(receiver.received.lock().unwrap().deliveries.len(), 1);
+    let delivered: serde_json::Value = serde_json::from_slice(&deliveries[0].1).unwrap();
+    assert_eq!(delivered["order_id"], 123);
+}
+
+#[tokio::test]
+async fn retries_a_failed_delivery() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, ENV).await;
+    let receiver = Receiver::start(&[StatusCode::SERVICE_UNAVAILABLE]).await;
+
+    let path = format!("/v1/compute?callback_url={}", receiver.url());
+    service.post(&path, &order(ZIP)).await;
+
+    let deliveries = receiver.deliveries(2).await;
+    assert_eq!(
+        header(&deliveries[0].0, "x-webhook-delivery"),
+        header(&deliveries[1].0, "x-webhook-delivery")
+    );
+    assert_eq!(deliveries[0].1, deliveries[1].1);
+}
+
+#[tokio::test]
+async fn posts_the_result_of_a_finished_job() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let service = TestService::start(&mock, ENV).await;
+    let receiver = Receiver::start(&[]).await;
+
+    let path = format!("/v1/jobs?callback_url={}", receiver.url());
+    let queued = service
+        .post(&path, &format!("[{},{}]", order(ZIP), order("00000")))
+        .await;
+
+    assert_eq!(queued.status, StatusCode::ACCEPTED);
+    let deliveries = receiver.deliveries(1).await;
+    let (headers, body) = &deliveries[0];
+    assert_eq!(header(headers, "x-webhook-event"), "job.finished");
+    assert_signed(headers, body);
+    let result: serde_json::Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(result["job_id"], queued.json["job_id"]);
+    assert_eq!(result["results"][0]["total"], 21.65);
+    assert_eq!(result["results"][1]["code"], "TAX_RATE_UNAVAILABLE");
+}
+
+#[tokio::test]
+async fn rejects_callbacks_unless_a_secret_is_configured() {
+    let mock = MockTaxService::start().await;
+    mock.respond(ZIP, MockResponse::rate("0.0825"));
+    let disabled = TestService::start(&mock, &[]).await;
+    let enabled = TestService::start(&mock, ENV).await;
+
+    let without_secret = disabled
+        .post("/v1/compute?callback_url=http://example.com/", &order(ZIP))
+        .await;
+    let bad_scheme = enabled
+        .post("/v1/compute?callback_url=ftp://example.com/", &order(ZIP))
+        .await;
+
+    for response in [without_secret, bad_scheme] {
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.json["errors"][0]["field"], "callback_url");
+    }
+    assert_eq!(mock.hits(ZIP), 0);
+}
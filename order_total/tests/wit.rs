@@ -0,0 +1,147 @@
+//! The WIT world of the computation, `core/wit/order-total.wit`, against the
+//! schemas `/openapi.json` publishes, so that the two describe the same
+//! orders and errors.
+#![cfg(all(feature = "native", feature = "server"))]
+
+mod common;
+
+use common::{MockTaxService, TestService};
+use hyper::Method;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use wit_parser::{Resolve, Type, TypeDefKind, WorldItem};
+
+const WIT: &str = include_str!("../core/wit/order-total.wit");
+
+/// The WIT types named differently from their schema, with the properties
+/// only the HTTP API has.
+const RENAMED: [(&str, &str, &[&str]); 1] =
+    [("compute-error", "ErrorResponse", &["status", "request_id"])];
+
+fn resolve() -> Resolve {
+    let mut resolve = Resolve::default();
+    resolve.push_str("order-total.wit", WIT).unwrap();
+    resolve
+}
+
+async fn schemas() -> serde_json::Map<String, Value> {
+    let mock = MockTaxService::start().await;
+    let service = TestService::start(&mock, &[]).await;
+    let mut docs = service.send(Method::GET, "/openapi.json", &[], "").await;
+    match docs.json["components"]["schemas"].take() {
+        Value::Object(schemas) => schemas,
+        other => panic!("no component schemas: {other}"),
+    }
+}
+
+/// `line-item` is `LineItem`.
+fn schema_name(wit_name: &str) -> String {
+    wit_name
+        .split('-')
+        .map(|word| word[..1].to_uppercase() + &word[1..])
+        .collect()
+}
+
+/// `half_up` and `HALF_UP` are `half-up`.
+fn kebab(name: &str) -> String {
+    name.to_lowercase().replace('_', "-")
+}
+
+#[test]
+fn the_calculator_imports_rates_and_exports_compute_total() {
+    let resolve = resolve();
+    let (_, world) = resolve
+        .worlds
+        .iter()
+        .find(|(_, world)| world.name == "calculator")
+        .unwrap();
+    let interfaces = |items: &mut dyn Iterator<Item = &WorldItem>| -> Vec<String> {
+        items
+            .filter_map(|item| match item {
+                WorldItem::Interface { id, .. } => resolve.interfaces[*id].name.clone(),
+                _ => None,
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        interfaces(&mut world.imports.values()),
+        ["types", "tax-rates"]
+    );
+    assert_eq!(interfaces(&mut world.exports.values()), ["compute"]);
+    let (_, compute) = resolve
+        .interfaces
+        .iter()
+        .find(|(_, interface)| interface.name.as_deref() == Some("compute"))
+        .unwrap();
+    let function = &compute.functions["compute-total"];
+    assert_eq!(function.params.len(), 1);
+    assert!(function.result.is_some());
+}
+
+#[tokio::test]
+async fn the_types_mirror_the_api_schemas() {
+    let resolve = resolve();
+    let schemas = schemas().await;
+    let (_, types) = resolve
+        .interfaces
+        .iter()
+        .find(|(_, interface)| interface.name.as_deref() == Some("types"))
+        .unwrap();
+
+    let mut compared = 0;
+    for (name, id) in &types.types {
+        let (schema_name, http_only) = match RENAMED.iter().find(|(wit, ..)| wit == name) {
+            Some((_, schema, http_only)) => (schema.to_string(), *http_only),
+            None => (schema_name(name), &[][..]),
+        };
+        match &resolve.types[*id].kind {
+            TypeDefKind::Record(record) => {
+                let schema = &schemas[&schema_name];
+                let properties: BTreeSet<String> = schema["properties"]
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .filter(|property| !http_only.contains(&property.as_str()))
+                    .map(|property| kebab(property))
+                    .collect();
+                let fields: BTreeSet<String> = record
+                    .fields
+                    .iter()
+                    .map(|field| field.name.clone())
+                    .collect();
+                assert_eq!(fields, properties, "the fields of {name}");
+                let required = schema["required"].as_array().into_iter().flatten();
+                for required in required.filter_map(Value::as_str) {
+                    if http_only.contains(&required) {
+                        continue;
+                    }
+                    let field = record
+                        .fields
+                        .iter()
+                        .find(|field| field.name == kebab(required))
+                        .unwrap_or_else(|| panic!("{name} has no field {required}"));
+                    let optional = matches!(
+                        field.ty,
+                        Type::Id(id) if matches!(resolve.types[id].kind, TypeDefKind::Option(_))
+                    );
+                    assert!(!optional, "{name}.{} is required", field.name);
+                }
+            }
+            TypeDefKind::Enum(cases) => {
+                let values: BTreeSet<String> = schemas[&schema_name]["enum"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|value| kebab(value.as_str().unwrap()))
+                    .collect();
+                let cases: BTreeSet<String> =
+                    cases.cases.iter().map(|case| case.name.clone()).collect();
+                assert_eq!(cases, values, "the cases of {name}");
+            }
+            _ => continue,
+        }
+        compared += 1;
+    }
+    assert_eq!(compared, 19);
+}
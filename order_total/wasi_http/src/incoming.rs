@@ -0,0 +1,76 @@
+//! The `wasi:http/incoming-handler` export: routes `POST /v1/compute` to
+//! the calculator and writes its answer back to the host.
+
+use crate::{read_to_end, OutgoingRates};
+use order_total_core::{Calculator, ComputeError};
+use std::io::Write;
+use std::sync::Arc;
+use wasip2::exports::http::incoming_handler::Guest;
+use wasip2::http::types::{
+    Fields, IncomingRequest, Method, OutgoingBody, OutgoingResponse, ResponseOutparam,
+};
+
+struct Component;
+
+impl Guest for Component {
+    fn handle(request: IncomingRequest, response_out: ResponseOutparam) {
+        let path = request.path_with_query().unwrap_or_default();
+        let path = path.split('?').next().unwrap_or_default();
+        let (status, body) = match (request.method(), path) {
+            (Method::Post, "/v1/compute" | "/compute") => match request.consume() {
+                Ok(body) => match read_to_end(body) {
+                    Ok(body) => answer(&body),
+                    Err(err) => {
+                        tracing::warn!(error = %err, "could not read the request body");
+                        ComputeError::InvalidRequest.into_json()
+                    }
+                },
+                Err(()) => ComputeError::InvalidRequest.into_json(),
+            },
+            _ => (404, String::new()),
+        };
+        respond(response_out, status, body);
+    }
+}
+
+// The export names are not valid symbols for a native linker, and only a
+// component has a host to call them.
+#[cfg(target_arch = "wasm32")]
+wasip2::http::proxy::export!(Component);
+
+/// Answers an order with the calculator of the environment.
+fn answer(body: &[u8]) -> (u16, String) {
+    let url = std::env::var("SALES_TAX_RATE_SERVICE")
+        .unwrap_or_else(|_| format!("http://localhost:8001{}", models::FIND_RATE_PATH));
+    let calculator =
+        OutgoingRates::new(&url).and_then(|rates| Calculator::for_component(Arc::new(rates)));
+    match calculator {
+        Ok(calculator) => futures_executor::block_on(calculator.compute_json(body)),
+        Err(err) => ComputeError::Unexpected(err.into()).into_json(),
+    }
+}
+
+fn respond(out: ResponseOutparam, status: u16, body: String) {
+    let headers = match body.is_empty() {
+        true => Fields::new(),
+        false => Fields::from_list(&[("content-type".to_owned(), b"application/json".to_vec())])
+            .expect("a valid header"),
+    };
+    let response = OutgoingResponse::new(headers);
+    response
+        .set_status_code(status)
+        .expect("a valid status code");
+    let outgoing = response.body().expect("the body is taken once");
+    ResponseOutparam::set(out, Ok(response));
+    let mut stream = outgoing.write().expect("the stream is taken once");
+    if let Err(err) = stream
+        .write_all(body.as_bytes())
+        .and_then(|()| Write::flush(&mut stream))
+    {
+        tracing::warn!(error = %err, "could not write the response body");
+    }
+    drop(stream);
+    if let Err(err) = OutgoingBody::finish(outgoing, None) {
+        tracing::warn!(error = ?err, "could not finish the response body");
+    }
+}
@@ -0,0 +1,41 @@
+//! `POST /v1/compute` as a `wasi:http/proxy` component, for `wasmtime
+//! serve`, Spin and other hosts of that world: the request's order is
+//! computed by `order_total_core`'s `Calculator`, which looks its rate up
+//! from the sales tax rate service through `wasi:http/outgoing-handler`,
+//! and answered with the computed order or the error body the service
+//! would answer.
+//!
+//! ```bash
+//! cargo build -p order-total-wasi-http --target wasm32-wasip2 --release
+//! wasmtime serve -S cli --env SALES_TAX_RATE_SERVICE \
+//!     target/wasm32-wasip2/release/order_total_wasi_http.wasm
+//! ```
+//!
+//! The calculator is configured from the component's environment like the
+//! service is (`ROUNDING_MODE`, `TAX_CATEGORY_TABLE`, `FX_RATE_TABLE` and so
+//! on), except that there is no product catalog and exemption certificates
+//! are accepted when well formed. Each request runs one instance, so lookups
+//! are not retried, cached or coalesced.
+
+// The handler is only called by a host, so natively it is never used.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+mod incoming;
+mod outgoing;
+
+use std::io::Read;
+use wasip2::http::types::IncomingBody;
+
+pub use outgoing::OutgoingRates;
+
+/// Reads a request or response body to its end.
+pub(crate) fn read_to_end(body: IncomingBody) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut stream = body
+        .stream()
+        .map_err(|()| std::io::Error::other("the body was already read"))?;
+    stream.read_to_end(&mut bytes)?;
+    // The stream has to go before the body it belongs to.
+    drop(stream);
+    drop(body);
+    Ok(bytes)
+}
@@ -0,0 +1,102 @@
+use crate::read_to_end;
+use anyhow::Context;
+use async_trait::async_trait;
+use models::{RateRequest, RateResponse, RATE_JSON};
+use order_total_core::{ComputeError, TaxRate, TaxRateProvider};
+use std::io::Write;
+use wasip2::http::outgoing_handler;
+use wasip2::http::types::{Fields, Method, OutgoingBody, OutgoingRequest, Scheme};
+
+/// Looks rates up from the sales tax rate service through the host's
+/// `wasi:http/outgoing-handler`: a zip code it answers `404` for has no
+/// rate, and any other failure leaves the service unavailable.
+pub struct OutgoingRates {
+    scheme: Scheme,
+    authority: String,
+    path: String,
+}
+
+impl OutgoingRates {
+    /// `url` is the service's lookup URL, as in `SALES_TAX_RATE_SERVICE`.
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        let uri: http::Uri = url
+            .parse()
+            .with_context(|| format!("invalid SALES_TAX_RATE_SERVICE {url:?}"))?;
+        let scheme = match uri.scheme_str() {
+            Some("http") => Scheme::Http,
+            Some("https") => Scheme::Https,
+            _ => anyhow::bail!("SALES_TAX_RATE_SERVICE {url:?} is not an http or https URL"),
+        };
+        let authority = uri
+            .authority()
+            .with_context(|| format!("SALES_TAX_RATE_SERVICE {url:?} names no host"))?
+            .to_string();
+        Ok(Self {
+            scheme,
+            authority,
+            path: uri.path().to_owned(),
+        })
+    }
+
+    /// Sends the lookup and waits for its answer; the host's I/O blocks.
+    fn fetch(&self, request: &RateRequest) -> Result<String, ComputeError> {
+        let unavailable = |what: &str| {
+            tracing::warn!("sales tax rate service unavailable: {what}");
+            ComputeError::UpstreamUnavailable
+        };
+        let path = match request.query() {
+            Some(query) => format!("{}?{query}", self.path),
+            None => self.path.clone(),
+        };
+        let headers = Fields::from_list(&[("accept".to_owned(), RATE_JSON.as_bytes().to_vec())])
+            .map_err(|err| unavailable(&format!("{err:?}")))?;
+        let lookup = OutgoingRequest::new(headers);
+        lookup
+            .set_method(&Method::Post)
+            .and_then(|()| lookup.set_scheme(Some(&self.scheme)))
+            .and_then(|()| lookup.set_authority(Some(&self.authority)))
+            .and_then(|()| lookup.set_path_with_query(Some(&path)))
+            .map_err(|()| unavailable("the host refused the request"))?;
+        let body = lookup.body().map_err(|()| unavailable("no request body"))?;
+        let mut stream = body
+            .write()
+            .map_err(|()| unavailable("no request body stream"))?;
+        stream
+            .write_all(request.to_body().as_bytes())
+            .and_then(|()| Write::flush(&mut stream))
+            .map_err(|err| unavailable(&err.to_string()))?;
+        drop(stream);
+        OutgoingBody::finish(body, None).map_err(|err| unavailable(&format!("{err:?}")))?;
+
+        let future = outgoing_handler::handle(lookup, None)
+            .map_err(|err| unavailable(&format!("{err:?}")))?;
+        let response = loop {
+            match future.get() {
+                Some(response) => break response,
+                None => future.subscribe().block(),
+            }
+        };
+        let response = response
+            .map_err(|()| unavailable("the response was already taken"))?
+            .map_err(|err| unavailable(&format!("{err:?}")))?;
+        match response.status() {
+            200..=299 => {
+                let body = response
+                    .consume()
+                    .map_err(|()| unavailable("no response body"))?;
+                let body = read_to_end(body).map_err(|err| unavailable(&err.to_string()))?;
+                String::from_utf8(body).map_err(|_| unavailable("the rate is not UTF-8"))
+            }
+            404 => Err(ComputeError::TaxRateNotAvailable),
+            status => Err(unavailable(&format!("it answered {status}"))),
+        }
+    }
+}
+
+#[async_trait]
+impl TaxRateProvider for OutgoingRates {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        let body = self.fetch(request)?;
+        Ok(RateResponse::from_body(&body)?)
+    }
+}
@@ -0,0 +1,94 @@
+//! `POST /v1/compute` as a Cloudflare Worker: the fetch event's order is
+//! computed by `order_total_core`'s `Calculator`, which looks its rate up
+//! from the sales tax rate service with `worker::Fetch`, and answered with
+//! the computed order or the error body the service would answer.
+//!
+//! ```bash
+//! cd order_total/worker && npx wrangler deploy
+//! ```
+//!
+//! A Worker has neither a process environment nor a file system, so the
+//! tables and rounding are the built-in defaults, there is no product
+//! catalog, and exemption certificates are accepted when well formed. The
+//! service is the `SALES_TAX_RATE_SERVICE` variable of `wrangler.toml`;
+//! lookups are not retried, cached or coalesced.
+
+use async_trait::async_trait;
+use models::{RateRequest, RateResponse, RATE_JSON};
+use order_total_core::{Calculator, ComputeError, TaxRate, TaxRateProvider};
+use std::sync::Arc;
+use worker::send::SendFuture;
+use worker::wasm_bindgen::JsValue;
+use worker::{event, Context, Env, Fetch, Headers, Method, Request, RequestInit, Response, Router};
+
+#[event(fetch)]
+async fn fetch(req: Request, env: Env, _ctx: Context) -> worker::Result<Response> {
+    Router::new()
+        .post_async("/v1/compute", handle)
+        .post_async("/compute", handle)
+        .run(req, env)
+        .await
+}
+
+async fn handle(mut req: Request, ctx: worker::RouteContext<()>) -> worker::Result<Response> {
+    let url = ctx
+        .env
+        .var("SALES_TAX_RATE_SERVICE")
+        .map(|var| var.to_string())
+        .unwrap_or_else(|_| format!("http://localhost:8001{}", models::FIND_RATE_PATH));
+    let (status, body) = match Calculator::for_component(Arc::new(FetchRates { url })) {
+        Ok(calculator) => calculator.compute_json(&req.bytes().await?).await,
+        Err(err) => ComputeError::Unexpected(err.into()).into_json(),
+    };
+    let headers = Headers::new();
+    headers.set("content-type", "application/json")?;
+    Ok(Response::ok(body)?
+        .with_status(status)
+        .with_headers(headers))
+}
+
+/// Looks rates up from the sales tax rate service with the Workers `fetch`:
+/// a zip code it answers `404` for has no rate, and any other failure
+/// leaves the service unavailable.
+pub struct FetchRates {
+    pub url: String,
+}
+
+impl FetchRates {
+    async fn fetch(&self, request: &RateRequest) -> Result<String, ComputeError> {
+        let url = match request.query() {
+            Some(query) => format!("{}?{query}", self.url),
+            None => self.url.clone(),
+        };
+        let unavailable = |err: worker::Error| {
+            worker::console_warn!("sales tax rate service unavailable: {err}");
+            ComputeError::UpstreamUnavailable
+        };
+        let headers = Headers::new();
+        headers.set("accept", RATE_JSON).map_err(unavailable)?;
+        let mut init = RequestInit::new();
+        init.with_method(Method::Post)
+            .with_headers(headers)
+            .with_body(Some(JsValue::from_str(&request.to_body())));
+        let lookup = Request::new_with_init(&url, &init).map_err(unavailable)?;
+        let mut response = Fetch::Request(lookup).send().await.map_err(unavailable)?;
+        match response.status_code() {
+            200..=299 => response.text().await.map_err(unavailable),
+            404 => Err(ComputeError::TaxRateNotAvailable),
+            status => {
+                worker::console_warn!("sales tax rate service answered {status}");
+                Err(ComputeError::UpstreamUnavailable)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl TaxRateProvider for FetchRates {
+    async fn find_rate(&self, request: &RateRequest) -> Result<TaxRate, ComputeError> {
+        // The futures of `worker::Fetch` hold `JsValue`s, which are not
+        // `Send`; a Worker runs on a single thread, so they need not be.
+        let body = SendFuture::new(self.fetch(request)).await?;
+        Ok(RateResponse::from_body(&body)?)
+    }
+}
@@ -0,0 +1,64 @@
+//! The Worker's answer to a request body, with a provider in place of the
+//! `fetch` lookups.
+
+use futures_executor::block_on;
+use order_total_core::{Calculator, ComputeError, FixedRateProvider, TaxRate, TaxRateProvider};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::sync::Arc;
+
+const ORDER: &str = r#"{
+    "order_id": 123,
+    "product_id": 321,
+    "quantity": 2,
+    "subtotal": 20.0,
+    "shipping_address": "123 Main St, Anytown USA",
+    "shipping_zip": "78701",
+    "total": 0.0
+}"#;
+
+/// Knows no rate for any zip code.
+struct NoRates;
+
+#[async_trait::async_trait]
+impl TaxRateProvider for NoRates {
+    async fn find_rate(&self, _request: &models::RateRequest) -> Result<TaxRate, ComputeError> {
+        Err(ComputeError::TaxRateNotAvailable)
+    }
+}
+
+fn answer(tax_rates: Arc<dyn TaxRateProvider>, body: &str) -> (u16, Value) {
+    let calculator = Calculator::for_component(tax_rates).unwrap();
+    let (status, body) = block_on(calculator.compute_json(body.as_bytes()));
+    (status, serde_json::from_str(&body).unwrap())
+}
+
+#[test]
+fn the_order_is_answered_with_its_totals() {
+    let rate = Arc::new(FixedRateProvider(Decimal::new(825, 4)));
+
+    let (status, order) = answer(rate, ORDER);
+
+    assert_eq!(status, 200);
+    assert_eq!(order["tax_amount"], 1.65);
+    assert_eq!(order["total"], 21.65);
+}
+
+#[test]
+fn failures_are_answered_with_the_services_error_body() {
+    let (status, error) = answer(Arc::new(NoRates), ORDER);
+
+    assert_eq!(status, 503);
+    assert_eq!(error["code"], "TAX_RATE_UNAVAILABLE");
+}
+
+#[test]
+fn a_malformed_order_names_the_field() {
+    let body = ORDER.replace("\"quantity\": 2", "\"quantity\": \"two\"");
+
+    let (status, error) = answer(Arc::new(NoRates), &body);
+
+    assert_eq!(status, 400);
+    assert_eq!(error["code"], "INVALID_REQUEST");
+    assert_eq!(error["errors"][0]["field"], "quantity");
+}
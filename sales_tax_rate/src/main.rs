@@ -1,9 +1,13 @@
-use std::net::SocketAddr;
-use std::convert::Infallible;
-use std::str;
+mod telemetry;
+
+use csv::ReaderBuilder;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Method, Request, Response, StatusCode, Server};
-use csv::Reader;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use models::logging;
+use models::{RateRequest, RateRow, FIND_RATE_PATH, RATES_BY_ZIPCODE_CSV, RATE_JSON};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tracing::Span;
 
 /// This is our service handler. It receives a Request, routes on its
 /// path, and returns a Future of a Response.
@@ -14,27 +18,46 @@ async fn handle_request(req: Request<Body>) -> Result<Response<Body>, anyhow::Er
             "Try POSTing data to /find_rate such as: `curl localhost:8001/get_rate -XPOST -d '78701'`",
         ))),
 
-        (&Method::POST, "/find_rate") => {
+        (&Method::POST, FIND_RATE_PATH) => {
+            // Callers that accept JSON get the rate with its components.
+            let json = req
+                .headers()
+                .get(hyper::header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .is_some_and(|accept| accept.contains(RATE_JSON));
+            let query = req.uri().query().map(str::to_owned);
             let post_body = hyper::body::to_bytes(req.into_body()).await?;
-            let mut rate = "".to_string();
+            let request = RateRequest::from_body(&post_body)
+                .and_then(|request| request.with_query(query.as_deref()));
+            let Ok(request) = request else {
+                let mut bad_request = Response::default();
+                *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(bad_request);
+            };
+            Span::current().record("zip", request.zip.as_str());
 
-            let rates_data: &[u8] = include_bytes!("rates_by_zipcode.csv");
-            let mut rdr = Reader::from_reader(rates_data);
+            // A zip code has a row per rate it has had; the one in effect
+            // on the requested day (today, by default) is the answer.
+            let mut rows = Vec::new();
+            let mut rdr = ReaderBuilder::new().flexible(true).from_reader(RATES_BY_ZIPCODE_CSV);
             for result in rdr.records() {
                 let record = result?;
-                // dbg!("{:?}", record.clone());
-                if str::from_utf8(&post_body).unwrap().eq(&record[0]) {
-                    rate = record[1].to_string();
-                    break;
+                if request.zip == record[0] {
+                    rows.push(RateRow::from_csv_fields(record.iter().skip(1))?);
                 }
             }
+            let rate = RateRow::in_effect(&rows, request.effective_date()).map(|row| &row.rate);
 
-            if rate.is_empty() {
-                let mut not_found = Response::default();
-                *not_found.status_mut() = StatusCode::NOT_FOUND;
-                Ok(not_found)
-            } else {
-                Ok(Response::new(Body::from(rate)))
+            match rate {
+                Some(rate) if json => Ok(Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, RATE_JSON)
+                    .body(Body::from(rate.to_json()))?),
+                Some(rate) => Ok(Response::new(Body::from(rate.to_body()))),
+                None => {
+                    let mut not_found = Response::default();
+                    *not_found.status_mut() = StatusCode::NOT_FOUND;
+                    Ok(not_found)
+                }
             }
         }
 
@@ -47,20 +70,29 @@ async fn handle_request(req: Request<Body>) -> Result<Response<Body>, anyhow::Er
     }
 }
 
+/// Wraps `handle_request` in a span with the request id, method, path, zip
+/// code, status and latency of the request, joined to the caller's trace when
+/// it sends a W3C `traceparent`. The `X-Request-Id` set by `order_total` is
+/// logged and echoed back, so one id ties both services' logs together.
+async fn traced_request(req: Request<Body>) -> Result<Response<Body>, anyhow::Error> {
+    let request_id = req.headers().get(logging::REQUEST_ID_HEADER).cloned();
+    logging::traced(req, request_id, telemetry::set_parent, handle_request).await
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Logs go through `tracing`, filtered by `RUST_LOG` (default `info`);
+    // `LOG_FORMAT=json` emits one JSON object per line. Spans are also
+    // exported over OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+    logging::init(logging::env_filter(), telemetry::layer(), std::io::stdout);
+
     let addr = SocketAddr::from(([0, 0, 0, 0], 8001));
-    let make_svc = make_service_fn(|_| {
-        async move {
-            Ok::<_, Infallible>(service_fn(move |req| {
-                handle_request(req)
-            }))
-        }
-    });
+    let make_svc =
+        make_service_fn(|_| async move { Ok::<_, Infallible>(service_fn(traced_request)) });
     let server = Server::bind(&addr).serve(make_svc);
-    dbg!("Server started on port 8001");
+    tracing::info!(%addr, "server started");
     if let Err(e) = server.await {
-        eprintln!("server error: {}", e);
+        tracing::error!(error = %e, "server error");
     }
     Ok(())
 }
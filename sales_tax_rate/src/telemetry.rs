@@ -0,0 +1,142 @@
+use futures_util::Stream;
+use hyper::HeaderMap;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::KeyValue;
+use opentelemetry_http::{Bytes, HeaderExtractor, HttpClient, HttpError, Request, Response};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::runtime::{Runtime, RuntimeChannel, TrySend, TrySendError};
+use opentelemetry_sdk::{trace, Resource};
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Builds the layer that exports spans over OTLP/HTTP when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is
+/// set, and `None` otherwise. The exporter itself reads the standard
+/// `OTEL_EXPORTER_OTLP_*` variables; `OTEL_SERVICE_NAME` defaults to
+/// `sales_tax_rate`.
+///
+/// Must be called from within the tokio runtime, which runs the batch export
+/// task.
+pub fn layer<S>() -> anyhow::Result<Option<OpenTelemetryLayer<S, trace::Tracer>>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_none()
+        && std::env::var_os("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").is_none()
+    {
+        return Ok(None);
+    }
+
+    let service_name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "sales_tax_rate".into());
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_http_client(ExportClient(reqwest::Client::new()));
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(
+            trace::config()
+                .with_resource(Resource::new([KeyValue::new("service.name", service_name)])),
+        )
+        .install_batch(TokioWasi)?;
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Makes `span` a child of the trace in the request's W3C `traceparent`
+/// header, if there is one.
+pub fn set_parent(span: &Span, headers: &HeaderMap) {
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+    span.set_parent(parent);
+}
+
+/// Sends the OTLP export requests with the WASI-capable reqwest client.
+#[derive(Debug)]
+struct ExportClient(reqwest::Client);
+
+#[async_trait::async_trait]
+impl HttpClient for ExportClient {
+    async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Bytes>, HttpError> {
+        let (parts, body) = request.into_parts();
+        let response = self
+            .0
+            .request(parts.method, parts.uri.to_string())
+            .headers(parts.headers)
+            .body(body)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+        let status = response.status();
+        Ok(Response::builder()
+            .status(status)
+            .body(response.bytes().await?)?)
+    }
+}
+
+/// Same as in `order_total`: the SDK's tokio runtimes spawn threads, which WASI
+/// does not have, so the batch export task runs on our runtime instead.
+#[derive(Debug, Clone)]
+struct TokioWasi;
+
+impl Runtime for TokioWasi {
+    type Interval = Pin<Box<dyn Stream<Item = ()> + Send>>;
+    type Delay = Pin<Box<tokio::time::Sleep>>;
+
+    fn interval(&self, duration: Duration) -> Self::Interval {
+        Box::pin(futures_util::stream::unfold(
+            tokio::time::interval(duration),
+            |mut interval| async move {
+                interval.tick().await;
+                Some(((), interval))
+            },
+        ))
+    }
+
+    fn spawn(&self, future: futures_util::future::BoxFuture<'static, ()>) {
+        tokio::spawn(future);
+    }
+
+    fn delay(&self, duration: Duration) -> Self::Delay {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+impl RuntimeChannel for TokioWasi {
+    type Receiver<T: Debug + Send> = Receiver<T>;
+    type Sender<T: Debug + Send> = Sender<T>;
+
+    fn batch_message_channel<T: Debug + Send>(&self, capacity: usize) -> (Sender<T>, Receiver<T>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (Sender(tx), Receiver(rx))
+    }
+}
+
+#[derive(Debug)]
+struct Sender<T>(mpsc::Sender<T>);
+
+impl<T: Send> TrySend for Sender<T> {
+    type Message = T;
+
+    fn try_send(&self, item: T) -> Result<(), TrySendError> {
+        self.0.try_send(item).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => TrySendError::ChannelFull,
+            mpsc::error::TrySendError::Closed(_) => TrySendError::ChannelClosed,
+        })
+    }
+}
+
+struct Receiver<T>(mpsc::Receiver<T>);
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.0.poll_recv(cx)
+    }
+}